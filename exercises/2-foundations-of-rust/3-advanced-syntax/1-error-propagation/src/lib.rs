@@ -0,0 +1,425 @@
+//! Counting the lines, words, characters and bytes of files, like the UNIX `wc` program.
+//!
+//! [count] counts anything that can be read, and [count_bytes_and_lines] counts a file, in
+//! parallel with [count_file] if it is large. A [Counter] can be fed an input in chunks. Errors
+//! reading the input are returned rather than panicking, so the `wc` binary can report them and
+//! go on with the next file.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::ops::AddAssign;
+
+mod parallel;
+
+pub use parallel::{count_file, count_parallel, PARALLEL_THRESHOLD};
+
+/// The width of a tab stop, for the length of lines
+const TAB_WIDTH: usize = 8;
+
+/// The counts `wc` reports for an input
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Counts {
+    /// The number of newlines
+    pub lines: usize,
+    /// The number of maximal sequences of non-whitespace characters
+    pub words: usize,
+    /// The number of valid UTF-8 characters
+    pub chars: usize,
+    /// The number of bytes
+    pub bytes: usize,
+    /// The display width of the longest line
+    pub max_line_length: usize,
+}
+
+impl AddAssign for Counts {
+    /// Add the counts of another input to a total, which has the longest line of both
+    fn add_assign(&mut self, other: Counts) {
+        self.lines += other.lines;
+        self.words += other.words;
+        self.chars += other.chars;
+        self.bytes += other.bytes;
+        self.max_line_length = self.max_line_length.max(other.max_line_length);
+    }
+}
+
+/// Counts the contents of an input that is fed to it in chunks of bytes.
+///
+/// Any bytes are accepted. Bytes that are not part of a valid UTF-8 character
+/// are counted as bytes, and as part of a word, but not as characters.
+/// A character split between two chunks is counted once both halves are fed.
+///
+/// Counters of consecutive parts of an input can be combined with
+/// [merge](Counter::merge), which is how [count_parallel] counts the parts
+/// of an input on separate threads.
+#[derive(Debug, Default, Clone)]
+pub struct Counter {
+    /// The counts so far, where `max_line_length` only includes lines that
+    /// both start and end after the first line break
+    counts: Counts,
+    /// Whether the first character is part of a word, or `None` if there
+    /// were no characters yet
+    starts_in_word: Option<bool>,
+    in_word: bool,
+    /// The width of the first line, once it has ended. Its start is the end
+    /// of whatever came before this counter.
+    first_line: Option<Width>,
+    /// The width of the current line
+    line: Width,
+    /// The start of a character that continues in the next chunk
+    partial: [u8; 4],
+    partial_len: usize,
+}
+
+impl Counter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count the next chunk of the input
+    pub fn update(&mut self, mut bytes: &[u8]) {
+        self.counts.bytes += bytes.len();
+
+        if self.partial_len > 0 {
+            // Complete the character from the previous chunk first
+            let len = utf8_len(self.partial[0]);
+            let taken = bytes
+                .iter()
+                .take(len - self.partial_len)
+                .take_while(|&&b| b & 0b1100_0000 == 0b1000_0000)
+                .count();
+            self.partial[self.partial_len..self.partial_len + taken]
+                .copy_from_slice(&bytes[..taken]);
+            self.partial_len += taken;
+            bytes = &bytes[taken..];
+
+            if self.partial_len < len && bytes.is_empty() {
+                // Still incomplete
+                return;
+            }
+            let partial = self.partial;
+            match std::str::from_utf8(&partial[..self.partial_len]) {
+                Ok(c) => self.text(c),
+                Err(_) => (0..self.partial_len).for_each(|_| self.invalid()),
+            }
+            self.partial_len = 0;
+        }
+
+        loop {
+            match std::str::from_utf8(bytes) {
+                Ok(text) => {
+                    self.text(text);
+                    return;
+                }
+                Err(e) => {
+                    let (valid, rest) = bytes.split_at(e.valid_up_to());
+                    // SAFETY: `from_utf8` checked that these bytes are valid
+                    self.text(unsafe { std::str::from_utf8_unchecked(valid) });
+                    match e.error_len() {
+                        Some(len) => {
+                            (0..len).for_each(|_| self.invalid());
+                            bytes = &rest[len..];
+                        }
+                        None => {
+                            self.partial[..rest.len()].copy_from_slice(rest);
+                            self.partial_len = rest.len();
+                            return;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// The counts of everything fed so far
+    pub fn finish(mut self) -> Counts {
+        self.end_partial();
+        let first_line = self.first_line.unwrap_or_default().end(0);
+        self.counts.max_line_length = self
+            .counts
+            .max_line_length
+            .max(first_line)
+            .max(self.line.end(0));
+        self.counts
+    }
+
+    /// Combine this counter with the counter of the part of the input that
+    /// follows it.
+    ///
+    /// The parts must be split at a character boundary: an incomplete
+    /// character at the end of either part is counted as invalid bytes.
+    pub fn merge(mut self, mut next: Counter) -> Counter {
+        self.end_partial();
+        next.end_partial();
+
+        let mut counts = Counts {
+            lines: self.counts.lines + next.counts.lines,
+            words: self.counts.words + next.counts.words,
+            chars: self.counts.chars + next.counts.chars,
+            bytes: self.counts.bytes + next.counts.bytes,
+            max_line_length: self.counts.max_line_length.max(next.counts.max_line_length),
+        };
+        if self.in_word && next.starts_in_word == Some(true) {
+            // A word continues across the split
+            counts.words -= 1;
+        }
+
+        // The current line of `self` continues with the first line of `next`
+        let mut first_line = self.first_line;
+        let line = match next.first_line {
+            Some(next_first_line) => {
+                let joined = self.line.then(next_first_line);
+                match first_line {
+                    Some(_) => counts.max_line_length = counts.max_line_length.max(joined.end(0)),
+                    None => first_line = Some(joined),
+                }
+                next.line
+            }
+            None => self.line.then(next.line),
+        };
+
+        Counter {
+            counts,
+            starts_in_word: self.starts_in_word.or(next.starts_in_word),
+            in_word: match next.starts_in_word {
+                Some(_) => next.in_word,
+                None => self.in_word,
+            },
+            first_line,
+            line,
+            partial: [0; 4],
+            partial_len: 0,
+        }
+    }
+
+    /// Count an incomplete character at the end of the input as invalid bytes
+    fn end_partial(&mut self) {
+        (0..self.partial_len).for_each(|_| self.invalid());
+        self.partial_len = 0;
+    }
+
+    fn text(&mut self, text: &str) {
+        for c in text.chars() {
+            self.char(c);
+        }
+    }
+
+    fn char(&mut self, c: char) {
+        self.counts.chars += 1;
+        match c {
+            '\n' | '\r' | '\x0c' => {
+                if c == '\n' {
+                    self.counts.lines += 1;
+                }
+                match self.first_line {
+                    Some(_) => {
+                        let width = self.line.end(0);
+                        self.counts.max_line_length = self.counts.max_line_length.max(width);
+                    }
+                    None => self.first_line = Some(self.line),
+                }
+                self.line = Width::default();
+            }
+            '\t' => self.line.tab(),
+            c if c.is_control() => {}
+            _ => self.line.char(),
+        }
+
+        if c.is_whitespace() {
+            self.starts_in_word.get_or_insert(false);
+            self.in_word = false;
+        } else {
+            self.word_char();
+        }
+    }
+
+    fn invalid(&mut self) {
+        self.word_char();
+    }
+
+    fn word_char(&mut self) {
+        self.starts_in_word.get_or_insert(true);
+        if !self.in_word {
+            self.in_word = true;
+            self.counts.words += 1;
+        }
+    }
+}
+
+/// The width of a part of a line, which depends on the column it starts at
+/// if it contains a tab
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Width {
+    /// The width before the first tab
+    before_tab: usize,
+    /// The width after the first tab, starting from the tab stop it moved to
+    after_tab: Option<usize>,
+}
+
+impl Width {
+    fn char(&mut self) {
+        match &mut self.after_tab {
+            Some(width) => *width += 1,
+            None => self.before_tab += 1,
+        }
+    }
+
+    fn tab(&mut self) {
+        match &mut self.after_tab {
+            Some(width) => *width = next_tab_stop(*width),
+            None => self.after_tab = Some(0),
+        }
+    }
+
+    /// The column this part ends at, if it starts at column `start`
+    fn end(self, start: usize) -> usize {
+        match self.after_tab {
+            Some(width) => next_tab_stop(start + self.before_tab) + width,
+            None => start + self.before_tab,
+        }
+    }
+
+    /// The width of this part followed by `next`
+    fn then(self, next: Width) -> Width {
+        match (self.after_tab, next.after_tab) {
+            (None, None) => Width {
+                before_tab: self.before_tab + next.before_tab,
+                after_tab: None,
+            },
+            (Some(width), None) => Width {
+                before_tab: self.before_tab,
+                after_tab: Some(width + next.before_tab),
+            },
+            (None, Some(next_width)) => Width {
+                before_tab: self.before_tab + next.before_tab,
+                after_tab: Some(next_width),
+            },
+            // The first tab of `self` moved to a tab stop, so the tab stops
+            // of `next` are the same as if it started at column 0
+            (Some(width), Some(next_width)) => Width {
+                before_tab: self.before_tab,
+                after_tab: Some(next_tab_stop(width + next.before_tab) + next_width),
+            },
+        }
+    }
+}
+
+fn next_tab_stop(column: usize) -> usize {
+    column + TAB_WIDTH - column % TAB_WIDTH
+}
+
+/// The length of a UTF-8 character, from its first byte
+fn utf8_len(first: u8) -> usize {
+    match first {
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        _ => 4,
+    }
+}
+
+/// The size of the chunks read by [count]
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Count everything that can be read from `input`
+pub fn count(mut input: impl Read) -> Result<Counts, io::Error> {
+    let mut counter = Counter::new();
+    let mut buf = vec![0; CHUNK_SIZE];
+    loop {
+        match input.read(&mut buf) {
+            Ok(0) => return Ok(counter.finish()),
+            Ok(n) => counter.update(&buf[..n]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Count the contents of the file `filename`, in parallel if it is large
+pub fn count_bytes_and_lines(filename: &str) -> Result<Counts, io::Error> {
+    count_file(File::open(filename)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn counts(input: &[u8]) -> Counts {
+        count(input).unwrap()
+    }
+
+    #[test]
+    fn test_text() {
+        assert_eq!(counts(b""), Counts::default());
+        assert_eq!(
+            counts("héllo wörld\n  two\tlines\n".as_bytes()),
+            Counts {
+                lines: 2,
+                words: 4,
+                chars: 24,
+                bytes: 26,
+                max_line_length: 13,
+            }
+        );
+        // The last line doesn't need a newline, but only newlines are counted
+        let no_newline = counts(b"one\ntwo three");
+        assert_eq!((no_newline.lines, no_newline.words), (1, 3));
+        assert_eq!(no_newline.max_line_length, 9);
+    }
+
+    #[test]
+    fn test_binary() {
+        // Invalid bytes are part of words, but not characters, even when they
+        // are the start of a character that is cut off
+        let input = b"ab\xff\xfe cd \xe2\x82\n\xf0\x9f\x98\x80";
+        assert_eq!(
+            counts(input),
+            Counts {
+                lines: 1,
+                words: 4,
+                chars: 8,
+                bytes: input.len(),
+                max_line_length: 6,
+            }
+        );
+    }
+
+    #[test]
+    fn test_chunks() {
+        let input = [
+            "a €uro, \u{1F600} and\x0b".as_bytes(),
+            b"\xf0\x9f tab\tend\r\n",
+            "last \u{a0}word".as_bytes(),
+            b"\xe2\x82\xac\xe2\x82 \xff",
+        ]
+        .concat();
+        let expected = counts(&input);
+        for chunk_size in 1..8 {
+            let mut counter = Counter::new();
+            input
+                .chunks(chunk_size)
+                .for_each(|chunk| counter.update(chunk));
+            assert_eq!(counter.finish(), expected, "chunks of {chunk_size}");
+        }
+    }
+
+    #[test]
+    fn test_total() {
+        let mut total = counts(b"a b\nlonger line\n");
+        total += counts(b"c\n");
+        assert_eq!(
+            total,
+            Counts {
+                lines: 3,
+                words: 5,
+                chars: 18,
+                bytes: 18,
+                max_line_length: 11,
+            }
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = count_bytes_and_lines("does/not/exist").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+    }
+}
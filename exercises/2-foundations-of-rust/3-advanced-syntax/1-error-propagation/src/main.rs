@@ -1,50 +1,203 @@
-// This is a very basic rust implementation that approximates the UNIX 'wc' program for text-files; for example, the file you are reading now
-// has 50 lines, 303 words, and 2076 bytes, which you can verify by running cargo run -- src/main.rs
-//
-// However, reading a particular line in a file can fail (for example, if it is not a valid UTF-8 file); this will be very rare, and the code
-// just uses unwrap() to convert a Result<String,Error> into a String. If this doesn't succeed, the program panics.
-// And of course, opening a file can fail for obvious reasons.
-//
-// You can trigger this behaviour by running this program on something that is
-// not a text file, e.g. cargo run -- /bin/bash or cargo run -- c:\windows\system32\cmd.exe
-//
-// Your tasks:
-// 1) change the functions read_lines and count_bytes_and_lines so they return a Result<TYPE, io::Error>, and make them propagate errors.
-//
-// 2) handle these errors in main, reporting any error that occurred in main() using eprintln!
-
 use std::env;
-use std::fs::File;
-use std::io::{self, BufRead, BufReader, Lines};
-
-//change this into:
-//fn read_lines(filename: &str) -> Result<Lines<BufReader<File>>, io::Error> {
-fn read_lines(filename: &str) -> Lines<BufReader<File>> {
-    let file = File::open(filename).unwrap(); // this can easily fail
-    BufReader::new(file).lines()
+use std::io::{self, Write};
+use std::process::ExitCode;
+
+use error_propagating::{count, count_bytes_and_lines, Counts};
+
+const USAGE: &str = "usage: wc [-lwcmL] [file ...]";
+
+/// Which counts to print, in the order `wc` prints them
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Columns {
+    lines: bool,
+    words: bool,
+    chars: bool,
+    bytes: bool,
+    max_line_length: bool,
 }
 
-//change this into:
-//fn count_bytes_and_lines(filename: &str) -> Result<(usize, usize, usize), io::Error> {
-fn count_bytes_and_lines(filename: &str) -> (usize, usize, usize) {
-    let lines = read_lines(filename);
-    let mut line_count = 0;
-    let mut word_count = 0;
-    let mut byte_count = 0;
-    for line in lines {
-        let text = line.unwrap(); // this will usually not fail
-        line_count += 1;
-        word_count += text.split_whitespace().count();
-        byte_count += text.len();
-    }
-
-    (line_count, word_count, byte_count)
+impl Columns {
+    /// The counts `wc` prints without flags
+    const DEFAULT: Columns = Columns {
+        lines: true,
+        words: true,
+        chars: false,
+        bytes: true,
+        max_line_length: false,
+    };
+
+    fn values(self, counts: &Counts) -> impl Iterator<Item = usize> {
+        [
+            (self.lines, counts.lines),
+            (self.words, counts.words),
+            (self.chars, counts.chars),
+            (self.bytes, counts.bytes),
+            (self.max_line_length, counts.max_line_length),
+        ]
+        .into_iter()
+        .filter_map(|(enabled, value)| enabled.then_some(value))
+    }
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    let filename = &args[1];
+/// Parse the command line arguments into the columns to print and the files
+/// to count, where "-" is standard input
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<(Columns, Vec<String>), String> {
+    let mut columns = Columns::default();
+    let mut files = Vec::new();
+    let mut options = true;
+    for arg in args {
+        if !options || arg == "-" || !arg.starts_with('-') {
+            files.push(arg);
+        } else if arg == "--" {
+            options = false;
+        } else {
+            for flag in arg.chars().skip(1) {
+                match flag {
+                    'l' => columns.lines = true,
+                    'w' => columns.words = true,
+                    'c' => columns.bytes = true,
+                    'm' => columns.chars = true,
+                    'L' => columns.max_line_length = true,
+                    _ => return Err(format!("invalid option -- '{flag}'")),
+                }
+            }
+        }
+    }
 
-    let (lines, words, bytes) = count_bytes_and_lines(filename);
-    println!("{filename}: {lines} lines, {words} words, {bytes} bytes");
+    if columns == Columns::default() {
+        columns = Columns::DEFAULT;
+    }
+    Ok((columns, files))
+}
+
+/// Write a line of counts, right-aligned to `width`, followed by the name of the input
+fn write_row(
+    out: &mut impl Write,
+    columns: Columns,
+    counts: &Counts,
+    width: usize,
+    name: Option<&str>,
+) -> io::Result<()> {
+    let values: Vec<String> = columns
+        .values(counts)
+        .map(|value| format!("{value:>width$}"))
+        .collect();
+    match name {
+        Some(name) => writeln!(out, "{} {name}", values.join(" ")),
+        None => writeln!(out, "{}", values.join(" ")),
+    }
+}
+
+fn main() -> ExitCode {
+    let (columns, files) = match parse_args(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("wc: {e}\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut status = ExitCode::SUCCESS;
+    let mut results = Vec::new();
+    if files.is_empty() {
+        match count(io::stdin().lock()) {
+            Ok(counts) => results.push((counts, None)),
+            Err(e) => {
+                eprintln!("wc: {e}");
+                status = ExitCode::FAILURE;
+            }
+        }
+    }
+    for file in &files {
+        let counts = if file == "-" {
+            count(io::stdin().lock())
+        } else {
+            count_bytes_and_lines(file)
+        };
+        match counts {
+            Ok(counts) => results.push((counts, Some(file.as_str()))),
+            Err(e) => {
+                // Report the error, and carry on with the other files
+                eprintln!("wc: {file}: {e}");
+                status = ExitCode::FAILURE;
+            }
+        }
+    }
+
+    let mut total = Counts::default();
+    for (counts, _) in &results {
+        total += *counts;
+    }
+    if files.len() > 1 {
+        results.push((total, Some("total")));
+    }
+
+    // Align all columns to the widest number, which is in the totals
+    let width = columns
+        .values(&total)
+        .map(|value| value.to_string().len())
+        .max()
+        .unwrap_or(1);
+    let mut out = io::stdout().lock();
+    for (counts, name) in &results {
+        if let Err(e) = write_row(&mut out, columns, counts, width, *name) {
+            eprintln!("wc: {e}");
+            return ExitCode::FAILURE;
+        }
+    }
+    status
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(args: &[&str]) -> Result<(Columns, Vec<String>), String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(args(&[]), Ok((Columns::DEFAULT, vec![])));
+        let (columns, files) = args(&["-lm", "a", "-", "-L", "--", "-w"]).unwrap();
+        assert_eq!(
+            columns,
+            Columns {
+                lines: true,
+                chars: true,
+                max_line_length: true,
+                ..Columns::default()
+            }
+        );
+        assert_eq!(files, ["a", "-", "-w"]);
+        assert_eq!(args(&["-x"]), Err("invalid option -- 'x'".to_owned()));
+    }
+
+    #[test]
+    fn test_write_row() {
+        let counts = Counts {
+            lines: 3,
+            words: 12,
+            chars: 70,
+            bytes: 75,
+            max_line_length: 30,
+        };
+        let mut out = Vec::new();
+        write_row(&mut out, Columns::DEFAULT, &counts, 4, Some("a.txt")).unwrap();
+        write_row(
+            &mut out,
+            Columns {
+                chars: true,
+                ..Columns::default()
+            },
+            &counts,
+            2,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "   3   12   75 a.txt\n70\n"
+        );
+    }
 }
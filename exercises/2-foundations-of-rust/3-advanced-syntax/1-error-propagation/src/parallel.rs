@@ -0,0 +1,137 @@
+//! Counting large files on multiple threads.
+
+use std::fs::File;
+use std::io;
+
+use memmap2::Mmap;
+use rayon::prelude::*;
+
+use crate::{count, Counter, Counts};
+
+/// Files at least this large are memory-mapped and counted in parallel
+pub const PARALLEL_THRESHOLD: u64 = 4 << 20;
+
+/// The approximate size of the parts counted by each task
+const CHUNK_SIZE: usize = 1 << 20;
+
+/// Count `bytes` by counting parts of it in parallel, and merging the results.
+///
+/// This gives exactly the same counts as [count].
+pub fn count_parallel(bytes: &[u8]) -> Counts {
+    count_chunks(bytes, CHUNK_SIZE)
+}
+
+fn count_chunks(bytes: &[u8], chunk_size: usize) -> Counts {
+    chunks(bytes, chunk_size)
+        .collect::<Vec<_>>()
+        .into_par_iter()
+        .map(|chunk| {
+            let mut counter = Counter::new();
+            counter.update(chunk);
+            counter
+        })
+        .reduce(Counter::new, Counter::merge)
+        .finish()
+}
+
+/// Split `bytes` into parts of about `chunk_size` bytes, at character boundaries
+fn chunks(mut bytes: &[u8], chunk_size: usize) -> impl Iterator<Item = &[u8]> {
+    std::iter::from_fn(move || {
+        if bytes.is_empty() {
+            return None;
+        }
+        let mut end = chunk_size.min(bytes.len());
+        // Never split between the bytes of a character
+        while end < bytes.len() && bytes[end] & 0b1100_0000 == 0b1000_0000 {
+            end += 1;
+        }
+        let (chunk, rest) = bytes.split_at(end);
+        bytes = rest;
+        Some(chunk)
+    })
+}
+
+/// Count the contents of `file`, memory-mapping it and counting in parallel
+/// if it is large
+pub fn count_file(file: File) -> Result<Counts, io::Error> {
+    let metadata = file.metadata()?;
+    if !metadata.is_file() || metadata.len() < PARALLEL_THRESHOLD {
+        return count(file);
+    }
+    // SAFETY: the mapping is only read. If another process changes the file
+    // while it is being counted, the counts may be off, just like they would
+    // be when reading it.
+    let map = unsafe { Mmap::map(&file)? };
+    Ok(count_parallel(&map))
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    /// Random bytes with lots of whitespace, multi-byte characters, and
+    /// invalid UTF-8
+    fn random_input(rng: &mut StdRng, len: usize) -> Vec<u8> {
+        const PIECES: [&[u8]; 12] = [
+            b"a",
+            b"word",
+            b" ",
+            b"\t",
+            b"\n",
+            b"\r",
+            b"\x0b",
+            "é".as_bytes(),
+            "\u{a0}".as_bytes(),
+            "\u{1F600}".as_bytes(),
+            b"\xf0\x9f",
+            b"\xff",
+        ];
+        let mut input = Vec::with_capacity(len + 4);
+        while input.len() < len {
+            input.extend_from_slice(PIECES[rng.gen_range(0..PIECES.len())]);
+        }
+        input
+    }
+
+    #[test]
+    fn test_matches_count() {
+        let mut rng = StdRng::seed_from_u64(0xc0de);
+        for _ in 0..300 {
+            let len = rng.gen_range(0..200);
+            let input = random_input(&mut rng, len);
+            let expected = count(input.as_slice()).unwrap();
+            for chunk_size in [1, 2, 3, 7, 64] {
+                assert_eq!(
+                    count_chunks(&input, chunk_size),
+                    expected,
+                    "{:?} in chunks of {chunk_size}",
+                    String::from_utf8_lossy(&input)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_chunks() {
+        let input = "aé€\u{1F600}".as_bytes();
+        let chunks: Vec<_> = chunks(input, 1).collect();
+        assert_eq!(chunks, ["a", "é", "€", "\u{1F600}"].map(str::as_bytes));
+        assert_eq!(super::chunks(b"", 1).count(), 0);
+    }
+
+    #[test]
+    fn test_large_file() {
+        let mut rng = StdRng::seed_from_u64(0xf11e);
+        let input = random_input(&mut rng, PARALLEL_THRESHOLD as usize + 12345);
+        // deleted when dropped, even if the test fails
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(&input).unwrap();
+
+        let counts = count_file(File::open(file.path()).unwrap()).unwrap();
+        assert_eq!(counts, count(input.as_slice()).unwrap());
+    }
+}
@@ -0,0 +1,3 @@
+pub mod prompt;
+
+pub use prompt::{prompt, InvalidAnswer, Prompt, PromptError};
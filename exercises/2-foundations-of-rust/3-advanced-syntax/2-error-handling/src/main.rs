@@ -1,54 +1,80 @@
-/// While taking a first stab at programs, using panic!() is a quick-and-dirty way to do error handling; but panic!() has the obvious drawback
-/// that it is all-or-nothing: you cannot recover from it (in general).
-
-// Consider this "interactive hello world" (that is a bit fussy about what is a valid name), where the intent is that the program repeats
-// the question if the user entered an invalid name.
-//
-// 1) Take a few moments to read and understand the various parts of this program.
-// Note: we are sorry about the poor formatting, please fix it yourself ('cargo fmt' is your friend, note the changes it makes!)
-//
-// 2) Besides the explicit panic!()'s, there is a second source of errors that this program currently ignores; what are these?
-//
-// 3) We have provided an error type for properly reporting all errors that get_username() might generate; change the function get_username
-// so that it returns a Result<String, MyError>.
-//
-// Tip: to keep your main() working while making this change, you can temporarily replace get_username() with get_username().unwrap()
-// (this will still result in a panic! in case get_username() returns an error)
-//
-// 4) Finally, handle the errors in main() properly: an IOError should quit the program, but after an InvalidName error it should repeat
-// the question to the user.
-//
-// NOTE: You will (hopefully) discover that "?" doesn't work in this context, and the resulting code
-// is a bit explicit about the errors --- we can solve that with traits, next week!
-
-use std::io::{BufRead, self, Write};
+//! An "interactive hello world" that is a bit fussy about what is a valid name: it repeats the
+//! question until it gets one, and gives up after a few tries or when the input fails.
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::process::ExitCode;
+
+use error_handling::{prompt, PromptError};
+
+/// Give up after this many invalid names
+const MAX_ATTEMPTS: usize = 5;
 
 #[derive(Debug)]
-enum MyError{ InvalidName,IOError( io::Error),
+enum MyError {
+    InvalidName,
+    IOError(io::Error),
 }
 
-fn get_username( )
-->  String
-{
-    print!("Username: ");
-    io::stdout().flush();
-
-    let mut input=String::new();
-    io::stdin().lock().read_line(&mut input); input=input.trim().to_string();
+impl fmt::Display for MyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MyError::InvalidName => write!(f, "that's not a valid name"),
+            MyError::IOError(e) => write!(f, "{e}"),
+        }
+    }
+}
 
-    for c in input.chars()
-    {
-	if !char::is_alphabetic(c) { panic!("that's not a valid name, try again"); }
+fn valid_name(name: &str) -> Result<(), MyError> {
+    if name.is_empty() || !name.chars().all(char::is_alphabetic) {
+        return Err(MyError::InvalidName);
     }
+    Ok(())
+}
 
-if input.is_empty() {
-panic!("that's not a valid name, try again");
+fn get_username(input: &mut impl BufRead, output: &mut impl Write) -> Result<String, MyError> {
+    prompt::<String>("Username")
+        .validate(|name: &String| valid_name(name))
+        .max_attempts(MAX_ATTEMPTS)
+        .ask_with(input, output)
+        .map_err(|e| match e {
+            PromptError::Io(e) => MyError::IOError(e),
+            PromptError::Eof => MyError::IOError(io::ErrorKind::UnexpectedEof.into()),
+            PromptError::TooManyAttempts(_) => MyError::InvalidName,
+        })
 }
 
-    input
+fn main() -> ExitCode {
+    match get_username(&mut io::stdin().lock(), &mut io::stdout()) {
+        Ok(name) => {
+            println!("Hello {name}!");
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
 }
 
-fn main() {
-    let name=get_username();
-    println!("Hello {name}!")
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_get_username() {
+        let mut output = Vec::new();
+        let name = get_username(&mut "\nR2D2\n  Ferris \n".as_bytes(), &mut output);
+        assert_eq!(name.unwrap(), "Ferris");
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Username: that's not a valid name, try again\n\
+             Username: that's not a valid name, try again\n\
+             Username: "
+        );
+
+        let name = get_username(&mut "".as_bytes(), &mut io::sink());
+        assert!(matches!(name, Err(MyError::IOError(_))));
+        let name = get_username(&mut "1\n2\n3\n4\n5\n6\n".as_bytes(), &mut io::sink());
+        assert!(matches!(name, Err(MyError::InvalidName)));
+    }
 }
@@ -0,0 +1,345 @@
+//! Asking the user a question until they give a valid answer.
+//!
+//! ```no_run
+//! use error_handling::prompt;
+//!
+//! let age = prompt::<u32>("Age")
+//!     .validate(|age| if *age < 150 { Ok(()) } else { Err("that's too old") })
+//!     .max_attempts(3)
+//!     .ask();
+//! ```
+
+use std::convert::Infallible;
+use std::error::Error;
+use std::fmt::{self, Debug, Display};
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+
+/// Why an answer was not accepted
+#[derive(Debug, PartialEq, Eq)]
+pub enum InvalidAnswer<P, E> {
+    /// The answer could not be parsed
+    Parse(P),
+    /// A validator rejected the answer
+    Rejected(E),
+}
+
+impl<P: Display, E: Display> Display for InvalidAnswer<P, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvalidAnswer::Parse(e) => write!(f, "invalid answer: {e}"),
+            InvalidAnswer::Rejected(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+/// Why no answer was given
+#[derive(Debug)]
+pub enum PromptError<P, E> {
+    /// Reading the answer or writing the question failed
+    Io(io::Error),
+    /// The input ended before a valid answer was given
+    Eof,
+    /// The maximum number of attempts was reached; this is why the last
+    /// answer was not accepted
+    TooManyAttempts(InvalidAnswer<P, E>),
+}
+
+impl<P: Display, E: Display> Display for PromptError<P, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PromptError::Io(e) => write!(f, "{e}"),
+            PromptError::Eof => write!(f, "no answer given"),
+            PromptError::TooManyAttempts(e) => write!(f, "too many attempts: {e}"),
+        }
+    }
+}
+
+impl<P: Debug + Display, E: Debug + Display> Error for PromptError<P, E> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PromptError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl<P, E> From<io::Error> for PromptError<P, E> {
+    fn from(e: io::Error) -> Self {
+        PromptError::Io(e)
+    }
+}
+
+type Validator<T, E> = Box<dyn Fn(&T) -> Result<(), E>>;
+
+/// A question, and what makes a valid answer to it.
+///
+/// Answers are parsed into a `T` with [FromStr], after trimming whitespace,
+/// and then checked by validators returning errors of type `E`.
+pub struct Prompt<T: FromStr, E = Infallible> {
+    message: String,
+    default: Option<(T, String)>,
+    max_attempts: Option<usize>,
+    validators: Vec<Validator<T, E>>,
+}
+
+/// Ask for a `T`, showing `message` as the question
+pub fn prompt<T: FromStr>(message: impl Into<String>) -> Prompt<T> {
+    Prompt {
+        message: message.into(),
+        default: None,
+        max_attempts: None,
+        validators: Vec::new(),
+    }
+}
+
+impl<T: FromStr + 'static> Prompt<T> {
+    /// Only accept answers for which `validator` returns `Ok`.
+    ///
+    /// The first validator determines the type of the errors of the prompt;
+    /// more validators can be added with [and_validate](Prompt::and_validate).
+    pub fn validate<E, F>(self, validator: F) -> Prompt<T, E>
+    where
+        F: Fn(&T) -> Result<(), E> + 'static,
+    {
+        let mut validators: Vec<Validator<T, E>> = self
+            .validators
+            .into_iter()
+            .map(|infallible| -> Validator<T, E> {
+                Box::new(move |value| infallible(value).map_err(|never| match never {}))
+            })
+            .collect();
+        validators.push(Box::new(validator));
+        Prompt {
+            message: self.message,
+            default: self.default,
+            max_attempts: self.max_attempts,
+            validators,
+        }
+    }
+}
+
+impl<T: FromStr, E> Prompt<T, E> {
+    /// Also require `validator` to return `Ok`, after the earlier validators
+    pub fn and_validate<F>(mut self, validator: F) -> Self
+    where
+        F: Fn(&T) -> Result<(), E> + 'static,
+    {
+        self.validators.push(Box::new(validator));
+        self
+    }
+
+    /// Give up after `attempts` invalid answers, instead of asking forever
+    pub fn max_attempts(mut self, attempts: usize) -> Self {
+        assert!(attempts > 0, "at least one attempt is needed");
+        self.max_attempts = Some(attempts);
+        self
+    }
+
+    /// Use `value` if the answer is empty. It is shown with the question, and
+    /// is not validated.
+    pub fn default(mut self, value: T) -> Self
+    where
+        T: Display,
+    {
+        let shown = value.to_string();
+        self.default = Some((value, shown));
+        self
+    }
+
+    /// Ask the question on standard output, and read the answers from standard input
+    pub fn ask(self) -> Result<T, PromptError<T::Err, E>>
+    where
+        T::Err: Display,
+        E: Display,
+    {
+        self.ask_with(&mut io::stdin().lock(), &mut io::stdout())
+    }
+
+    /// Ask the question on `output`, and read the answers from `input`.
+    ///
+    /// After an invalid answer, the reason is written to `output`, and the
+    /// question is repeated.
+    pub fn ask_with(
+        mut self,
+        input: &mut impl BufRead,
+        output: &mut impl Write,
+    ) -> Result<T, PromptError<T::Err, E>>
+    where
+        T::Err: Display,
+        E: Display,
+    {
+        let mut attempts = 0;
+        loop {
+            match &self.default {
+                Some((_, shown)) => write!(output, "{} [{shown}]: ", self.message)?,
+                None => write!(output, "{}: ", self.message)?,
+            }
+            output.flush()?;
+
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                writeln!(output)?;
+                return Err(PromptError::Eof);
+            }
+            let answer = line.trim();
+            if answer.is_empty() {
+                if let Some((value, _)) = self.default.take() {
+                    return Ok(value);
+                }
+            }
+
+            let invalid = match answer.parse() {
+                Ok(value) => match self.check(&value) {
+                    Ok(()) => return Ok(value),
+                    Err(e) => InvalidAnswer::Rejected(e),
+                },
+                Err(e) => InvalidAnswer::Parse(e),
+            };
+            attempts += 1;
+            if self.max_attempts == Some(attempts) {
+                writeln!(output, "{invalid}")?;
+                return Err(PromptError::TooManyAttempts(invalid));
+            }
+            writeln!(output, "{invalid}, try again")?;
+        }
+    }
+
+    fn check(&self, value: &T) -> Result<(), E> {
+        self.validators
+            .iter()
+            .try_for_each(|validator| validator(value))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::num::ParseIntError;
+
+    use super::*;
+
+    /// Ask `prompt`, answering with the lines of `session`, and return the
+    /// result and everything written
+    fn run<T: FromStr, E: Display>(
+        prompt: Prompt<T, E>,
+        session: &str,
+    ) -> (Result<T, PromptError<T::Err, E>>, String)
+    where
+        T::Err: Display,
+    {
+        let mut output = Vec::new();
+        let result = prompt.ask_with(&mut session.as_bytes(), &mut output);
+        (result, String::from_utf8(output).unwrap())
+    }
+
+    #[derive(Debug, PartialEq, Eq)]
+    enum AgeError {
+        TooOld,
+        Odd,
+    }
+
+    impl Display for AgeError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                AgeError::TooOld => write!(f, "nobody is that old"),
+                AgeError::Odd => write!(f, "only even ages please"),
+            }
+        }
+    }
+
+    fn age() -> Prompt<u32, AgeError> {
+        prompt("Age")
+            .validate(|&age| match age {
+                0..=149 => Ok(()),
+                _ => Err(AgeError::TooOld),
+            })
+            .and_validate(|age| match age % 2 {
+                0 => Ok(()),
+                _ => Err(AgeError::Odd),
+            })
+    }
+
+    #[test]
+    fn test_retry() {
+        let (result, output) = run(age(), "abc\n200\n 31\n  42  \n");
+        assert_eq!(result.unwrap(), 42);
+        assert_eq!(
+            output,
+            "Age: invalid answer: invalid digit found in string, try again\n\
+             Age: nobody is that old, try again\n\
+             Age: only even ages please, try again\n\
+             Age: "
+        );
+    }
+
+    #[test]
+    fn test_max_attempts() {
+        let (result, output) = run(age().max_attempts(2), "7\n151\n42\n");
+        assert!(matches!(
+            result,
+            Err(PromptError::TooManyAttempts(InvalidAnswer::Rejected(
+                AgeError::TooOld
+            )))
+        ));
+        assert_eq!(
+            output,
+            "Age: only even ages please, try again\nAge: nobody is that old\n"
+        );
+
+        let (result, _) = run(prompt::<i8>("Small").max_attempts(1), "1000\n");
+        let Err(PromptError::TooManyAttempts(InvalidAnswer::Parse(e))) = result else {
+            panic!("expected a parse error");
+        };
+        assert_eq!(e, "1000".parse::<i8>().unwrap_err());
+    }
+
+    #[test]
+    fn test_eof() {
+        let (result, output) = run(age(), "");
+        assert!(matches!(result, Err(PromptError::Eof)));
+        assert_eq!(output, "Age: \n");
+
+        // The last line does not need a newline
+        let (result, _) = run(age(), "3\n4");
+        assert_eq!(result.unwrap(), 4);
+    }
+
+    #[test]
+    fn test_default() {
+        let (result, output) = run(prompt::<String>("Name").default("World".into()), "\n");
+        assert_eq!(result.unwrap(), "World");
+        assert_eq!(output, "Name [World]: ");
+
+        // The default is not validated, but answers are
+        let (result, _) = run(age().default(1), "  \n");
+        assert_eq!(result.unwrap(), 1);
+        let (result, _) = run(age().default(1), "3\n\n");
+        assert_eq!(result.unwrap(), 1);
+
+        let (result, output) = run(prompt::<u32>("Count"), "\n5\n");
+        assert_eq!(result.unwrap(), 5);
+        assert_eq!(
+            output,
+            "Count: invalid answer: cannot parse integer from empty string, try again\nCount: "
+        );
+    }
+
+    #[test]
+    fn test_io_error() {
+        struct Failing;
+        impl Write for Failing {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::BrokenPipe.into())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let result = prompt::<u32>("Age").ask_with(&mut "1\n".as_bytes(), &mut Failing);
+        let Err(PromptError::<ParseIntError, Infallible>::Io(e)) = result else {
+            panic!("expected an I/O error");
+        };
+        assert_eq!(e.kind(), io::ErrorKind::BrokenPipe);
+    }
+}
@@ -0,0 +1,48 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use slices::{merge_sort_in_place_by_key, par_merge_sort_by_key};
+
+/// Records with few distinct keys, tagged with their original position
+fn records(len: usize) -> Vec<(u32, usize)> {
+    let mut rng = StdRng::seed_from_u64(len as u64);
+    (0..len).map(|i| (rng.gen_range(0..100), i)).collect()
+}
+
+/// Compare sorting records by key with the standard library's stable sort.
+///
+/// Before measuring, every sort is checked to produce exactly the same order
+/// as `slice::sort_by_key`, which is only possible if it is stable too.
+fn bench_sort(c: &mut Criterion) {
+    type Sort = fn(&mut [(u32, usize)]);
+    let sorts: [(&str, Sort); 3] = [
+        ("slice::sort", |data| data.sort_by_key(|&(key, _)| key)),
+        ("merge_sort_in_place", |data| {
+            merge_sort_in_place_by_key(data, |&(key, _)| key)
+        }),
+        ("par_merge_sort", |data| {
+            par_merge_sort_by_key(data, |&(key, _)| key)
+        }),
+    ];
+
+    let mut group = c.benchmark_group("sort");
+    for len in [1_000, 100_000, 1_000_000] {
+        let data = records(len);
+        let mut expected = data.clone();
+        expected.sort_by_key(|&(key, _)| key);
+        group.throughput(Throughput::Elements(len as u64));
+
+        for (name, sort) in sorts {
+            let mut sorted = data.clone();
+            sort(&mut sorted);
+            assert_eq!(sorted, expected, "{name} is not stable");
+
+            group.bench_with_input(BenchmarkId::new(name, len), &data, |b, data| {
+                b.iter_batched_ref(|| data.clone(), |data| sort(data), BatchSize::LargeInput)
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_sort);
+criterion_main!(benches);
@@ -0,0 +1,51 @@
+//! Sort the lines of a file, or of standard input, that may not fit in memory.
+//!
+//! Usage: `external-sort [-n] [-m <bytes>] [-T <dir>] [file]`
+
+use std::{
+    fs::File,
+    io::{self, BufReader},
+    process::ExitCode,
+};
+
+use slices::{ExternalSort, Key};
+
+fn usage() -> ExitCode {
+    eprintln!("usage: external-sort [-n] [-m <bytes>] [-T <dir>] [file]");
+    ExitCode::FAILURE
+}
+
+fn main() -> ExitCode {
+    let mut sort = ExternalSort::new();
+    let mut path = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-n" => sort = sort.key(Key::Numeric),
+            "-m" => match args.next().and_then(|bytes| bytes.parse().ok()) {
+                Some(bytes) => sort = sort.memory_budget(bytes),
+                None => return usage(),
+            },
+            "-T" => match args.next() {
+                Some(dir) => sort = sort.temp_dir(dir),
+                None => return usage(),
+            },
+            _ if path.is_none() && !arg.starts_with('-') => path = Some(arg),
+            _ => return usage(),
+        }
+    }
+
+    let stdout = io::stdout().lock();
+    let result = match &path {
+        Some(path) => File::open(path).and_then(|file| sort.sort(BufReader::new(file), stdout)),
+        None => sort.sort(io::stdin().lock(), stdout),
+    };
+    match result {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("external-sort: {}: {e}", path.as_deref().unwrap_or("-"));
+            ExitCode::FAILURE
+        }
+    }
+}
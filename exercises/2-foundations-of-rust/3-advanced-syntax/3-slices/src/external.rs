@@ -0,0 +1,426 @@
+//! Sorting inputs that don't fit in memory.
+//!
+//! The input is read in runs of lines that fit in the memory budget. Each run
+//! is sorted with [merge_sort_in_place_by] and spilled to a temporary file.
+//! The sorted runs are then merged using a heap holding the next line of
+//! every run being merged. That is the same merge as [merge](crate::merge),
+//! which can't be used itself: it merges two slices in memory, while runs
+//! are read from files a line at a time, up to `MAX_FAN_IN` of them at once.
+
+use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    mem,
+    path::PathBuf,
+};
+
+use tempfile::{NamedTempFile, TempPath};
+
+use crate::merge_sort_in_place_by;
+
+/// The bytes of memory a run may use, besides the bytes of its lines
+const RECORD_OVERHEAD: usize = mem::size_of::<Record>();
+
+/// The size of the read buffer of every run that is being merged
+const MERGE_BUFFER: usize = 64 * 1024;
+
+/// The most runs merged at once, whatever the memory budget. Every run being
+/// merged is an open file, and processes may often only have 1024 open.
+const MAX_FAN_IN: usize = 128;
+
+/// How lines are compared
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Key {
+    /// Compare the bytes of lines lexicographically
+    #[default]
+    Lines,
+    /// Compare lines by the integer they hold, ignoring surrounding whitespace
+    Numeric,
+}
+
+impl Key {
+    fn record(self, line: Vec<u8>, line_number: u64) -> io::Result<Record> {
+        let number = match self {
+            Key::Lines => 0,
+            Key::Numeric => std::str::from_utf8(&line)
+                .ok()
+                .and_then(|line| line.trim().parse().ok())
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "line {line_number}: {:?} is not a number",
+                            String::from_utf8_lossy(&line)
+                        ),
+                    )
+                })?,
+        };
+        Ok(Record { number, line })
+    }
+
+    fn compare(self, a: &Record, b: &Record) -> Ordering {
+        match self {
+            Key::Lines => a.line.cmp(&b.line),
+            Key::Numeric => a.number.cmp(&b.number),
+        }
+    }
+}
+
+/// A line without its terminator, and the number it holds if sorting by [Key::Numeric]
+struct Record {
+    number: i64,
+    line: Vec<u8>,
+}
+
+/// What happened during an [ExternalSort::sort]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SortStats {
+    /// The number of lines sorted
+    pub lines: u64,
+    /// The number of sorted runs spilled to temporary files, or 0 if the
+    /// input fit in memory
+    pub runs: usize,
+    /// The number of times the runs were merged
+    pub merge_passes: usize,
+}
+
+/// Sorts lines of text that may not fit in memory.
+///
+/// The sort is stable: lines with equal keys are written in the order they
+/// were read.
+#[derive(Debug, Clone)]
+pub struct ExternalSort {
+    key: Key,
+    memory_budget: usize,
+    temp_dir: Option<PathBuf>,
+}
+
+impl Default for ExternalSort {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExternalSort {
+    /// The default memory budget, in bytes
+    pub const DEFAULT_MEMORY_BUDGET: usize = 64 << 20;
+
+    /// Sort lines lexicographically, within the default memory budget
+    pub fn new() -> Self {
+        ExternalSort {
+            key: Key::Lines,
+            memory_budget: Self::DEFAULT_MEMORY_BUDGET,
+            temp_dir: None,
+        }
+    }
+
+    /// Set how lines are compared
+    pub fn key(mut self, key: Key) -> Self {
+        self.key = key;
+        self
+    }
+
+    /// Set the approximate number of bytes used to hold lines in memory.
+    ///
+    /// This determines both the size of the runs, and how many runs are
+    /// merged at once. When there are more runs than fit in the budget,
+    /// groups of them are merged into longer runs first.
+    pub fn memory_budget(mut self, bytes: usize) -> Self {
+        self.memory_budget = bytes;
+        self
+    }
+
+    /// Set the directory to create temporary files in, instead of the
+    /// system's temporary directory
+    pub fn temp_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.temp_dir = Some(dir.into());
+        self
+    }
+
+    /// Sort the lines of `input`, and write them to `output`.
+    ///
+    /// Every line written is terminated by a newline, including the last.
+    pub fn sort(&self, mut input: impl BufRead, mut output: impl Write) -> io::Result<SortStats> {
+        let mut stats = SortStats::default();
+        let mut runs = Vec::new();
+        let mut records = Vec::new();
+        let mut size = 0;
+
+        while let Some(line) = read_line(&mut input)? {
+            stats.lines += 1;
+            size += line.len() + RECORD_OVERHEAD;
+            records.push(self.key.record(line, stats.lines)?);
+            if size >= self.memory_budget {
+                runs.push(self.spill(&mut records)?);
+                size = 0;
+            }
+        }
+
+        if runs.is_empty() {
+            // Everything fit in memory
+            self.write_sorted(&mut records, &mut output)?;
+            output.flush()?;
+            return Ok(stats);
+        }
+        if !records.is_empty() {
+            runs.push(self.spill(&mut records)?);
+        }
+        stats.runs = runs.len();
+
+        let fan_in = self.fan_in();
+        while runs.len() > fan_in {
+            let mut merged = Vec::with_capacity(runs.len().div_ceil(fan_in));
+            let mut remaining = runs.into_iter();
+            loop {
+                // Merge neighbouring runs only, so lines with equal keys stay
+                // in order
+                let mut group: Vec<TempPath> = remaining.by_ref().take(fan_in).collect();
+                match group.len() {
+                    0 => break,
+                    1 => merged.extend(group.pop()),
+                    _ => {
+                        let file = self.temp_file()?;
+                        let mut writer = BufWriter::new(file);
+                        self.merge(&group, &mut writer)?;
+                        let file = writer.into_inner().map_err(|e| e.into_error())?;
+                        // the merged runs are deleted here
+                        drop(group);
+                        merged.push(file.into_temp_path());
+                    }
+                }
+            }
+            runs = merged;
+            stats.merge_passes += 1;
+        }
+
+        self.merge(&runs, &mut output)?;
+        stats.merge_passes += 1;
+        output.flush()?;
+        Ok(stats)
+    }
+
+    /// How many runs to merge at once: as many as fit in the memory budget,
+    /// up to [MAX_FAN_IN]
+    fn fan_in(&self) -> usize {
+        (self.memory_budget / MERGE_BUFFER).clamp(2, MAX_FAN_IN)
+    }
+
+    fn temp_file(&self) -> io::Result<NamedTempFile> {
+        match &self.temp_dir {
+            Some(dir) => NamedTempFile::new_in(dir),
+            None => NamedTempFile::new(),
+        }
+    }
+
+    fn write_sorted(&self, records: &mut Vec<Record>, output: &mut impl Write) -> io::Result<()> {
+        merge_sort_in_place_by(records, |a, b| self.key.compare(a, b));
+        for record in records.drain(..) {
+            output.write_all(&record.line)?;
+            output.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    /// Sort `records` into a new run, leaving `records` empty. The run is
+    /// closed until it is merged, so that there can be more runs than open
+    /// files, and deleted when the path is dropped.
+    fn spill(&self, records: &mut Vec<Record>) -> io::Result<TempPath> {
+        let mut writer = BufWriter::new(self.temp_file()?);
+        self.write_sorted(records, &mut writer)?;
+        let file = writer.into_inner().map_err(|e| e.into_error())?;
+        Ok(file.into_temp_path())
+    }
+
+    /// Merge the sorted `runs` into `output`
+    fn merge(&self, runs: &[TempPath], output: &mut impl Write) -> io::Result<()> {
+        let mut readers = runs
+            .iter()
+            .map(|run| Ok(BufReader::with_capacity(MERGE_BUFFER, File::open(run)?)))
+            .collect::<io::Result<Vec<_>>>()?;
+        let mut heap = BinaryHeap::with_capacity(readers.len());
+        for (run, reader) in readers.iter_mut().enumerate() {
+            if let Some(line) = read_line(reader)? {
+                heap.push(self.head(line, run)?);
+            }
+        }
+
+        while let Some(Head { record, run, .. }) = heap.pop() {
+            output.write_all(&record.line)?;
+            output.write_all(b"\n")?;
+            if let Some(line) = read_line(&mut readers[run])? {
+                heap.push(self.head(line, run)?);
+            }
+        }
+        Ok(())
+    }
+
+    fn head(&self, line: Vec<u8>, run: usize) -> io::Result<Head> {
+        Ok(Head {
+            // Runs only hold lines that were parsed successfully before
+            record: self.key.record(line, 0)?,
+            run,
+            key: self.key,
+        })
+    }
+}
+
+/// Read a line without its newline, or `None` at the end of the input
+fn read_line(reader: &mut impl BufRead) -> io::Result<Option<Vec<u8>>> {
+    let mut line = Vec::new();
+    if reader.read_until(b'\n', &mut line)? == 0 {
+        return Ok(None);
+    }
+    if line.last() == Some(&b'\n') {
+        line.pop();
+    }
+    Ok(Some(line))
+}
+
+/// The next line of a run during a merge
+struct Head {
+    record: Record,
+    run: usize,
+    key: Key,
+}
+
+impl Ord for Head {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // BinaryHeap pops the greatest element first, so the comparison is
+        // reversed. Of equal lines, the one from the earliest run goes first.
+        self.key
+            .compare(&other.record, &self.record)
+            .then(other.run.cmp(&self.run))
+    }
+}
+
+impl PartialOrd for Head {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Head {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Head {}
+
+#[cfg(test)]
+mod test {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    fn sorted(sort: &ExternalSort, input: &str) -> (String, SortStats) {
+        let mut output = Vec::new();
+        let stats = sort.sort(input.as_bytes(), &mut output).unwrap();
+        (String::from_utf8(output).unwrap(), stats)
+    }
+
+    #[test]
+    fn test_in_memory() {
+        let sort = ExternalSort::new();
+        assert_eq!(sorted(&sort, ""), (String::new(), SortStats::default()));
+        let (output, stats) = sorted(&sort, "pear\napple\n\nfig");
+        assert_eq!(output, "\napple\nfig\npear\n");
+        assert_eq!(
+            stats,
+            SortStats {
+                lines: 4,
+                runs: 0,
+                merge_passes: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_numeric() {
+        let sort = ExternalSort::new().key(Key::Numeric);
+        let (output, _) = sorted(&sort, "10\n-3\n 2 \n02\n2\n");
+        assert_eq!(output, "-3\n 2 \n02\n2\n10\n");
+
+        let error = sort
+            .sort("1\n2\nthree\n".as_bytes(), io::sink())
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "line 3: \"three\" is not a number");
+    }
+
+    #[test]
+    fn test_spilled_numeric() {
+        let mut rng = StdRng::seed_from_u64(0xe550);
+        // Write equal numbers in different ways, to check they keep their order
+        let lines: Vec<String> = (0..5000)
+            .map(|i| {
+                let n: i64 = rng.gen_range(-100..100);
+                format!("{}{n:0width$}", " ".repeat(i % 3), width = i % 5)
+            })
+            .collect();
+        let mut expected = lines.clone();
+        expected.sort_by_key(|line| line.trim().parse::<i64>().unwrap());
+
+        let sort = ExternalSort::new().key(Key::Numeric).memory_budget(4096);
+        let (output, stats) = sorted(&sort, &lines.join("\n"));
+        assert_eq!(output.lines().collect::<Vec<_>>(), expected);
+        assert_eq!(stats.lines, 5000);
+        assert!(stats.runs > 2, "{stats:?}");
+    }
+
+    #[test]
+    fn test_spilled_lines() {
+        let mut rng = StdRng::seed_from_u64(0x11e5);
+        let lines: Vec<String> = (0..5000)
+            .map(|_| {
+                let len = rng.gen_range(0..12);
+                (0..len).map(|_| rng.gen_range('a'..='e')).collect()
+            })
+            .collect();
+        let mut expected = lines.clone();
+        expected.sort();
+
+        let dir = tempfile::tempdir().unwrap();
+        let sort = ExternalSort::new().memory_budget(1024).temp_dir(dir.path());
+        let (output, stats) = sorted(&sort, &lines.join("\n"));
+        assert_eq!(output.lines().collect::<Vec<_>>(), expected);
+        // With this budget, only two runs are merged at once
+        assert!(stats.merge_passes > 1, "{stats:?}");
+    }
+
+    #[test]
+    fn test_many_runs() {
+        let mut rng = StdRng::seed_from_u64(0x7a11);
+        let lines: Vec<String> = (0..3000)
+            .map(|_| format!("{:04}", rng.gen_range(0..1000)))
+            .collect();
+        let mut expected = lines.clone();
+        expected.sort();
+
+        // every line is a run of its own, more than there may be open files
+        let dir = tempfile::tempdir().unwrap();
+        let sort = ExternalSort::new().memory_budget(1).temp_dir(dir.path());
+        let (output, stats) = sorted(&sort, &lines.join("\n"));
+        assert_eq!(output.lines().collect::<Vec<_>>(), expected);
+        assert_eq!(stats.runs, 3000);
+        // ceil(log2(3000)) passes of pairs
+        assert_eq!(stats.merge_passes, 12);
+        // the runs were deleted after merging
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_fan_in() {
+        assert_eq!(ExternalSort::new().memory_budget(0).fan_in(), 2);
+        assert_eq!(
+            ExternalSort::new()
+                .memory_budget(10 * MERGE_BUFFER)
+                .fan_in(),
+            10
+        );
+        // the default budget has room for 1024 runs, but that's too many files
+        assert_eq!(ExternalSort::new().fan_in(), MAX_FAN_IN);
+    }
+}
@@ -0,0 +1,182 @@
+//! Stable merge sort, in several flavours:
+//!
+//! - [merge_sort] sorts into a new vector, merging with [merge];
+//! - [merge_sort_in_place] sorts a slice without needing `Clone`, using one scratch buffer;
+//! - [par_merge_sort] does the same on multiple threads;
+//! - [ExternalSort] sorts lines that don't fit in memory, spilling sorted runs to disk.
+//!
+//! Each comes with `_by` and `_by_key` variants taking a comparison or a sort key.
+
+use std::cmp::Ordering;
+
+mod external;
+mod merge;
+mod parallel;
+
+pub use external::{ExternalSort, Key, SortStats};
+pub use parallel::{par_merge_sort, par_merge_sort_by, par_merge_sort_by_key};
+
+/// Merge two array slices (that have to be sorted) into a vector
+pub fn merge<T: Ord + Clone>(a: &[T], b: &[T]) -> Vec<T> {
+    merge_by(a, b, T::cmp)
+}
+
+/// Merge two array slices (that have to be sorted by `compare`) into a vector.
+///
+/// Elements of `a` come before equal elements of `b`.
+pub fn merge_by<T, F>(a: &[T], b: &[T], mut compare: F) -> Vec<T>
+where
+    T: Clone,
+    F: FnMut(&T, &T) -> Ordering,
+{
+    let mut dest = Vec::with_capacity(a.len() + b.len());
+
+    let mut a_idx = 0;
+    let mut b_idx = 0;
+
+    while a_idx < a.len() && b_idx < b.len() {
+        if compare(&a[a_idx], &b[b_idx]) != Ordering::Greater {
+            dest.push(a[a_idx].clone());
+            a_idx += 1
+        } else {
+            dest.push(b[b_idx].clone());
+            b_idx += 1
+        }
+    }
+
+    dest.extend_from_slice(&a[a_idx..]);
+    dest.extend_from_slice(&b[b_idx..]);
+
+    dest
+}
+
+/// Take an array slice, and sort into a freshly constructed vector.
+///
+/// The sort is stable: equal elements keep their relative order.
+pub fn merge_sort<T: Ord + Clone>(data: &[T]) -> Vec<T> {
+    merge_sort_by(data, T::cmp)
+}
+
+/// Like [merge_sort], but comparing elements with `compare`
+pub fn merge_sort_by<T, F>(data: &[T], compare: F) -> Vec<T>
+where
+    T: Clone,
+    F: FnMut(&T, &T) -> Ordering,
+{
+    let mut sorted = data.to_vec();
+    merge_sort_in_place_by(&mut sorted, compare);
+    sorted
+}
+
+/// Like [merge_sort], but comparing the keys that `key` extracts from the elements
+pub fn merge_sort_by_key<T, K, F>(data: &[T], mut key: F) -> Vec<T>
+where
+    T: Clone,
+    K: Ord,
+    F: FnMut(&T) -> K,
+{
+    merge_sort_by(data, |a, b| key(a).cmp(&key(b)))
+}
+
+/// Sort a slice in place, without requiring its elements to be [Clone].
+///
+/// The sort is stable, and allocates a single scratch buffer holding half of
+/// the elements, which is reused by all merges.
+pub fn merge_sort_in_place<T: Ord>(data: &mut [T]) {
+    merge_sort_in_place_by(data, T::cmp)
+}
+
+/// Like [merge_sort_in_place], but comparing elements with `compare`.
+///
+/// If `compare` panics, `data` is left in an unspecified order, but still
+/// holds every element exactly once.
+pub fn merge_sort_in_place_by<T, F>(data: &mut [T], mut compare: F)
+where
+    F: FnMut(&T, &T) -> Ordering,
+{
+    let mut scratch = Vec::with_capacity(data.len() / 2);
+    merge::sort(
+        data,
+        &mut scratch.spare_capacity_mut()[..data.len() / 2],
+        &mut |a, b| compare(a, b) == Ordering::Less,
+    );
+}
+
+/// Like [merge_sort_in_place], but comparing the keys that `key` extracts from the elements
+pub fn merge_sort_in_place_by_key<T, K, F>(data: &mut [T], mut key: F)
+where
+    K: Ord,
+    F: FnMut(&T) -> K,
+{
+    merge_sort_in_place_by(data, |a, b| key(a).cmp(&key(b)))
+}
+
+// you can run these automatic tests by typing 'cargo test'
+#[cfg(test)]
+mod test {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn test_merge() {
+        assert_eq!(merge::<i32>(&[], &[]), vec![]);
+        assert_eq!(
+            merge(&[1, 4, 9], &[2, 3, 10, 11]),
+            vec![1, 2, 3, 4, 9, 10, 11]
+        );
+        let by_number = |a: &(i32, char), b: &(i32, char)| a.0.cmp(&b.0);
+        assert_eq!(
+            merge_by(&[(1, 'a'), (2, 'a')], &[(1, 'b'), (2, 'b')], by_number),
+            vec![(1, 'a'), (1, 'b'), (2, 'a'), (2, 'b')]
+        );
+    }
+
+    #[test]
+    fn test_sort() {
+        assert_eq!(merge_sort::<i32>(&[]), vec![]);
+        assert_eq!(merge_sort(&[5]), vec![5]);
+        assert_eq!(merge_sort(&[1, 2, 3]), vec![1, 2, 3]);
+        assert_eq!(merge_sort(&[47, 42, 5, 1]), vec![1, 5, 42, 47]);
+        assert_eq!(
+            merge_sort(&[6, 47, 42, 5, 1, 123]),
+            vec![1, 5, 6, 42, 47, 123]
+        );
+    }
+
+    #[test]
+    fn test_strings() {
+        let words = ["pear", "apple", "fig", "banana", "kiwi"].map(String::from);
+        assert_eq!(
+            merge_sort(&words),
+            ["apple", "banana", "fig", "kiwi", "pear"]
+        );
+        assert_eq!(
+            merge_sort_by_key(&words, String::len),
+            ["fig", "pear", "kiwi", "apple", "banana"]
+        );
+        assert_eq!(
+            merge_sort_by(&words, |a, b| b.cmp(a)),
+            ["pear", "kiwi", "fig", "banana", "apple"]
+        );
+    }
+
+    #[test]
+    fn test_stable() {
+        let mut rng = StdRng::seed_from_u64(0x5047);
+        for len in [0, 1, 2, 3, 19, 20, 21, 64, 100, 1000, 4321] {
+            // Few distinct keys, so there are many runs of equal elements
+            let data: Vec<(u8, usize)> = (0..len).map(|i| (rng.gen_range(0..8), i)).collect();
+            let mut expected = data.clone();
+            expected.sort_by_key(|&(key, _)| key);
+
+            assert_eq!(merge_sort_by_key(&data, |&(key, _)| key), expected);
+            let mut sorted = data.clone();
+            merge_sort_in_place_by_key(&mut sorted, |&(key, _)| key);
+            assert_eq!(sorted, expected);
+
+            expected.sort();
+            assert_eq!(merge_sort(&data), expected);
+        }
+    }
+}
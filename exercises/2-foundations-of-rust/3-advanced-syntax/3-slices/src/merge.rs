@@ -0,0 +1,167 @@
+//! The in-place merge sort shared by the sequential and parallel versions.
+
+use std::{mem::MaybeUninit, ptr};
+
+/// Below this length, runs are sorted with an insertion sort instead
+pub(crate) const INSERTION_THRESHOLD: usize = 20;
+
+/// Sort `data` stably, using `scratch` to hold the left run of each merge.
+///
+/// `scratch` must hold at least `data.len() / 2` elements. Its first
+/// `data.len() / 4` elements are enough to sort the left half, and the rest
+/// to sort the right half.
+pub(crate) fn sort<T, F>(data: &mut [T], scratch: &mut [MaybeUninit<T>], is_less: &mut F)
+where
+    F: FnMut(&T, &T) -> bool,
+{
+    if data.len() <= INSERTION_THRESHOLD {
+        insertion_sort(data, is_less);
+        return;
+    }
+    let mid = data.len() / 2;
+    let (left, right) = data.split_at_mut(mid);
+    let (left_scratch, right_scratch) = scratch.split_at_mut(mid / 2);
+    sort(left, left_scratch, is_less);
+    sort(right, right_scratch, is_less);
+    merge_halves(data, scratch, is_less);
+}
+
+/// Sort a short slice by moving every element left past the larger elements before it
+pub(crate) fn insertion_sort<T, F>(data: &mut [T], is_less: &mut F)
+where
+    F: FnMut(&T, &T) -> bool,
+{
+    for i in 1..data.len() {
+        let mut j = i;
+        while j > 0 && is_less(&data[j], &data[j - 1]) {
+            data.swap(j, j - 1);
+            j -= 1;
+        }
+    }
+}
+
+/// Merge the sorted halves `data[..mid]` and `data[mid..]`, where `mid` is
+/// `data.len() / 2`.
+///
+/// The left half is moved into `scratch`, after which the merged elements fill
+/// the gap that opens up in front of the right half.
+pub(crate) fn merge_halves<T, F>(data: &mut [T], scratch: &mut [MaybeUninit<T>], is_less: &mut F)
+where
+    F: FnMut(&T, &T) -> bool,
+{
+    let len = data.len();
+    let mid = len / 2;
+    assert!(scratch.len() >= mid, "scratch buffer too small");
+    if mid == 0 || !is_less(&data[mid], &data[mid - 1]) {
+        // The halves are already in order
+        return;
+    }
+
+    // SAFETY: the left half is moved into `scratch`, which has room for it.
+    // From then on, the gap between `hole.dest` and `right` is exactly as long
+    // as the part of the left half that is still in `scratch`. Every element
+    // is moved into the gap once, and if `is_less` panics, `hole` moves the
+    // remaining elements of the left half back into it. So `data` always ends
+    // up holding every element exactly once.
+    unsafe {
+        let v = data.as_mut_ptr();
+        let buf = scratch.as_mut_ptr().cast::<T>();
+        ptr::copy_nonoverlapping(v, buf, mid);
+
+        let mut hole = Hole {
+            start: buf,
+            end: buf.add(mid),
+            dest: v,
+        };
+        let mut right = v.add(mid);
+        let end = v.add(len);
+
+        while hole.start < hole.end && right < end {
+            // Only take from the right half if it is strictly less, so equal
+            // elements keep their order
+            let src = if is_less(&*right, &*hole.start) {
+                right = right.add(1);
+                right.sub(1)
+            } else {
+                hole.start = hole.start.add(1);
+                hole.start.sub(1)
+            };
+            ptr::copy_nonoverlapping(src, hole.dest, 1);
+            hole.dest = hole.dest.add(1);
+        }
+        // Dropping `hole` moves what is left of the left half into place. What
+        // is left of the right half already is.
+    }
+}
+
+/// The part of the left half that is still in the scratch buffer, and where
+/// it has to go
+struct Hole<T> {
+    start: *mut T,
+    end: *mut T,
+    dest: *mut T,
+}
+
+impl<T> Drop for Hole<T> {
+    fn drop(&mut self) {
+        // SAFETY: `start..end` holds the elements that were not merged yet,
+        // and the gap at `dest` has room for exactly that many.
+        unsafe {
+            let remaining = self.end.offset_from(self.start) as usize;
+            ptr::copy_nonoverlapping(self.start, self.dest, remaining);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        cell::Cell,
+        panic::{catch_unwind, AssertUnwindSafe},
+    };
+
+    /// Counts how often it is dropped
+    struct Counted<'c>(u32, &'c Cell<usize>);
+
+    impl Drop for Counted<'_> {
+        fn drop(&mut self) {
+            self.1.set(self.1.get() + 1);
+        }
+    }
+
+    #[test]
+    fn test_panic_safety() {
+        const LEN: usize = 500;
+        for panic_after in [0, 10, 100, 1000, 3000] {
+            let drops = Cell::new(0);
+            let mut data: Vec<_> = (0..LEN as u32)
+                .map(|i| Counted(i.wrapping_mul(7919) % 257, &drops))
+                .collect();
+
+            let mut comparisons = 0;
+            let result = catch_unwind(AssertUnwindSafe(|| {
+                crate::merge_sort_in_place_by(&mut data, |a, b| {
+                    comparisons += 1;
+                    if comparisons > panic_after {
+                        panic!("comparison failed");
+                    }
+                    a.0.cmp(&b.0)
+                })
+            }));
+            assert!(result.is_err());
+
+            // Every element is still there, and none has been dropped
+            assert_eq!(drops.get(), 0);
+            let mut values: Vec<u32> = data.iter().map(|c| c.0).collect();
+            values.sort();
+            let mut expected: Vec<u32> = (0..LEN as u32)
+                .map(|i| i.wrapping_mul(7919) % 257)
+                .collect();
+            expected.sort();
+            assert_eq!(values, expected);
+
+            drop(data);
+            assert_eq!(drops.get(), LEN);
+        }
+    }
+}
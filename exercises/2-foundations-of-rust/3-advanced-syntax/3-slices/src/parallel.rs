@@ -0,0 +1,84 @@
+//! Sorting large slices on multiple threads.
+
+use std::{cmp::Ordering, mem::MaybeUninit};
+
+use crate::merge;
+
+/// Below this length, a slice is sorted on a single thread
+const PARALLEL_THRESHOLD: usize = 1 << 13;
+
+/// Sort a slice in place like [merge_sort_in_place](crate::merge_sort_in_place),
+/// sorting both halves of large slices in parallel using rayon.
+pub fn par_merge_sort<T: Ord + Send>(data: &mut [T]) {
+    par_merge_sort_by(data, T::cmp)
+}
+
+/// Like [par_merge_sort], but comparing elements with `compare`
+pub fn par_merge_sort_by<T, F>(data: &mut [T], compare: F)
+where
+    T: Send,
+    F: Fn(&T, &T) -> Ordering + Sync,
+{
+    let mut scratch = Vec::with_capacity(data.len() / 2);
+    sort(
+        data,
+        &mut scratch.spare_capacity_mut()[..data.len() / 2],
+        &|a, b| compare(a, b) == Ordering::Less,
+    );
+}
+
+/// Like [par_merge_sort], but comparing the keys that `key` extracts from the elements
+pub fn par_merge_sort_by_key<T, K, F>(data: &mut [T], key: F)
+where
+    T: Send,
+    K: Ord,
+    F: Fn(&T) -> K + Sync,
+{
+    par_merge_sort_by(data, |a, b| key(a).cmp(&key(b)))
+}
+
+fn sort<T, F>(data: &mut [T], scratch: &mut [MaybeUninit<T>], is_less: &F)
+where
+    T: Send,
+    F: Fn(&T, &T) -> bool + Sync,
+{
+    if data.len() <= PARALLEL_THRESHOLD {
+        merge::sort(data, scratch, &mut |a, b| is_less(a, b));
+        return;
+    }
+    let mid = data.len() / 2;
+    let (left, right) = data.split_at_mut(mid);
+    let (left_scratch, right_scratch) = scratch.split_at_mut(mid / 2);
+    rayon::join(
+        || sort(left, left_scratch, is_less),
+        || sort(right, right_scratch, is_less),
+    );
+    merge::merge_halves(data, scratch, &mut |a, b| is_less(a, b));
+}
+
+#[cfg(test)]
+mod test {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn test_matches_std() {
+        let mut rng = StdRng::seed_from_u64(0x9a7a);
+        for len in [0, 1, 1000, PARALLEL_THRESHOLD + 1, 100_000, 123_457] {
+            let data: Vec<(u16, usize)> = (0..len).map(|i| (rng.gen_range(0..1000), i)).collect();
+
+            let mut expected = data.clone();
+            expected.sort_by_key(|&(key, _)| key);
+            let mut sorted = data.clone();
+            par_merge_sort_by_key(&mut sorted, |&(key, _)| key);
+            assert_eq!(sorted, expected);
+
+            let mut expected: Vec<u16> = data.iter().map(|&(key, _)| key).collect();
+            let mut sorted = expected.clone();
+            expected.sort();
+            par_merge_sort(&mut sorted);
+            assert_eq!(sorted, expected);
+        }
+    }
+}
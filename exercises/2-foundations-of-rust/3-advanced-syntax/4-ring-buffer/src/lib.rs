@@ -0,0 +1,11 @@
+//! Ring buffers that can be shared between threads.
+//!
+//! The exercise in `main.rs` builds a single-threaded `RingBuffer`. This library
+//! contains a lock-free single-producer single-consumer variant for handing data
+//! from e.g. a sampling thread to a processing thread. It does not allocate and
+//! does not depend on `std`, so it can be used in the embedded exercises as well.
+#![cfg_attr(not(test), no_std)]
+
+pub mod spsc;
+
+mod sync;
@@ -0,0 +1,441 @@
+//! A lock-free single-producer single-consumer ring buffer.
+//!
+//! The buffer is created once, for example as a `static` or on the stack of the
+//! main thread, and then [split](RingBuffer::split) into a [Producer] and a
+//! [Consumer] that can be moved to different threads:
+//!
+//! ```
+//! use ring_buffer::spsc::RingBuffer;
+//!
+//! let mut buffer = RingBuffer::<u32, 16>::new();
+//! let (mut producer, mut consumer) = buffer.split();
+//!
+//! std::thread::scope(|s| {
+//!     s.spawn(move || {
+//!         for sample in 0..100 {
+//!             while producer.push(sample).is_err() {
+//!                 std::hint::spin_loop();
+//!             }
+//!         }
+//!     });
+//!
+//!     let mut received = 0;
+//!     while received < 100 {
+//!         if let Some(sample) = consumer.pop() {
+//!             assert_eq!(sample, received);
+//!             received += 1;
+//!         }
+//!     }
+//! });
+//! ```
+
+use core::{fmt, marker::PhantomData, mem::MaybeUninit};
+
+use crate::sync::{AtomicUsize, CachePadded, Ordering, UnsafeCell};
+
+/// A fixed-size queue that can hold up to `N` items of type `T`.
+///
+/// `head` is only ever written by the [Consumer] and `tail` only by the
+/// [Producer]. Both indices keep counting up (wrapping on overflow), so
+/// `tail - head` is the number of items in the buffer and all `N` slots can be
+/// used.
+pub struct RingBuffer<T, const N: usize> {
+    /// Index of the next item to be read
+    head: CachePadded<AtomicUsize>,
+    /// Index of the next slot to be written
+    tail: CachePadded<AtomicUsize>,
+    buffer: [UnsafeCell<MaybeUninit<T>>; N],
+}
+
+// SAFETY: the `Producer` and `Consumer` never access the same slot at the same
+// time, and items are only moved from one thread to the other, which is
+// fine as long as `T: Send`.
+unsafe impl<T: Send, const N: usize> Sync for RingBuffer<T, N> {}
+
+impl<T, const N: usize> RingBuffer<T, N> {
+    /// Create a new, empty ring buffer. This is a `const fn`, so it can be
+    /// used to initialize a `static`.
+    #[cfg(not(loom))]
+    pub const fn new() -> Self {
+        const { assert!(N > 0, "a RingBuffer needs room for at least one item") };
+        Self {
+            head: CachePadded(AtomicUsize::new(0)),
+            tail: CachePadded(AtomicUsize::new(0)),
+            buffer: [const { UnsafeCell::new(MaybeUninit::uninit()) }; N],
+        }
+    }
+
+    /// Create a new, empty ring buffer.
+    #[cfg(loom)]
+    pub fn new() -> Self {
+        const { assert!(N > 0, "a RingBuffer needs room for at least one item") };
+        Self {
+            head: CachePadded(AtomicUsize::new(0)),
+            tail: CachePadded(AtomicUsize::new(0)),
+            buffer: core::array::from_fn(|_| UnsafeCell::new(MaybeUninit::uninit())),
+        }
+    }
+
+    /// Split the buffer into its producing and consuming half. As both halves
+    /// borrow the buffer mutably, there can only ever be one of each.
+    pub fn split(&mut self) -> (Producer<'_, T, N>, Consumer<'_, T, N>) {
+        (
+            Producer {
+                buffer: self,
+                _not_sync: PhantomData,
+            },
+            Consumer {
+                buffer: self,
+                _not_sync: PhantomData,
+            },
+        )
+    }
+
+    /// The maximum number of items the buffer can hold
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// The number of items currently in the buffer. If the other half is in
+    /// use on another thread, this may already be out of date on return.
+    pub fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Acquire);
+        let tail = self.tail.load(Ordering::Acquire);
+        tail.wrapping_sub(head)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == N
+    }
+}
+
+impl<T, const N: usize> Default for RingBuffer<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> fmt::Debug for RingBuffer<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RingBuffer")
+            .field("len", &self.len())
+            .field("capacity", &N)
+            .finish()
+    }
+}
+
+impl<T, const N: usize> Drop for RingBuffer<T, N> {
+    fn drop(&mut self) {
+        // Both halves have been dropped at this point, so we have exclusive
+        // access and can drop whatever has not been consumed.
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Relaxed);
+        let mut index = head;
+        while index != tail {
+            // SAFETY: all slots between `head` and `tail` are initialized
+            self.buffer[index % N].with_mut(|slot| unsafe { (*slot).assume_init_drop() });
+            index = index.wrapping_add(1);
+        }
+    }
+}
+
+/// The writing half of a [RingBuffer]
+pub struct Producer<'a, T, const N: usize> {
+    buffer: &'a RingBuffer<T, N>,
+    /// Makes sure a `&Producer` can't be shared between threads, as `push`
+    /// takes `&mut self` anyway.
+    _not_sync: PhantomData<*const ()>,
+}
+
+// SAFETY: the producer can be moved to another thread as long as the items can
+unsafe impl<T: Send, const N: usize> Send for Producer<'_, T, N> {}
+
+impl<T, const N: usize> Producer<'_, T, N> {
+    /// Try to add `value` to the back of the queue. If the queue is full,
+    /// `value` is handed back in the `Err` variant.
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        let tail = self.buffer.tail.load(Ordering::Relaxed);
+        // Acquire makes sure the consumer is done reading the slot we are
+        // about to overwrite.
+        let head = self.buffer.head.load(Ordering::Acquire);
+        if tail.wrapping_sub(head) == N {
+            return Err(value);
+        }
+
+        // SAFETY: the slot at `tail` is not visible to the consumer until we
+        // publish the new tail below
+        self.buffer.buffer[tail % N].with_mut(|slot| unsafe { (*slot).write(value) });
+        // Release makes the write above visible to the consumer before it sees
+        // the new tail.
+        self.buffer
+            .tail
+            .store(tail.wrapping_add(1), Ordering::Release);
+        Ok(())
+    }
+
+    /// Returns true if and only if the next call to `push` will succeed
+    pub fn has_room(&self) -> bool {
+        !self.buffer.is_full()
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+}
+
+impl<T, const N: usize> fmt::Debug for Producer<'_, T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Producer").field(self.buffer).finish()
+    }
+}
+
+/// The reading half of a [RingBuffer]
+pub struct Consumer<'a, T, const N: usize> {
+    buffer: &'a RingBuffer<T, N>,
+    /// Makes sure a `&Consumer` can't be shared between threads, as `peek`
+    /// hands out references into the buffer.
+    _not_sync: PhantomData<*const ()>,
+}
+
+// SAFETY: the consumer can be moved to another thread as long as the items can
+unsafe impl<T: Send, const N: usize> Send for Consumer<'_, T, N> {}
+
+impl<T, const N: usize> Consumer<'_, T, N> {
+    /// Try to take the item from the front of the queue. Returns `None` if the
+    /// queue is empty.
+    pub fn pop(&mut self) -> Option<T> {
+        let head = self.buffer.head.load(Ordering::Relaxed);
+        // Acquire makes sure we see the item the producer wrote before it
+        // published `tail`.
+        let tail = self.buffer.tail.load(Ordering::Acquire);
+        if head == tail {
+            return None;
+        }
+
+        // SAFETY: the slot at `head` was initialized by the producer, and it
+        // won't touch it again until we publish the new head below
+        let value = self.buffer.buffer[head % N].with(|slot| unsafe { (*slot).assume_init_read() });
+        // Release makes sure we are done reading before the producer may
+        // overwrite the slot.
+        self.buffer
+            .head
+            .store(head.wrapping_add(1), Ordering::Release);
+        Some(value)
+    }
+
+    /// Returns a reference to the item at the front of the queue without
+    /// removing it, or `None` if the queue is empty.
+    pub fn peek(&self) -> Option<&T> {
+        let head = self.buffer.head.load(Ordering::Relaxed);
+        let tail = self.buffer.tail.load(Ordering::Acquire);
+        if head == tail {
+            return None;
+        }
+
+        // SAFETY: the slot at `head` is initialized, and the producer will not
+        // overwrite it as long as `self` is borrowed, as only `pop` moves
+        // `head` forward.
+        Some(self.buffer.buffer[head % N].with(|slot| unsafe { (*slot).assume_init_ref() }))
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+}
+
+impl<T, const N: usize> Iterator for Consumer<'_, T, N> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.pop()
+    }
+}
+
+impl<T, const N: usize> fmt::Debug for Consumer<'_, T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Consumer").field(self.buffer).finish()
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use std::{rc::Rc, thread, vec::Vec};
+
+    use super::RingBuffer;
+
+    #[test]
+    fn test_push_pop() {
+        let mut buffer = RingBuffer::<u8, 4>::new();
+        let (mut tx, mut rx) = buffer.split();
+
+        assert_eq!(rx.pop(), None);
+        for i in 0..4 {
+            assert_eq!(tx.push(i), Ok(()));
+        }
+        assert!(!tx.has_room());
+        assert_eq!(tx.push(4), Err(4));
+        assert_eq!(rx.len(), 4);
+
+        assert_eq!(rx.peek(), Some(&0));
+        assert_eq!(rx.pop(), Some(0));
+        assert_eq!(tx.push(4), Ok(()));
+        assert_eq!(rx.collect::<Vec<_>>(), [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_wrap_around() {
+        let mut buffer = RingBuffer::<usize, 3>::new();
+        let (mut tx, mut rx) = buffer.split();
+        for i in 0..100 {
+            tx.push(i).unwrap();
+            tx.push(i + 1).unwrap();
+            assert_eq!(rx.pop(), Some(i));
+            assert_eq!(rx.pop(), Some(i + 1));
+            assert!(rx.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_drops_remaining_items() {
+        let item = Rc::new(());
+        {
+            let mut buffer = RingBuffer::<Rc<()>, 4>::new();
+            let (mut tx, mut rx) = buffer.split();
+            for _ in 0..3 {
+                tx.push(item.clone()).unwrap();
+            }
+            drop(rx.pop());
+            assert_eq!(Rc::strong_count(&item), 3);
+        }
+        assert_eq!(Rc::strong_count(&item), 1);
+    }
+
+    #[test]
+    fn test_static() {
+        static mut BUFFER: RingBuffer<u32, 8> = RingBuffer::new();
+        // SAFETY: this is the only place that accesses `BUFFER`
+        #[allow(static_mut_refs)]
+        let (mut tx, mut rx) = unsafe { BUFFER.split() };
+        tx.push(42).unwrap();
+        assert_eq!(rx.pop(), Some(42));
+    }
+
+    #[test]
+    fn test_threads() {
+        const COUNT: u64 = 100_000;
+        let mut buffer = RingBuffer::<u64, 32>::new();
+        let (mut tx, rx) = buffer.split();
+
+        let sum = thread::scope(|s| {
+            s.spawn(move || {
+                for i in 0..COUNT {
+                    let mut item = i;
+                    while let Err(i) = tx.push(item) {
+                        item = i;
+                        thread::yield_now();
+                    }
+                }
+            });
+            let consumer = s.spawn(move || {
+                let mut rx = rx;
+                let mut expected = 0;
+                let mut sum = 0;
+                while expected < COUNT {
+                    match rx.pop() {
+                        Some(i) => {
+                            assert_eq!(i, expected);
+                            sum += i;
+                            expected += 1;
+                        }
+                        None => thread::yield_now(),
+                    }
+                }
+                sum
+            });
+            consumer.join().unwrap()
+        });
+        assert_eq!(sum, COUNT * (COUNT - 1) / 2);
+    }
+}
+
+/// Run with `RUSTFLAGS="--cfg loom" cargo test --release --lib`
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use loom::{sync::Arc, thread};
+    use std::boxed::Box;
+
+    use super::RingBuffer;
+
+    #[test]
+    fn test_push_pop() {
+        loom::model(|| {
+            let buffer = Box::leak(Box::new(RingBuffer::<Arc<usize>, 2>::new()));
+            let (mut tx, mut rx) = buffer.split();
+
+            let producer = thread::spawn(move || {
+                for i in 0..3 {
+                    let mut item = Arc::new(i);
+                    while let Err(i) = tx.push(item) {
+                        item = i;
+                        thread::yield_now();
+                    }
+                }
+            });
+
+            let mut expected = 0;
+            while expected < 3 {
+                match rx.pop() {
+                    Some(i) => {
+                        assert_eq!(*i, expected);
+                        expected += 1;
+                    }
+                    None => thread::yield_now(),
+                }
+            }
+            producer.join().unwrap();
+        });
+    }
+
+    #[test]
+    fn test_peek() {
+        loom::model(|| {
+            let buffer = Box::leak(Box::new(RingBuffer::<usize, 1>::new()));
+            let (mut tx, rx) = buffer.split();
+
+            let producer = thread::spawn(move || {
+                tx.push(1).unwrap();
+                // the buffer is full until the consumer pops
+                assert!(tx.push(2).is_err());
+            });
+
+            let value = loop {
+                if let Some(value) = rx.peek() {
+                    break *value;
+                }
+                thread::yield_now();
+            };
+            assert_eq!(value, 1);
+            producer.join().unwrap();
+        });
+    }
+}
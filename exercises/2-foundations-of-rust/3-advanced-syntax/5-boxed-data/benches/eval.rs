@@ -0,0 +1,59 @@
+use boxed_data::{compile_closure, eval, parse, Env, Program, Vm};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+/// Expressions of increasing size, each evaluated for a range of values of `x`
+const EXPRESSIONS: [(&str, &str); 3] = [
+    ("linear", "3 * x + 5"),
+    ("polynomial", "3 * x^3 - 2 * x^2 + x / 7 - sum(x, 1, 2)"),
+    ("sigma", "sigma(i, 1, 10, i * x - x^2 / i)"),
+];
+
+/// Compare evaluating an expression for many inputs by walking the tree with
+/// [eval], by running compiled bytecode, and by calling compiled closures.
+fn bench_strategies(c: &mut Criterion) {
+    let inputs: Vec<i64> = (-500..500).collect();
+
+    for (name, input) in EXPRESSIONS {
+        let expr = parse(input).unwrap();
+        let mut group = c.benchmark_group(name);
+        group.throughput(Throughput::Elements(inputs.len() as u64));
+
+        group.bench_with_input(BenchmarkId::new("tree-walk", input), &expr, |b, expr| {
+            let mut env = Env::new();
+            b.iter(|| {
+                for &x in &inputs {
+                    env.set("x", x);
+                    black_box(eval(expr, &env).ok());
+                }
+            })
+        });
+
+        let program = Program::compile(&expr, &["x"]).unwrap();
+        group.bench_with_input(
+            BenchmarkId::new("bytecode", input),
+            &program,
+            |b, program| {
+                let mut vm = Vm::new();
+                b.iter(|| {
+                    for &x in &inputs {
+                        black_box(vm.run(program, &[x]).ok());
+                    }
+                })
+            },
+        );
+
+        let f = compile_closure(&expr, "x").unwrap();
+        group.bench_with_input(BenchmarkId::new("closure", input), &f, |b, f| {
+            b.iter(|| {
+                for &x in &inputs {
+                    black_box(f(x).ok());
+                }
+            })
+        });
+
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_strategies);
+criterion_main!(benches);
@@ -0,0 +1,410 @@
+//! Compiling expressions to bytecode for a small stack machine.
+//!
+//! Evaluating the same expression for many different inputs with [eval](crate::eval)
+//! walks the tree and looks up every variable by name each time. A compiled
+//! [Program] resolves variables to numbered slots once, and runs as a flat
+//! list of instructions on a [Vm], which reuses its buffers between runs.
+//!
+//! ```
+//! use boxed_data::{parse, Program, Vm};
+//!
+//! let expr = parse("sigma(i, 1, n, i * x)").unwrap();
+//! let program = Program::compile(&expr, &["x", "n"]).unwrap();
+//! let mut vm = Vm::new();
+//! assert_eq!(vm.run(&program, &[2, 3]), Ok(12));
+//! assert_eq!(vm.run(&program, &[5, 4]), Ok(50));
+//! ```
+
+use crate::{checked_div, checked_pow, count_iterations, EvalError, Expr};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instr {
+    /// Push a constant
+    Const(i64),
+    /// Push the value of a slot
+    Load(u32),
+    /// Pop a value into a slot
+    Store(u32),
+    /// Pop the right and left operand and push the result
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    /// Negate the value on top of the stack
+    Neg,
+    /// Jump to `target` if the value in slot `counter` is greater than the value in slot `end`.
+    /// Otherwise a loop from `counter` to `end` starts, which counts towards
+    /// [MAX_ITERATIONS](crate::MAX_ITERATIONS).
+    JumpIfGreater {
+        counter: u32,
+        end: u32,
+        target: u32,
+    },
+    /// Continue with the next instruction if the values in slots `counter` and `end` are
+    /// equal. Otherwise increment `counter` and jump to `target`.
+    Loop {
+        counter: u32,
+        end: u32,
+        target: u32,
+    },
+}
+
+/// A compiled expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    code: Vec<Instr>,
+    /// The number of inputs, which are stored in the first slots
+    inputs: usize,
+    /// The total number of slots, including the ones used by `Sigma` loops
+    slots: usize,
+    /// The maximum size of the stack while running
+    max_stack: usize,
+}
+
+impl Program {
+    /// Compile `expr`, where the variables in `inputs` get their value from the
+    /// corresponding element of the inputs passed to [Vm::run].
+    ///
+    /// Unlike [eval](crate::eval), which only fails on an unknown variable once it
+    /// is evaluated, all variables must be known at compile time.
+    pub fn compile(expr: &Expr, inputs: &[&str]) -> Result<Program, EvalError> {
+        let mut compiler = Compiler {
+            code: Vec::new(),
+            slots: Slots::new(inputs),
+            depth: 0,
+            max_depth: 0,
+        };
+        compiler.compile(expr)?;
+        Ok(Program {
+            code: compiler.code,
+            inputs: inputs.len(),
+            slots: compiler.slots.count,
+            max_stack: compiler.max_depth,
+        })
+    }
+
+    pub fn code(&self) -> &[Instr] {
+        &self.code
+    }
+
+    /// Run the program once. Use a [Vm] to run it many times.
+    pub fn run(&self, inputs: &[i64]) -> Result<i64, EvalError> {
+        Vm::new().run(self, inputs)
+    }
+}
+
+/// Keeps track of which variable lives in which slot
+pub(crate) struct Slots<'e> {
+    /// The variables that are currently in scope. The innermost binding is at
+    /// the end, and shadows the others.
+    scope: Vec<(&'e str, u32)>,
+    /// The total number of slots allocated so far
+    pub(crate) count: usize,
+}
+
+impl<'e> Slots<'e> {
+    pub(crate) fn new(inputs: &[&'e str]) -> Self {
+        Self {
+            scope: inputs
+                .iter()
+                .enumerate()
+                .map(|(i, name)| (*name, i as u32))
+                .collect(),
+            count: inputs.len(),
+        }
+    }
+
+    pub(crate) fn lookup(&self, name: &str) -> Result<u32, EvalError> {
+        match self.scope.iter().rev().find(|(bound, _)| *bound == name) {
+            Some((_, slot)) => Ok(*slot),
+            None => Err(EvalError::UnknownVariable(name.to_owned())),
+        }
+    }
+
+    /// Allocate a slot that is not bound to a variable
+    pub(crate) fn allocate(&mut self) -> u32 {
+        self.count += 1;
+        (self.count - 1) as u32
+    }
+
+    /// Allocate a slot for `name`, which stays in scope until [Slots::unbind] is called
+    pub(crate) fn bind(&mut self, name: &'e str) -> u32 {
+        let slot = self.allocate();
+        self.scope.push((name, slot));
+        slot
+    }
+
+    pub(crate) fn unbind(&mut self) {
+        self.scope.pop();
+    }
+}
+
+struct Compiler<'e> {
+    code: Vec<Instr>,
+    slots: Slots<'e>,
+    /// The size of the stack after running the instructions emitted so far
+    depth: usize,
+    max_depth: usize,
+}
+
+impl<'e> Compiler<'e> {
+    fn emit(&mut self, instr: Instr) {
+        match instr {
+            Instr::Const(_) | Instr::Load(_) => {
+                self.depth += 1;
+                self.max_depth = self.max_depth.max(self.depth);
+            }
+            Instr::Store(_) | Instr::Add | Instr::Sub | Instr::Mul | Instr::Div | Instr::Pow => {
+                self.depth -= 1
+            }
+            Instr::Neg | Instr::JumpIfGreater { .. } | Instr::Loop { .. } => {}
+        }
+        self.code.push(instr);
+    }
+
+    fn binary(&mut self, lhs: &'e Expr, rhs: &'e Expr, instr: Instr) -> Result<(), EvalError> {
+        self.compile(lhs)?;
+        self.compile(rhs)?;
+        self.emit(instr);
+        Ok(())
+    }
+
+    fn compile(&mut self, expr: &'e Expr) -> Result<(), EvalError> {
+        use Expr::*;
+        match expr {
+            Const(k) => self.emit(Instr::Const(*k)),
+            Var(name) => {
+                let slot = self.slots.lookup(name)?;
+                self.emit(Instr::Load(slot));
+            }
+            Add(lhs, rhs) => self.binary(lhs, rhs, Instr::Add)?,
+            Sub(lhs, rhs) => self.binary(lhs, rhs, Instr::Sub)?,
+            Mul(lhs, rhs) => self.binary(lhs, rhs, Instr::Mul)?,
+            Div(lhs, rhs) => self.binary(lhs, rhs, Instr::Div)?,
+            Pow(lhs, rhs) => self.binary(lhs, rhs, Instr::Pow)?,
+            Neg(expr) => {
+                self.compile(expr)?;
+                self.emit(Instr::Neg);
+            }
+            Summation(exprs) => match exprs.split_first() {
+                None => self.emit(Instr::Const(0)),
+                Some((first, rest)) => {
+                    self.compile(first)?;
+                    for expr in rest {
+                        self.compile(expr)?;
+                        self.emit(Instr::Add);
+                    }
+                }
+            },
+            Sigma {
+                var,
+                from,
+                to,
+                body,
+            } => {
+                // the bounds are evaluated outside of the scope of the loop variable
+                self.compile(from)?;
+                self.compile(to)?;
+                let end = self.slots.allocate();
+                self.emit(Instr::Store(end));
+                let counter = self.slots.bind(var);
+                self.emit(Instr::Store(counter));
+
+                self.emit(Instr::Const(0));
+                let skip = self.code.len();
+                self.emit(Instr::JumpIfGreater {
+                    counter,
+                    end,
+                    target: 0,
+                });
+                let start = self.code.len() as u32;
+                self.compile(body)?;
+                self.emit(Instr::Add);
+                self.emit(Instr::Loop {
+                    counter,
+                    end,
+                    target: start,
+                });
+                let exit = self.code.len() as u32;
+                self.code[skip] = Instr::JumpIfGreater {
+                    counter,
+                    end,
+                    target: exit,
+                };
+                self.slots.unbind();
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A stack machine to run [Program]s on
+#[derive(Debug, Default)]
+pub struct Vm {
+    stack: Vec<i64>,
+    slots: Vec<i64>,
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `program`, with its inputs set to `inputs`.
+    ///
+    /// # Panics
+    /// Panics if the number of inputs is not the number of input variables the
+    /// program was compiled with.
+    pub fn run(&mut self, program: &Program, inputs: &[i64]) -> Result<i64, EvalError> {
+        assert_eq!(
+            inputs.len(),
+            program.inputs,
+            "the program expects {} inputs",
+            program.inputs
+        );
+        let stack = &mut self.stack;
+        stack.clear();
+        stack.reserve(program.max_stack);
+        let slots = &mut self.slots;
+        slots.clear();
+        slots.extend_from_slice(inputs);
+        slots.resize(program.slots, 0);
+
+        let mut iterations_left = crate::MAX_ITERATIONS;
+        let mut pc = 0;
+        while let Some(&instr) = program.code.get(pc) {
+            pc += 1;
+            match instr {
+                Instr::Const(k) => stack.push(k),
+                Instr::Load(slot) => stack.push(slots[slot as usize]),
+                Instr::Store(slot) => slots[slot as usize] = pop(stack),
+                Instr::Add => binary(stack, |a, b| a.checked_add(b).ok_or(EvalError::Overflow))?,
+                Instr::Sub => binary(stack, |a, b| a.checked_sub(b).ok_or(EvalError::Overflow))?,
+                Instr::Mul => binary(stack, |a, b| a.checked_mul(b).ok_or(EvalError::Overflow))?,
+                Instr::Div => binary(stack, checked_div)?,
+                Instr::Pow => binary(stack, checked_pow)?,
+                Instr::Neg => {
+                    let top = stack.last_mut().expect("stack underflow");
+                    *top = top.checked_neg().ok_or(EvalError::Overflow)?;
+                }
+                Instr::JumpIfGreater {
+                    counter,
+                    end,
+                    target,
+                } => {
+                    let (from, to) = (slots[counter as usize], slots[end as usize]);
+                    if from > to {
+                        pc = target as usize;
+                    } else {
+                        count_iterations(&mut iterations_left, from, to)?;
+                    }
+                }
+                Instr::Loop {
+                    counter,
+                    end,
+                    target,
+                } => {
+                    if slots[counter as usize] != slots[end as usize] {
+                        slots[counter as usize] += 1;
+                        pc = target as usize;
+                    }
+                }
+            }
+        }
+        Ok(pop(stack))
+    }
+}
+
+fn pop(stack: &mut Vec<i64>) -> i64 {
+    stack.pop().expect("stack underflow")
+}
+
+/// Replace the two values on top of the stack by `op(lhs, rhs)`
+fn binary(
+    stack: &mut Vec<i64>,
+    op: impl FnOnce(i64, i64) -> Result<i64, EvalError>,
+) -> Result<(), EvalError> {
+    let rhs = pop(stack);
+    let lhs = stack.last_mut().expect("stack underflow");
+    *lhs = op(*lhs, rhs)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::{eval, parse, testutil::random_expr, Env};
+
+    #[test]
+    fn test_code() {
+        let program = Program::compile(&parse("2 * x - -y").unwrap(), &["x", "y"]).unwrap();
+        assert_eq!(
+            program.code(),
+            [
+                Instr::Const(2),
+                Instr::Load(0),
+                Instr::Mul,
+                Instr::Load(1),
+                Instr::Neg,
+                Instr::Sub
+            ]
+        );
+        assert_eq!(program.max_stack, 2);
+        assert_eq!(program.run(&[3, 4]), Ok(10));
+    }
+
+    #[test]
+    fn test_sigma() {
+        let program = Program::compile(&parse("sigma(x, 1, x, x)").unwrap(), &["x"]).unwrap();
+        let mut vm = Vm::new();
+        assert_eq!(vm.run(&program, &[4]), Ok(10));
+        assert_eq!(vm.run(&program, &[0]), Ok(0));
+        let program = Program::compile(
+            &parse("sigma(i, 1, 3, sigma(j, i, 3, i * j))").unwrap(),
+            &[],
+        )
+        .unwrap();
+        assert_eq!(vm.run(&program, &[]), Ok(25));
+        let program = Program::compile(
+            &parse("sigma(i, 9223372036854775806, 9223372036854775807, 1)").unwrap(),
+            &[],
+        )
+        .unwrap();
+        assert_eq!(vm.run(&program, &[]), Ok(2));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            Program::compile(&parse("x + y").unwrap(), &["x"]),
+            Err(EvalError::UnknownVariable("y".to_owned()))
+        );
+        assert_eq!(
+            Program::compile(&parse("sigma(i, 1, 2, i) + i").unwrap(), &["x"]),
+            Err(EvalError::UnknownVariable("i".to_owned()))
+        );
+        let program = Program::compile(&parse("1 / x").unwrap(), &["x"]).unwrap();
+        assert_eq!(program.run(&[0]), Err(EvalError::DivisionByZero));
+        let program =
+            Program::compile(&parse("sigma(i, 0, x, sigma(j, 1, x, 1))").unwrap(), &["x"]).unwrap();
+        assert_eq!(program.run(&[4_000]), Err(EvalError::TooManyIterations));
+        assert_eq!(program.run(&[i64::MAX]), Err(EvalError::TooManyIterations));
+    }
+
+    #[test]
+    fn test_matches_eval() {
+        let mut rng = StdRng::seed_from_u64(0xc0de);
+        let mut vm = Vm::new();
+        for _ in 0..2000 {
+            let expr = random_expr(&mut rng, 5, true);
+            let program = Program::compile(&expr, &["x", "y"]).unwrap();
+            for x in -3..=3 {
+                let env = Env::new().with("x", x).with("y", 7);
+                assert_eq!(vm.run(&program, &[x, 7]), eval(&expr, &env), "{expr}");
+            }
+        }
+    }
+}
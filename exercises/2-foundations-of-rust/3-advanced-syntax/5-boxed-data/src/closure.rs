@@ -0,0 +1,162 @@
+//! Compiling expressions to a tree of closures.
+//!
+//! Like the [bytecode](crate::Program), variables are resolved to slots once,
+//! but instead of interpreting instructions, every node of the expression
+//! becomes a closure that calls the closures of its operands directly.
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use crate::{
+    bytecode::Slots, checked_div, checked_pow, count_iterations, EvalError, Expr, MAX_ITERATIONS,
+};
+
+/// How many more terms `Sigma`s can add up in this run, shared by their nodes
+type IterationsLeft = Rc<Cell<u64>>;
+
+/// A compiled node, reading the values of variables from the slots
+type Node = Box<dyn Fn(&mut [i64]) -> Result<i64, EvalError>>;
+
+/// A function evaluating an expression of a single variable
+pub type Compiled = Box<dyn Fn(i64) -> Result<i64, EvalError>>;
+
+/// Compile `expr` into a closure that takes the value of `var`.
+///
+/// All variables other than `var` must be bound by a `Sigma`.
+pub fn compile_closure(expr: &Expr, var: &str) -> Result<Compiled, EvalError> {
+    let mut slots = Slots::new(&[var]);
+    let iterations_left = IterationsLeft::default();
+    let node = compile(expr, &mut slots, &iterations_left)?;
+    let slots = RefCell::new(vec![0; slots.count]);
+    Ok(Box::new(move |value| {
+        let mut slots = slots.borrow_mut();
+        slots[0] = value;
+        iterations_left.set(MAX_ITERATIONS);
+        node(&mut slots)
+    }))
+}
+
+fn binary<'e>(
+    lhs: &'e Expr,
+    rhs: &'e Expr,
+    slots: &mut Slots<'e>,
+    left: &IterationsLeft,
+    op: fn(i64, i64) -> Result<i64, EvalError>,
+) -> Result<Node, EvalError> {
+    let (lhs, rhs) = (compile(lhs, slots, left)?, compile(rhs, slots, left)?);
+    Ok(Box::new(move |s| op(lhs(s)?, rhs(s)?)))
+}
+
+fn compile<'e>(
+    expr: &'e Expr,
+    slots: &mut Slots<'e>,
+    left: &IterationsLeft,
+) -> Result<Node, EvalError> {
+    use Expr::*;
+    let node: Node = match expr {
+        Const(k) => {
+            let k = *k;
+            Box::new(move |_| Ok(k))
+        }
+        Var(name) => {
+            let slot = slots.lookup(name)? as usize;
+            Box::new(move |s| Ok(s[slot]))
+        }
+        Add(lhs, rhs) => binary(lhs, rhs, slots, left, |a, b| {
+            a.checked_add(b).ok_or(EvalError::Overflow)
+        })?,
+        Sub(lhs, rhs) => binary(lhs, rhs, slots, left, |a, b| {
+            a.checked_sub(b).ok_or(EvalError::Overflow)
+        })?,
+        Mul(lhs, rhs) => binary(lhs, rhs, slots, left, |a, b| {
+            a.checked_mul(b).ok_or(EvalError::Overflow)
+        })?,
+        Div(lhs, rhs) => binary(lhs, rhs, slots, left, checked_div)?,
+        Pow(lhs, rhs) => binary(lhs, rhs, slots, left, checked_pow)?,
+        Neg(expr) => {
+            let expr = compile(expr, slots, left)?;
+            Box::new(move |s| expr(s)?.checked_neg().ok_or(EvalError::Overflow))
+        }
+        Summation(exprs) => {
+            let exprs = exprs
+                .iter()
+                .map(|expr| compile(expr, slots, left))
+                .collect::<Result<Vec<_>, _>>()?;
+            Box::new(move |s| {
+                let mut acc: i64 = 0;
+                for expr in &exprs {
+                    acc = acc.checked_add(expr(s)?).ok_or(EvalError::Overflow)?;
+                }
+                Ok(acc)
+            })
+        }
+        Sigma {
+            var,
+            from,
+            to,
+            body,
+        } => {
+            let (from, to) = (compile(from, slots, left)?, compile(to, slots, left)?);
+            let slot = slots.bind(var) as usize;
+            let body = compile(body, slots, left);
+            slots.unbind();
+            let body = body?;
+            let left = left.clone();
+            Box::new(move |s| {
+                let (from, to) = (from(s)?, to(s)?);
+                let mut iterations_left = left.get();
+                count_iterations(&mut iterations_left, from, to)?;
+                left.set(iterations_left);
+                let mut acc: i64 = 0;
+                for value in from..=to {
+                    s[slot] = value;
+                    acc = acc.checked_add(body(s)?).ok_or(EvalError::Overflow)?;
+                }
+                Ok(acc)
+            })
+        }
+    };
+    Ok(node)
+}
+
+#[cfg(test)]
+mod test {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::{eval, parse, sigma, testutil::random_expr, var, Env};
+
+    #[test]
+    fn test_closure() {
+        let f = compile_closure(&parse("3 * x^2 - sum(x, 1)").unwrap(), "x").unwrap();
+        assert_eq!(f(2), Ok(9));
+        assert_eq!(f(-1), Ok(3));
+        let f = compile_closure(&parse("sigma(i, 1, x, i)").unwrap(), "x").unwrap();
+        assert_eq!(f(100), Ok(5050));
+        assert_eq!(
+            compile_closure(&sigma("i", Expr::Const(1), Expr::Const(2), var("j")), "x").err(),
+            Some(EvalError::UnknownVariable("j".to_owned()))
+        );
+        let f = compile_closure(&parse("sigma(i, 0, x, sigma(j, 1, x, 1))").unwrap(), "x").unwrap();
+        assert_eq!(f(4_000), Err(EvalError::TooManyIterations));
+        // every call starts counting from scratch
+        assert_eq!(f(3), Ok(12));
+        assert_eq!(f(i64::MAX), Err(EvalError::TooManyIterations));
+    }
+
+    #[test]
+    fn test_matches_eval() {
+        let mut rng = StdRng::seed_from_u64(0xc105);
+        for _ in 0..2000 {
+            let expr = random_expr(&mut rng, 5, true);
+            if expr.depends_on("y") {
+                continue;
+            }
+            let f = compile_closure(&expr, "x").unwrap();
+            for x in -3..=3 {
+                let env = Env::new().with("x", x);
+                assert_eq!(f(x), eval(&expr, &env), "{expr}");
+            }
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! Symbolic differentiation of expressions.
+
+use std::fmt;
+
+use crate::{add, div, mul, neg, pow, simplify, sub, Expr};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeriveError {
+    /// The exponent of a power depends on the variable, as in `2^x`.
+    /// Its derivative involves a logarithm, which we can't express.
+    VariableExponent,
+    /// The bounds of a `Sigma` depend on the variable, so the number of terms
+    /// changes with it
+    VariableBounds,
+}
+
+impl fmt::Display for DeriveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeriveError::VariableExponent => write!(f, "exponent depends on the variable"),
+            DeriveError::VariableBounds => write!(f, "sigma bounds depend on the variable"),
+        }
+    }
+}
+
+impl std::error::Error for DeriveError {}
+
+/// The derivative of `expr` with respect to the variable `var`, simplified.
+///
+/// The derivative treats `expr` as a function on the real numbers, so the
+/// quotient rule is used for `/`, even though [eval](crate::eval) rounds the
+/// result of a division towards zero. Note that [simplify] does the same when
+/// folding constants, so only the derivatives of expressions without
+/// division are exact.
+pub fn derive(expr: &Expr, var: &str) -> Result<Expr, DeriveError> {
+    Ok(simplify(derivative(expr, var)?))
+}
+
+fn derivative(expr: &Expr, var: &str) -> Result<Expr, DeriveError> {
+    use Expr::*;
+    let d = |expr| derivative(expr, var);
+    let derivative = match expr {
+        Const(_) => Const(0),
+        Var(name) => Const(if name == var { 1 } else { 0 }),
+        Add(a, b) => add(d(a)?, d(b)?),
+        Sub(a, b) => sub(d(a)?, d(b)?),
+        // (a * b)' = a' * b + a * b'
+        Mul(a, b) => add(mul(d(a)?, (**b).clone()), mul((**a).clone(), d(b)?)),
+        // (a / b)' = (a' * b - a * b') / b^2
+        Div(a, b) => div(
+            sub(mul(d(a)?, (**b).clone()), mul((**a).clone(), d(b)?)),
+            pow((**b).clone(), Const(2)),
+        ),
+        // (a^n)' = n * a^(n - 1) * a'
+        Pow(a, n) => {
+            if n.depends_on(var) {
+                return Err(DeriveError::VariableExponent);
+            }
+            mul(
+                mul(
+                    (**n).clone(),
+                    pow((**a).clone(), sub((**n).clone(), Const(1))),
+                ),
+                d(a)?,
+            )
+        }
+        Neg(a) => neg(d(a)?),
+        Summation(exprs) => Summation(exprs.iter().map(d).collect::<Result<_, _>>()?),
+        Sigma {
+            var: bound,
+            from,
+            to,
+            body,
+        } => {
+            if from.depends_on(var) || to.depends_on(var) {
+                return Err(DeriveError::VariableBounds);
+            }
+            if bound == var {
+                // `var` is shadowed by the loop variable inside the body
+                Const(0)
+            } else {
+                Sigma {
+                    var: bound.clone(),
+                    from: from.clone(),
+                    to: to.clone(),
+                    body: Box::new(d(body)?),
+                }
+            }
+        }
+    };
+    Ok(derivative)
+}
+
+#[cfg(test)]
+mod test {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::{parse, testutil::random_expr};
+
+    fn derived(input: &str) -> String {
+        derive(&parse(input).unwrap(), "x").unwrap().to_string()
+    }
+
+    #[test]
+    fn test_rules() {
+        assert_eq!(derived("5"), "0");
+        assert_eq!(derived("x"), "1");
+        assert_eq!(derived("y"), "0");
+        assert_eq!(derived("3 * x + 2"), "3");
+        assert_eq!(derived("x^3"), "3 * x^2");
+        assert_eq!(derived("x * x"), "2 * x");
+        assert_eq!(derived("y * x^2"), "2 * x * y");
+        assert_eq!(derived("-x"), "-1");
+        assert_eq!(derived("1 / x"), "-1 / x^2");
+        assert_eq!(derived("sum(x, x^2, 7)"), "sum(1, 2 * x)");
+        assert_eq!(derived("sigma(i, 1, 10, i * x)"), "sigma(i, 1, 10, i)");
+        assert_eq!(derived("sigma(x, 1, 10, x)"), "0");
+    }
+
+    #[test]
+    fn test_errors() {
+        let expr = parse("2^x").unwrap();
+        assert_eq!(derive(&expr, "x"), Err(DeriveError::VariableExponent));
+        assert_eq!(derive(&expr, "y"), Ok(Expr::Const(0)));
+        let expr = parse("sigma(i, 1, x, i)").unwrap();
+        assert_eq!(derive(&expr, "x"), Err(DeriveError::VariableBounds));
+    }
+
+    /// Evaluate `expr` on the real numbers, with `x` set to `x` and `y` to 2
+    fn eval_real(expr: &Expr, x: f64, scope: &mut Vec<(String, f64)>) -> f64 {
+        use Expr::*;
+        let mut eval = |expr: &Expr| eval_real(expr, x, scope);
+        match expr {
+            Const(k) => *k as f64,
+            Var(name) => match scope.iter().rev().find(|(bound, _)| bound == name) {
+                Some((_, value)) => *value,
+                None if name == "x" => x,
+                None => 2.0,
+            },
+            Add(a, b) => eval(a) + eval(b),
+            Sub(a, b) => eval(a) - eval(b),
+            Mul(a, b) => eval(a) * eval(b),
+            Div(a, b) => eval(a) / eval(b),
+            Pow(a, b) => eval(a).powf(eval(b)),
+            Neg(a) => -eval(a),
+            Summation(exprs) => exprs.iter().map(eval).sum(),
+            Sigma {
+                var,
+                from,
+                to,
+                body,
+            } => {
+                let (from, to) = (eval(from) as i64, eval(to) as i64);
+                let mut acc = 0.0;
+                for i in from..=to {
+                    scope.push((var.clone(), i as f64));
+                    acc += eval_real(body, x, scope);
+                    scope.pop();
+                }
+                acc
+            }
+        }
+    }
+
+    #[test]
+    fn test_finite_differences() {
+        const H: f64 = 1e-4;
+        let mut rng = StdRng::seed_from_u64(0xd1ff);
+        for _ in 0..2000 {
+            let expr = random_expr(&mut rng, 4, false);
+            let derivative = derive(&expr, "x").unwrap();
+            for _ in 0..5 {
+                let x = rng.gen_range(-2.0..2.0);
+                let f = |x| eval_real(&expr, x, &mut Vec::new());
+                let expected = (f(x + H) - f(x - H)) / (2.0 * H);
+                let actual = eval_real(&derivative, x, &mut Vec::new());
+                let tolerance = 1e-4 * expected.abs().max(1.0);
+                assert!(
+                    (expected - actual).abs() < tolerance,
+                    "d/dx {expr} = {derivative}, at x = {x}: expected {expected}, got {actual}"
+                );
+            }
+        }
+    }
+}
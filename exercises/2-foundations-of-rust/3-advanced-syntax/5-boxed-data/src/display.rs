@@ -0,0 +1,175 @@
+//! Printing expressions in the same infix notation the parser accepts, using
+//! as few parentheses as possible while still parsing back into the same tree.
+
+use std::fmt;
+
+use crate::Expr;
+
+/// Binding strength of the outermost operator of an expression. Higher binds tighter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Precedence {
+    Additive,
+    Multiplicative,
+    Unary,
+    Power,
+    Atom,
+}
+
+impl Expr {
+    fn precedence(&self) -> Precedence {
+        match self {
+            Expr::Add(..) | Expr::Sub(..) => Precedence::Additive,
+            Expr::Mul(..) | Expr::Div(..) => Precedence::Multiplicative,
+            Expr::Neg(_) => Precedence::Unary,
+            Expr::Const(k) if *k < 0 => Precedence::Unary,
+            Expr::Pow(..) => Precedence::Power,
+            Expr::Const(_) | Expr::Var(_) | Expr::Summation(_) | Expr::Sigma { .. } => {
+                Precedence::Atom
+            }
+        }
+    }
+}
+
+/// Write `expr`, wrapped in parentheses if it binds less tightly than `min`
+fn write_operand(f: &mut fmt::Formatter<'_>, expr: &Expr, min: Precedence) -> fmt::Result {
+    if expr.precedence() < min {
+        write!(f, "({expr})")
+    } else {
+        write!(f, "{expr}")
+    }
+}
+
+/// Write a left-associative binary operator: the right operand needs
+/// parentheses if it has the same precedence, as in `a - (b - c)`.
+fn write_binary(
+    f: &mut fmt::Formatter<'_>,
+    lhs: &Expr,
+    op: &str,
+    rhs: &Expr,
+    precedence: Precedence,
+) -> fmt::Result {
+    write_operand(f, lhs, precedence)?;
+    write!(f, " {op} ")?;
+    if rhs.precedence() <= precedence {
+        write!(f, "({rhs})")
+    } else {
+        write!(f, "{rhs}")
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let precedence = self.precedence();
+        match self {
+            Expr::Const(k) => write!(f, "{k}"),
+            Expr::Var(name) => write!(f, "{name}"),
+            Expr::Add(lhs, rhs) => write_binary(f, lhs, "+", rhs, precedence),
+            Expr::Sub(lhs, rhs) => write_binary(f, lhs, "-", rhs, precedence),
+            Expr::Mul(lhs, rhs) => write_binary(f, lhs, "*", rhs, precedence),
+            Expr::Div(lhs, rhs) => write_binary(f, lhs, "/", rhs, precedence),
+            // `^` is right-associative, so here it's the base that needs
+            // parentheses if it's a power itself, as in `(a^b)^c`
+            Expr::Pow(base, exponent) => {
+                write_operand(f, base, Precedence::Atom)?;
+                write!(f, "^")?;
+                write_operand(f, exponent, Precedence::Unary)
+            }
+            // `-5` would be parsed as a negative constant instead
+            Expr::Neg(expr) if matches!(**expr, Expr::Const(k) if k >= 0) => write!(f, "-({expr})"),
+            Expr::Neg(expr) => {
+                write!(f, "-")?;
+                write_operand(f, expr, Precedence::Unary)
+            }
+            Expr::Summation(exprs) => {
+                write!(f, "sum(")?;
+                for (i, expr) in exprs.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{expr}")?;
+                }
+                write!(f, ")")
+            }
+            Expr::Sigma {
+                var,
+                from,
+                to,
+                body,
+            } => write!(f, "sigma({var}, {from}, {to}, {body})"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::{add, div, mul, neg, parse, pow, sigma, sub, testutil::random_expr, var, Expr};
+    use Expr::{Const, Summation};
+
+    #[test]
+    fn test_minimal_parentheses() {
+        assert_eq!(
+            add(sub(var("x"), Const(5)), Const(5)).to_string(),
+            "x - 5 + 5"
+        );
+        assert_eq!(
+            sub(var("x"), add(Const(5), Const(5))).to_string(),
+            "x - (5 + 5)"
+        );
+        assert_eq!(
+            add(var("x"), mul(Const(2), var("x"))).to_string(),
+            "x + 2 * x"
+        );
+        assert_eq!(
+            mul(add(var("x"), Const(2)), var("x")).to_string(),
+            "(x + 2) * x"
+        );
+        assert_eq!(
+            div(var("x"), mul(Const(2), var("x"))).to_string(),
+            "x / (2 * x)"
+        );
+        assert_eq!(sub(var("x"), Const(-5)).to_string(), "x - -5");
+        assert_eq!(mul(Const(-2), var("x")).to_string(), "-2 * x");
+        assert_eq!(
+            Summation(vec![add(var("x"), Const(1)), Const(2)]).to_string(),
+            "sum(x + 1, 2)"
+        );
+        assert_eq!(
+            pow(pow(var("x"), Const(2)), Const(3)).to_string(),
+            "(x^2)^3"
+        );
+        assert_eq!(pow(var("x"), pow(Const(2), Const(3))).to_string(), "x^2^3");
+        assert_eq!(pow(Const(-2), Const(2)).to_string(), "(-2)^2");
+        assert_eq!(
+            pow(var("x"), add(Const(1), var("x"))).to_string(),
+            "x^(1 + x)"
+        );
+        assert_eq!(neg(pow(Const(2), Const(2))).to_string(), "-2^2");
+        assert_eq!(neg(Const(5)).to_string(), "-(5)");
+        assert_eq!(neg(Const(-5)).to_string(), "--5");
+        assert_eq!(neg(add(var("x"), var("x"))).to_string(), "-(x + x)");
+        assert_eq!(mul(neg(var("x")), var("x")).to_string(), "-x * x");
+        assert_eq!(
+            sigma("i", Const(1), var("x"), mul(var("i"), var("x"))).to_string(),
+            "sigma(i, 1, x, i * x)"
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        for _ in 0..1000 {
+            let expr = random_expr(&mut rng, 5, true);
+            let printed = expr.to_string();
+            assert_eq!(parse(&printed), Ok(expr), "{printed}");
+        }
+        // the random constants are small
+        for n in [i64::MIN, i64::MAX] {
+            for expr in [Const(n), neg(Const(n)), pow(Const(n), Const(n))] {
+                let printed = expr.to_string();
+                assert_eq!(parse(&printed), Ok(expr), "{printed}");
+            }
+        }
+    }
+}
@@ -0,0 +1,395 @@
+/// The abstract syntax tree of an integer expression, evaluated by [eval].
+///
+/// An [Expr::Summation] holds a `Vec<Expr>` rather than a `Box`: the vector already keeps its
+/// items on the heap, so the enum has a known size without another indirection.
+///
+/// Instead of building expressions by hand, they can also be parsed from text with [parse] (or `str::parse`),
+/// and printed back with `Display`: `parse("x - 5 + 5")` results in `add(sub(var("x"), Const(5)), Const(5))`.
+///
+/// The ordering of expressions is only used to put them in a canonical order when simplifying.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Debug)]
+pub enum Expr {
+    Const(i64),
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+    Div(Box<Expr>, Box<Expr>),
+    /// Raise the left operand to the power of the right operand
+    Pow(Box<Expr>, Box<Expr>),
+    Neg(Box<Expr>),
+    /// A variable, looked up in the [Env] passed to [eval]
+    Var(String),
+    Summation(Vec<Expr>),
+    /// The sum of `body` for every value of `var` from `from` up to and including `to`
+    Sigma {
+        var: String,
+        from: Box<Expr>,
+        to: Box<Expr>,
+        body: Box<Expr>,
+    },
+}
+
+mod bytecode;
+mod closure;
+mod derive;
+mod display;
+mod parser;
+mod simplify;
+#[cfg(test)]
+mod testutil;
+
+use std::{collections::HashMap, fmt};
+
+pub use bytecode::{Instr, Program, Vm};
+pub use closure::{compile_closure, Compiled};
+pub use derive::{derive, DeriveError};
+pub use parser::{parse, ParseError, ParseErrorKind, MAX_DEPTH};
+pub use simplify::simplify;
+
+// These are convenience functions, so you don't have to type "Box::new" as often
+// when building test-data types
+pub fn add(x: Expr, y: Expr) -> Expr {
+    Expr::Add(Box::new(x), Box::new(y))
+}
+
+pub fn sub(x: Expr, y: Expr) -> Expr {
+    Expr::Sub(Box::new(x), Box::new(y))
+}
+
+pub fn mul(x: Expr, y: Expr) -> Expr {
+    Expr::Mul(Box::new(x), Box::new(y))
+}
+
+pub fn div(x: Expr, y: Expr) -> Expr {
+    Expr::Div(Box::new(x), Box::new(y))
+}
+
+pub fn pow(x: Expr, y: Expr) -> Expr {
+    Expr::Pow(Box::new(x), Box::new(y))
+}
+
+pub fn neg(x: Expr) -> Expr {
+    Expr::Neg(Box::new(x))
+}
+
+pub fn var(name: &str) -> Expr {
+    Expr::Var(name.to_owned())
+}
+
+pub fn sigma(var: &str, from: Expr, to: Expr, body: Expr) -> Expr {
+    Expr::Sigma {
+        var: var.to_owned(),
+        from: Box::new(from),
+        to: Box::new(to),
+        body: Box::new(body),
+    }
+}
+
+impl Expr {
+    /// Whether the value of this expression depends on the variable `name`.
+    /// Inside the body of a `Sigma` that binds `name`, it refers to the loop
+    /// variable instead.
+    pub fn depends_on(&self, name: &str) -> bool {
+        use Expr::*;
+        match self {
+            Const(_) => false,
+            Var(var) => var == name,
+            Add(a, b) | Sub(a, b) | Mul(a, b) | Div(a, b) | Pow(a, b) => {
+                a.depends_on(name) || b.depends_on(name)
+            }
+            Neg(a) => a.depends_on(name),
+            Summation(exprs) => exprs.iter().any(|expr| expr.depends_on(name)),
+            Sigma {
+                var,
+                from,
+                to,
+                body,
+            } => {
+                from.depends_on(name)
+                    || to.depends_on(name)
+                    || (var != name && body.depends_on(name))
+            }
+        }
+    }
+}
+
+/// The values of the variables used in an expression
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Env {
+    vars: HashMap<String, i64>,
+}
+
+impl Env {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builder-style version of [Env::set]
+    pub fn with(mut self, name: &str, value: i64) -> Self {
+        self.set(name, value);
+        self
+    }
+
+    pub fn set(&mut self, name: &str, value: i64) {
+        self.vars.insert(name.to_owned(), value);
+    }
+
+    pub fn get(&self, name: &str) -> Option<i64> {
+        self.vars.get(name).copied()
+    }
+}
+
+impl<'a> FromIterator<(&'a str, i64)> for Env {
+    fn from_iter<I: IntoIterator<Item = (&'a str, i64)>>(iter: I) -> Self {
+        let mut env = Env::new();
+        for (name, value) in iter {
+            env.set(name, value);
+        }
+        env
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvalError {
+    DivisionByZero,
+    /// The result of an operation does not fit in an `i64`
+    Overflow,
+    /// Integers can only be raised to non-negative powers
+    NegativeExponent,
+    UnknownVariable(String),
+    /// The `Sigma`s of an expression would add up more than [MAX_ITERATIONS]
+    /// terms
+    TooManyIterations,
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalError::DivisionByZero => write!(f, "division by zero"),
+            EvalError::Overflow => write!(f, "integer overflow"),
+            EvalError::NegativeExponent => write!(f, "negative exponent"),
+            EvalError::UnknownVariable(name) => write!(f, "unknown variable '{name}'"),
+            EvalError::TooManyIterations => {
+                write!(f, "sums of more than {MAX_ITERATIONS} terms")
+            }
+        }
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+/// How many terms the `Sigma`s in an expression can add up together, counting
+/// every time an inner `Sigma` is evaluated. A sum from 0 to `i64::MAX` is a
+/// valid expression, but evaluating it would never finish.
+pub const MAX_ITERATIONS: u64 = 10_000_000;
+
+/// Evaluate `expr`, looking up its variables in `env`
+pub fn eval(expr: &Expr, env: &Env) -> Result<i64, EvalError> {
+    let mut scope = Scope {
+        vars: Vec::new(),
+        iterations_left: MAX_ITERATIONS,
+    };
+    eval_scoped(expr, env, &mut scope)
+}
+
+struct Scope<'e> {
+    /// The variables bound by enclosing `Sigma`s. The innermost binding is at
+    /// the end, and shadows the others and the [Env].
+    vars: Vec<(&'e str, i64)>,
+    /// How many more terms `Sigma`s can add up
+    iterations_left: u64,
+}
+
+/// Evaluate `expr` within `scope`
+fn eval_scoped<'e>(expr: &'e Expr, env: &Env, scope: &mut Scope<'e>) -> Result<i64, EvalError> {
+    use Expr::*;
+    let mut eval = |expr: &'e Expr| eval_scoped(expr, env, scope);
+    let value = match expr {
+        Const(k) => Some(*k),
+        Var(name) => {
+            let bound = scope.vars.iter().rev().find(|(bound, _)| bound == name);
+            let value = bound.map(|(_, value)| *value).or_else(|| env.get(name));
+            return value.ok_or_else(|| EvalError::UnknownVariable(name.clone()));
+        }
+        Add(lhs, rhs) => eval(lhs)?.checked_add(eval(rhs)?),
+        Sub(lhs, rhs) => eval(lhs)?.checked_sub(eval(rhs)?),
+        Mul(lhs, rhs) => eval(lhs)?.checked_mul(eval(rhs)?),
+        Div(lhs, rhs) => return checked_div(eval(lhs)?, eval(rhs)?),
+        Pow(base, exponent) => return checked_pow(eval(base)?, eval(exponent)?),
+        Neg(expr) => eval(expr)?.checked_neg(),
+
+        Summation(exprs) => {
+            let mut acc: i64 = 0;
+            for e in exprs {
+                acc = acc.checked_add(eval(e)?).ok_or(EvalError::Overflow)?;
+            }
+            Some(acc)
+        }
+        Sigma {
+            var,
+            from,
+            to,
+            body,
+        } => {
+            let (from, to) = (eval(from)?, eval(to)?);
+            count_iterations(&mut scope.iterations_left, from, to)?;
+            let mut acc: i64 = 0;
+            for value in from..=to {
+                scope.vars.push((var, value));
+                let result = eval_scoped(body, env, scope);
+                scope.vars.pop();
+                acc = acc.checked_add(result?).ok_or(EvalError::Overflow)?;
+            }
+            Some(acc)
+        }
+    };
+    value.ok_or(EvalError::Overflow)
+}
+
+/// Take the terms of a sum from `from` to `to` out of the `left` of
+/// [MAX_ITERATIONS]. This is checked before the sum starts, rather than after
+/// it ran for a while.
+pub(crate) fn count_iterations(left: &mut u64, from: i64, to: i64) -> Result<(), EvalError> {
+    let terms = (i128::from(to) - i128::from(from) + 1).max(0);
+    if terms > i128::from(*left) {
+        return Err(EvalError::TooManyIterations);
+    }
+    *left -= terms as u64;
+    Ok(())
+}
+
+/// `lhs / rhs`, rounded towards zero
+pub(crate) fn checked_div(lhs: i64, rhs: i64) -> Result<i64, EvalError> {
+    if rhs == 0 {
+        return Err(EvalError::DivisionByZero);
+    }
+    lhs.checked_div(rhs).ok_or(EvalError::Overflow)
+}
+
+pub(crate) fn checked_pow(base: i64, exponent: i64) -> Result<i64, EvalError> {
+    if exponent < 0 {
+        return Err(EvalError::NegativeExponent);
+    }
+    let value = match (base, u32::try_from(exponent)) {
+        (_, Ok(exponent)) => base.checked_pow(exponent),
+        // the only bases that don't overflow for huge exponents
+        (0 | 1, Err(_)) => Some(base),
+        (-1, Err(_)) => Some(if exponent % 2 == 0 { 1 } else { -1 }),
+        (_, Err(_)) => None,
+    };
+    value.ok_or(EvalError::Overflow)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use Expr::{Const, Summation};
+
+    #[test]
+    fn test_cases() {
+        let env = Env::new().with("x", 42);
+        let x = || var("x");
+        assert_eq!(eval(&Const(5), &env), Ok(5));
+        assert_eq!(eval(&x(), &env), Ok(42));
+        assert_eq!(eval(&sub(x(), Const(5)), &env), Ok(37));
+        assert_eq!(eval(&sub(x(), x()), &env), Ok(0));
+        assert_eq!(eval(&add(sub(x(), Const(5)), Const(5)), &env), Ok(42));
+        assert_eq!(eval(&Summation(vec![x(), Const(1)]), &env), Ok(43));
+        assert_eq!(eval(&mul(x(), Const(2)), &env), Ok(84));
+        assert_eq!(eval(&div(x(), Const(5)), &env), Ok(8));
+        assert_eq!(eval(&pow(x(), Const(2)), &env), Ok(1764));
+        assert_eq!(eval(&neg(x()), &env), Ok(-42));
+    }
+
+    #[test]
+    fn test_variables() {
+        let env: Env = [("x", 3), ("y", 4)].into_iter().collect();
+        let expr = add(mul(var("x"), var("x")), mul(var("y"), var("y")));
+        assert_eq!(eval(&expr, &env), Ok(25));
+        assert_eq!(
+            eval(&add(var("x"), var("z")), &env),
+            Err(EvalError::UnknownVariable("z".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let env = Env::new();
+        assert_eq!(
+            eval(&div(Const(1), Const(0)), &env),
+            Err(EvalError::DivisionByZero)
+        );
+        assert_eq!(
+            eval(&add(Const(i64::MAX), Const(1)), &env),
+            Err(EvalError::Overflow)
+        );
+        assert_eq!(
+            eval(&div(Const(i64::MIN), Const(-1)), &env),
+            Err(EvalError::Overflow)
+        );
+        assert_eq!(eval(&neg(Const(i64::MIN)), &env), Err(EvalError::Overflow));
+        assert_eq!(
+            eval(&pow(Const(2), Const(63)), &env),
+            Err(EvalError::Overflow)
+        );
+        assert_eq!(
+            eval(&pow(Const(2), Const(-1)), &env),
+            Err(EvalError::NegativeExponent)
+        );
+        assert_eq!(eval(&pow(Const(-1), Const(i64::MAX)), &env), Ok(-1));
+        assert_eq!(
+            eval(&Summation(vec![Const(i64::MAX), Const(1)]), &env),
+            Err(EvalError::Overflow)
+        );
+    }
+
+    #[test]
+    fn test_sigma() {
+        let env = Env::new().with("x", 10);
+        // Sigma(Const(1), Const(5), Var) is equivalent to Summation(vec![Const(1), .., Const(5)])
+        let expr = sigma("i", Const(1), Const(5), var("i"));
+        assert_eq!(eval(&expr, &env), Ok(15));
+        // the bounds can refer to other variables
+        let expr = sigma("i", Const(1), var("x"), var("i"));
+        assert_eq!(eval(&expr, &env), Ok(55));
+        // an empty range sums to 0
+        let expr = sigma("i", Const(5), Const(1), var("i"));
+        assert_eq!(eval(&expr, &env), Ok(0));
+        // the loop variable shadows the environment, but only inside the body
+        let expr = add(sigma("x", Const(1), Const(3), var("x")), var("x"));
+        assert_eq!(eval(&expr, &env), Ok(16));
+        // nested sums: sum over i and j from 1 to 3 of i * j
+        let inner = sigma("j", Const(1), Const(3), mul(var("i"), var("j")));
+        let expr = sigma("i", Const(1), Const(3), inner);
+        assert_eq!(eval(&expr, &env), Ok(36));
+        // the loop variable is not visible outside
+        let expr = sigma("i", Const(1), var("i"), Const(1));
+        assert_eq!(
+            eval(&expr, &env),
+            Err(EvalError::UnknownVariable("i".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_sigma_limit() {
+        let env = Env::new();
+        let n = MAX_ITERATIONS as i64;
+        let expr = sigma("i", Const(1), Const(n), Const(1));
+        assert_eq!(eval(&expr, &env), Ok(n));
+        let expr = sigma("i", Const(0), Const(n), Const(1));
+        assert_eq!(eval(&expr, &env), Err(EvalError::TooManyIterations));
+        let expr = sigma("i", Const(i64::MIN), Const(i64::MAX), Const(0));
+        assert_eq!(eval(&expr, &env), Err(EvalError::TooManyIterations));
+        // the inner sum counts every time it is evaluated, and so does the
+        // outer one: 999 + 999 * 10_000 terms fit, 1_000 + 1_000 * 10_000 don't
+        let inner = sigma("j", Const(1), Const(10_000), Const(1));
+        let expr = sigma("i", Const(1), Const(999), inner.clone());
+        assert_eq!(eval(&expr, &env), Ok(9_990_000));
+        let expr = sigma("i", Const(1), Const(1_000), inner);
+        assert_eq!(eval(&expr, &env), Err(EvalError::TooManyIterations));
+        assert_eq!(
+            EvalError::TooManyIterations.to_string(),
+            "sums of more than 10000000 terms"
+        );
+    }
+}
@@ -0,0 +1,647 @@
+//! A recursive descent parser turning infix text like `x - 5 + 5` into an [Expr].
+//!
+//! The grammar, from lowest to highest precedence:
+//!
+//! ```text
+//! expr  := term (('+' | '-') term)*
+//! term  := unary (('*' | '/') unary)*
+//! unary := '-' unary | power
+//! power := atom ('^' unary)?
+//! atom  := NUMBER | NAME | '(' expr ')'
+//!        | 'sum' '(' (expr (',' expr)*)? ')'
+//!        | 'sigma' '(' NAME ',' expr ',' expr ',' expr ')'
+//! ```
+//!
+//! The binary operators `+`, `-`, `*` and `/` are left-associative, so `a - b - c`
+//! means `(a - b) - c`. Exponentiation is right-associative and binds tighter than
+//! unary minus: `-x^2^3` means `-(x^(2^3))`.
+//!
+//! Every level of parentheses, function call or unary minus is another level of
+//! recursion, so inputs nested deeper than [MAX_DEPTH] are rejected rather than
+//! overflowing the stack.
+
+use std::{fmt, iter::Peekable, str::CharIndices, str::FromStr};
+
+use crate::{add, div, mul, neg, pow, sub, Expr};
+
+/// How deeply expressions may be nested
+pub const MAX_DEPTH: usize = 256;
+
+/// Parse `input` into an [Expr]
+pub fn parse(input: &str) -> Result<Expr, ParseError> {
+    let mut parser = Parser::new(input)?;
+    let expr = parser.expr()?;
+    match parser.current {
+        Token {
+            kind: TokenKind::End,
+            ..
+        } => Ok(expr),
+        token => Err(token.unexpected("an operator or the end of the input")),
+    }
+}
+
+impl FromStr for Expr {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse(s)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// The kind of error that occurred
+    pub kind: ParseErrorKind,
+    /// The column (counted in characters, starting at 1) at which the error occurred
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// A character that is not part of any token
+    InvalidCharacter(char),
+    /// A token that is not allowed at this position
+    UnexpectedToken {
+        found: String,
+        expected: &'static str,
+    },
+    /// The input ended while more was expected
+    UnexpectedEnd {
+        expected: &'static str,
+    },
+    /// A number that does not fit in an `i64`
+    NumberTooLarge,
+    UnknownFunction(String),
+    /// Parentheses, function calls or unary minuses nested deeper than [MAX_DEPTH]
+    TooDeeplyNested,
+}
+
+impl ParseError {
+    /// Render the error below the offending input, with a marker pointing at the column:
+    ///
+    /// ```text
+    /// x + * 5
+    ///     ^ expected a number, variable or '(', found '*'
+    /// ```
+    pub fn highlight(&self, input: &str) -> String {
+        format!(
+            "{input}\n{:>width$} {}",
+            "^",
+            self.kind,
+            width = self.column
+        )
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at column {}", self.kind, self.column)
+    }
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseErrorKind::InvalidCharacter(c) => write!(f, "invalid character '{c}'"),
+            ParseErrorKind::UnexpectedToken { found, expected } => {
+                write!(f, "expected {expected}, found '{found}'")
+            }
+            ParseErrorKind::UnexpectedEnd { expected } => {
+                write!(f, "expected {expected}, found the end of the input")
+            }
+            ParseErrorKind::NumberTooLarge => write!(f, "number too large"),
+            ParseErrorKind::UnknownFunction(name) => write!(f, "unknown function '{name}'"),
+            ParseErrorKind::TooDeeplyNested => {
+                write!(f, "expression nested more than {MAX_DEPTH} levels deep")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TokenKind {
+    Number(u64),
+    Ident(String),
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Caret,
+    LParen,
+    RParen,
+    Comma,
+    End,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Token {
+    kind: TokenKind,
+    column: usize,
+}
+
+impl Token {
+    fn unexpected(self, expected: &'static str) -> ParseError {
+        let kind = match self.kind {
+            TokenKind::End => ParseErrorKind::UnexpectedEnd { expected },
+            kind => ParseErrorKind::UnexpectedToken {
+                found: kind.to_string(),
+                expected,
+            },
+        };
+        ParseError {
+            kind,
+            column: self.column,
+        }
+    }
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenKind::Number(n) => write!(f, "{n}"),
+            TokenKind::Ident(name) => write!(f, "{name}"),
+            TokenKind::Plus => write!(f, "+"),
+            TokenKind::Minus => write!(f, "-"),
+            TokenKind::Star => write!(f, "*"),
+            TokenKind::Slash => write!(f, "/"),
+            TokenKind::Caret => write!(f, "^"),
+            TokenKind::LParen => write!(f, "("),
+            TokenKind::RParen => write!(f, ")"),
+            TokenKind::Comma => write!(f, ","),
+            TokenKind::End => Ok(()),
+        }
+    }
+}
+
+/// Splits the input into tokens, keeping track of the column of each token
+struct Lexer<'a> {
+    input: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+    column: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(input: &'a str) -> Self {
+        Self {
+            input,
+            chars: input.char_indices().peekable(),
+            column: 0,
+        }
+    }
+
+    fn bump(&mut self) -> Option<(usize, char)> {
+        let next = self.chars.next();
+        if next.is_some() {
+            self.column += 1;
+        }
+        next
+    }
+
+    /// Consume characters as long as `pred` holds, returning the slice from
+    /// the already consumed character `first` at byte offset `start` onwards
+    fn take_while(&mut self, start: usize, first: char, pred: impl Fn(char) -> bool) -> &'a str {
+        let mut end = start + first.len_utf8();
+        while let Some(&(i, c)) = self.chars.peek() {
+            if !pred(c) {
+                break;
+            }
+            end = i + c.len_utf8();
+            self.bump();
+        }
+        &self.input[start..end]
+    }
+
+    fn next_token(&mut self) -> Result<Token, ParseError> {
+        while self.chars.peek().is_some_and(|&(_, c)| c.is_whitespace()) {
+            self.bump();
+        }
+
+        let Some((start, c)) = self.bump() else {
+            return Ok(Token {
+                kind: TokenKind::End,
+                column: self.column + 1,
+            });
+        };
+        let column = self.column;
+
+        let kind = match c {
+            '+' => TokenKind::Plus,
+            '-' => TokenKind::Minus,
+            '*' => TokenKind::Star,
+            '/' => TokenKind::Slash,
+            '^' => TokenKind::Caret,
+            '(' => TokenKind::LParen,
+            ')' => TokenKind::RParen,
+            ',' => TokenKind::Comma,
+            '0'..='9' => {
+                let digits = self.take_while(start, c, |c| c.is_ascii_digit());
+                let n = digits.parse().map_err(|_| ParseError {
+                    kind: ParseErrorKind::NumberTooLarge,
+                    column,
+                })?;
+                TokenKind::Number(n)
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let name = self.take_while(start, c, |c| c.is_alphanumeric() || c == '_');
+                TokenKind::Ident(name.to_owned())
+            }
+            c => {
+                return Err(ParseError {
+                    kind: ParseErrorKind::InvalidCharacter(c),
+                    column,
+                })
+            }
+        };
+
+        Ok(Token { kind, column })
+    }
+}
+
+struct Parser<'a> {
+    lexer: Lexer<'a>,
+    /// The token we are currently looking at
+    current: Token,
+    /// How many calls to [unary](Self::unary) we are in
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str) -> Result<Self, ParseError> {
+        let mut lexer = Lexer::new(input);
+        let current = lexer.next_token()?;
+        Ok(Self {
+            lexer,
+            current,
+            depth: 0,
+        })
+    }
+
+    /// Move on to the next token, returning the current one
+    fn advance(&mut self) -> Result<Token, ParseError> {
+        let next = self.lexer.next_token()?;
+        Ok(std::mem::replace(&mut self.current, next))
+    }
+
+    fn expect(&mut self, kind: TokenKind, expected: &'static str) -> Result<(), ParseError> {
+        if self.current.kind == kind {
+            self.advance()?;
+            Ok(())
+        } else {
+            Err(self.current.clone().unexpected(expected))
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, ParseError> {
+        let mut lhs = self.term()?;
+        loop {
+            let op = match self.current.kind {
+                TokenKind::Plus => add,
+                TokenKind::Minus => sub,
+                _ => return Ok(lhs),
+            };
+            self.advance()?;
+            lhs = op(lhs, self.term()?);
+        }
+    }
+
+    fn term(&mut self) -> Result<Expr, ParseError> {
+        let mut lhs = self.unary()?;
+        loop {
+            let op = match self.current.kind {
+                TokenKind::Star => mul,
+                TokenKind::Slash => div,
+                _ => return Ok(lhs),
+            };
+            self.advance()?;
+            lhs = op(lhs, self.unary()?);
+        }
+    }
+
+    /// Every recursion goes through here, so this is where the depth is limited
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        if self.depth == MAX_DEPTH {
+            return Err(ParseError {
+                kind: ParseErrorKind::TooDeeplyNested,
+                column: self.current.column,
+            });
+        }
+        self.depth += 1;
+        let expr = self.unary_or_power();
+        self.depth -= 1;
+        expr
+    }
+
+    fn unary_or_power(&mut self) -> Result<Expr, ParseError> {
+        if self.current.kind != TokenKind::Minus {
+            return self.power();
+        }
+        self.advance()?;
+
+        // A minus directly in front of a number is part of the constant, which
+        // is also the only way to write `i64::MIN`. Unless the number is the
+        // base of a power, as `-2^2` means `-(2^2)`.
+        if let TokenKind::Number(n) = self.current.kind {
+            let column = self.current.column;
+            self.advance()?;
+            let too_large = ParseError {
+                kind: ParseErrorKind::NumberTooLarge,
+                column,
+            };
+            if self.current.kind == TokenKind::Caret {
+                let base = i64::try_from(n).map_err(|_| too_large)?;
+                return Ok(neg(self.exponent(Expr::Const(base))?));
+            }
+            return 0i64
+                .checked_sub_unsigned(n)
+                .map(Expr::Const)
+                .ok_or(too_large);
+        }
+
+        Ok(neg(self.unary()?))
+    }
+
+    fn power(&mut self) -> Result<Expr, ParseError> {
+        let base = self.atom()?;
+        self.exponent(base)
+    }
+
+    /// Parse the optional `^ exponent` following `base`
+    fn exponent(&mut self, base: Expr) -> Result<Expr, ParseError> {
+        if self.current.kind != TokenKind::Caret {
+            return Ok(base);
+        }
+        self.advance()?;
+        Ok(pow(base, self.unary()?))
+    }
+
+    fn atom(&mut self) -> Result<Expr, ParseError> {
+        const EXPECTED: &str = "a number, variable or '('";
+
+        let token = self.advance()?;
+        match token.kind {
+            TokenKind::Number(n) => match i64::try_from(n) {
+                Ok(k) => Ok(Expr::Const(k)),
+                Err(_) => Err(ParseError {
+                    kind: ParseErrorKind::NumberTooLarge,
+                    column: token.column,
+                }),
+            },
+            TokenKind::LParen => {
+                let expr = self.expr()?;
+                self.expect(TokenKind::RParen, "')'")?;
+                Ok(expr)
+            }
+            TokenKind::Ident(name) if self.current.kind == TokenKind::LParen => {
+                self.advance()?;
+                match name.as_str() {
+                    "sum" => Ok(Expr::Summation(self.arguments()?)),
+                    "sigma" => self.sigma(),
+                    _ => Err(ParseError {
+                        kind: ParseErrorKind::UnknownFunction(name),
+                        column: token.column,
+                    }),
+                }
+            }
+            TokenKind::Ident(name) => Ok(Expr::Var(name)),
+            _ => Err(token.unexpected(EXPECTED)),
+        }
+    }
+
+    /// Parse the arguments of `sigma(var, from, to, body)`, after the opening parenthesis
+    fn sigma(&mut self) -> Result<Expr, ParseError> {
+        let token = self.advance()?;
+        let TokenKind::Ident(var) = token.kind else {
+            return Err(token.unexpected("a variable name"));
+        };
+        self.expect(TokenKind::Comma, "','")?;
+        let from = self.expr()?;
+        self.expect(TokenKind::Comma, "','")?;
+        let to = self.expr()?;
+        self.expect(TokenKind::Comma, "','")?;
+        let body = self.expr()?;
+        self.expect(TokenKind::RParen, "')'")?;
+        Ok(Expr::Sigma {
+            var,
+            from: Box::new(from),
+            to: Box::new(to),
+            body: Box::new(body),
+        })
+    }
+
+    /// Parse a comma separated argument list, after the opening parenthesis
+    fn arguments(&mut self) -> Result<Vec<Expr>, ParseError> {
+        let mut args = Vec::new();
+        if self.current.kind == TokenKind::RParen {
+            self.advance()?;
+            return Ok(args);
+        }
+        loop {
+            args.push(self.expr()?);
+            match self.current.kind {
+                TokenKind::Comma => {
+                    self.advance()?;
+                }
+                TokenKind::RParen => {
+                    self.advance()?;
+                    return Ok(args);
+                }
+                _ => return Err(self.current.clone().unexpected("',' or ')'")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{sigma, var};
+    use Expr::{Const, Summation};
+
+    fn error(input: &str) -> (ParseErrorKind, usize) {
+        let err = parse(input).unwrap_err();
+        (err.kind, err.column)
+    }
+
+    #[test]
+    fn test_precedence() {
+        assert_eq!(
+            parse("x - 5 + 5"),
+            Ok(add(sub(var("x"), Const(5)), Const(5)))
+        );
+        assert_eq!(
+            parse("1 + 2 * x"),
+            Ok(add(Const(1), mul(Const(2), var("x"))))
+        );
+        assert_eq!(
+            parse("(1 + 2) * x"),
+            Ok(mul(add(Const(1), Const(2)), var("x")))
+        );
+        assert_eq!(
+            parse("8 / 4 / 2"),
+            Ok(div(div(Const(8), Const(4)), Const(2)))
+        );
+        assert_eq!(
+            parse("8 - (4 - 2)"),
+            Ok(sub(Const(8), sub(Const(4), Const(2))))
+        );
+    }
+
+    #[test]
+    fn test_unary_minus() {
+        assert_eq!(parse("-5"), Ok(Const(-5)));
+        assert_eq!(parse("x - -5"), Ok(sub(var("x"), Const(-5))));
+        assert_eq!(parse("-x * 2"), Ok(mul(neg(var("x")), Const(2))));
+        assert_eq!(parse("--x"), Ok(neg(neg(var("x")))));
+        assert_eq!(parse("-(5)"), Ok(neg(Const(5))));
+        assert_eq!(parse("-9223372036854775808"), Ok(Const(i64::MIN)));
+    }
+
+    #[test]
+    fn test_power() {
+        assert_eq!(parse("x^2"), Ok(pow(var("x"), Const(2))));
+        assert_eq!(parse("2^x^3"), Ok(pow(Const(2), pow(var("x"), Const(3)))));
+        assert_eq!(parse("-x^2"), Ok(neg(pow(var("x"), Const(2)))));
+        assert_eq!(parse("-2^2"), Ok(neg(pow(Const(2), Const(2)))));
+        assert_eq!(parse("(-2)^2"), Ok(pow(Const(-2), Const(2))));
+        assert_eq!(parse("x^-1"), Ok(pow(var("x"), Const(-1))));
+        assert_eq!(parse("2 * x^2"), Ok(mul(Const(2), pow(var("x"), Const(2)))));
+    }
+
+    #[test]
+    fn test_variables() {
+        assert_eq!(
+            parse("width * height_2"),
+            Ok(mul(var("width"), var("height_2")))
+        );
+        // without parentheses, `sum` is an ordinary variable
+        assert_eq!(parse("sum + 1"), Ok(add(var("sum"), Const(1))));
+    }
+
+    #[test]
+    fn test_sigma() {
+        assert_eq!(
+            parse("sigma(i, 1, n, i * x)"),
+            Ok(sigma("i", Const(1), var("n"), mul(var("i"), var("x"))))
+        );
+    }
+
+    #[test]
+    fn test_sum() {
+        assert_eq!(parse("sum()"), Ok(Summation(vec![])));
+        assert_eq!(
+            parse("sum(x, 1, 2 * x)"),
+            Ok(Summation(vec![var("x"), Const(1), mul(Const(2), var("x"))]))
+        );
+        assert_eq!(
+            parse("1 + sum(x)"),
+            Ok(add(Const(1), Summation(vec![var("x")])))
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            error("x + * 5"),
+            (
+                ParseErrorKind::UnexpectedToken {
+                    found: "*".to_owned(),
+                    expected: "a number, variable or '('"
+                },
+                5
+            )
+        );
+        assert_eq!(
+            error("(x + 1"),
+            (ParseErrorKind::UnexpectedEnd { expected: "')'" }, 7)
+        );
+        assert_eq!(error("x # 1"), (ParseErrorKind::InvalidCharacter('#'), 3));
+        assert_eq!(
+            error("sigma(1, 1, 2, 3)"),
+            (
+                ParseErrorKind::UnexpectedToken {
+                    found: "1".to_owned(),
+                    expected: "a variable name"
+                },
+                7
+            )
+        );
+        assert_eq!(
+            error("sigma(i, 1, 2)"),
+            (
+                ParseErrorKind::UnexpectedToken {
+                    found: ")".to_owned(),
+                    expected: "','"
+                },
+                14
+            )
+        );
+        assert_eq!(
+            error("max(1, 2)"),
+            (ParseErrorKind::UnknownFunction("max".to_owned()), 1)
+        );
+        assert_eq!(
+            error("sum(1 2)"),
+            (
+                ParseErrorKind::UnexpectedToken {
+                    found: "2".to_owned(),
+                    expected: "',' or ')'"
+                },
+                7
+            )
+        );
+        assert_eq!(
+            error("9223372036854775808"),
+            (ParseErrorKind::NumberTooLarge, 1)
+        );
+        assert_eq!(
+            error("1 2"),
+            (
+                ParseErrorKind::UnexpectedToken {
+                    found: "2".to_owned(),
+                    expected: "an operator or the end of the input"
+                },
+                3
+            )
+        );
+    }
+
+    #[test]
+    fn test_depth() {
+        let nested = |depth| format!("{}x{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(parse(&nested(MAX_DEPTH - 1)).is_ok());
+        assert_eq!(
+            error(&nested(MAX_DEPTH)),
+            (ParseErrorKind::TooDeeplyNested, MAX_DEPTH + 1)
+        );
+        // deep enough to overflow the stack without the limit
+        assert_eq!(error(&nested(100_000)).0, ParseErrorKind::TooDeeplyNested);
+        assert_eq!(
+            error(&"-".repeat(100_000)).0,
+            ParseErrorKind::TooDeeplyNested
+        );
+        assert_eq!(
+            error(&"sum(".repeat(100_000)).0,
+            ParseErrorKind::TooDeeplyNested
+        );
+        assert_eq!(
+            error(&"x^".repeat(100_000)).0,
+            ParseErrorKind::TooDeeplyNested
+        );
+    }
+
+    #[test]
+    fn test_highlight() {
+        let input = "x + * 5";
+        let err = parse(input).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "expected a number, variable or '(', found '*' at column 5"
+        );
+        assert_eq!(
+            err.highlight(input),
+            "x + * 5\n    ^ expected a number, variable or '(', found '*'"
+        );
+    }
+}
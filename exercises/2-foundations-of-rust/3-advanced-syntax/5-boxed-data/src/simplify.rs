@@ -0,0 +1,337 @@
+//! Algebraic simplification of expressions.
+//!
+//! The rules assume that every subexpression can be evaluated without errors:
+//! `1 / 0 - 1 / 0` is simplified to `0`, even though evaluating the original
+//! expression fails with a division by zero. Whenever the original expression
+//! does evaluate successfully, the simplified expression evaluates to the same value.
+
+use crate::{eval, mul, neg, pow, Env, Expr};
+
+/// Simplify `expr` by folding constants, eliminating identities like `x + 0`
+/// and `x * 1`, flattening nested `Summation`s and putting the operands of
+/// commutative operators in a canonical order.
+pub fn simplify(expr: Expr) -> Expr {
+    let mut expr = expr;
+    loop {
+        let next = simplify_once(expr.clone());
+        if next == expr {
+            return next;
+        }
+        expr = next;
+    }
+}
+
+/// Simplify bottom-up in a single pass. Applying a rule may enable others
+/// higher up the tree, so [simplify] repeats this until nothing changes.
+fn simplify_once(expr: Expr) -> Expr {
+    use Expr::*;
+    match expr {
+        Const(_) | Var(_) => expr,
+        Add(a, b) => simplify_add(simplify_once(*a), simplify_once(*b)),
+        Sub(a, b) => simplify_sub(simplify_once(*a), simplify_once(*b)),
+        Mul(a, b) => simplify_mul(simplify_once(*a), simplify_once(*b)),
+        Div(a, b) => simplify_div(simplify_once(*a), simplify_once(*b)),
+        Pow(a, b) => simplify_pow(simplify_once(*a), simplify_once(*b)),
+        Neg(a) => simplify_neg(simplify_once(*a)),
+        Summation(exprs) => simplify_summation(exprs.into_iter().map(simplify_once).collect()),
+        Sigma {
+            var,
+            from,
+            to,
+            body,
+        } => simplify_sigma(
+            var,
+            simplify_once(*from),
+            simplify_once(*to),
+            simplify_once(*body),
+        ),
+    }
+}
+
+/// Evaluate an expression consisting of constants only. Expressions that fail
+/// to evaluate are left alone, so the error is preserved.
+fn fold(expr: Expr) -> Expr {
+    match eval(&expr, &Env::new()) {
+        Ok(k) => Expr::Const(k),
+        Err(_) => expr,
+    }
+}
+
+/// Build `op(a, b)`, swapping the operands if needed to put them in canonical order
+fn commutative(op: fn(Box<Expr>, Box<Expr>) -> Expr, a: Expr, b: Expr) -> Expr {
+    if a <= b {
+        op(Box::new(a), Box::new(b))
+    } else {
+        op(Box::new(b), Box::new(a))
+    }
+}
+
+fn simplify_add(a: Expr, b: Expr) -> Expr {
+    use Expr::*;
+    match (a, b) {
+        (a @ Const(_), b @ Const(_)) => fold(Add(Box::new(a), Box::new(b))),
+        (Const(0), e) | (e, Const(0)) => e,
+        (e, Neg(b)) | (Neg(b), e) => Sub(Box::new(e), b),
+        (a, b) if a == b => mul(Const(2), a),
+        (a, b) => commutative(Add, a, b),
+    }
+}
+
+fn simplify_sub(a: Expr, b: Expr) -> Expr {
+    use Expr::*;
+    match (a, b) {
+        (a @ Const(_), b @ Const(_)) => fold(Sub(Box::new(a), Box::new(b))),
+        (e, Const(0)) => e,
+        (Const(0), e) => simplify_neg(e),
+        (a, b) if a == b => Const(0),
+        (e, Neg(b)) => Add(Box::new(e), b),
+        (a, b) => Sub(Box::new(a), Box::new(b)),
+    }
+}
+
+fn simplify_mul(a: Expr, b: Expr) -> Expr {
+    use Expr::*;
+    match (a, b) {
+        (a @ Const(_), b @ Const(_)) => fold(Mul(Box::new(a), Box::new(b))),
+        (Const(0), _) | (_, Const(0)) => Const(0),
+        (Const(1), e) | (e, Const(1)) => e,
+        (Const(-1), e) | (e, Const(-1)) => simplify_neg(e),
+        (Neg(a), Neg(b)) => commutative(Mul, *a, *b),
+        // 2 * (3 * x) => 6 * x
+        (Const(x), Mul(c, e)) if matches!(*c, Const(_)) => match fold(mul(Const(x), *c)) {
+            k @ Const(_) => Mul(Box::new(k), e),
+            c => Mul(Box::new(Const(x)), Box::new(Mul(Box::new(c), e))),
+        },
+        // x * x => x^2, x * x^2 => x^3 and x^2 * x^3 => x^5
+        (a, b) => match (as_power(&a), as_power(&b)) {
+            (Some((base_a, n)), Some((base_b, m))) if base_a == base_b => match n.checked_add(m) {
+                Some(sum) => pow(base_a.clone(), Const(sum)),
+                None => commutative(Mul, a, b),
+            },
+            _ => commutative(Mul, a, b),
+        },
+    }
+}
+
+/// View `expr` as `base^n` with a constant, non-negative `n`, where `x` is `x^1`
+fn as_power(expr: &Expr) -> Option<(&Expr, i64)> {
+    match expr {
+        Expr::Const(_) => None,
+        Expr::Pow(base, exponent) => match **exponent {
+            Expr::Const(n) if n >= 0 => Some((base, n)),
+            _ => None,
+        },
+        _ => Some((expr, 1)),
+    }
+}
+
+fn simplify_div(a: Expr, b: Expr) -> Expr {
+    use Expr::*;
+    match (a, b) {
+        (a @ Const(_), b @ Const(_)) => fold(Div(Box::new(a), Box::new(b))),
+        (e, Const(1)) => e,
+        (e, Const(-1)) => simplify_neg(e),
+        (a, b) => Div(Box::new(a), Box::new(b)),
+    }
+}
+
+fn simplify_pow(a: Expr, b: Expr) -> Expr {
+    use Expr::*;
+    match (a, b) {
+        (a @ Const(_), b @ Const(_)) => fold(Pow(Box::new(a), Box::new(b))),
+        (_, Const(0)) => Const(1),
+        (e, Const(1)) => e,
+        // (x^2)^3 => x^6
+        (Pow(base, n), Const(m)) if m >= 0 => match *n {
+            Const(n) if n >= 0 => match n.checked_mul(m) {
+                Some(k) => Pow(base, Box::new(Const(k))),
+                None => pow(Pow(base, Box::new(Const(n))), Const(m)),
+            },
+            n => pow(Pow(base, Box::new(n)), Const(m)),
+        },
+        (a, b) => Pow(Box::new(a), Box::new(b)),
+    }
+}
+
+fn simplify_neg(e: Expr) -> Expr {
+    use Expr::*;
+    match e {
+        Const(k) => fold(neg(Const(k))),
+        Neg(e) => *e,
+        // -(a - b) => b - a
+        Sub(a, b) => Sub(b, a),
+        e => neg(e),
+    }
+}
+
+fn simplify_summation(exprs: Vec<Expr>) -> Expr {
+    let mut terms = Vec::with_capacity(exprs.len());
+    let mut constant: i64 = 0;
+    for expr in exprs {
+        match expr {
+            // sum(a, sum(b, c)) => sum(a, b, c)
+            Expr::Summation(inner) => terms.extend(inner),
+            expr => terms.push(expr),
+        }
+    }
+    // fold all constants into a single one, unless that overflows
+    terms.retain(|term| match term {
+        Expr::Const(k) => match constant.checked_add(*k) {
+            Some(sum) => {
+                constant = sum;
+                false
+            }
+            None => true,
+        },
+        _ => true,
+    });
+    if constant != 0 {
+        terms.push(Expr::Const(constant));
+    }
+    terms.sort();
+
+    match terms.len() {
+        0 => Expr::Const(0),
+        1 => terms.pop().unwrap(),
+        _ => Expr::Summation(terms),
+    }
+}
+
+fn simplify_sigma(var: String, from: Expr, to: Expr, body: Expr) -> Expr {
+    use Expr::*;
+    match (from, to) {
+        (Const(from), Const(to)) if from > to => Const(0),
+        // the body is the same in every iteration: sigma(i, 1, 3, x) => 3 * x
+        (Const(from), Const(to)) if !body.depends_on(&var) => {
+            match to.checked_sub(from).and_then(|n| n.checked_add(1)) {
+                Some(count) => simplify_mul(Const(count), body),
+                None => Expr::Sigma {
+                    var,
+                    from: Box::new(Const(from)),
+                    to: Box::new(Const(to)),
+                    body: Box::new(body),
+                },
+            }
+        }
+        (from, to) => Expr::Sigma {
+            var,
+            from: Box::new(from),
+            to: Box::new(to),
+            body: Box::new(body),
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::{add, div, parse, sigma, sub, testutil::random_expr, var};
+    use Expr::{Const, Summation};
+
+    fn simplified(input: &str) -> String {
+        simplify(parse(input).unwrap()).to_string()
+    }
+
+    #[test]
+    fn test_constant_folding() {
+        assert_eq!(simplified("1 + 2 * 3"), "7");
+        assert_eq!(simplified("x + 2 * 3"), "6 + x");
+        assert_eq!(simplified("2^10 - 24"), "1000");
+        assert_eq!(simplified("sum(1, x, 2, 3)"), "sum(6, x)");
+        // errors are preserved
+        assert_eq!(simplified("x + 1 / 0"), "1 / 0 + x");
+        assert_eq!(
+            simplified("9223372036854775807 + 1"),
+            "9223372036854775807 + 1"
+        );
+    }
+
+    #[test]
+    fn test_identities() {
+        assert_eq!(simplified("x + 0"), "x");
+        assert_eq!(simplified("0 + x"), "x");
+        assert_eq!(simplified("x - 0"), "x");
+        assert_eq!(simplified("0 - x"), "-x");
+        assert_eq!(simplified("x * 1"), "x");
+        assert_eq!(simplified("1 * x"), "x");
+        assert_eq!(simplified("x * 0"), "0");
+        assert_eq!(simplified("x / 1"), "x");
+        assert_eq!(simplified("x^1"), "x");
+        assert_eq!(simplified("x^0"), "1");
+        assert_eq!(simplified("x - x"), "0");
+        assert_eq!(simplified("(x + y) - (y + x)"), "0");
+        assert_eq!(simplified("--x"), "x");
+        assert_eq!(simplified("x - -y"), "x + y");
+        assert_eq!(simplified("-(x - y)"), "y - x");
+    }
+
+    #[test]
+    fn test_combining() {
+        assert_eq!(simplified("x + x"), "2 * x");
+        assert_eq!(simplified("2 * (3 * x)"), "6 * x");
+        assert_eq!(simplified("x * x"), "x^2");
+        assert_eq!(simplified("x^2 * x^3"), "x^5");
+        assert_eq!(simplified("(x^2)^3"), "x^6");
+        assert_eq!(simplified("-x * -y"), "x * y");
+    }
+
+    #[test]
+    fn test_flatten_summation() {
+        assert_eq!(
+            simplify(Summation(vec![
+                var("x"),
+                Summation(vec![var("y"), Summation(vec![var("z"), Const(0)])]),
+            ])),
+            Summation(vec![var("x"), var("y"), var("z")])
+        );
+        assert_eq!(simplified("sum()"), "0");
+        assert_eq!(simplified("sum(x)"), "x");
+        assert_eq!(simplified("sum(x, sum(1, -1))"), "x");
+    }
+
+    #[test]
+    fn test_canonical_order() {
+        assert_eq!(
+            simplify(add(var("y"), var("x"))),
+            simplify(add(var("x"), var("y")))
+        );
+        assert_eq!(simplified("y * x * 2"), simplified("2 * (x * y)"));
+        assert_eq!(simplified("sum(z, y, x)"), "sum(x, y, z)");
+        // subtraction and division are not commutative
+        assert_eq!(simplify(sub(var("y"), var("x"))), sub(var("y"), var("x")));
+        assert_eq!(simplify(div(var("y"), var("x"))), div(var("y"), var("x")));
+    }
+
+    #[test]
+    fn test_sigma() {
+        assert_eq!(simplified("sigma(i, 5, 1, i)"), "0");
+        assert_eq!(simplified("sigma(i, 1, 10, x)"), "10 * x");
+        assert_eq!(simplified("sigma(i, 1, n, i * 1)"), "sigma(i, 1, n, i)");
+        assert_eq!(simplified("sigma(i, 1, 3, i)"), "sigma(i, 1, 3, i)");
+        assert_eq!(
+            simplify(sigma("i", Const(1), Const(3), var("x"))),
+            mul(Const(3), var("x"))
+        );
+    }
+
+    #[test]
+    fn test_preserves_value() {
+        let mut rng = StdRng::seed_from_u64(0x51);
+        for _ in 0..5000 {
+            let expr = random_expr(&mut rng, 4, true);
+            let simplified = simplify(expr.clone());
+            for x in -3..=3 {
+                let env = Env::new().with("x", x).with("y", 2);
+                if let Ok(value) = eval(&expr, &env) {
+                    assert_eq!(
+                        eval(&simplified, &env),
+                        Ok(value),
+                        "{expr} simplified to {simplified}, x = {x}"
+                    );
+                }
+            }
+        }
+    }
+}
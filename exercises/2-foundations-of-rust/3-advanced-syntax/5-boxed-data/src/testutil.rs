@@ -0,0 +1,43 @@
+//! Random expressions for property tests
+
+use rand::{rngs::StdRng, Rng};
+
+use crate::Expr;
+
+/// Generate a random expression of at most the given depth, that can be
+/// evaluated quickly and with few errors: constants are small, exponents are
+/// constants between 0 and 3, and `Sigma` bounds are constants between 0 and 3.
+/// It uses the variables `x` and `y`, and `i` inside the body of a `Sigma`.
+pub(crate) fn random_expr(rng: &mut StdRng, depth: u32, division: bool) -> Expr {
+    random_expr_in(rng, depth, division, false)
+}
+
+fn random_expr_in(rng: &mut StdRng, depth: u32, division: bool, in_sigma: bool) -> Expr {
+    let leaf = depth == 0 || rng.gen_ratio(1, 4);
+    if leaf {
+        return match rng.gen_range(0..5) {
+            0 | 1 => Expr::Var("x".to_owned()),
+            2 => Expr::Var("y".to_owned()),
+            3 if in_sigma => Expr::Var("i".to_owned()),
+            _ => Expr::Const(rng.gen_range(-5..=5)),
+        };
+    }
+    let op = rng.gen_range(0..8);
+    let mut operand = || Box::new(random_expr_in(rng, depth - 1, division, in_sigma));
+    match op {
+        0 => Expr::Add(operand(), operand()),
+        1 => Expr::Sub(operand(), operand()),
+        2 => Expr::Mul(operand(), operand()),
+        3 if division => Expr::Div(operand(), operand()),
+        3 => Expr::Mul(operand(), operand()),
+        4 => Expr::Pow(operand(), Box::new(Expr::Const(rng.gen_range(0..=3)))),
+        5 => Expr::Neg(operand()),
+        6 => Expr::Sigma {
+            var: "i".to_owned(),
+            from: Box::new(Expr::Const(rng.gen_range(0..=3))),
+            to: Box::new(Expr::Const(rng.gen_range(0..=3))),
+            body: Box::new(random_expr_in(rng, depth - 1, division, true)),
+        },
+        _ => Expr::Summation((0..depth % 4).map(|_| *operand()).collect()),
+    }
+}
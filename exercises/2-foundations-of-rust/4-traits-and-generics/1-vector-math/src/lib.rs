@@ -1,54 +1,155 @@
-use std::ops::{Add, Mul};
+//! Small vectors and matrices, generic over the kind of number they hold.
+//!
+//! The crate is `no_std`. Square roots, sines and the like come from the
+//! standard library with the default `std` feature, or from the `libm` crate
+//! with the `libm` feature, for targets without an operating system.
+//!
+//! With the `serde` feature, vectors and matrices can be serialized, and with
+//! the `bytemuck` feature, they can be cast to and from bytes.
 
-#[derive(Copy, Clone)]
-struct Vec2D {
-    x: i32,
-    y: i32,
-}
+#![no_std]
 
-impl Add for Vec2D {
-    type Output = Self;
+#[cfg(any(feature = "std", test))]
+extern crate std;
 
-    fn add(self, rhs: Self) -> Self::Output {
-        todo!();
-    }
-}
+#[cfg(not(any(feature = "std", feature = "libm")))]
+compile_error!("vector-math needs the `std` or the `libm` feature for its float functions");
+
+mod matrix;
+mod num;
+mod vector;
+
+pub use matrix::{Mat2, Mat3, Mat4};
+pub use num::{Float, Number, Signed};
+pub use vector::{Vec2, Vec3};
 
 #[cfg(test)]
 mod test {
-    use crate::Vec2D;
+    use crate::{Vec2, Vec3};
 
     #[test]
     fn integer_addition() {
-        let a = Vec2D { x: 1, y: 2 };
-        let b = Vec2D { x: 3, y: 4 };
+        let a = Vec2 { x: 1, y: 2 };
+        let b = Vec2 { x: 3, y: 4 };
         let res = a + b;
         assert_eq!(res.x, a.x + b.x);
         assert_eq!(res.y, a.y + b.y);
     }
 
-    // #[test]
-    // fn integer_dot_product() {
-    //     let a = Vec2D { x: 1, y: 2 };
-    //     let b = Vec2D { x: 3, y: 4 };
-    //     let res = a * b;
-    //     assert_eq!(res, a.x * b.x + a.y * b.y);
-    // }
-
-    // #[test]
-    // fn float_addition() {
-    //     let a = Vec2D { x: 1.5, y: 2.5 };
-    //     let b = Vec2D { x: 3.7, y: 4.2 };
-    //     let res = a + b;
-    //     assert_eq!(res.x, a.x + b.x);
-    //     assert_eq!(res.y, a.y + b.y);
-    // }
-
-    // #[test]
-    // fn float_dot_product() {
-    //     let a = Vec2D { x: 1.5, y: 2.5 };
-    //     let b = Vec2D { x: 3.7, y: 4.2 };
-    //     let res = a * b;
-    //     assert_eq!(res, a.x * b.x + a.y * b.y);
-    // }
+    #[test]
+    fn integer_dot_product() {
+        let a = Vec2 { x: 1, y: 2 };
+        let b = Vec2 { x: 3, y: 4 };
+        let res = a * b;
+        assert_eq!(res, a.x * b.x + a.y * b.y);
+    }
+
+    #[test]
+    fn float_addition() {
+        let a = Vec2 { x: 1.5, y: 2.5 };
+        let b = Vec2 { x: 3.7, y: 4.2 };
+        let res = a + b;
+        assert_eq!(res.x, a.x + b.x);
+        assert_eq!(res.y, a.y + b.y);
+    }
+
+    #[test]
+    fn float_dot_product() {
+        let a = Vec2 { x: 1.5, y: 2.5 };
+        let b = Vec2 { x: 3.7, y: 4.2 };
+        let res = a * b;
+        assert_eq!(res, a.x * b.x + a.y * b.y);
+    }
+
+    #[test]
+    fn operators() {
+        let mut a = Vec3::new(1, -2, 3);
+        let b = Vec3::new(4, 5, 6);
+        assert_eq!(a - b, Vec3::new(-3, -7, -3));
+        assert_eq!(-a, Vec3::new(-1, 2, -3));
+        assert_eq!(a * 2, Vec3::new(2, -4, 6));
+        assert_eq!(2 * a, a * 2);
+        assert_eq!(b / 2, Vec3::new(2, 2, 3));
+
+        a += b;
+        assert_eq!(a, Vec3::new(5, 3, 9));
+        a -= b;
+        a *= 3;
+        assert_eq!(a, Vec3::new(3, -6, 9));
+        a /= 3;
+        assert_eq!(a, Vec3::new(1, -2, 3));
+
+        let sum: Vec2<u8> = [(1, 2), (3, 4), (5, 6)].into_iter().map(Vec2::from).sum();
+        assert_eq!(sum, Vec2::new(9, 12));
+    }
+
+    #[test]
+    fn cross_product() {
+        assert_eq!(Vec3::<i32>::X.cross(Vec3::Y), Vec3::Z);
+        assert_eq!(Vec3::<i32>::Y.cross(Vec3::X), -Vec3::Z);
+        let (a, b) = (Vec3::new(2, 3, 4), Vec3::new(5, 6, 7));
+        assert_eq!(a.cross(b), Vec3::new(-3, 6, -3));
+        assert_eq!(a.cross(b) * a, 0);
+
+        assert_eq!(Vec2::<i32>::X.cross(Vec2::Y), 1);
+        assert_eq!(Vec2::new(3, 1).perp(), Vec2::new(-1, 3));
+        assert_eq!(Vec2::new(3, 1).cross(Vec2::new(-1, 3)), 10);
+    }
+
+    #[test]
+    fn length() {
+        let v = Vec2::new(3.0, 4.0);
+        assert_eq!(Vec2::new(3, 4).length_squared(), 25);
+        assert_eq!(v.length(), 5.0);
+        assert_eq!(v.distance(Vec2::ZERO), 5.0);
+        assert_eq!(v.normalize(), Vec2::new(0.6, 0.8));
+        assert!((Vec3::new(1.0f32, 2.0, 3.0).normalize().length() - 1.0).abs() < 1e-6);
+
+        assert_eq!(Vec3::<f64>::ZERO.try_normalize(), None);
+        assert!(Vec2::<f64>::ZERO.normalize().x.is_nan());
+    }
+
+    #[test]
+    fn angle() {
+        use core::f64::consts::{FRAC_PI_2, PI};
+
+        assert_eq!(Vec2::new(1.0, 0.0).angle(), 0.0);
+        assert_eq!(Vec2::new(0.0, 2.0).angle(), FRAC_PI_2);
+        assert_eq!(Vec2::new(-1.0, 0.0).angle(), PI);
+        assert_eq!(Vec2::new(0.0, -1.0).angle(), -FRAC_PI_2);
+        let v = Vec2::<f64>::from_angle(2.0);
+        assert!((v.length() - 1.0).abs() < 1e-12);
+        assert!((v.angle() - 2.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn conversions() {
+        let v: Vec2<i64> = (1, 2).into();
+        assert_eq!(v, Vec2::from([1, 2]));
+        assert_eq!(<(i64, i64)>::from(v), (1, 2));
+        let v = Vec3::from([1.0, 2.0, 3.0]);
+        assert_eq!(<[f64; 3]>::from(v), [1.0, 2.0, 3.0]);
+        assert_eq!(<(f64, f64, f64)>::from(v), (1.0, 2.0, 3.0));
+        assert_eq!(v.map(|c| c as i32), Vec3::new(1, 2, 3));
+        assert_eq!(v.truncate().extend(0.0), Vec3::new(1.0, 2.0, 0.0));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        let v = Vec3::new(1, 2, 3);
+        let json = serde_json::to_string(&v).unwrap();
+        assert_eq!(json, r#"{"x":1,"y":2,"z":3}"#);
+        assert_eq!(serde_json::from_str::<Vec3<i32>>(&json).unwrap(), v);
+    }
+
+    #[cfg(feature = "bytemuck")]
+    #[test]
+    fn bytemuck() {
+        let vs = [Vec2::new(1u16, 2), Vec2::new(3, 4)];
+        let components: &[u16] = bytemuck::cast_slice(&vs);
+        assert_eq!(components, [1, 2, 3, 4]);
+        let v: Vec3<f32> = bytemuck::cast([1.0f32, 2.0, 3.0]);
+        assert_eq!(v, Vec3::new(1.0, 2.0, 3.0));
+    }
 }
@@ -0,0 +1,539 @@
+//! Square matrices, and the transforms they represent.
+//!
+//! Matrices are stored row by row, and multiplying a matrix by a vector
+//! treats the vector as a column. So in `a * b * v`, the transform `b` is
+//! applied to `v` first.
+//!
+//! [Mat2] holds linear transforms of [Vec2]s. [Mat3] holds linear transforms
+//! of [Vec3]s, or affine transforms of [Vec2]s, and [Mat4] holds affine
+//! transforms of [Vec3]s. Angles are in radians, counter-clockwise.
+
+use core::array;
+use core::ops::{Add, Index, IndexMut, Mul, Sub};
+
+use crate::{Float, Number, Signed, Vec2, Vec3};
+
+/// A 2×2 matrix
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(C)]
+pub struct Mat2<T> {
+    pub rows: [[T; 2]; 2],
+}
+
+/// A 3×3 matrix
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(C)]
+pub struct Mat3<T> {
+    pub rows: [[T; 3]; 3],
+}
+
+/// A 4×4 matrix
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(C)]
+pub struct Mat4<T> {
+    pub rows: [[T; 4]; 4],
+}
+
+/// Implement the operations shared by all matrices
+macro_rules! impl_matrix {
+    ($name:ident, $n:literal) => {
+        impl<T> $name<T> {
+            pub const fn new(rows: [[T; $n]; $n]) -> Self {
+                $name { rows }
+            }
+        }
+
+        impl<T: Number> $name<T> {
+            pub const ZERO: Self = $name {
+                rows: [[T::ZERO; $n]; $n],
+            };
+
+            pub fn identity() -> Self {
+                Self::diagonal([T::ONE; $n])
+            }
+
+            /// A matrix with `values` on the diagonal, and zero elsewhere
+            pub fn diagonal(values: [T; $n]) -> Self {
+                $name {
+                    rows: array::from_fn(|i| {
+                        array::from_fn(|j| if i == j { values[i] } else { T::ZERO })
+                    }),
+                }
+            }
+
+            pub fn transpose(self) -> Self {
+                $name {
+                    rows: array::from_fn(|i| array::from_fn(|j| self.rows[j][i])),
+                }
+            }
+
+            pub fn column(&self, j: usize) -> [T; $n] {
+                array::from_fn(|i| self.rows[i][j])
+            }
+        }
+
+        impl<T: Signed> $name<T> {
+            pub fn determinant(&self) -> T {
+                (0..$n).fold(T::ZERO, |det, j| {
+                    det + self.rows[0][j] * self.cofactor(0, j)
+                })
+            }
+        }
+
+        impl<T: Float> $name<T> {
+            /// The inverse of the matrix, or `None` if it is singular.
+            ///
+            /// Rounding means a singular matrix rarely has a determinant of
+            /// exactly zero, so a matrix counts as singular when its
+            /// determinant is within rounding error of zero, compared to the
+            /// largest it could be for rows of these lengths.
+            pub fn inverse(&self) -> Option<Self> {
+                let det = self.determinant();
+                let max = self.rows.iter().fold(T::ONE, |product, row| {
+                    let length = row.iter().fold(T::ZERO, |sum, &x| sum + x * x).sqrt();
+                    product * length
+                });
+                if det.abs() <= max * T::EPSILON {
+                    return None;
+                }
+                Some($name {
+                    rows: array::from_fn(|i| array::from_fn(|j| self.cofactor(j, i) / det)),
+                })
+            }
+        }
+
+        impl<T: Number> Mul for $name<T> {
+            type Output = Self;
+
+            fn mul(self, rhs: Self) -> Self::Output {
+                $name {
+                    rows: array::from_fn(|i| {
+                        array::from_fn(|j| {
+                            (0..$n).fold(T::ZERO, |sum, k| sum + self.rows[i][k] * rhs.rows[k][j])
+                        })
+                    }),
+                }
+            }
+        }
+
+        impl<T: Number> Mul<T> for $name<T> {
+            type Output = Self;
+
+            fn mul(self, rhs: T) -> Self::Output {
+                $name {
+                    rows: self.rows.map(|row| row.map(|value| value * rhs)),
+                }
+            }
+        }
+
+        impl<T: Number> Add for $name<T> {
+            type Output = Self;
+
+            fn add(self, rhs: Self) -> Self::Output {
+                $name {
+                    rows: array::from_fn(|i| array::from_fn(|j| self.rows[i][j] + rhs.rows[i][j])),
+                }
+            }
+        }
+
+        impl<T: Number> Sub for $name<T> {
+            type Output = Self;
+
+            fn sub(self, rhs: Self) -> Self::Output {
+                $name {
+                    rows: array::from_fn(|i| array::from_fn(|j| self.rows[i][j] - rhs.rows[i][j])),
+                }
+            }
+        }
+
+        /// Index by row
+        impl<T> Index<usize> for $name<T> {
+            type Output = [T; $n];
+
+            fn index(&self, row: usize) -> &Self::Output {
+                &self.rows[row]
+            }
+        }
+
+        impl<T> IndexMut<usize> for $name<T> {
+            fn index_mut(&mut self, row: usize) -> &mut Self::Output {
+                &mut self.rows[row]
+            }
+        }
+
+        impl<T> From<[[T; $n]; $n]> for $name<T> {
+            fn from(rows: [[T; $n]; $n]) -> Self {
+                $name { rows }
+            }
+        }
+
+        #[cfg(feature = "bytemuck")]
+        // SAFETY: the struct is `repr(C)` and only holds `T`s, so it has no
+        // padding, and all zeroes is a valid value if it is for `T`
+        unsafe impl<T: bytemuck::Zeroable> bytemuck::Zeroable for $name<T> {}
+
+        #[cfg(feature = "bytemuck")]
+        // SAFETY: as above, and any bit pattern is valid if it is for `T`
+        unsafe impl<T: bytemuck::Pod> bytemuck::Pod for $name<T> {}
+    };
+}
+
+impl_matrix!(Mat2, 2);
+impl_matrix!(Mat3, 3);
+impl_matrix!(Mat4, 4);
+
+/// Implement the minors and cofactors of a matrix from the determinants of
+/// the smaller matrix type
+macro_rules! impl_minor {
+    ($name:ident, $smaller:ident) => {
+        impl<T: Copy> $name<T> {
+            /// The matrix without row `row` and column `column`
+            pub fn minor(&self, row: usize, column: usize) -> $smaller<T> {
+                $smaller {
+                    rows: array::from_fn(|i| {
+                        let i = if i < row { i } else { i + 1 };
+                        array::from_fn(|j| {
+                            let j = if j < column { j } else { j + 1 };
+                            self.rows[i][j]
+                        })
+                    }),
+                }
+            }
+        }
+
+        impl<T: Signed> $name<T> {
+            fn cofactor(&self, row: usize, column: usize) -> T {
+                let det = self.minor(row, column).determinant();
+                if (row + column) % 2 == 0 {
+                    det
+                } else {
+                    -det
+                }
+            }
+        }
+    };
+}
+
+impl_minor!(Mat3, Mat2);
+impl_minor!(Mat4, Mat3);
+
+impl<T: Signed> Mat2<T> {
+    fn cofactor(&self, row: usize, column: usize) -> T {
+        let value = self.rows[1 - row][1 - column];
+        if row == column {
+            value
+        } else {
+            -value
+        }
+    }
+}
+
+impl<T: Number> Mul<Vec2<T>> for Mat2<T> {
+    type Output = Vec2<T>;
+
+    fn mul(self, v: Vec2<T>) -> Vec2<T> {
+        Vec2::new(
+            self[0][0] * v.x + self[0][1] * v.y,
+            self[1][0] * v.x + self[1][1] * v.y,
+        )
+    }
+}
+
+impl<T: Number> Mul<Vec3<T>> for Mat3<T> {
+    type Output = Vec3<T>;
+
+    fn mul(self, v: Vec3<T>) -> Vec3<T> {
+        let row = |i: usize| Vec3::from(self.rows[i]).dot(v);
+        Vec3::new(row(0), row(1), row(2))
+    }
+}
+
+impl<T: Number> Mat2<T> {
+    /// Scale by `factors` along the axes
+    pub fn scale(factors: Vec2<T>) -> Self {
+        Mat2::diagonal(factors.into())
+    }
+}
+
+impl<T: Float> Mat2<T> {
+    pub fn rotation(angle: T) -> Self {
+        let (sin, cos) = (angle.sin(), angle.cos());
+        Mat2::new([[cos, -sin], [sin, cos]])
+    }
+}
+
+impl<T: Number> Mat3<T> {
+    /// Scale by `factors` along the axes
+    pub fn scale(factors: Vec3<T>) -> Self {
+        Mat3::diagonal(factors.into())
+    }
+
+    /// Move points in two dimensions by `offset`
+    pub fn translation_2d(offset: Vec2<T>) -> Self {
+        let mut m = Self::identity();
+        m[0][2] = offset.x;
+        m[1][2] = offset.y;
+        m
+    }
+
+    /// Scale points in two dimensions by `factors` along the axes
+    pub fn scale_2d(factors: Vec2<T>) -> Self {
+        Mat3::diagonal([factors.x, factors.y, T::ONE])
+    }
+
+    /// Apply an affine transform in two dimensions to a point, which moves
+    /// with translations
+    pub fn transform_point(&self, point: Vec2<T>) -> Vec2<T> {
+        (*self * point.extend(T::ONE)).truncate()
+    }
+
+    /// Apply an affine transform in two dimensions to a direction, which
+    /// does not move with translations
+    pub fn transform_vector(&self, vector: Vec2<T>) -> Vec2<T> {
+        (*self * vector.extend(T::ZERO)).truncate()
+    }
+}
+
+impl<T: Float> Mat3<T> {
+    /// Rotate points in two dimensions around the origin
+    pub fn rotation_2d(angle: T) -> Self {
+        Mat2::rotation(angle).into()
+    }
+
+    /// Rotate around the x axis, from the y axis towards the z axis
+    pub fn rotation_x(angle: T) -> Self {
+        let (sin, cos) = (angle.sin(), angle.cos());
+        let (zero, one) = (T::ZERO, T::ONE);
+        Mat3::new([[one, zero, zero], [zero, cos, -sin], [zero, sin, cos]])
+    }
+
+    /// Rotate around the y axis, from the z axis towards the x axis
+    pub fn rotation_y(angle: T) -> Self {
+        let (sin, cos) = (angle.sin(), angle.cos());
+        let (zero, one) = (T::ZERO, T::ONE);
+        Mat3::new([[cos, zero, sin], [zero, one, zero], [-sin, zero, cos]])
+    }
+
+    /// Rotate around the z axis, from the x axis towards the y axis
+    pub fn rotation_z(angle: T) -> Self {
+        Self::rotation_2d(angle)
+    }
+
+    /// Rotate around `axis`, which must have length 1, counter-clockwise
+    /// when looking down the axis
+    pub fn rotation_axis(axis: Vec3<T>, angle: T) -> Self {
+        let (sin, cos) = (angle.sin(), angle.cos());
+        let Vec3 { x, y, z } = axis;
+        let c = T::ONE - cos;
+        Mat3::new([
+            [cos + x * x * c, x * y * c - z * sin, x * z * c + y * sin],
+            [y * x * c + z * sin, cos + y * y * c, y * z * c - x * sin],
+            [z * x * c - y * sin, z * y * c + x * sin, cos + z * z * c],
+        ])
+    }
+}
+
+impl<T: Number> Mat4<T> {
+    /// Move points by `offset`
+    pub fn translation(offset: Vec3<T>) -> Self {
+        let mut m = Self::identity();
+        m[0][3] = offset.x;
+        m[1][3] = offset.y;
+        m[2][3] = offset.z;
+        m
+    }
+
+    /// Scale by `factors` along the axes
+    pub fn scale(factors: Vec3<T>) -> Self {
+        Mat4::diagonal([factors.x, factors.y, factors.z, T::ONE])
+    }
+
+    /// Apply an affine transform to a point, which moves with translations
+    pub fn transform_point(&self, point: Vec3<T>) -> Vec3<T> {
+        self.transform(point, T::ONE)
+    }
+
+    /// Apply an affine transform to a direction, which does not move with
+    /// translations
+    pub fn transform_vector(&self, vector: Vec3<T>) -> Vec3<T> {
+        self.transform(vector, T::ZERO)
+    }
+
+    fn transform(&self, v: Vec3<T>, w: T) -> Vec3<T> {
+        let row = |i: usize| {
+            let [a, b, c, d] = self.rows[i];
+            a * v.x + b * v.y + c * v.z + d * w
+        };
+        Vec3::new(row(0), row(1), row(2))
+    }
+}
+
+impl<T: Float> Mat4<T> {
+    pub fn rotation_x(angle: T) -> Self {
+        Mat3::rotation_x(angle).into()
+    }
+
+    pub fn rotation_y(angle: T) -> Self {
+        Mat3::rotation_y(angle).into()
+    }
+
+    pub fn rotation_z(angle: T) -> Self {
+        Mat3::rotation_z(angle).into()
+    }
+
+    pub fn rotation_axis(axis: Vec3<T>, angle: T) -> Self {
+        Mat3::rotation_axis(axis, angle).into()
+    }
+}
+
+/// Use a linear transform in two dimensions as an affine transform
+impl<T: Number> From<Mat2<T>> for Mat3<T> {
+    fn from(m: Mat2<T>) -> Self {
+        let mut result = Self::identity();
+        for i in 0..2 {
+            result[i][..2].copy_from_slice(&m[i]);
+        }
+        result
+    }
+}
+
+/// Use a linear transform in three dimensions as an affine transform
+impl<T: Number> From<Mat3<T>> for Mat4<T> {
+    fn from(m: Mat3<T>) -> Self {
+        let mut result = Self::identity();
+        for i in 0..3 {
+            result[i][..3].copy_from_slice(&m[i]);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::f64::consts::{FRAC_PI_2, PI};
+
+    use super::*;
+
+    fn assert_close<const N: usize>(actual: [f64; N], expected: [f64; N]) {
+        let close = actual
+            .iter()
+            .zip(&expected)
+            .all(|(a, e)| (a - e).abs() < 1e-9);
+        assert!(close, "{actual:?} is not close to {expected:?}");
+    }
+
+    fn assert_close_mat4(actual: Mat4<f64>, expected: Mat4<f64>) {
+        assert_close(
+            actual.rows.concat().try_into().unwrap(),
+            <[f64; 16]>::try_from(expected.rows.concat()).unwrap(),
+        );
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let a = Mat2::new([[1, 2], [3, 4]]);
+        let b = Mat2::new([[0, 1], [1, 0]]);
+        assert_eq!(a * b, Mat2::new([[2, 1], [4, 3]]));
+        assert_eq!(b * a, Mat2::new([[3, 4], [1, 2]]));
+        assert_eq!(a * Mat2::identity(), a);
+        assert_eq!(a + b - a, b);
+        assert_eq!(a * 2, a + a);
+        assert_eq!(a * Vec2::new(1, 1), Vec2::new(3, 7));
+        assert_eq!(a.transpose(), Mat2::new([[1, 3], [2, 4]]));
+        assert_eq!(a.column(1), [2, 4]);
+
+        let m = Mat3::new([[1, 2, 3], [4, 5, 6], [7, 8, 10]]);
+        assert_eq!(m * Vec3::X, Vec3::new(1, 4, 7));
+        assert_eq!((m * m).transpose(), m.transpose() * m.transpose());
+    }
+
+    #[test]
+    fn test_determinant() {
+        assert_eq!(Mat2::new([[1, 2], [3, 4]]).determinant(), -2);
+        let m = Mat3::new([[1, 2, 3], [4, 5, 6], [7, 8, 10]]);
+        assert_eq!(m.determinant(), -3);
+        assert_eq!(m.minor(1, 1), Mat2::new([[1, 3], [7, 10]]));
+        assert_eq!(
+            Mat3::new([[1, 2, 3], [4, 5, 6], [7, 8, 9]]).determinant(),
+            0
+        );
+        let m = Mat4::new([[2, 0, 0, 1], [0, 3, 0, 0], [0, 0, 4, 0], [1, 0, 0, 5]]);
+        assert_eq!(m.determinant(), 108);
+        assert_eq!(Mat4::<i64>::identity().determinant(), 1);
+    }
+
+    #[test]
+    fn test_inverse() {
+        let m = Mat2::new([[4.0, 7.0], [2.0, 6.0]]);
+        assert_eq!(m.inverse().unwrap(), Mat2::new([[0.6, -0.7], [-0.2, 0.4]]));
+        assert_eq!(
+            Mat3::new([[1.0, 2.0, 3.0], [4.0, 5.0, 6.0], [7.0, 8.0, 9.0]]).inverse(),
+            None
+        );
+        // Singular, but the rounded determinant isn't exactly zero
+        let m = Mat3::new([[0.1, 0.2, 0.3], [0.4, 0.5, 0.6], [0.7, 0.8, 0.9]]);
+        assert_ne!(m.determinant(), 0.0);
+        assert_eq!(m.inverse(), None);
+        // Nearly singular: the rows differ by less than rounding error
+        assert_eq!(Mat2::new([[1.0, 2.0], [1.0, 2.0 + 1e-16]]).inverse(), None);
+        // Small, but far from singular
+        let m = Mat2::new([[1e-10, 0.0], [0.0, 1e-10]]);
+        assert_eq!(m.inverse(), Some(Mat2::new([[1e10, 0.0], [0.0, 1e10]])));
+
+        let m = Mat4::translation(Vec3::new(1.0, -2.0, 3.0))
+            * Mat4::rotation_axis(Vec3::new(1.0, 1.0, 1.0).normalize(), 0.7)
+            * Mat4::scale(Vec3::new(2.0, 3.0, 0.5));
+        let inverse = m.inverse().unwrap();
+        assert_close_mat4(m * inverse, Mat4::identity());
+        assert_close_mat4(inverse * m, Mat4::identity());
+    }
+
+    #[test]
+    fn test_transforms_2d() {
+        let quarter = Mat2::rotation(FRAC_PI_2);
+        assert_close((quarter * Vec2::new(1.0, 0.0)).into(), [0.0, 1.0]);
+
+        // Scale first, then rotate, then translate
+        let m = Mat3::translation_2d(Vec2::new(10.0, 0.0))
+            * Mat3::rotation_2d(PI)
+            * Mat3::scale_2d(Vec2::new(2.0, 3.0));
+        assert_close(m.transform_point(Vec2::new(1.0, 1.0)).into(), [8.0, -3.0]);
+        assert_close(m.transform_vector(Vec2::new(1.0, 1.0)).into(), [-2.0, -3.0]);
+        let back = m.inverse().unwrap();
+        assert_close(
+            back.transform_point(Vec2::new(8.0, -3.0)).into(),
+            [1.0, 1.0],
+        );
+
+        assert_eq!(
+            Mat3::scale_2d(Vec2::new(2, 3)).transform_point(Vec2::new(1, 1)),
+            Vec2::new(2, 3)
+        );
+    }
+
+    #[test]
+    fn test_transforms_3d() {
+        let x = Vec3::new(1.0, 0.0, 0.0);
+        let y = Vec3::new(0.0, 1.0, 0.0);
+        let z = Vec3::new(0.0, 0.0, 1.0);
+        assert_close((Mat3::rotation_x(FRAC_PI_2) * y).into(), z.into());
+        assert_close((Mat3::rotation_y(FRAC_PI_2) * z).into(), x.into());
+        assert_close((Mat3::rotation_z(FRAC_PI_2) * x).into(), y.into());
+        assert_close(
+            (Mat3::rotation_axis(z, 0.3) * x).into(),
+            (Mat3::rotation_z(0.3) * x).into(),
+        );
+
+        let m = Mat4::translation(Vec3::new(1.0, 2.0, 3.0)) * Mat4::rotation_z(FRAC_PI_2);
+        assert_close(m.transform_point(x).into(), [1.0, 3.0, 3.0]);
+        assert_close(m.transform_vector(x).into(), [0.0, 1.0, 0.0]);
+        assert_eq!(
+            Mat4::scale(Vec3::new(1, 2, 3)).transform_point(Vec3::new(1, 1, 1)),
+            Vec3::new(1, 2, 3)
+        );
+        assert_eq!(Mat3::scale(Vec3::new(1, 2, 3)).determinant(), 6);
+    }
+}
@@ -0,0 +1,107 @@
+//! The numbers vectors can be made of.
+
+use core::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+
+/// A number with the usual arithmetic operators
+pub trait Number:
+    Copy
+    + PartialEq
+    + PartialOrd
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + AddAssign
+    + SubAssign
+    + MulAssign
+    + DivAssign
+{
+    const ZERO: Self;
+    const ONE: Self;
+}
+
+/// A number that has a negative
+pub trait Signed: Number + Neg<Output = Self> {}
+
+/// A floating point number, for lengths and angles
+pub trait Float: Signed {
+    const EPSILON: Self;
+
+    fn abs(self) -> Self;
+    fn round(self) -> Self;
+    fn sqrt(self) -> Self;
+    fn sin(self) -> Self;
+    fn cos(self) -> Self;
+    /// The angle of the point `(x, y)` from the x axis, from -π to π
+    fn atan2(self, x: Self) -> Self;
+}
+
+macro_rules! impl_number {
+    ($($t:ty),*) => {
+        $(impl Number for $t {
+            const ZERO: Self = 0 as $t;
+            const ONE: Self = 1 as $t;
+        })*
+    };
+}
+
+impl_number!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64);
+
+impl Signed for i8 {}
+impl Signed for i16 {}
+impl Signed for i32 {}
+impl Signed for i64 {}
+impl Signed for i128 {}
+impl Signed for isize {}
+impl Signed for f32 {}
+impl Signed for f64 {}
+
+/// Forward the [Float] functions to the standard library, or to `libm`
+/// without it. `abs` is in `core`, so it works either way.
+macro_rules! impl_float {
+    ($($t:ident),*) => {
+        $(impl Float for $t {
+            const EPSILON: Self = $t::EPSILON;
+
+            fn abs(self) -> Self {
+                $t::abs(self)
+            }
+
+            fn round(self) -> Self {
+                float_fn!($t, round, self)
+            }
+
+            fn sqrt(self) -> Self {
+                float_fn!($t, sqrt, self)
+            }
+
+            fn sin(self) -> Self {
+                float_fn!($t, sin, self)
+            }
+
+            fn cos(self) -> Self {
+                float_fn!($t, cos, self)
+            }
+
+            fn atan2(self, x: Self) -> Self {
+                float_fn!($t, atan2, self, x)
+            }
+        })*
+    };
+}
+
+#[cfg(feature = "std")]
+macro_rules! float_fn {
+    ($t:ident, $f:ident, $($arg:expr),*) => {
+        std::primitive::$t::$f($($arg),*)
+    };
+}
+
+#[cfg(not(feature = "std"))]
+macro_rules! float_fn {
+    ($t:ident, $f:ident, $($arg:expr),*) => {
+        libm::Libm::<$t>::$f($($arg),*)
+    };
+}
+
+impl_float!(f32, f64);
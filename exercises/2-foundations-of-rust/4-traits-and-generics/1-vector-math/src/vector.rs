@@ -0,0 +1,294 @@
+//! Two- and three-dimensional vectors.
+
+use core::iter::Sum;
+use core::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+
+use crate::{Float, Number, Signed};
+
+/// A vector in two dimensions
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(C)]
+pub struct Vec2<T> {
+    pub x: T,
+    pub y: T,
+}
+
+/// A vector in three dimensions
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(C)]
+pub struct Vec3<T> {
+    pub x: T,
+    pub y: T,
+    pub z: T,
+}
+
+/// Implement the operators and conversions shared by all vectors
+macro_rules! impl_vector {
+    ($name:ident, $n:literal, $($field:ident),+) => {
+        impl<T> $name<T> {
+            pub const fn new($($field: T),+) -> Self {
+                $name { $($field),+ }
+            }
+
+            /// Apply `f` to every component
+            pub fn map<U>(self, mut f: impl FnMut(T) -> U) -> $name<U> {
+                $name { $($field: f(self.$field)),+ }
+            }
+        }
+
+        impl<T: Number> $name<T> {
+            pub const ZERO: Self = $name { $($field: T::ZERO),+ };
+
+            /// A vector with all components set to `value`
+            pub const fn splat(value: T) -> Self {
+                $name { $($field: value),+ }
+            }
+
+            pub fn dot(self, rhs: Self) -> T {
+                let mut sum = T::ZERO;
+                $(sum += self.$field * rhs.$field;)+
+                sum
+            }
+
+            /// The square of the length, which unlike the length is exact for integers
+            pub fn length_squared(self) -> T {
+                self.dot(self)
+            }
+        }
+
+        impl<T: Float> $name<T> {
+            pub fn length(self) -> T {
+                self.length_squared().sqrt()
+            }
+
+            pub fn distance(self, other: Self) -> T {
+                (self - other).length()
+            }
+
+            /// A vector in the same direction with length 1.
+            ///
+            /// The components of the zero vector become NaN; use
+            /// [try_normalize](Self::try_normalize) if that can happen.
+            pub fn normalize(self) -> Self {
+                self / self.length()
+            }
+
+            /// A vector in the same direction with length 1, or `None` for
+            /// (almost) zero vectors
+            pub fn try_normalize(self) -> Option<Self> {
+                let length = self.length();
+                (length > T::EPSILON).then(|| self / length)
+            }
+        }
+
+        impl<T: Number> Add for $name<T> {
+            type Output = Self;
+
+            fn add(self, rhs: Self) -> Self::Output {
+                $name { $($field: self.$field + rhs.$field),+ }
+            }
+        }
+
+        impl<T: Number> Sub for $name<T> {
+            type Output = Self;
+
+            fn sub(self, rhs: Self) -> Self::Output {
+                $name { $($field: self.$field - rhs.$field),+ }
+            }
+        }
+
+        /// The dot product
+        impl<T: Number> Mul for $name<T> {
+            type Output = T;
+
+            fn mul(self, rhs: Self) -> Self::Output {
+                self.dot(rhs)
+            }
+        }
+
+        /// Scale by a number
+        impl<T: Number> Mul<T> for $name<T> {
+            type Output = Self;
+
+            fn mul(self, rhs: T) -> Self::Output {
+                $name { $($field: self.$field * rhs),+ }
+            }
+        }
+
+        impl<T: Number> Div<T> for $name<T> {
+            type Output = Self;
+
+            fn div(self, rhs: T) -> Self::Output {
+                $name { $($field: self.$field / rhs),+ }
+            }
+        }
+
+        impl<T: Signed> Neg for $name<T> {
+            type Output = Self;
+
+            fn neg(self) -> Self::Output {
+                $name { $($field: -self.$field),+ }
+            }
+        }
+
+        impl<T: Number> AddAssign for $name<T> {
+            fn add_assign(&mut self, rhs: Self) {
+                $(self.$field += rhs.$field;)+
+            }
+        }
+
+        impl<T: Number> SubAssign for $name<T> {
+            fn sub_assign(&mut self, rhs: Self) {
+                $(self.$field -= rhs.$field;)+
+            }
+        }
+
+        impl<T: Number> MulAssign<T> for $name<T> {
+            fn mul_assign(&mut self, rhs: T) {
+                $(self.$field *= rhs;)+
+            }
+        }
+
+        impl<T: Number> DivAssign<T> for $name<T> {
+            fn div_assign(&mut self, rhs: T) {
+                $(self.$field /= rhs;)+
+            }
+        }
+
+        impl<T: Number> Sum for $name<T> {
+            fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+                iter.fold(Self::ZERO, Add::add)
+            }
+        }
+
+        impl<T> From<[T; $n]> for $name<T> {
+            fn from([$($field),+]: [T; $n]) -> Self {
+                $name { $($field),+ }
+            }
+        }
+
+        impl<T> From<$name<T>> for [T; $n] {
+            fn from(v: $name<T>) -> Self {
+                [$(v.$field),+]
+            }
+        }
+
+        #[cfg(feature = "bytemuck")]
+        // SAFETY: the struct is `repr(C)` and only has fields of type `T`, so
+        // it has no padding, and all zeroes is a valid value if it is for `T`
+        unsafe impl<T: bytemuck::Zeroable> bytemuck::Zeroable for $name<T> {}
+
+        #[cfg(feature = "bytemuck")]
+        // SAFETY: as above, and any bit pattern is valid if it is for `T`
+        unsafe impl<T: bytemuck::Pod> bytemuck::Pod for $name<T> {}
+    };
+}
+
+impl_vector!(Vec2, 2, x, y);
+impl_vector!(Vec3, 3, x, y, z);
+
+/// Scale by a number on the left, like `2.0 * v`
+macro_rules! impl_scalar_mul {
+    ($($t:ty),*) => {
+        $(
+            impl Mul<Vec2<$t>> for $t {
+                type Output = Vec2<$t>;
+
+                fn mul(self, rhs: Vec2<$t>) -> Self::Output {
+                    rhs * self
+                }
+            }
+
+            impl Mul<Vec3<$t>> for $t {
+                type Output = Vec3<$t>;
+
+                fn mul(self, rhs: Vec3<$t>) -> Self::Output {
+                    rhs * self
+                }
+            }
+        )*
+    };
+}
+
+impl_scalar_mul!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64);
+
+impl<T> From<(T, T)> for Vec2<T> {
+    fn from((x, y): (T, T)) -> Self {
+        Vec2 { x, y }
+    }
+}
+
+impl<T> From<Vec2<T>> for (T, T) {
+    fn from(v: Vec2<T>) -> Self {
+        (v.x, v.y)
+    }
+}
+
+impl<T> From<(T, T, T)> for Vec3<T> {
+    fn from((x, y, z): (T, T, T)) -> Self {
+        Vec3 { x, y, z }
+    }
+}
+
+impl<T> From<Vec3<T>> for (T, T, T) {
+    fn from(v: Vec3<T>) -> Self {
+        (v.x, v.y, v.z)
+    }
+}
+
+impl<T: Number> Vec2<T> {
+    pub const X: Self = Vec2::new(T::ONE, T::ZERO);
+    pub const Y: Self = Vec2::new(T::ZERO, T::ONE);
+
+    /// The z component of the cross product of the vectors extended to three
+    /// dimensions, which is positive if `rhs` is counter-clockwise from `self`
+    pub fn cross(self, rhs: Self) -> T {
+        self.x * rhs.y - self.y * rhs.x
+    }
+
+    /// Extend to three dimensions
+    pub fn extend(self, z: T) -> Vec3<T> {
+        Vec3::new(self.x, self.y, z)
+    }
+}
+
+impl<T: Signed> Vec2<T> {
+    /// The vector rotated a quarter turn counter-clockwise
+    pub fn perp(self) -> Self {
+        Vec2::new(-self.y, self.x)
+    }
+}
+
+impl<T: Float> Vec2<T> {
+    /// The unit vector at `angle` radians counter-clockwise from the x axis
+    pub fn from_angle(angle: T) -> Self {
+        Vec2::new(angle.cos(), angle.sin())
+    }
+
+    /// The angle counter-clockwise from the x axis, from -π to π
+    pub fn angle(self) -> T {
+        self.y.atan2(self.x)
+    }
+}
+
+impl<T: Number> Vec3<T> {
+    pub const X: Self = Vec3::new(T::ONE, T::ZERO, T::ZERO);
+    pub const Y: Self = Vec3::new(T::ZERO, T::ONE, T::ZERO);
+    pub const Z: Self = Vec3::new(T::ZERO, T::ZERO, T::ONE);
+
+    /// A vector perpendicular to both vectors, following the right-hand rule
+    pub fn cross(self, rhs: Self) -> Self {
+        Vec3 {
+            x: self.y * rhs.z - self.z * rhs.y,
+            y: self.z * rhs.x - self.x * rhs.z,
+            z: self.x * rhs.y - self.y * rhs.x,
+        }
+    }
+
+    /// Drop the z component
+    pub fn truncate(self) -> Vec2<T> {
+        Vec2::new(self.x, self.y)
+    }
+}
@@ -0,0 +1,189 @@
+//! Iterators that move items out of a [LocalStorageVec].
+//!
+//! Both hand out items by reading them from the buffer of the vector, after
+//! shortening it so that it no longer owns them. The pointers are fetched
+//! again for every item, as the buffer moves with the vector when it is on
+//! the stack.
+
+use std::iter::FusedIterator;
+use std::{ptr, slice};
+
+use crate::LocalStorageVec;
+
+/// An iterator over the items of a [LocalStorageVec], which it owns
+pub struct LocalStorageVecIter<T, const N: usize> {
+    /// Has length zero, the items in `front..back` belong to the iterator
+    vec: LocalStorageVec<T, N>,
+    front: usize,
+    back: usize,
+}
+
+impl<T, const N: usize> LocalStorageVecIter<T, N> {
+    pub(crate) fn new(mut vec: LocalStorageVec<T, N>) -> Self {
+        let back = vec.len();
+        // SAFETY: zero is always a valid length; the items are now ours
+        unsafe { vec.set_len(0) };
+        LocalStorageVecIter {
+            vec,
+            front: 0,
+            back,
+        }
+    }
+
+    /// The items that have not been returned yet
+    pub fn as_slice(&self) -> &[T] {
+        // SAFETY: the items in `front..back` are initialized, and still ours
+        unsafe { slice::from_raw_parts(self.vec.as_ptr().add(self.front), self.back - self.front) }
+    }
+}
+
+impl<T, const N: usize> Iterator for LocalStorageVecIter<T, N> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+        self.front += 1;
+        // SAFETY: the item was initialized, and is no longer in `front..back`
+        Some(unsafe { self.vec.as_ptr().add(self.front - 1).read() })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.back - self.front;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<T, const N: usize> DoubleEndedIterator for LocalStorageVecIter<T, N> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+        self.back -= 1;
+        // SAFETY: the item was initialized, and is no longer in `front..back`
+        Some(unsafe { self.vec.as_ptr().add(self.back).read() })
+    }
+}
+
+impl<T, const N: usize> ExactSizeIterator for LocalStorageVecIter<T, N> {}
+
+impl<T, const N: usize> FusedIterator for LocalStorageVecIter<T, N> {}
+
+impl<T, const N: usize> Drop for LocalStorageVecIter<T, N> {
+    fn drop(&mut self) {
+        let remaining = ptr::slice_from_raw_parts_mut(
+            // SAFETY: `front <= back <= capacity`
+            unsafe { self.vec.as_mut_ptr().add(self.front) },
+            self.back - self.front,
+        );
+        self.front = self.back;
+        // SAFETY: the items were initialized, and will not be used again. If
+        // one of them panics, the rest are still dropped, and `vec` frees
+        // the buffer.
+        unsafe { ptr::drop_in_place(remaining) };
+    }
+}
+
+/// An iterator removing a range of items from a [LocalStorageVec], returned
+/// by [LocalStorageVec::drain]
+pub struct Drain<'a, T, const N: usize> {
+    /// Has length `start`, the items in `front..back` belong to the
+    /// iterator, and those from `end` to `tail_end` to the vector again,
+    /// once they are moved back
+    vec: &'a mut LocalStorageVec<T, N>,
+    start: usize,
+    front: usize,
+    back: usize,
+    end: usize,
+    tail_end: usize,
+}
+
+impl<'a, T, const N: usize> Drain<'a, T, N> {
+    pub(crate) fn new(vec: &'a mut LocalStorageVec<T, N>, start: usize, end: usize) -> Self {
+        let tail_end = vec.len();
+        // SAFETY: the items before `start` are still initialized; the rest
+        // are ours until the `Drain` is dropped, or leaked if it never is
+        unsafe { vec.set_len(start) };
+        Drain {
+            vec,
+            start,
+            front: start,
+            back: end,
+            end,
+            tail_end,
+        }
+    }
+
+    /// The items that have not been returned yet
+    pub fn as_slice(&self) -> &[T] {
+        // SAFETY: the items in `front..back` are initialized, and still ours
+        unsafe { slice::from_raw_parts(self.vec.as_ptr().add(self.front), self.back - self.front) }
+    }
+}
+
+impl<T, const N: usize> Iterator for Drain<'_, T, N> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+        self.front += 1;
+        // SAFETY: the item was initialized, and is no longer in `front..back`
+        Some(unsafe { self.vec.as_ptr().add(self.front - 1).read() })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.back - self.front;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<T, const N: usize> DoubleEndedIterator for Drain<'_, T, N> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+        self.back -= 1;
+        // SAFETY: the item was initialized, and is no longer in `front..back`
+        Some(unsafe { self.vec.as_ptr().add(self.back).read() })
+    }
+}
+
+impl<T, const N: usize> ExactSizeIterator for Drain<'_, T, N> {}
+
+impl<T, const N: usize> FusedIterator for Drain<'_, T, N> {}
+
+impl<T, const N: usize> Drop for Drain<'_, T, N> {
+    fn drop(&mut self) {
+        /// Moves the items after the range back in place, even if dropping
+        /// the rest of the range panics
+        struct MoveTail<'r, 'a, T, const N: usize>(&'r mut Drain<'a, T, N>);
+
+        impl<T, const N: usize> Drop for MoveTail<'_, '_, T, N> {
+            fn drop(&mut self) {
+                let drain = &mut *self.0;
+                let tail_len = drain.tail_end - drain.end;
+                // SAFETY: both ranges are within the capacity, and the tail
+                // is initialized. Afterwards the first `start + tail_len`
+                // items are, and all of them belong to the vector again.
+                unsafe {
+                    let base = drain.vec.as_mut_ptr();
+                    ptr::copy(base.add(drain.end), base.add(drain.start), tail_len);
+                    drain.vec.set_len(drain.start + tail_len);
+                }
+            }
+        }
+
+        let remaining = ptr::slice_from_raw_parts_mut(
+            // SAFETY: `front <= back <= capacity`
+            unsafe { self.vec.as_mut_ptr().add(self.front) },
+            self.back - self.front,
+        );
+        self.front = self.back;
+        let _guard = MoveTail(self);
+        // SAFETY: the items were initialized, and will not be used again
+        unsafe { ptr::drop_in_place(remaining) };
+    }
+}
@@ -1,45 +1,302 @@
+//! A vector that keeps a small number of items inline, and only moves them
+//! to the heap when it grows larger, and a string built on top of it.
+//!
+//! With the `serde` feature, both can be serialized, and are deserialized
+//! straight into the stack buffer when they fit.
+//!
+//! The inline items live in uninitialized memory, so the tests are also meant
+//! to be run under Miri, with `cargo +nightly miri test`.
+
+use std::fmt::{self, Debug};
+use std::hash::{Hash, Hasher};
+use std::ops::{Bound, Deref, DerefMut, Index, IndexMut, RangeBounds};
+use std::slice::{self, SliceIndex};
+
+mod iter;
+#[cfg(feature = "serde")]
+mod serialize;
+mod stack;
+mod string;
+
+pub use iter::{Drain, LocalStorageVecIter};
+pub use stack::StackBuf;
+pub use string::{FromUtf8Error, LocalStorageString};
+
 /// A growable, generic list that resides on the stack if it's small,
 /// but is moved to the heap to grow larger if needed.
 /// This list is generic over the items it contains as well as the
 /// size of its buffer if it's on the stack.
 pub enum LocalStorageVec<T, const N: usize> {
-    // TODO add some variants containing data
-    // to make the compiler happy
+    /// At most `N` items, stored inline
+    Stack(StackBuf<T, N>),
+    /// Items that did not fit in `N`. A heap vector is never moved back to
+    /// the stack, even if it shrinks.
+    Heap(Vec<T>),
+}
+
+impl<T, const N: usize> LocalStorageVec<T, N> {
+    /// An empty vector, which does not allocate
+    pub const fn new() -> Self {
+        Self::Stack(StackBuf::new())
+    }
+
+    /// An empty vector with room for at least `capacity` items, which only
+    /// allocates if that does not fit on the stack
+    pub fn with_capacity(capacity: usize) -> Self {
+        if capacity <= N {
+            Self::new()
+        } else {
+            Self::Heap(Vec::with_capacity(capacity))
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Stack(buf) => buf.len(),
+            Self::Heap(v) => v.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of items that fit without allocating
+    pub fn capacity(&self) -> usize {
+        match self {
+            Self::Stack(_) => N,
+            Self::Heap(v) => v.capacity(),
+        }
+    }
+
+    /// Make room for at least `additional` more items, moving to the heap if
+    /// they do not fit on the stack
+    pub fn reserve(&mut self, additional: usize) {
+        match self {
+            Self::Stack(buf) if additional > N - buf.len() => self.spill(additional),
+            Self::Stack(_) => {}
+            Self::Heap(v) => v.reserve(additional),
+        }
+    }
+
+    pub fn push(&mut self, value: T) {
+        match self {
+            Self::Stack(buf) => {
+                if let Err(value) = buf.try_push(value) {
+                    self.spill(1);
+                    self.push(value);
+                }
+            }
+            Self::Heap(v) => v.push(value),
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        match self {
+            Self::Stack(buf) => buf.pop(),
+            Self::Heap(v) => v.pop(),
+        }
+    }
+
+    /// Insert `value` at `index`, shifting the items after it to the right.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index > len`.
+    pub fn insert(&mut self, index: usize, value: T) {
+        let len = self.len();
+        assert!(
+            index <= len,
+            "insertion index (is {index}) should be <= len (is {len})"
+        );
+        self.push(value);
+        self[index..].rotate_right(1);
+    }
+
+    /// Remove and return the item at `index`, shifting the items after it to
+    /// the left.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index >= len`.
+    pub fn remove(&mut self, index: usize) -> T {
+        let len = self.len();
+        assert!(
+            index < len,
+            "removal index (is {index}) should be < len (is {len})"
+        );
+        self[index..].rotate_left(1);
+        self.pop().unwrap()
+    }
+
+    /// Remove and return the item at `index`, replacing it with the last
+    /// item. This does not keep the order, but is O(1).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index >= len`.
+    pub fn swap_remove(&mut self, index: usize) -> T {
+        let len = self.len();
+        assert!(
+            index < len,
+            "swap_remove index (is {index}) should be < len (is {len})"
+        );
+        self.swap(index, len - 1);
+        self.pop().unwrap()
+    }
+
+    /// Keep only the first `len` items, dropping the rest
+    pub fn truncate(&mut self, new_len: usize) {
+        match self {
+            Self::Stack(buf) => buf.truncate(new_len),
+            Self::Heap(v) => v.truncate(new_len),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.truncate(0);
+    }
+
+    /// Keep only the items for which `keep` returns `true`, in their
+    /// original order
+    pub fn retain(&mut self, mut keep: impl FnMut(&T) -> bool) {
+        if let Self::Heap(v) = self {
+            return v.retain(keep);
+        }
+        // If `keep` panics, the items are all still there, though perhaps
+        // in a different order
+        let slice = self.as_mut_slice();
+        let mut kept = 0;
+        for i in 0..slice.len() {
+            if keep(&slice[i]) {
+                slice.swap(kept, i);
+                kept += 1;
+            }
+        }
+        self.truncate(kept);
+    }
+
+    /// Remove the items in `range`, returning them as an iterator.
+    ///
+    /// The items are removed even if the iterator is dropped before it
+    /// returned them all. If it is leaked instead, so are the items after
+    /// the range.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is out of bounds, or starts after it ends.
+    pub fn drain(&mut self, range: impl RangeBounds<usize>) -> Drain<'_, T, N> {
+        let len = self.len();
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start
+                .checked_add(1)
+                .expect("attempted to index slice from after maximum usize"),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end
+                .checked_add(1)
+                .expect("attempted to index slice up to maximum usize"),
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => len,
+        };
+        assert!(
+            start <= end,
+            "slice index starts at {start} but ends at {end}"
+        );
+        assert!(
+            end <= len,
+            "range end index {end} out of range for slice of length {len}"
+        );
+        Drain::new(self, start, end)
+    }
+
+    pub fn extend_from_slice(&mut self, items: &[T])
+    where
+        T: Clone,
+    {
+        self.extend(items.iter().cloned());
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        match self {
+            Self::Stack(buf) => buf.as_slice(),
+            Self::Heap(v) => v,
+        }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        match self {
+            Self::Stack(buf) => buf.as_mut_slice(),
+            Self::Heap(v) => v,
+        }
+    }
+
+    pub fn as_ptr(&self) -> *const T {
+        match self {
+            Self::Stack(buf) => buf.as_ptr(),
+            Self::Heap(v) => v.as_ptr(),
+        }
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut T {
+        match self {
+            Self::Stack(buf) => buf.as_mut_ptr(),
+            Self::Heap(v) => v.as_mut_ptr(),
+        }
+    }
+
+    /// Set the number of items, without dropping or initializing anything.
+    ///
+    /// # Safety
+    ///
+    /// `new_len` must be at most the capacity, and the first `new_len` items
+    /// must be initialized.
+    pub unsafe fn set_len(&mut self, new_len: usize) {
+        match self {
+            // SAFETY: the capacity on the stack is `N`; the rest is up to the caller
+            Self::Stack(buf) => unsafe { buf.set_len(new_len) },
+            // SAFETY: up to the caller
+            Self::Heap(v) => unsafe { v.set_len(new_len) },
+        }
+    }
+
+    pub fn iter(&self) -> slice::Iter<'_, T> {
+        self.as_slice().iter()
+    }
+
+    pub fn iter_mut(&mut self) -> slice::IterMut<'_, T> {
+        self.as_mut_slice().iter_mut()
+    }
+
+    /// Move the items to a heap vector with room for `additional` more
+    fn spill(&mut self, additional: usize) {
+        if let Self::Stack(buf) = self {
+            // Leave room to grow as much again, like a full `Vec` would
+            *self = Self::Heap(buf.take_vec(additional.max(N)));
+        }
+    }
+}
+
+impl<T, const N: usize> Default for LocalStorageVec<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-// **Below `From` implementation is used in the tests and are therefore given. However,
-// you should have a thorough look at it as they contain various new concepts.**
 // This implementation is generic not only over the type `T`, but also over the
 // constants `N` and 'M', allowing us to support conversions from arrays of any
 // length to `LocalStorageVec`s of with any stack buffer size.
 // In Rust, we call this feature 'const generics'
-impl<T, const N: usize, const M: usize> From<[T; N]> for LocalStorageVec<T, M>
-where
-    // We require that `T` implement `Default`, in case we need to fill up our
-    // stack-based array without resorting to uninitialized memory. Once
-    // we are more proficient in working with uninitialized memory, we'll be
-    // able to remove this bound.
-    T: Default,
-{
+impl<T, const N: usize, const M: usize> From<[T; N]> for LocalStorageVec<T, M> {
     fn from(array: [T; N]) -> Self {
         if N <= M {
-            // In this case, the passed array should fit on the stack.
-
-            // We crate an `Iterator` of the passed array,
-            let mut it = array.into_iter();
-            Self::Stack {
-                // This is a trick for copying an array into another one that's
-                // at least as long as the original, without having to create
-                // default values more than strictly necessary. The `[(); M]`
-                // array is zero-sized, meaning there's no cost to instantiate it.
-                // The `map` call iterates over each of its items, and maps them to
-                // the next item from the `array` passed to this function. If there
-                // are no more items left from `array`, we insert the default specified
-                // for `T`
-                buf: [(); M].map(|_| it.next().unwrap_or_default()),
-                // The length of the buffer on stack is the length of the original `array`: `N`
-                len: N,
-            }
+            // In this case, the passed array fits on the stack, so we move the
+            // items over one by one. The rest of the buffer stays uninitialized.
+            let mut vec = Self::new();
+            vec.extend(array);
+            vec
         } else {
             // If the passed array does not fit, we'll resort to moving it to the heap instead
             Self::Heap(Vec::from(array))
@@ -47,14 +304,162 @@ where
     }
 }
 
+impl<T, const N: usize> From<Vec<T>> for LocalStorageVec<T, N> {
+    fn from(v: Vec<T>) -> Self {
+        Self::Heap(v)
+    }
+}
+
+impl<T, const N: usize> AsRef<[T]> for LocalStorageVec<T, N> {
+    fn as_ref(&self) -> &[T] {
+        self.as_slice()
+    }
+}
+
+impl<T, const N: usize> AsMut<[T]> for LocalStorageVec<T, N> {
+    fn as_mut(&mut self) -> &mut [T] {
+        self.as_mut_slice()
+    }
+}
+
+impl<T, const N: usize> Deref for LocalStorageVec<T, N> {
+    type Target = [T];
+
+    fn deref(&self) -> &Self::Target {
+        self.as_slice()
+    }
+}
+
+impl<T, const N: usize> DerefMut for LocalStorageVec<T, N> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.as_mut_slice()
+    }
+}
+
+/// Index with anything a slice can be indexed with, like `vec[1]` or `vec[1..3]`
+impl<T, I: SliceIndex<[T]>, const N: usize> Index<I> for LocalStorageVec<T, N> {
+    type Output = I::Output;
+
+    fn index(&self, index: I) -> &Self::Output {
+        &self.as_slice()[index]
+    }
+}
+
+impl<T, I: SliceIndex<[T]>, const N: usize> IndexMut<I> for LocalStorageVec<T, N> {
+    fn index_mut(&mut self, index: I) -> &mut Self::Output {
+        &mut self.as_mut_slice()[index]
+    }
+}
+
+impl<T, const N: usize> Extend<T> for LocalStorageVec<T, N> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        self.reserve(iter.size_hint().0);
+        for value in iter {
+            self.push(value);
+        }
+    }
+}
+
+impl<'a, T: Copy + 'a, const N: usize> Extend<&'a T> for LocalStorageVec<T, N> {
+    fn extend<I: IntoIterator<Item = &'a T>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().copied());
+    }
+}
+
+impl<T, const N: usize> FromIterator<T> for LocalStorageVec<T, N> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut vec = Self::new();
+        vec.extend(iter);
+        vec
+    }
+}
+
+impl<T, const N: usize> IntoIterator for LocalStorageVec<T, N> {
+    type Item = T;
+    type IntoIter = LocalStorageVecIter<T, N>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        LocalStorageVecIter::new(self)
+    }
+}
+
+impl<'a, T, const N: usize> IntoIterator for &'a LocalStorageVec<T, N> {
+    type Item = &'a T;
+    type IntoIter = slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T, const N: usize> IntoIterator for &'a mut LocalStorageVec<T, N> {
+    type Item = &'a mut T;
+    type IntoIter = slice::IterMut<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+/// Keeps the variant, so a clone of a vector on the stack does not allocate
+impl<T: Clone, const N: usize> Clone for LocalStorageVec<T, N> {
+    fn clone(&self) -> Self {
+        match self {
+            Self::Stack(buf) => Self::Stack(buf.clone()),
+            Self::Heap(v) => Self::Heap(v.clone()),
+        }
+    }
+}
+
+/// Only the items in use are shown, as a list
+impl<T: Debug, const N: usize> Debug for LocalStorageVec<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// Vectors are equal if their items are, wherever they are stored
+impl<T: PartialEq, const N: usize, const M: usize> PartialEq<LocalStorageVec<T, M>>
+    for LocalStorageVec<T, N>
+{
+    fn eq(&self, other: &LocalStorageVec<T, M>) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+impl<T: Eq, const N: usize> Eq for LocalStorageVec<T, N> {}
+
+impl<T: PartialEq, const N: usize> PartialEq<[T]> for LocalStorageVec<T, N> {
+    fn eq(&self, other: &[T]) -> bool {
+        self.as_slice() == other
+    }
+}
+
+impl<T: PartialEq, const N: usize, const M: usize> PartialEq<[T; M]> for LocalStorageVec<T, N> {
+    fn eq(&self, other: &[T; M]) -> bool {
+        self.as_slice() == other
+    }
+}
+
+/// Hashes like a slice of the items, so it is consistent with [PartialEq]
+impl<T: Hash, const N: usize> Hash for LocalStorageVec<T, N> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_slice().hash(state);
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use crate::LocalStorageVec;
+    use std::cell::Cell;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    use crate::{LocalStorageVec, StackBuf};
 
     #[test]
     // Don't remove the #[ignore] attribute or your tests will take forever!
     #[ignore = "This test is just to validate the definition of `LocalStorageVec`. If it compiles, all is OK"]
-    #[allow(unreachable_code, unused_variables)]
+    #[allow(unreachable_code, unused_variables, clippy::empty_loop)]
     fn it_compiles() {
         // Here's a trick to 'initialize' a type while not actually
         // creating a value: an infinite `loop` expression diverges
@@ -67,9 +472,9 @@ mod test {
         // - https://doc.rust-lang.org/reference/expressions/loop-expr.html#infinite-loops
         let vec: LocalStorageVec<u32, 10> = loop {};
         match vec {
-            LocalStorageVec::Stack { buf, len } => {
-                let _buf: [u32; 10] = buf;
-                let _len: usize = len;
+            LocalStorageVec::Stack(buf) => {
+                let _len: usize = buf.len();
+                let _buf: StackBuf<u32, 10> = buf;
             }
             LocalStorageVec::Heap(v) => {
                 let _v: Vec<u32> = v;
@@ -77,206 +482,516 @@ mod test {
         }
     }
 
-    // Uncomment me for part B
-    // #[test]
-    // fn it_from_vecs() {
-    //     // The `vec!` macro creates a `Vec<T>` in a way that resembles
-    //     // array-initialization syntax.
-    //     let vec: LocalStorageVec<usize, 10> = LocalStorageVec::from(vec![1, 2, 3]);
-    //     // Assert that the call to `from` indeed yields a `Heap` variant
-    //     assert!(matches!(vec, LocalStorageVec::Heap(_)));
-    //
-    //     let vec: LocalStorageVec<usize, 2> = LocalStorageVec::from(vec![1, 2, 3]);
-    //
-    //     assert!(matches!(vec, LocalStorageVec::Heap(_)));
-    // }
-
-    // Uncomment me for part C
-    // #[test]
-    // fn it_as_refs() {
-    //     let vec: LocalStorageVec<i32, 256> = LocalStorageVec::from([0; 128]);
-    //     let slice: &[i32] = vec.as_ref();
-    //     assert!(slice.len() == 128);
-    //     let vec: LocalStorageVec<i32, 32> = LocalStorageVec::from([0; 128]);
-    //     let slice: &[i32] = vec.as_ref();
-    //     assert!(slice.len() == 128);
-    //
-    //     let mut vec: LocalStorageVec<i32, 256> = LocalStorageVec::from([0; 128]);
-    //     let slice_mut: &[i32] = vec.as_mut();
-    //     assert!(slice_mut.len() == 128);
-    //     let mut vec: LocalStorageVec<i32, 32> = LocalStorageVec::from([0; 128]);
-    //     let slice_mut: &[i32] = vec.as_mut();
-    //     assert!(slice_mut.len() == 128);
-    // }
-
-    // Uncomment me for part D
-    // #[test]
-    // fn it_constructs() {
-    //     let vec: LocalStorageVec<usize, 10> = LocalStorageVec::new();
-    //     // Assert that the call to `new` indeed yields a `Stack` variant with zero length
-    //     assert!(matches!(vec, LocalStorageVec::Stack { buf: _, len: 0 }));
-    // }
-
-    // Uncomment me for part D
-    // #[test]
-    // fn it_lens() {
-    //     let vec: LocalStorageVec<_, 3> = LocalStorageVec::from([0, 1, 2]);
-    //     assert_eq!(vec.len(), 3);
-    //     let vec: LocalStorageVec<_, 2> = LocalStorageVec::from([0, 1, 2]);
-    //     assert_eq!(vec.len(), 3);
-    // }
-
-    // Uncomment me for part D
-    // #[test]
-    // fn it_pushes() {
-    //     let mut vec: LocalStorageVec<_, 128> = LocalStorageVec::new();
-    //     for value in 0..128 {
-    //         vec.push(value);
-    //     }
-    //     assert!(matches!(vec, LocalStorageVec::Stack { len: 128, .. }));
-    //     for value in 128..256 {
-    //         vec.push(value);
-    //     }
-    //     assert!(matches!(vec, LocalStorageVec::Heap(v) if v.len() == 256))
-    // }
-
-    // Uncomment me for part D
-    // #[test]
-    // fn it_pops() {
-    //     let mut vec: LocalStorageVec<_, 128> = LocalStorageVec::from([0; 128]);
-    //     for _ in 0..128 {
-    //         assert_eq!(vec.pop(), Some(0))
-    //     }
-    //     assert_eq!(vec.pop(), None);
-    //
-    //     let mut vec: LocalStorageVec<_, 128> = LocalStorageVec::from([0; 256]);
-    //     for _ in 0..256 {
-    //         assert_eq!(vec.pop(), Some(0))
-    //     }
-    //     assert_eq!(vec.pop(), None);
-    //
-    //     let mut vec: LocalStorageVec<_, 128> = LocalStorageVec::from(vec![0; 256]);
-    //     for _ in 0..256 {
-    //         assert_eq!(vec.pop(), Some(0))
-    //     }
-    //     assert_eq!(vec.pop(), None);
-    // }
-
-    // Uncomment me for part D
-    // #[test]
-    // fn it_inserts() {
-    //     let mut vec: LocalStorageVec<_, 4> = LocalStorageVec::from([0, 1, 2]);
-    //     vec.insert(1, 3);
-    //     assert!(matches!(
-    //         vec,
-    //         LocalStorageVec::Stack {
-    //             buf: [0, 3, 1, 2],
-    //             len: 4
-    //         }
-    //     ));
-    //
-    //     let mut vec: LocalStorageVec<_, 4> = LocalStorageVec::from([0, 1, 2, 3]);
-    //     vec.insert(1, 3);
-    //     assert!(matches!(vec, LocalStorageVec::Heap { .. }));
-    //     assert_eq!(vec.as_ref(), &[0, 3, 1, 2, 3]);
-    //
-    //     let mut vec: LocalStorageVec<_, 4> = LocalStorageVec::from([0, 1, 2, 3, 4]);
-    //     vec.insert(1, 3);
-    //     assert!(matches!(vec, LocalStorageVec::Heap { .. }));
-    //     assert_eq!(vec.as_ref(), &[0, 3, 1, 2, 3, 4])
-    // }
-
-    // Uncomment me for part D
-    // #[test]
-    // fn it_removes() {
-    //     let mut vec: LocalStorageVec<_, 4> = LocalStorageVec::from([0, 1, 2]);
-    //     let elem = vec.remove(1);
-    //     assert!(matches!(
-    //         vec,
-    //         LocalStorageVec::Stack {
-    //             buf: [0, 2, _, _],
-    //             len: 2
-    //         }
-    //     ));
-    //     assert_eq!(elem, 1);
-    //
-    //     let mut vec: LocalStorageVec<_, 2> = LocalStorageVec::from([0, 1, 2]);
-    //     let elem = vec.remove(1);
-    //     assert!(matches!(vec, LocalStorageVec::Heap(..)));
-    //     assert_eq!(vec.as_ref(), &[0, 2]);
-    //     assert_eq!(elem, 1);
-    // }
-
-    // Uncomment me for part D
-    // #[test]
-    // fn it_clears() {
-    //     let mut vec: LocalStorageVec<_, 10> = LocalStorageVec::from([0, 1, 2, 3]);
-    //     assert!(matches!(vec, LocalStorageVec::Stack { buf: _, len: 4 }));
-    //     vec.clear();
-    //     assert_eq!(vec.len(), 0);
-    //
-    //     let mut vec: LocalStorageVec<_, 3> = LocalStorageVec::from([0, 1, 2, 3]);
-    //     assert!(matches!(vec, LocalStorageVec::Heap(_)));
-    //     vec.clear();
-    //     assert_eq!(vec.len(), 0);
-    // }
-
-    // Uncomment me for part E
-    // #[test]
-    // fn it_iters() {
-    //     let vec: LocalStorageVec<_, 128> = LocalStorageVec::from([0; 32]);
-    //     let mut iter = vec.into_iter();
-    //     for item in &mut iter {
-    //         assert_eq!(item, 0);
-    //     }
-    //     assert_eq!(iter.next(), None);
-    //
-    //     let vec: LocalStorageVec<_, 128> = LocalStorageVec::from(vec![0; 128]);
-    //     let mut iter = vec.into_iter();
-    //     for item in &mut iter {
-    //         assert_eq!(item, 0);
-    //     }
-    //     assert_eq!(iter.next(), None);
-    // }
-
-    // Uncomment me for part F
-    // #[test]
-    // fn it_indexes() {
-    //     let vec: LocalStorageVec<i32, 10> = LocalStorageVec::from([0, 1, 2, 3, 4, 5]);
-    //     assert_eq!(vec[1], 1);
-    //     assert_eq!(vec[..2], [0, 1]);
-    //     assert_eq!(vec[4..], [4, 5]);
-    //     assert_eq!(vec[1..3], [1, 2]);
-    // }
-
-    // Uncomment me for part H
-    // #[test]
-    // fn it_borrowing_iters() {
-    //     let vec: LocalStorageVec<String, 10> = LocalStorageVec::from([
-    //         "0".to_owned(),
-    //         "1".to_owned(),
-    //         "2".to_owned(),
-    //         "3".to_owned(),
-    //         "4".to_owned(),
-    //         "5".to_owned(),
-    //     ]);
-    //     let iter = vec.iter();
-    //     for _ in iter {}
-    //     // This requires the `vec` not to be consumed by the call to `iter()`
-    //     drop(vec);
-    // }
-
-    // Uncomment me for part J
-    // #[test]
-    // fn it_derefs() {
-    //     use std::ops::{Deref, DerefMut};
-    //     let vec: LocalStorageVec<_, 128> = LocalStorageVec::from([0; 128]);
-    //     // `chunks` is a method that's defined for slices `[T]`, that we can use thanks to `Deref`
-    //     let chunks = vec.chunks(4);
-    //     let slice: &[_] = vec.deref();
-    //
-    //     let mut vec: LocalStorageVec<_, 128> = LocalStorageVec::from([0; 128]);
-    //     let chunks = vec.chunks_mut(4);
-    //     let slice: &mut [_] = vec.deref_mut();
-    // }
+    #[test]
+    fn it_from_vecs() {
+        // The `vec!` macro creates a `Vec<T>` in a way that resembles
+        // array-initialization syntax.
+        let vec: LocalStorageVec<usize, 10> = LocalStorageVec::from(vec![1, 2, 3]);
+        // Assert that the call to `from` indeed yields a `Heap` variant
+        assert!(matches!(vec, LocalStorageVec::Heap(_)));
+
+        let vec: LocalStorageVec<usize, 2> = LocalStorageVec::from(vec![1, 2, 3]);
+
+        assert!(matches!(vec, LocalStorageVec::Heap(_)));
+    }
+
+    #[test]
+    fn it_as_refs() {
+        let vec: LocalStorageVec<i32, 256> = LocalStorageVec::from([0; 128]);
+        let slice: &[i32] = vec.as_ref();
+        assert!(slice.len() == 128);
+        let vec: LocalStorageVec<i32, 32> = LocalStorageVec::from([0; 128]);
+        let slice: &[i32] = vec.as_ref();
+        assert!(slice.len() == 128);
+
+        let mut vec: LocalStorageVec<i32, 256> = LocalStorageVec::from([0; 128]);
+        let slice_mut: &[i32] = vec.as_mut();
+        assert!(slice_mut.len() == 128);
+        let mut vec: LocalStorageVec<i32, 32> = LocalStorageVec::from([0; 128]);
+        let slice_mut: &[i32] = vec.as_mut();
+        assert!(slice_mut.len() == 128);
+    }
+
+    #[test]
+    fn it_constructs() {
+        let vec: LocalStorageVec<usize, 10> = LocalStorageVec::new();
+        // Assert that the call to `new` indeed yields a `Stack` variant with zero length
+        assert!(matches!(&vec, LocalStorageVec::Stack(buf) if buf.is_empty()));
+    }
+
+    #[test]
+    fn it_lens() {
+        let vec: LocalStorageVec<_, 3> = LocalStorageVec::from([0, 1, 2]);
+        assert_eq!(vec.len(), 3);
+        let vec: LocalStorageVec<_, 2> = LocalStorageVec::from([0, 1, 2]);
+        assert_eq!(vec.len(), 3);
+    }
+
+    #[test]
+    fn it_pushes() {
+        let mut vec: LocalStorageVec<_, 128> = LocalStorageVec::new();
+        for value in 0..128 {
+            vec.push(value);
+        }
+        assert!(matches!(&vec, LocalStorageVec::Stack(buf) if buf.len() == 128));
+        for value in 128..256 {
+            vec.push(value);
+        }
+        assert!(matches!(vec, LocalStorageVec::Heap(v) if v.len() == 256))
+    }
+
+    #[test]
+    fn it_pops() {
+        let mut vec: LocalStorageVec<_, 128> = LocalStorageVec::from([0; 128]);
+        for _ in 0..128 {
+            assert_eq!(vec.pop(), Some(0))
+        }
+        assert_eq!(vec.pop(), None);
+
+        let mut vec: LocalStorageVec<_, 128> = LocalStorageVec::from([0; 256]);
+        for _ in 0..256 {
+            assert_eq!(vec.pop(), Some(0))
+        }
+        assert_eq!(vec.pop(), None);
+
+        let mut vec: LocalStorageVec<_, 128> = LocalStorageVec::from(vec![0; 256]);
+        for _ in 0..256 {
+            assert_eq!(vec.pop(), Some(0))
+        }
+        assert_eq!(vec.pop(), None);
+    }
+
+    #[test]
+    fn it_inserts() {
+        let mut vec: LocalStorageVec<_, 4> = LocalStorageVec::from([0, 1, 2]);
+        vec.insert(1, 3);
+        assert!(matches!(vec, LocalStorageVec::Stack(_)));
+        assert_eq!(vec, [0, 3, 1, 2]);
+
+        let mut vec: LocalStorageVec<_, 4> = LocalStorageVec::from([0, 1, 2, 3]);
+        vec.insert(1, 3);
+        assert!(matches!(vec, LocalStorageVec::Heap { .. }));
+        assert_eq!(vec.as_ref(), &[0, 3, 1, 2, 3]);
+
+        let mut vec: LocalStorageVec<_, 4> = LocalStorageVec::from([0, 1, 2, 3, 4]);
+        vec.insert(1, 3);
+        assert!(matches!(vec, LocalStorageVec::Heap { .. }));
+        assert_eq!(vec.as_ref(), &[0, 3, 1, 2, 3, 4])
+    }
+
+    #[test]
+    fn it_removes() {
+        let mut vec: LocalStorageVec<_, 4> = LocalStorageVec::from([0, 1, 2]);
+        let elem = vec.remove(1);
+        assert!(matches!(vec, LocalStorageVec::Stack(_)));
+        assert_eq!(vec, [0, 2]);
+        assert_eq!(elem, 1);
+
+        let mut vec: LocalStorageVec<_, 2> = LocalStorageVec::from([0, 1, 2]);
+        let elem = vec.remove(1);
+        assert!(matches!(vec, LocalStorageVec::Heap(..)));
+        assert_eq!(vec.as_ref(), &[0, 2]);
+        assert_eq!(elem, 1);
+    }
+
+    #[test]
+    fn it_clears() {
+        let mut vec: LocalStorageVec<_, 10> = LocalStorageVec::from([0, 1, 2, 3]);
+        assert!(matches!(&vec, LocalStorageVec::Stack(buf) if buf.len() == 4));
+        vec.clear();
+        assert_eq!(vec.len(), 0);
+
+        let mut vec: LocalStorageVec<_, 3> = LocalStorageVec::from([0, 1, 2, 3]);
+        assert!(matches!(vec, LocalStorageVec::Heap(_)));
+        vec.clear();
+        assert_eq!(vec.len(), 0);
+    }
+
+    #[test]
+    fn it_iters() {
+        let vec: LocalStorageVec<_, 128> = LocalStorageVec::from([0; 32]);
+        let mut iter = vec.into_iter();
+        for item in &mut iter {
+            assert_eq!(item, 0);
+        }
+        assert_eq!(iter.next(), None);
+
+        let vec: LocalStorageVec<_, 128> = LocalStorageVec::from(vec![0; 128]);
+        let mut iter = vec.into_iter();
+        for item in &mut iter {
+            assert_eq!(item, 0);
+        }
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn it_indexes() {
+        let vec: LocalStorageVec<i32, 10> = LocalStorageVec::from([0, 1, 2, 3, 4, 5]);
+        assert_eq!(vec[1], 1);
+        assert_eq!(vec[..2], [0, 1]);
+        assert_eq!(vec[4..], [4, 5]);
+        assert_eq!(vec[1..3], [1, 2]);
+    }
+
+    #[test]
+    fn it_borrowing_iters() {
+        let vec: LocalStorageVec<String, 10> = LocalStorageVec::from([
+            "0".to_owned(),
+            "1".to_owned(),
+            "2".to_owned(),
+            "3".to_owned(),
+            "4".to_owned(),
+            "5".to_owned(),
+        ]);
+        let iter = vec.iter();
+        for _ in iter {}
+        // This requires the `vec` not to be consumed by the call to `iter()`
+        drop(vec);
+    }
+
+    #[test]
+    fn it_derefs() {
+        use std::ops::{Deref, DerefMut};
+        let vec: LocalStorageVec<_, 128> = LocalStorageVec::from([0; 128]);
+        // `chunks` is a method that's defined for slices `[T]`, that we can use thanks to `Deref`
+        let chunks = vec.chunks(4);
+        assert_eq!(chunks.len(), 32);
+        let slice: &[_] = vec.deref();
+        assert_eq!(slice.len(), 128);
+
+        let mut vec: LocalStorageVec<_, 128> = LocalStorageVec::from([0; 128]);
+        let chunks = vec.chunks_mut(4);
+        assert_eq!(chunks.len(), 32);
+        let slice: &mut [_] = vec.deref_mut();
+        slice[0] = 1;
+        assert_eq!(vec[0], 1);
+    }
+
+    #[test]
+    fn it_spills() {
+        let mut vec: LocalStorageVec<String, 2> = LocalStorageVec::new();
+        vec.push("a".to_owned());
+        vec.push("b".to_owned());
+        assert!(matches!(&vec, LocalStorageVec::Stack(buf) if buf.len() == 2));
+        vec.push("c".to_owned());
+        assert!(matches!(vec, LocalStorageVec::Heap(_)));
+        assert_eq!(vec, ["a", "b", "c"].map(String::from));
+
+        let mut vec: LocalStorageVec<u8, 4> = LocalStorageVec::from([1, 2]);
+        vec.reserve(2);
+        assert!(matches!(&vec, LocalStorageVec::Stack(buf) if buf.len() == 2));
+        vec.reserve(3);
+        assert!(matches!(vec, LocalStorageVec::Heap(_)));
+        assert!(vec.capacity() >= 5);
+        assert_eq!(vec, [1, 2]);
+
+        assert!(matches!(
+            LocalStorageVec::<u8, 4>::with_capacity(4),
+            LocalStorageVec::Stack(_)
+        ));
+        assert!(LocalStorageVec::<u8, 4>::with_capacity(5).capacity() >= 5);
+    }
+
+    #[test]
+    fn it_extends() {
+        let mut vec: LocalStorageVec<_, 4> = (0..3).collect();
+        assert!(matches!(&vec, LocalStorageVec::Stack(buf) if buf.len() == 3));
+        vec.extend([3]);
+        assert!(matches!(&vec, LocalStorageVec::Stack(buf) if buf.len() == 4));
+        vec.extend(&[4, 5]);
+        assert!(matches!(vec, LocalStorageVec::Heap(_)));
+        vec.extend_from_slice(&[6]);
+        assert_eq!(vec, [0, 1, 2, 3, 4, 5, 6]);
+
+        // Iterators without a useful size hint spill when they need to
+        let vec: LocalStorageVec<_, 4> = (0..10).filter(|i| i % 2 == 0).collect();
+        assert!(matches!(vec, LocalStorageVec::Heap(_)));
+        assert_eq!(vec, [0, 2, 4, 6, 8]);
+    }
+
+    #[test]
+    fn it_truncates_and_retains() {
+        let mut vec: LocalStorageVec<_, 8> = LocalStorageVec::from([0, 1, 2, 3, 4, 5]);
+        vec.truncate(10);
+        assert_eq!(vec.len(), 6);
+        vec.truncate(4);
+        assert!(matches!(vec, LocalStorageVec::Stack(_)));
+        assert_eq!(vec, [0, 1, 2, 3]);
+        vec.retain(|i| i % 2 == 1);
+        assert_eq!(vec, [1, 3]);
+
+        let mut vec: LocalStorageVec<_, 2> = (0..10).collect();
+        vec.retain(|i| i % 3 == 0);
+        assert_eq!(vec, [0, 3, 6, 9]);
+        vec.truncate(1);
+        assert_eq!(vec, [0]);
+
+        let mut vec: LocalStorageVec<_, 4> = LocalStorageVec::from([0, 1, 2, 3]);
+        assert_eq!(vec.swap_remove(0), 0);
+        assert_eq!(vec, [3, 1, 2]);
+    }
+
+    #[test]
+    fn it_drains() {
+        let mut vec: LocalStorageVec<_, 8> = LocalStorageVec::from([0, 1, 2, 3, 4, 5]);
+        let drained: Vec<_> = vec.drain(1..3).collect();
+        assert_eq!(drained, [1, 2]);
+        assert_eq!(vec, [0, 3, 4, 5]);
+
+        // Items that are not returned are removed as well
+        let mut drain = vec.drain(..=2);
+        assert_eq!(drain.next_back(), Some(4));
+        assert_eq!(drain.as_slice(), [0, 3]);
+        drop(drain);
+        assert!(matches!(vec, LocalStorageVec::Stack(_)));
+        assert_eq!(vec, [5]);
+
+        let mut vec: LocalStorageVec<_, 2> = (0..6).map(|i| i.to_string()).collect();
+        assert_eq!(vec.drain(4..).rev().collect::<Vec<_>>(), ["5", "4"]);
+        vec.drain(..);
+        assert!(vec.is_empty());
+    }
+
+    #[test]
+    #[should_panic = "range end index 4 out of range for slice of length 3"]
+    fn it_drains_in_bounds() {
+        let mut vec: LocalStorageVec<_, 8> = LocalStorageVec::from([0, 1, 2]);
+        vec.drain(1..4);
+    }
+
+    #[test]
+    #[should_panic = "attempted to index slice up to maximum usize"]
+    fn it_drains_up_to_usize_max() {
+        let mut vec: LocalStorageVec<_, 8> = LocalStorageVec::from([0, 1, 2]);
+        vec.drain(..=usize::MAX);
+    }
+
+    #[test]
+    #[should_panic = "attempted to index slice from after maximum usize"]
+    fn it_drains_from_after_usize_max() {
+        let mut vec: LocalStorageVec<_, 8> = LocalStorageVec::from([0, 1, 2]);
+        vec.drain((
+            std::ops::Bound::Excluded(usize::MAX),
+            std::ops::Bound::Unbounded,
+        ));
+    }
+
+    #[test]
+    fn it_iterates_both_ways() {
+        let vec: LocalStorageVec<_, 8> = LocalStorageVec::from([0, 1, 2, 3, 4]);
+        let mut iter = vec.into_iter();
+        assert_eq!(iter.len(), 5);
+        assert_eq!(iter.next(), Some(0));
+        assert_eq!(iter.next_back(), Some(4));
+        assert_eq!(iter.as_slice(), [1, 2, 3]);
+        assert_eq!(iter.nth(1), Some(2));
+        assert_eq!(iter.next_back(), Some(3));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+
+        let mut vec: LocalStorageVec<_, 2> = LocalStorageVec::from([1, 2, 3]);
+        for item in &mut vec {
+            *item *= 10;
+        }
+        assert_eq!((&vec).into_iter().sum::<i32>(), 60);
+    }
+
+    #[test]
+    fn it_compares_and_hashes() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+
+        fn hash(value: impl Hash) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            value.hash(&mut hasher);
+            hasher.finish()
+        }
+
+        let stack: LocalStorageVec<_, 4> = LocalStorageVec::from([1, 2, 3]);
+        let heap: LocalStorageVec<_, 4> = LocalStorageVec::from(vec![1, 2, 3]);
+        let other: LocalStorageVec<_, 2> = LocalStorageVec::from([1, 2, 3]);
+        assert_eq!(stack, heap);
+        assert_eq!(stack, other);
+        assert_eq!(hash(&stack), hash(&heap));
+        assert_eq!(hash(&stack), hash([1, 2, 3].as_slice()));
+        assert_ne!(stack, LocalStorageVec::<_, 4>::from([1, 2]));
+        assert_eq!(*stack, [1, 2, 3][..]);
+
+        let clone = stack.clone();
+        assert!(matches!(&clone, LocalStorageVec::Stack(buf) if buf.len() == 3));
+        assert_eq!(clone, stack);
+        assert!(matches!(heap.clone(), LocalStorageVec::Heap(_)));
+
+        assert_eq!(format!("{stack:?}"), "[1, 2, 3]");
+    }
+
+    /// An item that keeps count of how many of its kind are alive. The value
+    /// is boxed, so Miri notices items that are dropped twice or leaked.
+    #[derive(Debug)]
+    struct Tracked<'a> {
+        value: Box<u32>,
+        live: &'a Cell<usize>,
+    }
+
+    const PANIC_ON_CLONE: u32 = 13;
+    const PANIC_ON_DROP: u32 = 666;
+
+    impl<'a> Tracked<'a> {
+        fn new(live: &'a Cell<usize>, value: u32) -> Self {
+            live.set(live.get() + 1);
+            Tracked {
+                value: Box::new(value),
+                live,
+            }
+        }
+    }
+
+    impl Clone for Tracked<'_> {
+        fn clone(&self) -> Self {
+            assert_ne!(*self.value, PANIC_ON_CLONE, "cannot clone");
+            Tracked::new(self.live, *self.value)
+        }
+    }
+
+    impl Drop for Tracked<'_> {
+        fn drop(&mut self) {
+            self.live.set(self.live.get() - 1);
+            if *self.value == PANIC_ON_DROP && !std::thread::panicking() {
+                panic!("cannot drop");
+            }
+        }
+    }
+
+    fn tracked<const N: usize>(
+        live: &Cell<usize>,
+        values: impl IntoIterator<Item = u32>,
+    ) -> LocalStorageVec<Tracked<'_>, N> {
+        values.into_iter().map(|i| Tracked::new(live, i)).collect()
+    }
+
+    fn values<const N: usize>(vec: &LocalStorageVec<Tracked<'_>, N>) -> Vec<u32> {
+        vec.iter().map(|item| *item.value).collect()
+    }
+
+    #[test]
+    fn it_drops_items() {
+        let live = Cell::new(0);
+        let mut vec = tracked::<4>(&live, 0..3);
+        assert!(matches!(vec, LocalStorageVec::Stack(_)));
+        assert_eq!(vec.pop().map(|item| *item.value), Some(2));
+        vec.insert(0, Tracked::new(&live, 5));
+        assert_eq!(*vec.remove(1).value, 0);
+        assert_eq!(live.get(), 2);
+        drop(vec);
+        assert_eq!(live.get(), 0);
+
+        for len in [3, 6] {
+            let mut vec = tracked::<4>(&live, 0..len);
+            vec.truncate(2);
+            assert_eq!(live.get(), 2);
+            vec.extend([Tracked::new(&live, 7), Tracked::new(&live, 8)]);
+            vec.retain(|item| *item.value % 2 == 0);
+            assert_eq!(values(&vec), [0, 8]);
+            assert_eq!(live.get(), 2);
+            vec.clear();
+            assert_eq!(live.get(), 0);
+        }
+
+        // Spilling moves the items, without dropping or copying any
+        let mut vec = tracked::<2>(&live, 0..2);
+        vec.push(Tracked::new(&live, 2));
+        assert!(matches!(vec, LocalStorageVec::Heap(_)));
+        assert_eq!(values(&vec), [0, 1, 2]);
+        assert_eq!(live.get(), 3);
+        drop(vec);
+        assert_eq!(live.get(), 0);
+    }
+
+    #[test]
+    fn it_drops_unreturned_items() {
+        let live = Cell::new(0);
+        for len in [4, 8] {
+            let mut iter = tracked::<4>(&live, 0..len).into_iter();
+            assert_eq!(iter.next().map(|item| *item.value), Some(0));
+            assert_eq!(iter.next_back().map(|item| *item.value), Some(len - 1));
+            assert_eq!(live.get(), len as usize - 2);
+            drop(iter);
+            assert_eq!(live.get(), 0);
+
+            let mut vec = tracked::<4>(&live, 0..len);
+            let mut drain = vec.drain(1..3);
+            assert_eq!(drain.next().map(|item| *item.value), Some(1));
+            drop(drain);
+            assert_eq!(live.get(), len as usize - 2);
+            assert_eq!(values(&vec)[..2], [0, 3]);
+            drop(vec);
+            assert_eq!(live.get(), 0);
+        }
+
+        // A leaked `Drain` leaks the items after the range too, but leaves a
+        // valid vector behind
+        let mut vec: LocalStorageVec<_, 4> = LocalStorageVec::from([0, 1, 2, 3]);
+        std::mem::forget(vec.drain(1..2));
+        assert_eq!(vec, [0]);
+        vec.push(4);
+        assert_eq!(vec, [0, 4]);
+    }
+
+    #[test]
+    fn it_survives_panics() {
+        let live = Cell::new(0);
+
+        // The items cloned so far are dropped, and the original is untouched
+        let vec = tracked::<4>(&live, [1, 2, PANIC_ON_CLONE]);
+        let result = catch_unwind(AssertUnwindSafe(|| vec.clone()));
+        assert!(result.is_err());
+        assert_eq!(live.get(), 3);
+        assert_eq!(values(&vec), [1, 2, PANIC_ON_CLONE]);
+        drop(vec);
+
+        // The items extended with so far stay in the vector
+        for n in [2, 4] {
+            let mut vec = tracked::<4>(&live, 0..n);
+            let result = catch_unwind(AssertUnwindSafe(|| {
+                vec.extend((10..20).map(|i| {
+                    assert!(i < 12, "no more items");
+                    Tracked::new(&live, i)
+                }))
+            }));
+            assert!(result.is_err());
+            assert_eq!(values(&vec)[n as usize..], [10, 11]);
+            assert_eq!(live.get(), n as usize + 2);
+            drop(vec);
+            assert_eq!(live.get(), 0);
+        }
+
+        // The other items are still dropped, once
+        let vec = tracked::<4>(&live, [1, PANIC_ON_DROP, 3]);
+        assert!(catch_unwind(AssertUnwindSafe(|| drop(vec))).is_err());
+        assert_eq!(live.get(), 0);
+
+        let mut vec = tracked::<4>(&live, [1, PANIC_ON_DROP, 3, 4]);
+        assert!(catch_unwind(AssertUnwindSafe(|| vec.drain(..3).for_each(drop))).is_err());
+        assert_eq!(values(&vec), [4]);
+        drop(vec);
+        assert_eq!(live.get(), 0);
+
+        let mut vec = tracked::<4>(&live, [1, PANIC_ON_DROP, 3, 4]);
+        assert!(catch_unwind(AssertUnwindSafe(|| drop(vec.drain(..3)))).is_err());
+        assert_eq!(values(&vec), [4]);
+        drop(vec);
+        assert_eq!(live.get(), 0);
+    }
+
+    #[test]
+    fn it_holds_any_type() {
+        // None of these types have a default
+        struct NoDefault(#[allow(dead_code)] u8);
+        let mut vec: LocalStorageVec<_, 2> = LocalStorageVec::from([NoDefault(1)]);
+        vec.push(NoDefault(2));
+        assert_eq!(vec.len(), 2);
+
+        let vec: LocalStorageVec<&str, 3> = LocalStorageVec::from(["a", "b"]);
+        assert_eq!(vec.into_iter().collect::<String>(), "ab");
+    }
 }
@@ -0,0 +1,129 @@
+//! Serde support, with the `serde` feature.
+//!
+//! Vectors are serialized as sequences and strings as strings, like `Vec`
+//! and `String`. Both are deserialized into the stack buffer as long as
+//! they fit, and only allocate when they do not.
+
+use std::fmt;
+use std::marker::PhantomData;
+
+use serde::de::{self, Deserialize, Deserializer, SeqAccess, Visitor};
+use serde::ser::{Serialize, Serializer};
+
+use crate::{LocalStorageString, LocalStorageVec};
+
+/// Do not trust size hints beyond this many items, as they come from the
+/// input. Longer sequences still work, they just grow as they go.
+const MAX_PREALLOCATED: usize = 4096;
+
+impl<T: Serialize, const N: usize> Serialize for LocalStorageVec<T, N> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self)
+    }
+}
+
+impl<'de, T: Deserialize<'de>, const N: usize> Deserialize<'de> for LocalStorageVec<T, N> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct VecVisitor<T, const N: usize>(PhantomData<T>);
+
+        impl<'de, T: Deserialize<'de>, const N: usize> Visitor<'de> for VecVisitor<T, N> {
+            type Value = LocalStorageVec<T, N>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a sequence")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let capacity = seq.size_hint().unwrap_or(0).min(MAX_PREALLOCATED);
+                let mut vec = LocalStorageVec::with_capacity(capacity);
+                while let Some(item) = seq.next_element()? {
+                    vec.push(item);
+                }
+                Ok(vec)
+            }
+        }
+
+        deserializer.deserialize_seq(VecVisitor(PhantomData))
+    }
+}
+
+impl<const N: usize> Serialize for LocalStorageString<N> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self)
+    }
+}
+
+impl<'de, const N: usize> Deserialize<'de> for LocalStorageString<N> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct StringVisitor<const N: usize>;
+
+        impl<const N: usize> Visitor<'_> for StringVisitor<N> {
+            type Value = LocalStorageString<N>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a string")
+            }
+
+            // Borrowed and owned strings are copied in as well, so short
+            // strings end up on the stack
+            fn visit_str<E: de::Error>(self, s: &str) -> Result<Self::Value, E> {
+                Ok(s.into())
+            }
+
+            fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<Self::Value, E> {
+                match std::str::from_utf8(bytes) {
+                    Ok(s) => Ok(s.into()),
+                    Err(_) => Err(de::Error::invalid_value(
+                        de::Unexpected::Bytes(bytes),
+                        &self,
+                    )),
+                }
+            }
+        }
+
+        deserializer.deserialize_str(StringVisitor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_vec() {
+        let vec: LocalStorageVec<u32, 4> = LocalStorageVec::from([1, 2, 3]);
+        let json = serde_json::to_string(&vec).unwrap();
+        assert_eq!(json, "[1,2,3]");
+
+        let vec: LocalStorageVec<u32, 4> = serde_json::from_str(&json).unwrap();
+        assert!(matches!(vec, LocalStorageVec::Stack(_)));
+        assert_eq!(vec, [1, 2, 3]);
+        let vec: LocalStorageVec<u32, 2> = serde_json::from_str(&json).unwrap();
+        assert!(matches!(vec, LocalStorageVec::Heap(_)));
+        assert_eq!(vec, [1, 2, 3]);
+
+        let nested: Vec<LocalStorageVec<String, 2>> =
+            serde_json::from_str(r#"[[], ["a"], ["b", "c", "d"]]"#).unwrap();
+        assert_eq!(nested[2], ["b", "c", "d"].map(String::from));
+        assert!(serde_json::from_str::<LocalStorageVec<u8, 2>>(r#"[1, "x"]"#).is_err());
+    }
+
+    #[test]
+    fn test_string() {
+        let s: LocalStorageString<8> = "héllo".into();
+        let json = serde_json::to_string(&s).unwrap();
+        assert_eq!(json, r#""héllo""#);
+
+        // Escapes make serde_json hand over an owned string
+        for json in [r#""héllo""#, r#""h\u00e9llo""#] {
+            let s: LocalStorageString<8> = serde_json::from_str(json).unwrap();
+            assert!(matches!(s.clone().into_bytes(), LocalStorageVec::Stack(_)));
+            assert_eq!(s, "héllo");
+        }
+        let s: LocalStorageString<4> = serde_json::from_str(&json).unwrap();
+        assert!(matches!(s.clone().into_bytes(), LocalStorageVec::Heap(_)));
+        assert_eq!(s, "héllo");
+
+        assert!(serde_json::from_str::<LocalStorageString<4>>("[1]").is_err());
+    }
+}
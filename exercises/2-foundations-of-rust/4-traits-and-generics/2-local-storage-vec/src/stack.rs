@@ -0,0 +1,142 @@
+//! The inline storage of a [LocalStorageVec](crate::LocalStorageVec).
+//!
+//! This is the only place that deals with uninitialized memory: everything
+//! else goes through the safe methods here, or through the same raw parts
+//! that a `Vec` has.
+
+use std::fmt::{self, Debug};
+use std::mem::MaybeUninit;
+use std::ptr;
+
+/// Room for `N` items on the stack, of which the first `len` are initialized
+pub struct StackBuf<T, const N: usize> {
+    buf: [MaybeUninit<T>; N],
+    len: usize,
+}
+
+impl<T, const N: usize> StackBuf<T, N> {
+    pub const fn new() -> Self {
+        StackBuf {
+            buf: [const { MaybeUninit::uninit() }; N],
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        // SAFETY: the first `len` items are initialized
+        unsafe { std::slice::from_raw_parts(self.as_ptr(), self.len) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        // SAFETY: the first `len` items are initialized
+        unsafe { std::slice::from_raw_parts_mut(self.as_mut_ptr(), self.len) }
+    }
+
+    pub(crate) fn as_ptr(&self) -> *const T {
+        self.buf.as_ptr().cast()
+    }
+
+    pub(crate) fn as_mut_ptr(&mut self) -> *mut T {
+        self.buf.as_mut_ptr().cast()
+    }
+
+    /// Set the number of initialized items, without dropping or
+    /// initializing anything.
+    ///
+    /// # Safety
+    ///
+    /// `new_len` must be at most `N`, and the first `new_len` items must be
+    /// initialized.
+    pub(crate) unsafe fn set_len(&mut self, new_len: usize) {
+        debug_assert!(new_len <= N);
+        self.len = new_len;
+    }
+
+    /// Append `value`, or give it back if the buffer is full
+    pub(crate) fn try_push(&mut self, value: T) -> Result<(), T> {
+        if self.len == N {
+            return Err(value);
+        }
+        self.buf[self.len].write(value);
+        self.len += 1;
+        Ok(())
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        // SAFETY: the item was initialized, and is no longer counted in `len`,
+        // so it will not be read or dropped again
+        Some(unsafe { self.buf[self.len].assume_init_read() })
+    }
+
+    pub(crate) fn truncate(&mut self, new_len: usize) {
+        if new_len >= self.len {
+            return;
+        }
+        let tail = ptr::slice_from_raw_parts_mut(
+            // SAFETY: `new_len < len <= N`, so this is in bounds
+            unsafe { self.as_mut_ptr().add(new_len) },
+            self.len - new_len,
+        );
+        // Forget the items before dropping them: if a drop panics, the rest
+        // of the slice is still dropped, and none of it twice
+        self.len = new_len;
+        // SAFETY: the items were initialized, and are no longer counted in `len`
+        unsafe { ptr::drop_in_place(tail) };
+    }
+
+    /// Move all items to a new `Vec`, with room for `additional` more
+    pub(crate) fn take_vec(&mut self, additional: usize) -> Vec<T> {
+        let capacity = self.len.checked_add(additional).expect("capacity overflow");
+        let mut v = Vec::with_capacity(capacity);
+        // SAFETY: the `Vec` has room for `len` items, which cannot overlap a
+        // new allocation. Setting `len` to zero hands them over to the `Vec`.
+        unsafe {
+            ptr::copy_nonoverlapping(self.as_ptr(), v.as_mut_ptr(), self.len);
+            v.set_len(self.len);
+        }
+        self.len = 0;
+        v
+    }
+}
+
+impl<T, const N: usize> Default for StackBuf<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Drop for StackBuf<T, N> {
+    fn drop(&mut self) {
+        self.truncate(0);
+    }
+}
+
+impl<T: Clone, const N: usize> Clone for StackBuf<T, N> {
+    fn clone(&self) -> Self {
+        // If a clone panics, `clone` is dropped with only the items cloned so far
+        let mut clone = StackBuf::new();
+        for item in self.as_slice() {
+            // Cannot be full, as `self` has at most `N` items
+            let _ = clone.try_push(item.clone());
+        }
+        clone
+    }
+}
+
+impl<T: Debug, const N: usize> Debug for StackBuf<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.as_slice()).finish()
+    }
+}
@@ -0,0 +1,425 @@
+//! A string that keeps short contents inline, on top of [LocalStorageVec].
+
+use std::borrow::Borrow;
+use std::error::Error;
+use std::fmt::{self, Debug, Display};
+use std::hash::{Hash, Hasher};
+use std::ops::{Deref, DerefMut};
+use std::str::{self, FromStr, Utf8Error};
+
+use crate::LocalStorageVec;
+
+/// A growable UTF-8 string that resides on the stack if it fits in `N`
+/// bytes, and is moved to the heap to grow larger if needed
+#[derive(Clone, Default)]
+pub struct LocalStorageString<const N: usize> {
+    /// Always valid UTF-8
+    vec: LocalStorageVec<u8, N>,
+}
+
+/// Bytes that were not valid UTF-8, returned by
+/// [LocalStorageString::from_utf8]
+#[derive(Debug)]
+pub struct FromUtf8Error<const N: usize> {
+    bytes: LocalStorageVec<u8, N>,
+    error: Utf8Error,
+}
+
+impl<const N: usize> FromUtf8Error<N> {
+    /// The bytes that were passed in
+    pub fn into_bytes(self) -> LocalStorageVec<u8, N> {
+        self.bytes
+    }
+
+    /// Where the bytes stopped being valid UTF-8
+    pub fn utf8_error(&self) -> Utf8Error {
+        self.error
+    }
+}
+
+impl<const N: usize> Display for FromUtf8Error<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.error, f)
+    }
+}
+
+impl<const N: usize> Error for FromUtf8Error<N> {}
+
+impl<const N: usize> LocalStorageString<N> {
+    /// An empty string, which does not allocate
+    pub const fn new() -> Self {
+        LocalStorageString {
+            vec: LocalStorageVec::new(),
+        }
+    }
+
+    /// An empty string with room for at least `capacity` bytes, which only
+    /// allocates if that does not fit on the stack
+    pub fn with_capacity(capacity: usize) -> Self {
+        LocalStorageString {
+            vec: LocalStorageVec::with_capacity(capacity),
+        }
+    }
+
+    /// Check that `bytes` are valid UTF-8, and use them as a string
+    pub fn from_utf8(bytes: LocalStorageVec<u8, N>) -> Result<Self, FromUtf8Error<N>> {
+        match str::from_utf8(&bytes) {
+            Ok(_) => Ok(LocalStorageString { vec: bytes }),
+            Err(error) => Err(FromUtf8Error { bytes, error }),
+        }
+    }
+
+    /// The length in bytes
+    pub fn len(&self) -> usize {
+        self.vec.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vec.is_empty()
+    }
+
+    /// The number of bytes that fit without allocating
+    pub fn capacity(&self) -> usize {
+        self.vec.capacity()
+    }
+
+    /// Make room for at least `additional` more bytes, moving to the heap if
+    /// they do not fit on the stack
+    pub fn reserve(&mut self, additional: usize) {
+        self.vec.reserve(additional);
+    }
+
+    pub fn as_str(&self) -> &str {
+        // SAFETY: `vec` is always valid UTF-8
+        unsafe { str::from_utf8_unchecked(&self.vec) }
+    }
+
+    pub fn as_mut_str(&mut self) -> &mut str {
+        // SAFETY: `vec` is always valid UTF-8, and `str` keeps it that way
+        unsafe { str::from_utf8_unchecked_mut(&mut self.vec) }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.vec
+    }
+
+    pub fn into_bytes(self) -> LocalStorageVec<u8, N> {
+        self.vec
+    }
+
+    pub fn push(&mut self, c: char) {
+        self.push_str(c.encode_utf8(&mut [0; 4]));
+    }
+
+    pub fn push_str(&mut self, s: &str) {
+        self.vec.extend_from_slice(s.as_bytes());
+    }
+
+    /// Remove and return the last character
+    pub fn pop(&mut self) -> Option<char> {
+        let c = self.chars().next_back()?;
+        self.vec.truncate(self.len() - c.len_utf8());
+        Some(c)
+    }
+
+    /// Insert `c` at byte offset `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is not on a character boundary.
+    pub fn insert(&mut self, index: usize, c: char) {
+        self.insert_str(index, c.encode_utf8(&mut [0; 4]));
+    }
+
+    /// Insert `s` at byte offset `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is not on a character boundary.
+    pub fn insert_str(&mut self, index: usize, s: &str) {
+        assert!(self.is_char_boundary(index), "index is not a char boundary");
+        self.push_str(s);
+        self.vec[index..].rotate_right(s.len());
+    }
+
+    /// Remove and return the character at byte offset `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is not on a character boundary, or is the end of
+    /// the string.
+    pub fn remove(&mut self, index: usize) -> char {
+        let c = match self[index..].chars().next() {
+            Some(c) => c,
+            None => panic!("cannot remove a char from the end of a string"),
+        };
+        self.vec.drain(index..index + c.len_utf8());
+        c
+    }
+
+    /// Keep only the first `new_len` bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `new_len` is not on a character boundary.
+    pub fn truncate(&mut self, new_len: usize) {
+        if new_len < self.len() {
+            assert!(
+                self.is_char_boundary(new_len),
+                "new_len is not a char boundary"
+            );
+            self.vec.truncate(new_len);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.vec.clear();
+    }
+}
+
+impl<const N: usize> Deref for LocalStorageString<N> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl<const N: usize> DerefMut for LocalStorageString<N> {
+    fn deref_mut(&mut self) -> &mut str {
+        self.as_mut_str()
+    }
+}
+
+impl<const N: usize> AsRef<str> for LocalStorageString<N> {
+    fn as_ref(&self) -> &str {
+        self
+    }
+}
+
+impl<const N: usize> AsRef<[u8]> for LocalStorageString<N> {
+    fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+/// Lets strings be looked up by `&str` in maps and sets
+impl<const N: usize> Borrow<str> for LocalStorageString<N> {
+    fn borrow(&self) -> &str {
+        self
+    }
+}
+
+impl<const N: usize> fmt::Write for LocalStorageString<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push_str(s);
+        Ok(())
+    }
+
+    fn write_char(&mut self, c: char) -> fmt::Result {
+        self.push(c);
+        Ok(())
+    }
+}
+
+impl<const N: usize> Display for LocalStorageString<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(self.as_str(), f)
+    }
+}
+
+impl<const N: usize> Debug for LocalStorageString<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl<const N: usize> From<&str> for LocalStorageString<N> {
+    fn from(s: &str) -> Self {
+        let mut string = Self::with_capacity(s.len());
+        string.push_str(s);
+        string
+    }
+}
+
+impl<const N: usize> From<String> for LocalStorageString<N> {
+    /// Keeps the allocation of `s`, instead of moving short strings to the stack
+    fn from(s: String) -> Self {
+        LocalStorageString {
+            vec: s.into_bytes().into(),
+        }
+    }
+}
+
+impl<const N: usize> From<char> for LocalStorageString<N> {
+    fn from(c: char) -> Self {
+        Self::from(&*c.encode_utf8(&mut [0; 4]))
+    }
+}
+
+impl<const N: usize> FromStr for LocalStorageString<N> {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(s.into())
+    }
+}
+
+impl<const N: usize> Extend<char> for LocalStorageString<N> {
+    fn extend<I: IntoIterator<Item = char>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        self.reserve(iter.size_hint().0);
+        iter.for_each(|c| self.push(c));
+    }
+}
+
+impl<'a, const N: usize> Extend<&'a str> for LocalStorageString<N> {
+    fn extend<I: IntoIterator<Item = &'a str>>(&mut self, iter: I) {
+        iter.into_iter().for_each(|s| self.push_str(s));
+    }
+}
+
+impl<const N: usize> FromIterator<char> for LocalStorageString<N> {
+    fn from_iter<I: IntoIterator<Item = char>>(iter: I) -> Self {
+        let mut string = Self::new();
+        string.extend(iter);
+        string
+    }
+}
+
+impl<'a, const N: usize> FromIterator<&'a str> for LocalStorageString<N> {
+    fn from_iter<I: IntoIterator<Item = &'a str>>(iter: I) -> Self {
+        let mut string = Self::new();
+        string.extend(iter);
+        string
+    }
+}
+
+/// Strings are equal if their contents are, wherever they are stored
+impl<const N: usize, const M: usize> PartialEq<LocalStorageString<M>> for LocalStorageString<N> {
+    fn eq(&self, other: &LocalStorageString<M>) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl<const N: usize> Eq for LocalStorageString<N> {}
+
+impl<const N: usize> PartialEq<str> for LocalStorageString<N> {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl<const N: usize> PartialEq<&str> for LocalStorageString<N> {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl<const N: usize> PartialEq<String> for LocalStorageString<N> {
+    fn eq(&self, other: &String) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl<const N: usize> PartialOrd for LocalStorageString<N> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<const N: usize> Ord for LocalStorageString<N> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+/// Hashes like a `str`, as required by [Borrow]
+impl<const N: usize> Hash for LocalStorageString<N> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+    use std::fmt::Write;
+
+    use super::*;
+
+    #[test]
+    fn test_push_and_pop() {
+        let mut s: LocalStorageString<8> = LocalStorageString::new();
+        s.push_str("héllo");
+        assert_eq!(s.len(), 6);
+        s.push('!');
+        assert!(matches!(s.vec, LocalStorageVec::Stack(_)));
+        s.push('€');
+        assert!(matches!(s.vec, LocalStorageVec::Heap(_)));
+        assert_eq!(s, "héllo!€");
+        assert_eq!(s.pop(), Some('€'));
+        assert_eq!(s.pop(), Some('!'));
+        assert_eq!(s.to_uppercase(), "HÉLLO");
+
+        let mut s: LocalStorageString<4> = LocalStorageString::new();
+        assert_eq!(s.pop(), None);
+        let (n, c) = (1, 'x');
+        write!(s, "{n}-{c}").unwrap();
+        assert_eq!(s, "1-x");
+        assert_eq!(format!("{s:>5}|{s:?}"), "  1-x|\"1-x\"");
+    }
+
+    #[test]
+    fn test_edit() {
+        let mut s: LocalStorageString<16> = "añb".into();
+        s.insert(1, 'ü');
+        s.insert_str(0, "¡");
+        assert_eq!(s, "¡aüñb");
+        assert_eq!(s.remove(3), 'ü');
+        assert_eq!(s, "¡añb");
+        s.truncate(5);
+        assert_eq!(s, "¡añ");
+        s.make_ascii_uppercase();
+        assert_eq!(s, "¡Añ");
+        s.clear();
+        assert!(s.is_empty());
+    }
+
+    #[test]
+    #[should_panic = "not a char boundary"]
+    fn test_truncate_boundary() {
+        let mut s: LocalStorageString<16> = "añb".into();
+        s.truncate(2);
+    }
+
+    #[test]
+    fn test_utf8() {
+        let bytes: LocalStorageVec<u8, 4> = LocalStorageVec::from(*b"ok");
+        assert_eq!(LocalStorageString::from_utf8(bytes).unwrap(), "ok");
+
+        let bytes: LocalStorageVec<u8, 4> = LocalStorageVec::from([b'a', 0xff]);
+        let error = LocalStorageString::from_utf8(bytes).unwrap_err();
+        assert_eq!(error.utf8_error().valid_up_to(), 1);
+        assert_eq!(error.into_bytes(), [b'a', 0xff]);
+    }
+
+    #[test]
+    fn test_traits() {
+        let a: LocalStorageString<2> = "abc".parse().unwrap();
+        let b: LocalStorageString<8> = ["a", "b", "c"].into_iter().collect();
+        assert_eq!(a, b);
+        assert_eq!(b, String::from("abc"));
+        assert_eq!(b, *"abc");
+        let c: LocalStorageString<8> = "abd".chars().collect();
+        assert!(b < c);
+
+        let set: HashSet<LocalStorageString<8>> = [b, c].into_iter().collect();
+        assert!(set.contains("abc"));
+        assert!(!set.contains("abe"));
+
+        let s: LocalStorageString<8> = String::with_capacity(32).into();
+        assert!(s.capacity() >= 32);
+        assert_eq!(LocalStorageString::<1>::from('é'), "é");
+    }
+}
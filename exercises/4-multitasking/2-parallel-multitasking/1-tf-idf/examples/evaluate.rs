@@ -0,0 +1,93 @@
+//! Compare the scorers on a few queries for which we know the answer.
+//!
+//! Run with `cargo run --release --example evaluate`.
+
+use tf_idf::{evaluate, Bm25, Index, LabelledQuery, NormalizedTfIdf, Scorer, TfIdf, DOCUMENTS};
+
+const MIDDLEMARCH: &str = "Middlemarch";
+const ROMEO: &str = "THE TRAGEDY OF ROMEO AND JULIET";
+const ROOM: &str = "A Room With A View";
+const APRIL: &str = "The Enchanted April";
+const WOMEN: &str = "Little Women";
+
+const QUERIES: &[LabelledQuery] = &[
+    LabelledQuery {
+        query: "romeo juliet",
+        relevant: &[ROMEO],
+    },
+    LabelledQuery {
+        query: "verona friar",
+        relevant: &[ROMEO],
+    },
+    LabelledQuery {
+        query: "banished poison",
+        relevant: &[ROMEO],
+    },
+    LabelledQuery {
+        query: "dorothea casaubon",
+        relevant: &[MIDDLEMARCH],
+    },
+    LabelledQuery {
+        query: "clergyman doctor marriage",
+        relevant: &[MIDDLEMARCH],
+    },
+    LabelledQuery {
+        query: "lucy honeychurch",
+        relevant: &[ROOM],
+    },
+    LabelledQuery {
+        query: "florence",
+        relevant: &[ROOM],
+    },
+    LabelledQuery {
+        query: "lotty wilkins san salvatore",
+        relevant: &[APRIL],
+    },
+    LabelledQuery {
+        query: "mediterranean holiday",
+        relevant: &[APRIL],
+    },
+    LabelledQuery {
+        query: "italy",
+        relevant: &[ROOM, APRIL],
+    },
+    LabelledQuery {
+        query: "meg jo beth amy",
+        relevant: &[WOMEN],
+    },
+    LabelledQuery {
+        query: "christmas sisters",
+        relevant: &[WOMEN],
+    },
+];
+
+fn main() {
+    let index = Index::build(DOCUMENTS);
+
+    let scorers: &[(&str, &dyn Scorer)] = &[
+        ("tf-idf", &TfIdf),
+        ("normalized tf-idf", &NormalizedTfIdf),
+        ("bm25", &Bm25::default()),
+        ("bm25 (b = 0)", &Bm25 { k1: 1.2, b: 0.0 }),
+        ("bm25 (k1 = 2)", &Bm25 { k1: 2.0, b: 0.75 }),
+    ];
+
+    for k in [1, 2] {
+        println!("top {k}");
+        println!(
+            "{:<20}{:>8}{:>8}{:>8}{:>8}",
+            "scorer", "P", "R", "MRR", "MAP"
+        );
+        for (name, scorer) in scorers {
+            let evaluation = evaluate(&index, *scorer, QUERIES, k);
+            println!(
+                "{name:<20}{:>8.3}{:>8.3}{:>8.3}{:>8.3}",
+                evaluation.precision,
+                evaluation.recall,
+                evaluation.reciprocal_rank,
+                evaluation.average_precision,
+            );
+        }
+        println!();
+    }
+}
@@ -0,0 +1,323 @@
+//! Turning text into terms. Documents and queries go through the same
+//! analyzer, so that "Romeo," in a book is found by a query for "romeo".
+
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap};
+use std::ops::Range;
+
+use crate::stem::stem;
+
+/// The stop words of [Analyzer::default]: common English words that say
+/// little about what a document is about
+pub const ENGLISH_STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "if", "in", "into", "is", "it",
+    "no", "not", "of", "on", "or", "such", "that", "the", "their", "then", "there", "these",
+    "they", "this", "to", "was", "will", "with",
+];
+
+/// The steps that turn text into terms, in the order they are applied:
+///
+/// 1. removing the Project Gutenberg header and footer (documents only)
+/// 2. splitting on whitespace, or on punctuation as well
+/// 3. lowercasing
+/// 4. dropping stop words
+/// 5. stemming
+///
+/// [Analyzer::default] does all of these, [Analyzer::whitespace] none.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Analyzer {
+    pub(crate) strip_gutenberg: bool,
+    pub(crate) strip_punctuation: bool,
+    pub(crate) lowercase: bool,
+    pub(crate) stem: bool,
+    pub(crate) stop_words: BTreeSet<String>,
+}
+
+impl Default for Analyzer {
+    fn default() -> Self {
+        Analyzer {
+            strip_gutenberg: true,
+            strip_punctuation: true,
+            lowercase: true,
+            stem: true,
+            stop_words: ENGLISH_STOP_WORDS
+                .iter()
+                .map(|&word| word.to_owned())
+                .collect(),
+        }
+    }
+}
+
+impl Analyzer {
+    /// Split on whitespace and nothing else, like
+    /// [term_frequency](crate::term_frequency)
+    pub fn whitespace() -> Self {
+        Analyzer {
+            strip_gutenberg: false,
+            strip_punctuation: false,
+            lowercase: false,
+            stem: false,
+            stop_words: BTreeSet::new(),
+        }
+    }
+
+    /// Only index the text between the "*** START OF" and "*** END OF"
+    /// lines of Project Gutenberg books
+    pub fn strip_gutenberg(mut self, strip: bool) -> Self {
+        self.strip_gutenberg = strip;
+        self
+    }
+
+    /// Split words on punctuation too, rather than only on whitespace.
+    /// Apostrophes within words are dropped, as is a possessive "'s".
+    pub fn strip_punctuation(mut self, strip: bool) -> Self {
+        self.strip_punctuation = strip;
+        self
+    }
+
+    pub fn lowercase(mut self, lowercase: bool) -> Self {
+        self.lowercase = lowercase;
+        self
+    }
+
+    /// Reduce English words to their stem with the Porter stemmer, so that
+    /// for example "loved" and "loving" become "love"
+    pub fn stem(mut self, stem: bool) -> Self {
+        self.stem = stem;
+        self
+    }
+
+    /// Drop these words. They are compared before stemming, so they must be
+    /// lowercase and without punctuation if those steps are enabled. Words
+    /// containing whitespace can never match, and are ignored.
+    pub fn stop_words<I>(mut self, words: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.stop_words = words
+            .into_iter()
+            .map(Into::into)
+            .filter(|word| !word.is_empty() && !word.contains(char::is_whitespace))
+            .collect();
+        self
+    }
+
+    /// The part of a document that is indexed
+    pub fn document_body<'t>(&self, text: &'t str) -> &'t str {
+        if self.strip_gutenberg {
+            gutenberg_body(text)
+        } else {
+            text
+        }
+    }
+
+    /// The terms in `text`, which can be a query or the body of a document
+    pub fn terms<'t>(&'t self, text: &'t str) -> impl Iterator<Item = Cow<'t, str>> + 't {
+        self.terms_with_offsets(text).map(|(_, term)| term)
+    }
+
+    /// Like [terms](Self::terms), along with where in `text` the word each
+    /// term comes from is
+    pub fn terms_with_offsets<'t>(
+        &'t self,
+        text: &'t str,
+    ) -> impl Iterator<Item = (Range<usize>, Cow<'t, str>)> + 't {
+        let words: Box<dyn Iterator<Item = &str>> = if self.strip_punctuation {
+            Box::new(
+                text.split(|c: char| !c.is_alphanumeric() && !is_apostrophe(c))
+                    .map(|word| word.trim_matches(is_apostrophe))
+                    .filter(|word| !word.is_empty()),
+            )
+        } else {
+            Box::new(text.split_whitespace())
+        };
+
+        words.filter_map(move |word| {
+            // The words are slices of `text`
+            let start = word.as_ptr() as usize - text.as_ptr() as usize;
+            Some((start..start + word.len(), self.term(word)?))
+        })
+    }
+
+    /// The term for a single word, unless it is a stop word
+    fn term<'t>(&self, word: &'t str) -> Option<Cow<'t, str>> {
+        let mut term = Cow::Borrowed(word);
+        if self.lowercase && term.chars().any(char::is_uppercase) {
+            term = Cow::Owned(term.to_lowercase());
+        }
+        if self.strip_punctuation && term.contains(is_apostrophe) {
+            term = Cow::Owned(remove_apostrophes(&term));
+        }
+        if self.stop_words.contains(&*term) {
+            return None;
+        }
+        if self.stem {
+            term = match term {
+                Cow::Borrowed(term) => stem(term),
+                Cow::Owned(term) => Cow::Owned(stem(&term).into_owned()),
+            };
+        }
+        Some(term)
+    }
+
+    /// How often each term occurs in a document
+    pub fn term_frequency<'t>(&'t self, document: &'t str) -> HashMap<Cow<'t, str>, usize> {
+        let mut frequencies = HashMap::new();
+        for term in self.terms(self.document_body(document)) {
+            *frequencies.entry(term).or_insert(0) += 1;
+        }
+        frequencies
+    }
+}
+
+fn is_apostrophe(c: char) -> bool {
+    c == '\'' || c == '\u{2019}'
+}
+
+/// Drop a possessive "'s", and then any other apostrophes
+fn remove_apostrophes(word: &str) -> String {
+    let word = word
+        .strip_suffix("'s")
+        .or_else(|| word.strip_suffix("\u{2019}s"))
+        .unwrap_or(word);
+    word.replace(is_apostrophe, "")
+}
+
+/// `text` without the Project Gutenberg header and footer, if it has them
+fn gutenberg_body(text: &str) -> &str {
+    let is_marker = |line: &str, start: &str| {
+        line.starts_with(start) && line.to_ascii_uppercase().contains("PROJECT GUTENBERG")
+    };
+
+    let mut start = 0;
+    let mut end = text.len();
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        let line_start = offset;
+        offset += line.len();
+        if start == 0 && is_marker(line, "*** START OF") {
+            start = offset;
+        } else if start != 0 && is_marker(line, "*** END OF") {
+            end = line_start;
+            break;
+        }
+    }
+    &text[start..end]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn terms(analyzer: &Analyzer, text: &str) -> Vec<String> {
+        analyzer.terms(text).map(Cow::into_owned).collect()
+    }
+
+    #[test]
+    fn test_whitespace() {
+        let analyzer = Analyzer::whitespace();
+        assert_eq!(
+            terms(&analyzer, "  O Romeo, Romeo!\nwherefore art thou Romeo? "),
+            [
+                "O",
+                "Romeo,",
+                "Romeo!",
+                "wherefore",
+                "art",
+                "thou",
+                "Romeo?"
+            ]
+        );
+        assert!(analyzer
+            .terms("Romeo, Romeo!")
+            .all(|term| matches!(term, Cow::Borrowed(_))));
+    }
+
+    #[test]
+    fn test_default() {
+        let analyzer = Analyzer::default();
+        assert_eq!(
+            terms(&analyzer, "O Romeo, Romeo! Wherefore art thou Romeo?"),
+            ["o", "romeo", "romeo", "wherefor", "art", "thou", "romeo"]
+        );
+        assert_eq!(
+            terms(&analyzer, "The lovers' loving of Romeo's love—isn't it?"),
+            ["lover", "love", "romeo", "love", "isnt"]
+        );
+        assert_eq!(
+            terms(&analyzer, "ÉLAN élan, naïve 42 'quoted'"),
+            ["élan", "élan", "naïve", "42", "quot"]
+        );
+        assert_eq!(terms(&analyzer, "... -- ' ’"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_offsets() {
+        let text = "O Romeo, the Romeo’s!";
+        let offsets: Vec<_> = Analyzer::default()
+            .terms_with_offsets(text)
+            .map(|(range, term)| (&text[range], term.into_owned()))
+            .collect();
+        assert_eq!(
+            offsets,
+            [("O", "o"), ("Romeo", "romeo"), ("Romeo’s", "romeo")].map(|(w, t)| (w, t.to_owned()))
+        );
+        let offsets: Vec<_> = Analyzer::whitespace()
+            .terms_with_offsets(text)
+            .map(|(range, _)| range)
+            .collect();
+        assert_eq!(offsets, [0..1, 2..8, 9..12, 13..23]);
+    }
+
+    #[test]
+    fn test_steps() {
+        let text = "The Cats sat";
+        let analyzer = Analyzer::whitespace();
+        assert_eq!(
+            terms(&analyzer.clone().lowercase(true), text),
+            ["the", "cats", "sat"]
+        );
+        assert_eq!(
+            terms(&analyzer.clone().stem(true), text),
+            ["The", "Cats", "sat"]
+        );
+        assert_eq!(
+            terms(&analyzer.clone().stop_words(["The", "sat"]), text),
+            ["Cats"]
+        );
+        let analyzer = Analyzer::default().stop_words(["cats", "not a word"]);
+        assert_eq!(terms(&analyzer, text), ["the", "sat"]);
+        assert_eq!(analyzer.stop_words.len(), 1);
+    }
+
+    #[test]
+    fn test_gutenberg() {
+        let book = "The Project Gutenberg eBook of Romeo and Juliet\n\
+                    *** START OF THE PROJECT GUTENBERG EBOOK ROMEO AND JULIET ***\n\
+                    Two households\n\
+                    *** END OF THE PROJECT GUTENBERG EBOOK ROMEO AND JULIET ***\n\
+                    License\n";
+        assert_eq!(gutenberg_body(book), "Two households\n");
+        assert_eq!(
+            Analyzer::default()
+                .term_frequency(book)
+                .into_iter()
+                .collect::<std::collections::BTreeMap<_, _>>(),
+            [("household".into(), 1), ("two".into(), 1)].into()
+        );
+        assert_eq!(
+            Analyzer::default()
+                .strip_gutenberg(false)
+                .term_frequency(book)
+                .len(),
+            10
+        );
+
+        // Older books say "THIS" rather than "THE", and text without the
+        // markers is left alone
+        let book = "header\n*** START OF THIS PROJECT GUTENBERG EBOOK X ***\nbody";
+        assert_eq!(gutenberg_body(book), "body");
+        assert_eq!(gutenberg_body("*** END OF it"), "*** END OF it");
+    }
+}
@@ -0,0 +1,166 @@
+//! Search the `.txt` files in a directory, for example
+//!
+//! ```text
+//! cargo run --release --bin search -- documents '"star-crossed lovers" OR romeo~1'
+//! ```
+//!
+//! With `--index PATH`, the first run saves the index it builds in `PATH`,
+//! and later runs map it from there instead of indexing the files again.
+//! It is built again when the names of the documents no longer match it.
+
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::{env, fs, io, process};
+
+use rayon::prelude::*;
+use tf_idf::{search_query, Bm25, Highlighter, Index, MappedIndex, Markup, Query, SearchIndex};
+
+const USAGE: &str = "usage: search [--html] [-n RESULTS] [--index PATH] <DIRECTORY> <QUERY>...";
+
+struct Args {
+    html: bool,
+    n_results: usize,
+    index: Option<PathBuf>,
+    directory: PathBuf,
+    query: String,
+}
+
+fn parse_args() -> Option<Args> {
+    let mut args = env::args().skip(1);
+    let mut html = false;
+    let mut n_results = 5;
+    let mut index = None;
+    let mut positional = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--html" => html = true,
+            "-n" => n_results = args.next()?.parse().ok()?,
+            "--index" => index = Some(PathBuf::from(args.next()?)),
+            "-h" | "--help" => return None,
+            _ => positional.push(arg),
+        }
+    }
+    if positional.len() < 2 {
+        return None;
+    }
+    let directory = PathBuf::from(positional.remove(0));
+    Some(Args {
+        html,
+        n_results,
+        index,
+        directory,
+        query: positional.join(" "),
+    })
+}
+
+/// The `Title:` line of a Project Gutenberg book, or else the file name
+fn document_name(path: &Path, text: &str) -> String {
+    text.lines()
+        .take(100)
+        .find_map(|line| line.strip_prefix("Title:"))
+        .map(|title| title.trim().to_string())
+        .filter(|title| !title.is_empty())
+        .unwrap_or_else(|| path.file_name().unwrap().to_string_lossy().into_owned())
+}
+
+/// The names and contents of the `.txt` files in `directory`
+fn read_documents(directory: &Path) -> io::Result<Vec<(String, String)>> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|extension| extension == "txt") && path.is_file() {
+            paths.push(path);
+        }
+    }
+    // Sorted so that the document ids don't depend on the file system
+    paths.sort();
+    paths
+        .par_iter()
+        .map(|path| {
+            let text = fs::read_to_string(path)?;
+            Ok((document_name(path, &text), text))
+        })
+        .collect()
+}
+
+/// The index saved in `path`, if it is one of `documents`, or else a new
+/// one saved there
+fn open_or_build(path: &Path, documents: &[(String, String)]) -> io::Result<MappedIndex> {
+    if let Ok(index) = MappedIndex::open(path) {
+        let names = index.documents().map(|(_, document)| document.name);
+        if index.document_slots() as usize == documents.len()
+            && names.eq(documents.iter().map(|(name, _)| name.as_str()))
+        {
+            return Ok(index);
+        }
+    }
+    Index::build(documents).save(path)?;
+    MappedIndex::open(path)
+}
+
+fn main() {
+    let Some(args) = parse_args() else {
+        eprintln!("{USAGE}");
+        process::exit(2);
+    };
+
+    let documents = match read_documents(&args.directory) {
+        Ok(documents) => documents,
+        Err(error) => {
+            eprintln!("search: {}: {error}", args.directory.display());
+            process::exit(1);
+        }
+    };
+    match &args.index {
+        Some(path) => match open_or_build(path, &documents) {
+            Ok(index) => search(&index, &args, &documents),
+            Err(error) => {
+                eprintln!("search: {}: {error}", path.display());
+                process::exit(1);
+            }
+        },
+        None => search(&Index::build(&documents), &args, &documents),
+    }
+}
+
+/// Print the best matches for the query, with snippets
+fn search(index: &impl SearchIndex, args: &Args, documents: &[(String, String)]) {
+    let query = match Query::parse(&args.query, index.analyzer()) {
+        Ok(query) => query,
+        Err(error) => {
+            eprintln!("search: {error}");
+            eprintln!("  {}", args.query);
+            eprintln!("  {:>1$}", "^", error.position + 1);
+            process::exit(2);
+        }
+    };
+
+    let markup = if args.html {
+        Markup::Html
+    } else if io::stdout().is_terminal() {
+        Markup::Ansi
+    } else {
+        Markup::Plain
+    };
+    let highlighter = Highlighter::new(index.analyzer(), &query);
+
+    for (score, doc, name) in
+        search_query(index, &Bm25::default(), &query, args.n_results).into_documents()
+    {
+        // the index is of `documents`, so ids are positions
+        let (_, text) = &documents[doc as usize];
+        let snippets = highlighter.snippets(text);
+        let name = markup.escape(name);
+        if args.html {
+            println!("<h2>{name} ({score:.3})</h2>");
+            for snippet in snippets {
+                println!("<p>… {} …</p>", snippet.highlight(markup));
+            }
+        } else {
+            println!("{name} ({score:.3})");
+            for snippet in snippets {
+                println!("    … {} …", snippet.highlight(markup));
+            }
+        }
+    }
+}
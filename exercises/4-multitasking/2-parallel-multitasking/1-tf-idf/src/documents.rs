@@ -0,0 +1,19 @@
+/// Some random books from Project Gutenberg, by title. They are built into
+/// whatever uses them, so that the demo and the evaluation need no files at
+/// run time.
+pub const DOCUMENTS: &[(&str, &str)] = &[
+    ("Middlemarch", include_str!("../documents/pg145.txt")),
+    (
+        "THE TRAGEDY OF ROMEO AND JULIET",
+        include_str!("../documents/pg1513.txt"),
+    ),
+    (
+        "A Room With A View",
+        include_str!("../documents/pg2641.txt"),
+    ),
+    (
+        "The Enchanted April",
+        include_str!("../documents/pg16389.txt"),
+    ),
+    ("Little Women", include_str!("../documents/pg37106.txt")),
+];
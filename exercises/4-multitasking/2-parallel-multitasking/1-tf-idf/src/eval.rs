@@ -0,0 +1,144 @@
+//! Measuring how good the rankings of a [Scorer] are, on queries for which
+//! we know which documents should be found.
+
+use crate::{search_with, Scorer, SearchIndex};
+
+/// A query, and the names of the documents that are relevant to it
+#[derive(Debug, Clone, Copy)]
+pub struct LabelledQuery<'a> {
+    pub query: &'a str,
+    pub relevant: &'a [&'a str],
+}
+
+/// The quality of the top `k` results, averaged over all queries. Each
+/// measure goes from 0 (nothing relevant found) to 1 (perfect).
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Evaluation {
+    /// The fraction of the results that are relevant
+    pub precision: f64,
+    /// The fraction of the relevant documents that are in the results
+    pub recall: f64,
+    /// One over the rank of the first relevant result
+    pub reciprocal_rank: f64,
+    /// The precision at the rank of every relevant document, averaged over
+    /// the relevant documents. This rewards ranking them first.
+    pub average_precision: f64,
+}
+
+/// Search for every query in `queries` with `scorer`, and compare the top
+/// `k` results with the relevant documents. Documents that score 0 do not
+/// count as found.
+pub fn evaluate(
+    index: &impl SearchIndex,
+    scorer: &(impl Scorer + ?Sized),
+    queries: &[LabelledQuery],
+    k: usize,
+) -> Evaluation {
+    let mut total = Evaluation::default();
+    for labelled in queries {
+        let results = search_with(index, scorer, labelled.query, k).into_results();
+        let found = results
+            .iter()
+            .filter(|(score, _)| *score > 0.0)
+            .map(|(_, name)| labelled.relevant.contains(name));
+        let evaluation = evaluate_ranking(found, labelled.relevant.len(), k);
+        total.precision += evaluation.precision;
+        total.recall += evaluation.recall;
+        total.reciprocal_rank += evaluation.reciprocal_rank;
+        total.average_precision += evaluation.average_precision;
+    }
+
+    let n = queries.len().max(1) as f64;
+    Evaluation {
+        precision: total.precision / n,
+        recall: total.recall / n,
+        reciprocal_rank: total.reciprocal_rank / n,
+        average_precision: total.average_precision / n,
+    }
+}
+
+/// Evaluate a single ranking, given whether each result is relevant
+fn evaluate_ranking(ranking: impl Iterator<Item = bool>, relevant: usize, k: usize) -> Evaluation {
+    let mut evaluation = Evaluation::default();
+    let mut hits = 0;
+    for (rank, is_relevant) in (1..).zip(ranking) {
+        if !is_relevant {
+            continue;
+        }
+        hits += 1;
+        if hits == 1 {
+            evaluation.reciprocal_rank = 1.0 / rank as f64;
+        }
+        evaluation.average_precision += hits as f64 / rank as f64;
+    }
+
+    if k > 0 {
+        evaluation.precision = hits as f64 / k as f64;
+    }
+    if relevant > 0 {
+        evaluation.recall = hits as f64 / relevant as f64;
+        evaluation.average_precision /= relevant as f64;
+    }
+    evaluation
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Bm25, Index, TfIdf};
+
+    #[test]
+    fn test_evaluate_ranking() {
+        let evaluation = evaluate_ranking([false, true, false, true].into_iter(), 3, 4);
+        assert_eq!(
+            evaluation,
+            Evaluation {
+                precision: 0.5,
+                recall: 2.0 / 3.0,
+                reciprocal_rank: 0.5,
+                average_precision: (1.0 / 2.0 + 2.0 / 4.0) / 3.0,
+            }
+        );
+        assert_eq!(
+            evaluate_ranking([false, false].into_iter(), 1, 2),
+            Evaluation::default()
+        );
+        assert_eq!(
+            evaluate_ranking([true].into_iter(), 1, 1).average_precision,
+            1.0
+        );
+    }
+
+    #[test]
+    fn test_evaluate() {
+        // "sun" is all over the long document, which is only a little about
+        // it. BM25 does not let that outweigh the short one.
+        let long = format!("sun {}", "weather rain wind cloud ".repeat(50)).repeat(3);
+        let documents = [
+            ("weather", long.as_str()),
+            ("sun", "the sun is a star, the sun is hot"),
+            ("moon", "the moon is not a star"),
+        ];
+        let index = Index::build(&documents);
+        let queries = [
+            LabelledQuery {
+                query: "sun",
+                relevant: &["sun"],
+            },
+            LabelledQuery {
+                query: "star",
+                relevant: &["sun", "moon"],
+            },
+        ];
+
+        let tf_idf = evaluate(&index, &TfIdf, &queries, 2);
+        assert_eq!(tf_idf.recall, 1.0);
+        assert_eq!(tf_idf.reciprocal_rank, 0.75);
+        let bm25 = evaluate(&index, &Bm25::default(), &queries, 2);
+        assert_eq!(bm25.reciprocal_rank, 1.0);
+        assert_eq!(bm25.average_precision, 1.0);
+        assert!(bm25.average_precision > tf_idf.average_precision);
+
+        assert_eq!(evaluate(&index, &TfIdf, &[], 2), Evaluation::default());
+    }
+}
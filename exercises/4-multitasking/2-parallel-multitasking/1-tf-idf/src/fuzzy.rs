@@ -0,0 +1,286 @@
+//! Matching words within a few typos of a term.
+//!
+//! A Levenshtein automaton reads a word one character at a time, and knows
+//! after every character whether the word can still end up close enough to
+//! the term, so most words are rejected after their first few characters.
+//! Both kinds of index keep their terms sorted, so a fuzzy query
+//! [walks](LevenshteinAutomaton::walk) them in order: terms that share a
+//! prefix share the states for it, and once a prefix can't match, every
+//! term starting with it is skipped at once.
+
+use std::ops::Bound;
+
+/// Accepts the words within `max_distance` insertions, deletions and
+/// substitutions of a term.
+///
+/// A state is the last row of the edit distance table between the term and
+/// the characters read so far: how many edits turn each prefix of the term
+/// into them. Distances above `max_distance` are all stored as
+/// `max_distance + 1`, which keeps the number of states finite.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LevenshteinAutomaton {
+    term: Vec<char>,
+    max_distance: u32,
+}
+
+/// Where a [LevenshteinAutomaton] is after reading part of a word
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LevenshteinState(Vec<u32>);
+
+impl LevenshteinAutomaton {
+    pub fn new(term: &str, max_distance: u32) -> Self {
+        LevenshteinAutomaton {
+            term: term.chars().collect(),
+            max_distance,
+        }
+    }
+
+    /// The state before reading anything
+    pub fn start(&self) -> LevenshteinState {
+        LevenshteinState(
+            (0..=self.term.len() as u32)
+                .map(|distance| distance.min(self.max_distance + 1))
+                .collect(),
+        )
+    }
+
+    /// The state after reading `c` in `state`
+    pub fn step(&self, state: &LevenshteinState, c: char) -> LevenshteinState {
+        let cap = self.max_distance + 1;
+        let mut row = Vec::with_capacity(state.0.len());
+        row.push((state.0[0] + 1).min(cap));
+        for (i, &term_char) in self.term.iter().enumerate() {
+            let substitute = state.0[i] + u32::from(term_char != c);
+            let insert = state.0[i + 1] + 1;
+            let delete = row[i] + 1;
+            row.push(substitute.min(insert).min(delete).min(cap));
+        }
+        LevenshteinState(row)
+    }
+
+    /// Whether reading more characters can still lead to a match
+    pub fn can_match(&self, state: &LevenshteinState) -> bool {
+        state
+            .0
+            .iter()
+            .any(|&distance| distance <= self.max_distance)
+    }
+
+    /// The distance between the term and the characters read so far, if it
+    /// is at most `max_distance`
+    pub fn distance(&self, state: &LevenshteinState) -> Option<u32> {
+        let distance = *state.0.last().unwrap();
+        (distance <= self.max_distance).then_some(distance)
+    }
+
+    /// The distance between the term and `word`, if it is at most
+    /// `max_distance`
+    pub fn matches(&self, word: &str) -> Option<u32> {
+        let mut state = self.start();
+        for c in word.chars() {
+            state = self.step(&state, c);
+            if !self.can_match(&state) {
+                return None;
+            }
+        }
+        self.distance(&state)
+    }
+
+    /// The words of a sorted list within `max_distance` of the term, with
+    /// their distances, in order. `seek(bound)` gives the first word of the
+    /// list after `bound`.
+    pub fn walk<'a>(
+        &self,
+        mut seek: impl FnMut(Bound<&str>) -> Option<&'a str>,
+    ) -> Vec<(&'a str, u32)> {
+        let mut found = Vec::new();
+        let mut word: Vec<char> = Vec::new();
+        // `states[i]` is the state after reading the first `i` characters
+        // of `word`
+        let mut states = vec![self.start()];
+        let mut next = seek(Bound::Unbounded);
+        while let Some(next_word) = next {
+            let shared = word
+                .iter()
+                .zip(next_word.chars())
+                .take_while(|&(&a, b)| a == b)
+                .count();
+            states.truncate(shared.min(states.len() - 1) + 1);
+            word.clear();
+            word.extend(next_word.chars());
+
+            let mut dead = false;
+            for &c in &word[states.len() - 1..] {
+                let state = self.step(states.last().unwrap(), c);
+                if !self.can_match(&state) {
+                    dead = true;
+                    break;
+                }
+                states.push(state);
+            }
+
+            next = if dead {
+                // No word starting with the prefix read so far matches
+                successor(&word[..states.len()]).and_then(|after| seek(Bound::Included(&after)))
+            } else {
+                if let Some(distance) = self.distance(states.last().unwrap()) {
+                    found.push((next_word, distance));
+                }
+                seek(Bound::Excluded(next_word))
+            };
+        }
+        found
+    }
+}
+
+/// The first string after all the strings that start with `prefix`, if
+/// there is one
+fn successor(prefix: &[char]) -> Option<String> {
+    let mut prefix = prefix.to_vec();
+    while let Some(c) = prefix.pop() {
+        // Strings order by their UTF-8 bytes, which is the order of their
+        // characters, so the next character will do
+        let next = match c {
+            '\u{D7FF}' => Some('\u{E000}'),
+            c => char::from_u32(c as u32 + 1),
+        };
+        if let Some(next) = next {
+            prefix.push(next);
+            return Some(prefix.into_iter().collect());
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The textbook dynamic programming solution
+    fn levenshtein(a: &str, b: &str) -> u32 {
+        let b: Vec<char> = b.chars().collect();
+        let mut row: Vec<u32> = (0..=b.len() as u32).collect();
+        for a in a.chars() {
+            let mut next = vec![row[0] + 1];
+            for (i, &b) in b.iter().enumerate() {
+                next.push(
+                    (row[i] + u32::from(a != b))
+                        .min(row[i + 1] + 1)
+                        .min(next[i] + 1),
+                );
+            }
+            row = next;
+        }
+        row[b.len()]
+    }
+
+    #[test]
+    fn test_matches() {
+        let automaton = LevenshteinAutomaton::new("romeo", 1);
+        assert_eq!(automaton.matches("romeo"), Some(0));
+        assert_eq!(automaton.matches("romoe"), None);
+        assert_eq!(automaton.matches("rome"), Some(1));
+        assert_eq!(automaton.matches("romeos"), Some(1));
+        assert_eq!(automaton.matches("rameo"), Some(1));
+        assert_eq!(automaton.matches("juliet"), None);
+        assert_eq!(
+            LevenshteinAutomaton::new("romeo", 2).matches("romoe"),
+            Some(2)
+        );
+        assert_eq!(LevenshteinAutomaton::new("", 1).matches("a"), Some(1));
+        assert_eq!(LevenshteinAutomaton::new("é", 0).matches("é"), Some(0));
+    }
+
+    #[test]
+    fn test_early_rejection() {
+        let automaton = LevenshteinAutomaton::new("cat", 1);
+        let mut state = automaton.start();
+        for c in "xy".chars() {
+            state = automaton.step(&state, c);
+        }
+        // No ending turns "xy…" into something within one edit of "cat"
+        assert!(!automaton.can_match(&state));
+    }
+
+    #[test]
+    fn test_against_dynamic_programming() {
+        let words = [
+            "", "a", "ab", "ba", "abc", "acb", "kitten", "sitting", "sitten", "mitten", "kitchen",
+            "flaw", "lawn", "héllo", "hello",
+        ];
+        for term in words {
+            for max_distance in 0..3 {
+                let automaton = LevenshteinAutomaton::new(term, max_distance);
+                for word in words {
+                    let distance = levenshtein(term, word);
+                    let expected = (distance <= max_distance).then_some(distance);
+                    assert_eq!(automaton.matches(word), expected, "{term} {word}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_walk() {
+        let mut words = vec![
+            "",
+            "a",
+            "ab",
+            "abc",
+            "abd",
+            "acb",
+            "b",
+            "ba",
+            "kitchen",
+            "kitten",
+            "mitten",
+            "sitten",
+            "sitting",
+            "sittings",
+            "héllo",
+            "hello",
+            "hellö",
+            "x\u{D7FF}",
+            "x\u{D7FF}y",
+            "x\u{E000}",
+            "\u{10FFFF}",
+            "\u{10FFFF}a",
+        ];
+        words.sort_unstable();
+        let seek = |bound: Bound<&str>| {
+            let i = match bound {
+                Bound::Included(after) => words.partition_point(|&word| word < after),
+                Bound::Excluded(after) => words.partition_point(|&word| word <= after),
+                Bound::Unbounded => 0,
+            };
+            words.get(i).copied()
+        };
+        for term in [
+            "",
+            "a",
+            "abc",
+            "kitten",
+            "sitting",
+            "hello",
+            "x\u{D7FF}",
+            "\u{10FFFF}",
+        ] {
+            for max_distance in 0..3 {
+                let automaton = LevenshteinAutomaton::new(term, max_distance);
+                let scan: Vec<_> = words
+                    .iter()
+                    .filter_map(|&word| Some((word, automaton.matches(word)?)))
+                    .collect();
+                assert_eq!(automaton.walk(seek), scan, "{term} {max_distance}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_successor() {
+        assert_eq!(successor(&['a', 'b']).as_deref(), Some("ac"));
+        assert_eq!(successor(&['a', '\u{D7FF}']).as_deref(), Some("a\u{E000}"));
+        assert_eq!(successor(&['a', char::MAX]).as_deref(), Some("b"));
+        assert_eq!(successor(&[char::MAX]), None);
+    }
+}
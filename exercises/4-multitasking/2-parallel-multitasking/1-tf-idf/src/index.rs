@@ -0,0 +1,626 @@
+//! An inverted index: for every term, the documents it occurs in.
+//!
+//! Searching then only looks at the documents containing the query terms,
+//! instead of tokenizing every document again for every query.
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Bound;
+use std::sync::OnceLock;
+
+use rayon::prelude::*;
+
+use crate::{
+    Analyzer, Collection, LevenshteinAutomaton, Scorer, SearchResultQueue, TermMatch, TfIdf,
+};
+
+/// Identifies a document in an index. Ids are not reused after a document
+/// is removed.
+pub type DocId = u32;
+
+/// A document as the index knows it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Document<'a> {
+    pub name: &'a str,
+    /// The number of terms in the document
+    pub length: u32,
+}
+
+/// An occurrence of a term in a document
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Posting {
+    pub doc: DocId,
+    /// How often the term occurs in the document
+    pub frequency: u32,
+}
+
+/// What searching needs from an index, whether it is in memory or mapped
+/// from a file
+pub trait SearchIndex: Sync {
+    /// How the documents were turned into terms, and so how queries must be
+    fn analyzer(&self) -> &Analyzer;
+
+    /// The number of documents, not counting removed ones
+    fn document_count(&self) -> usize;
+
+    /// The number of distinct terms
+    fn term_count(&self) -> usize;
+
+    /// One more than the highest document id that was ever used
+    fn document_slots(&self) -> DocId;
+
+    /// The document with id `doc`, unless it was removed
+    fn document(&self, doc: DocId) -> Option<Document<'_>>;
+
+    /// The documents `term` occurs in, by increasing id
+    fn postings(&self, term: &str) -> Postings<'_>;
+
+    /// Like [postings](Self::postings), with the positions of the term in
+    /// each document
+    fn positions(&self, term: &str) -> Positions<'_>;
+
+    /// All distinct terms, in no particular order
+    fn terms(&self) -> impl Iterator<Item = &str>;
+
+    /// The terms `automaton` accepts, with their distances, sorted. This
+    /// runs every term through it, unless the index can
+    /// [walk](LevenshteinAutomaton::walk) its terms in order.
+    fn fuzzy_terms(&self, automaton: &LevenshteinAutomaton) -> Vec<(&str, u32)> {
+        let mut close: Vec<_> = self
+            .terms()
+            .filter_map(|term| Some((term, automaton.matches(term)?)))
+            .collect();
+        close.sort_unstable();
+        close
+    }
+
+    /// The document names, indexed as documents of their own under the same
+    /// ids, for `name:` queries. Built when first needed, and then kept.
+    fn names(&self) -> &Index;
+
+    /// All documents that were not removed, by increasing id
+    fn documents(&self) -> impl Iterator<Item = (DocId, Document<'_>)> {
+        (0..self.document_slots()).filter_map(|doc| Some((doc, self.document(doc)?)))
+    }
+
+    /// What a [Scorer] needs to know about the index as a whole. Every
+    /// query needs this, so it is kept up to date rather than computed.
+    fn collection(&self) -> Collection;
+}
+
+/// An iterator over the postings of a term
+pub struct Postings<'a>(pub(crate) PostingsInner<'a>);
+
+pub(crate) enum PostingsInner<'a> {
+    Memory(std::slice::Iter<'a, Posting>),
+    Mapped(crate::storage::PostingsDecoder<'a>),
+}
+
+impl Iterator for Postings<'_> {
+    type Item = Posting;
+
+    fn next(&mut self) -> Option<Posting> {
+        match &mut self.0 {
+            PostingsInner::Memory(postings) => postings.next().copied(),
+            PostingsInner::Mapped(postings) => postings.next(),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match &self.0 {
+            PostingsInner::Memory(postings) => postings.size_hint(),
+            PostingsInner::Mapped(postings) => postings.size_hint(),
+        }
+    }
+}
+
+impl ExactSizeIterator for Postings<'_> {}
+
+/// An iterator over the postings of a term, with the positions the term
+/// occurs at in each document, in increasing order. Positions count terms,
+/// not bytes, and skip stop words.
+pub struct Positions<'a>(pub(crate) PositionsInner<'a>);
+
+pub(crate) enum PositionsInner<'a> {
+    Memory(std::iter::Zip<std::slice::Iter<'a, Posting>, std::slice::Iter<'a, Box<[u32]>>>),
+    Mapped(crate::storage::PositionsDecoder<'a>),
+}
+
+impl Iterator for Positions<'_> {
+    type Item = (Posting, Vec<u32>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.0 {
+            PositionsInner::Memory(postings) => postings
+                .next()
+                .map(|(posting, positions)| (*posting, positions.to_vec())),
+            PositionsInner::Mapped(postings) => postings.next(),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match &self.0 {
+            PositionsInner::Memory(postings) => postings.size_hint(),
+            PositionsInner::Mapped(postings) => postings.size_hint(),
+        }
+    }
+}
+
+impl ExactSizeIterator for Positions<'_> {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct StoredDocument {
+    pub(crate) name: String,
+    pub(crate) length: u32,
+    /// The distinct terms of the document, sorted, so that removing it only
+    /// has to look at their postings
+    pub(crate) terms: Vec<String>,
+}
+
+/// An inverted index in memory, which documents can be added to and removed from
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Index {
+    pub(crate) analyzer: Analyzer,
+    /// Indexed by [DocId]; `None` for removed documents
+    pub(crate) documents: Vec<Option<StoredDocument>>,
+    /// Never empty. Sorted, so fuzzy queries can skip through the terms.
+    pub(crate) postings: BTreeMap<String, TermPostings>,
+    pub(crate) live: usize,
+    /// The sum of the lengths of the live documents
+    pub(crate) total_length: u64,
+    pub(crate) names: NameIndex,
+}
+
+/// The index of the document names of an index, once a query needed it.
+/// It only depends on the documents, so it is left out of comparisons.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct NameIndex(OnceLock<Box<Index>>);
+
+impl NameIndex {
+    pub(crate) fn get_or_build(&self, index: &impl SearchIndex) -> &Index {
+        self.0.get_or_init(|| {
+            let analyzer = index.analyzer().clone().strip_gutenberg(false);
+            let mut names = Index::with_analyzer(analyzer);
+            for doc in 0..index.document_slots() {
+                match index.document(doc) {
+                    Some(document) => {
+                        names.add(document.name, document.name);
+                    }
+                    None => {
+                        // Keep the ids the same as in the index
+                        let removed = names.add("", "");
+                        names.remove(removed);
+                    }
+                }
+            }
+            Box::new(names)
+        })
+    }
+}
+
+impl PartialEq for NameIndex {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for NameIndex {}
+
+/// The postings of a term
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct TermPostings {
+    /// Sorted by document id
+    pub(crate) postings: Vec<Posting>,
+    /// For each posting, the positions of the term in the document
+    pub(crate) positions: Vec<Box<[u32]>>,
+}
+
+impl TermPostings {
+    fn append(&mut self, mut other: TermPostings) {
+        self.postings.append(&mut other.postings);
+        self.positions.append(&mut other.positions);
+    }
+}
+
+/// The postings of a single document, or of consecutive documents once merged
+type PartialPostings<'a> = HashMap<Cow<'a, str>, TermPostings>;
+
+impl Index {
+    /// An empty index using the [default](Analyzer::default) analyzer
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_analyzer(analyzer: Analyzer) -> Self {
+        Index {
+            analyzer,
+            ..Self::default()
+        }
+    }
+
+    /// Index `documents`, given as pairs of names and contents, in parallel
+    /// with the [default](Analyzer::default) analyzer. The documents get ids
+    /// in the order they are given.
+    pub fn build<N, T>(documents: &[(N, T)]) -> Self
+    where
+        N: AsRef<str> + Sync,
+        T: AsRef<str> + Sync,
+    {
+        Self::build_with(Analyzer::default(), documents)
+    }
+
+    /// Like [build](Self::build), with another analyzer
+    pub fn build_with<N, T>(analyzer: Analyzer, documents: &[(N, T)]) -> Self
+    where
+        N: AsRef<str> + Sync,
+        T: AsRef<str> + Sync,
+    {
+        // Documents are combined in order, so appending keeps the postings
+        // sorted by document id
+        let postings = documents
+            .par_iter()
+            .enumerate()
+            .map(|(doc, (_, text))| document_postings(&analyzer, doc as DocId, text.as_ref()))
+            .reduce(PartialPostings::new, |mut a, b| {
+                for (term, postings) in b {
+                    a.entry(term).or_default().append(postings);
+                }
+                a
+            });
+
+        let mut lengths = vec![0; documents.len()];
+        for posting in postings.values().flat_map(|term| &term.postings) {
+            lengths[posting.doc as usize] += posting.frequency;
+        }
+        let total_length = lengths.iter().map(|&length| u64::from(length)).sum();
+
+        let postings = postings
+            .into_iter()
+            .map(|(term, postings)| (term.into_owned(), postings))
+            .collect();
+        let mut index = Index {
+            analyzer,
+            documents: documents
+                .iter()
+                .zip(lengths)
+                .map(|((name, _), length)| {
+                    Some(StoredDocument {
+                        name: name.as_ref().to_owned(),
+                        length,
+                        terms: Vec::new(),
+                    })
+                })
+                .collect(),
+            postings,
+            live: documents.len(),
+            total_length,
+            names: NameIndex::default(),
+        };
+        index.record_terms();
+        index
+    }
+
+    /// Fill in the terms of every document from the postings
+    pub(crate) fn record_terms(&mut self) {
+        for (term, postings) in &self.postings {
+            for posting in &postings.postings {
+                if let Some(document) = &mut self.documents[posting.doc as usize] {
+                    document.terms.push(term.clone());
+                }
+            }
+        }
+        for document in self.documents.iter_mut().flatten() {
+            document.terms.sort_unstable();
+        }
+    }
+
+    /// Index one more document
+    pub fn add(&mut self, name: &str, text: &str) -> DocId {
+        let doc = DocId::try_from(self.documents.len()).expect("too many documents");
+        let mut length = 0;
+        let mut terms = Vec::new();
+        for (term, postings) in document_postings(&self.analyzer, doc, text) {
+            length += postings.postings[0].frequency;
+            terms.push(term.clone().into_owned());
+            // `doc` is the highest id so far, so this keeps the postings sorted
+            match self.postings.get_mut(&*term) {
+                Some(existing) => existing.append(postings),
+                None => {
+                    self.postings.insert(term.into_owned(), postings);
+                }
+            }
+        }
+        terms.sort_unstable();
+        self.documents.push(Some(StoredDocument {
+            name: name.to_owned(),
+            length,
+            terms,
+        }));
+        self.live += 1;
+        self.total_length += u64::from(length);
+        if let Some(names) = self.names.0.get_mut() {
+            names.add(name, name);
+        }
+        doc
+    }
+
+    /// Remove a document, returning its name if it was in the index
+    pub fn remove(&mut self, doc: DocId) -> Option<String> {
+        let removed = self.documents.get_mut(doc as usize)?.take()?;
+        self.live -= 1;
+        self.total_length -= u64::from(removed.length);
+        for term in &removed.terms {
+            let Some(postings) = self.postings.get_mut(term) else {
+                continue;
+            };
+            if let Ok(i) = postings
+                .postings
+                .binary_search_by_key(&doc, |posting| posting.doc)
+            {
+                postings.postings.remove(i);
+                postings.positions.remove(i);
+            }
+            if postings.postings.is_empty() {
+                self.postings.remove(term);
+            }
+        }
+        if let Some(names) = self.names.0.get_mut() {
+            names.remove(doc);
+        }
+        Some(removed.name)
+    }
+}
+
+/// Where each term occurs in a document
+fn document_postings<'a>(analyzer: &'a Analyzer, doc: DocId, text: &'a str) -> PartialPostings<'a> {
+    let mut positions = HashMap::<_, Vec<u32>>::new();
+    for (position, term) in analyzer.terms(analyzer.document_body(text)).enumerate() {
+        let position = u32::try_from(position).expect("document is too long");
+        positions.entry(term).or_default().push(position);
+    }
+    positions
+        .into_iter()
+        .map(|(term, positions)| {
+            let frequency = positions.len() as u32;
+            let postings = TermPostings {
+                postings: vec![Posting { doc, frequency }],
+                positions: vec![positions.into_boxed_slice()],
+            };
+            (term, postings)
+        })
+        .collect()
+}
+
+impl SearchIndex for Index {
+    fn analyzer(&self) -> &Analyzer {
+        &self.analyzer
+    }
+
+    fn document_count(&self) -> usize {
+        self.live
+    }
+
+    fn collection(&self) -> Collection {
+        Collection::new(self.live, self.total_length)
+    }
+
+    fn term_count(&self) -> usize {
+        self.postings.len()
+    }
+
+    fn document_slots(&self) -> DocId {
+        self.documents.len() as DocId
+    }
+
+    fn document(&self, doc: DocId) -> Option<Document<'_>> {
+        let stored = self.documents.get(doc as usize)?.as_ref()?;
+        Some(Document {
+            name: &stored.name,
+            length: stored.length,
+        })
+    }
+
+    fn postings(&self, term: &str) -> Postings<'_> {
+        let postings = self
+            .postings
+            .get(term)
+            .map_or(&[][..], |term| &term.postings);
+        Postings(PostingsInner::Memory(postings.iter()))
+    }
+
+    fn positions(&self, term: &str) -> Positions<'_> {
+        let (postings, positions) = match self.postings.get(term) {
+            Some(term) => (&term.postings[..], &term.positions[..]),
+            None => (&[][..], &[][..]),
+        };
+        Positions(PositionsInner::Memory(postings.iter().zip(positions)))
+    }
+
+    fn terms(&self) -> impl Iterator<Item = &str> {
+        self.postings.keys().map(String::as_str)
+    }
+
+    fn names(&self) -> &Index {
+        self.names.get_or_build(self)
+    }
+
+    fn fuzzy_terms(&self, automaton: &LevenshteinAutomaton) -> Vec<(&str, u32)> {
+        automaton.walk(|bound| {
+            let mut after = self.postings.range::<str, _>((bound, Bound::Unbounded));
+            Some(after.next()?.0.as_str())
+        })
+    }
+}
+
+/// Find the `n_results` documents that best match `query` by [TfIdf],
+/// using only the postings of the query terms. The query is analyzed like
+/// the documents were.
+///
+/// With [Analyzer::whitespace], this gives the same scores as
+/// [search](crate::search).
+pub fn search_index<'a>(
+    index: &'a impl SearchIndex,
+    query: &str,
+    n_results: usize,
+) -> SearchResultQueue<'a> {
+    search_with(index, &TfIdf, query, n_results)
+}
+
+/// Like [search_index], scoring with `scorer`
+pub fn search_with<'a>(
+    index: &'a impl SearchIndex,
+    scorer: &(impl Scorer + ?Sized),
+    query: &str,
+    n_results: usize,
+) -> SearchResultQueue<'a> {
+    let collection = index.collection();
+    let mut scores = vec![0.0; index.document_slots() as usize];
+    for term in index.analyzer().terms(query) {
+        let postings = index.postings(&term);
+        let document_frequency = postings.len();
+        for posting in postings {
+            // Postings only refer to documents that are in the index
+            let document_length = index.document(posting.doc).map_or(0, |d| d.length);
+            let term = TermMatch {
+                frequency: posting.frequency,
+                document_length,
+                document_frequency,
+            };
+            scores[posting.doc as usize] += scorer.score(&collection, &term);
+        }
+    }
+
+    scores
+        .par_iter()
+        .enumerate()
+        .filter_map(|(doc, &score)| {
+            let doc = doc as DocId;
+            Some((score, doc, index.document(doc)?.name))
+        })
+        .fold(
+            || SearchResultQueue::new(n_results),
+            |mut state, (score, doc, name)| {
+                state.push(score, doc, name);
+                state
+            },
+        )
+        .reduce(
+            || SearchResultQueue::new(n_results),
+            SearchResultQueue::append,
+        )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::search;
+
+    const DOCUMENTS: &[(&str, &str)] = &[
+        ("fruit", "apple banana apple cherry"),
+        ("trees", "apple tree pear tree oak"),
+        ("colours", "red green blue cherry red"),
+    ];
+
+    fn postings(index: &impl SearchIndex, term: &str) -> Vec<(DocId, u32)> {
+        index
+            .postings(term)
+            .map(|posting| (posting.doc, posting.frequency))
+            .collect()
+    }
+
+    /// The collection statistics counted from scratch
+    fn recount(index: &impl SearchIndex) -> Collection {
+        let lengths = index.documents().map(|(_, d)| u64::from(d.length));
+        Collection::new(index.document_count(), lengths.sum())
+    }
+
+    #[test]
+    fn test_build() {
+        let index = Index::build_with(Analyzer::whitespace(), DOCUMENTS);
+        assert_eq!(index.document_count(), 3);
+        assert_eq!(index.term_count(), 9);
+        assert_eq!(postings(&index, "apple"), [(0, 2), (1, 1)]);
+        assert_eq!(postings(&index, "red"), [(2, 2)]);
+        assert_eq!(postings(&index, "grape"), []);
+        assert_eq!(
+            index.document(1),
+            Some(Document {
+                name: "trees",
+                length: 5
+            })
+        );
+
+        let mut incremental = Index::with_analyzer(Analyzer::whitespace());
+        for (name, text) in DOCUMENTS {
+            incremental.add(name, text);
+        }
+        assert_eq!(incremental, index);
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut index = Index::build_with(Analyzer::whitespace(), DOCUMENTS);
+        assert_eq!(index.remove(0).as_deref(), Some("fruit"));
+        assert_eq!(index.remove(0), None);
+        assert_eq!(index.remove(7), None);
+        assert_eq!(index.document_count(), 2);
+        assert_eq!(index.document(0), None);
+        assert_eq!(postings(&index, "apple"), [(1, 1)]);
+        assert_eq!(postings(&index, "banana"), []);
+        assert_eq!(index.term_count(), 8);
+        assert_eq!(index.collection(), recount(&index));
+
+        // Ids are not reused
+        assert_eq!(index.add("fruit", "banana"), 3);
+        assert_eq!(postings(&index, "banana"), [(3, 1)]);
+        assert_eq!(index.collection(), recount(&index));
+        let names: Vec<_> = index.documents().map(|(doc, d)| (doc, d.name)).collect();
+        assert_eq!(names, [(1, "trees"), (2, "colours"), (3, "fruit")]);
+
+        for doc in 1..4 {
+            assert!(index.remove(doc).is_some());
+        }
+        assert_eq!(index.term_count(), 0);
+        assert_eq!(index.collection(), recount(&index));
+    }
+
+    #[test]
+    fn test_search_index() {
+        let index = Index::build_with(Analyzer::whitespace(), DOCUMENTS);
+        for query in ["apple", "cherry red", "tree apple", "nothing"] {
+            assert_eq!(
+                search_index(&index, query, 2).into_results(),
+                search(query, DOCUMENTS, 2).into_results(),
+                "{query}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_analyzer() {
+        let documents = [
+            ("fruit", "Apples, bananas and an apple."),
+            ("trees", "The apple tree; the pear trees."),
+        ];
+        let index = Index::build(&documents);
+        assert_eq!(postings(&index, "appl"), [(0, 2), (1, 1)]);
+        assert_eq!(postings(&index, "tree"), [(1, 2)]);
+        assert_eq!(postings(&index, "the"), []);
+        assert_eq!(index.document(1).unwrap().length, 4);
+
+        let mut incremental = Index::new();
+        for (name, text) in documents {
+            incremental.add(name, text);
+        }
+        assert_eq!(incremental, index);
+
+        // Queries are analyzed the same way
+        let results = search_index(&index, "BANANA", 2).into_results();
+        assert_eq!(results[0].1, "fruit");
+        assert!(results[0].0 > 0.0);
+        assert_eq!(
+            search_index(&index, "Trees!", 2).into_results(),
+            search_index(&index, "tree", 2).into_results()
+        );
+    }
+}
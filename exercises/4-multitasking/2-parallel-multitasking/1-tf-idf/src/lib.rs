@@ -0,0 +1,293 @@
+use rayon::prelude::*;
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BinaryHeap, HashMap},
+};
+
+mod analyzer;
+mod documents;
+mod eval;
+mod fuzzy;
+mod index;
+mod query;
+mod score;
+mod snippet;
+mod stem;
+mod storage;
+
+pub use analyzer::{Analyzer, ENGLISH_STOP_WORDS};
+pub use documents::DOCUMENTS;
+pub use eval::{evaluate, Evaluation, LabelledQuery};
+pub use fuzzy::{LevenshteinAutomaton, LevenshteinState};
+pub use index::{
+    search_index, search_with, DocId, Document, Index, Positions, Posting, Postings, SearchIndex,
+};
+pub use query::{search_query, Field, ParseError, Query};
+pub use score::{Bm25, Collection, NormalizedTfIdf, Scorer, TermMatch, TfIdf};
+pub use snippet::{Highlighter, Markup, Snippet};
+pub use stem::stem;
+pub use storage::MappedIndex;
+
+// in this exercise we implement a basic version of tf-idf using rayon.
+
+/// For each word in the document, how often does it occur in the document
+pub fn term_frequency(document: &str) -> HashMap<&str, usize> {
+    document
+        // good enough definition of "word" for this exercise
+        .split_whitespace()
+        // using fold to get some extra practice with monoids. Using a for loop is also totally fine.
+        .fold(HashMap::default(), |mut hash_map, word| {
+            *hash_map.entry(word).or_insert(0) += 1;
+            hash_map
+        })
+}
+
+pub fn combine_occurences<'a>(
+    a: HashMap<&'a str, usize>,
+    b: HashMap<&'a str, usize>,
+) -> HashMap<&'a str, usize> {
+    // combine the counts from maps a and b. If a word is in both maps, add up their counts,
+    // otherwise just use the count from one of the maps.
+    //
+    // NOTE: we're already using all of our cores to process whole documents. Using a parallel iterator
+    // here would likely make performance worse!
+    b.into_iter().fold(a, |mut hash_map, (word, count)| {
+        *hash_map.entry(word).or_insert(0) += count;
+        hash_map
+    })
+}
+
+/// Map each word in the document to the value 1
+pub fn term_occurence(document: &str) -> HashMap<&str, usize> {
+    document.split_whitespace().map(|word| (word, 1)).collect()
+}
+
+/// For each word, in how many of the documents it occurs
+pub fn document_frequency<'a>(
+    documents: impl rayon::iter::ParallelIterator<Item = &'a str>,
+) -> HashMap<&'a str, usize> {
+    documents
+        .map(term_occurence)
+        .reduce(HashMap::default, combine_occurences)
+}
+
+/// The tf·idf score of a document out of `document_count` documents
+pub fn score_document(
+    query: &str,
+    term_frequencies: &HashMap<&str, usize>,
+    document_frequencies: &HashMap<&str, usize>,
+    document_count: usize,
+) -> f64 {
+    let n = document_count as f64;
+
+    query
+        .split_whitespace()
+        .map(|word| {
+            let tf = *term_frequencies.get(word).unwrap_or(&0) as f64;
+            // a word that is in no document is not in this one either
+            let Some(&df) = document_frequencies.get(word) else {
+                return 0.0;
+            };
+            let idf = (n / df as f64).log10();
+
+            tf * idf
+        })
+        .sum::<f64>()
+}
+
+/// A search result, ordered by score, then by name and then by document,
+/// so that ties are broken the same way however the results were
+/// collected. The greatest result is the best one.
+#[derive(Debug, Clone, Copy)]
+struct Ranked<'a>(f64, &'a str, DocId);
+
+impl PartialEq for Ranked<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Ranked<'_> {}
+
+impl PartialOrd for Ranked<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Ranked<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // among equal scores, the name that sorts first ranks higher, and
+        // among equal names the document added first
+        f64::total_cmp(&self.0, &other.0)
+            .then_with(|| other.1.cmp(self.1))
+            .then_with(|| other.2.cmp(&self.2))
+    }
+}
+
+/// The best `n_results` results pushed into it
+#[derive(Debug)]
+pub struct SearchResultQueue<'a> {
+    // a min-heap, so the result to drop next is on top
+    results: BinaryHeap<Reverse<Ranked<'a>>>,
+    n_results: usize,
+}
+
+impl<'a> SearchResultQueue<'a> {
+    pub fn new(n_results: usize) -> Self {
+        Self {
+            results: BinaryHeap::with_capacity(n_results),
+            n_results,
+        }
+    }
+
+    /// Add the document `doc`, called `name`, if it is among the best so far
+    pub fn push(&mut self, score: f64, doc: DocId, name: &'a str) {
+        let result = Ranked(score, name, doc);
+
+        if self.results.len() < self.n_results {
+            self.results.push(Reverse(result));
+        } else if let Some(mut worst) = self.results.peek_mut() {
+            if result > worst.0 {
+                *worst = Reverse(result);
+            }
+        }
+    }
+
+    pub fn append(self, other: Self) -> Self {
+        // push the smaller queue into the bigger one
+        let (mut big, small) = if self.results.len() >= other.results.len() {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        for Reverse(Ranked(score, name, doc)) in small.results {
+            big.push(score, doc, name);
+        }
+        big
+    }
+
+    /// The results, best first
+    pub fn into_results(self) -> Vec<(f64, &'a str)> {
+        self.into_documents()
+            .into_iter()
+            .map(|(score, _, name)| (score, name))
+            .collect()
+    }
+
+    /// The results, best first, with their documents. Names need not be
+    /// unique, so this is what to look documents up by.
+    pub fn into_documents(self) -> Vec<(f64, DocId, &'a str)> {
+        // ascending `Reverse`s are descending results
+        self.results
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse(Ranked(score, name, doc))| (score, doc, name))
+            .collect()
+    }
+}
+
+/// Score every document for `query`, tokenizing all of them again. The
+/// documents are numbered by their position, like [Index::build] does.
+pub fn search<'a>(
+    query: &str,
+    documents: &'a [(&'a str, &'a str)],
+    n_results: usize,
+) -> SearchResultQueue<'a> {
+    let document_frequencies = document_frequency(documents.par_iter().map(|t| t.1));
+
+    documents
+        .par_iter()
+        .enumerate()
+        .fold(
+            || SearchResultQueue::new(n_results),
+            |mut state, (i, (name, doc))| {
+                let term_frequencies = term_frequency(doc);
+                let score = score_document(
+                    query,
+                    &term_frequencies,
+                    &document_frequencies,
+                    documents.len(),
+                );
+
+                state.push(score, i as DocId, name);
+
+                state
+            },
+        )
+        .reduce(
+            || SearchResultQueue::new(n_results),
+            SearchResultQueue::append,
+        )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use proptest::prelude::*;
+
+    /// Sort everything and keep the best `n_results`
+    fn top(results: &[(f64, String)], n_results: usize) -> Vec<(f64, &str)> {
+        let mut sorted: Vec<_> = results.iter().map(|(s, n)| (*s, n.as_str())).collect();
+        sorted.sort_by(|(s1, n1), (s2, n2)| f64::total_cmp(s2, s1).then(n1.cmp(n2)));
+        sorted.truncate(n_results);
+        sorted
+    }
+
+    /// Scores as bits, so that NaNs equal themselves
+    fn bits(results: Vec<(f64, &str)>) -> Vec<(u64, &str)> {
+        results.into_iter().map(|(s, n)| (s.to_bits(), n)).collect()
+    }
+
+    fn results() -> impl Strategy<Value = Vec<(f64, String)>> {
+        // few distinct scores and names, so that there are plenty of ties
+        let score = prop_oneof![(0..5).prop_map(f64::from), any::<f64>()];
+        prop::collection::vec((score, "[a-d]{1,2}"), 0..50)
+    }
+
+    #[test]
+    fn test_push() {
+        let mut queue = SearchResultQueue::new(2);
+        for (doc, (score, name)) in [(1.0, "a"), (3.0, "b"), (2.0, "c"), (0.5, "d")]
+            .into_iter()
+            .enumerate()
+        {
+            queue.push(score, doc as DocId, name);
+        }
+        // 2.0 replaces 1.0, though it is less than the best score so far
+        assert_eq!(queue.into_results(), [(3.0, "b"), (2.0, "c")]);
+
+        let mut queue = SearchResultQueue::new(0);
+        queue.push(1.0, 0, "a");
+        assert_eq!(queue.into_results(), []);
+    }
+
+    proptest! {
+        #[test]
+        fn prop_push(results in results(), n_results in 0..10usize) {
+            let mut queue = SearchResultQueue::new(n_results);
+            for (doc, (score, name)) in results.iter().enumerate() {
+                queue.push(*score, doc as DocId, name);
+            }
+            let expected = top(&results, n_results);
+            prop_assert_eq!(bits(queue.into_results()), bits(expected));
+        }
+
+        #[test]
+        fn prop_rayon(results in results(), n_results in 0..10usize) {
+            let queue = results
+                .par_iter()
+                .enumerate()
+                .with_max_len(3)
+                .fold(
+                    || SearchResultQueue::new(n_results),
+                    |mut queue, (doc, (score, name))| {
+                        queue.push(*score, doc as DocId, name);
+                        queue
+                    },
+                )
+                .reduce(|| SearchResultQueue::new(n_results), SearchResultQueue::append);
+            prop_assert_eq!(bits(queue.into_results()), bits(top(&results, n_results)));
+        }
+    }
+}
@@ -0,0 +1,934 @@
+//! A query language on top of the index.
+//!
+//! - `romeo juliet`: documents with either term, like a plain search
+//! - `romeo AND juliet`, `romeo OR juliet`, `romeo NOT juliet`, `NOT romeo`,
+//!   and parentheses to group them. `NOT` binds tightest, then `AND`,
+//!   then `OR`. The operators must be in capitals.
+//! - `"romeo and juliet"`: the terms right after each other
+//! - `name:romeo`, `name:"romeo and juliet"`: search the document names
+//!   rather than their text, which is `text:`
+//! - `romeo^2`, `(romeo juliet)^0.5`: multiply the score
+//! - `romoe~`, `romoe~1`: terms within 2, or the given number, of typos
+//!
+//! Words and phrases go through the analyzer of the index, so they match
+//! like they would in a plain search.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use rayon::prelude::*;
+
+use crate::{
+    Analyzer, Collection, DocId, LevenshteinAutomaton, Scorer, SearchIndex, SearchResultQueue,
+    TermMatch,
+};
+
+/// The most typos a fuzzy term can allow for
+const MAX_FUZZY_DISTANCE: u32 = 2;
+
+/// How deeply parentheses and `NOT`s can nest. The parser and the search
+/// recurse into nested queries, so a deeper one could overflow the stack.
+const MAX_DEPTH: usize = 256;
+
+/// A part of a document that can be searched
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Field {
+    #[default]
+    Text,
+    Name,
+}
+
+/// A parsed query
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    /// The documents containing the term
+    Term { field: Field, term: String },
+    /// The documents containing a term within `max_distance` typos of this
+    /// one. Closer terms score higher.
+    Fuzzy {
+        field: Field,
+        term: String,
+        max_distance: u32,
+    },
+    /// The documents containing the terms right after each other
+    Phrase { field: Field, terms: Vec<String> },
+    /// The documents matching all of these queries
+    And(Vec<Query>),
+    /// The documents matching any of these queries. Without any, this
+    /// matches nothing.
+    Or(Vec<Query>),
+    /// The documents not matching the query, with a score of 0
+    Not(Box<Query>),
+    /// The documents matching the query, with their score multiplied
+    Boost(Box<Query>, f64),
+}
+
+/// Why a query could not be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// The offset in the query, in bytes
+    pub position: usize,
+    pub message: &'static str,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {}", self.message, self.position)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl Query {
+    /// Parse `query`, analyzing its words with `analyzer`
+    pub fn parse(query: &str, analyzer: &Analyzer) -> Result<Query, ParseError> {
+        let tokens = tokenize(query)?;
+        if tokens.is_empty() {
+            return Ok(Query::Or(Vec::new()));
+        }
+        let mut parser = Parser {
+            tokens: &tokens,
+            next: 0,
+            end: query.len(),
+            depth: 0,
+            analyzer,
+        };
+        let parsed = parser.or()?;
+        if let Some(&(position, _)) = parser.tokens.get(parser.next) {
+            return Err(ParseError {
+                position,
+                message: "unmatched ')'",
+            });
+        }
+        Ok(parsed.unwrap_or(Query::Or(Vec::new())))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Token<'q> {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    Word(Field, &'q str),
+    Phrase(Field, &'q str),
+    Boost(f64),
+    Fuzzy(u32),
+}
+
+fn is_special(c: char) -> bool {
+    matches!(c, '(' | ')' | '"' | '^' | '~')
+}
+
+/// Split `query` into tokens, with their offsets
+fn tokenize(query: &str) -> Result<Vec<(usize, Token<'_>)>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut rest = query;
+    loop {
+        rest = rest.trim_start();
+        let position = query.len() - rest.len();
+        let error = |message| ParseError { position, message };
+        let Some(c) = rest.chars().next() else {
+            return Ok(tokens);
+        };
+
+        // The length of the token, and the token
+        let (len, token) = match c {
+            '(' => (1, Token::Open),
+            ')' => (1, Token::Close),
+            '^' => {
+                let number = number_prefix(&rest[1..]);
+                match number.parse::<f64>() {
+                    Ok(boost) if boost.is_finite() => (1 + number.len(), Token::Boost(boost)),
+                    _ => return Err(error("expected a number after '^'")),
+                }
+            }
+            '~' => {
+                let number = number_prefix(&rest[1..]);
+                let distance = match number {
+                    "" => MAX_FUZZY_DISTANCE,
+                    _ => number
+                        .parse()
+                        .ok()
+                        .filter(|&d| d <= MAX_FUZZY_DISTANCE)
+                        .ok_or_else(|| error("expected a number of typos from 0 to 2 after '~'"))?,
+                };
+                (1 + number.len(), Token::Fuzzy(distance))
+            }
+            _ => {
+                let (field, field_len) = match rest.split_once(':') {
+                    Some(("text", _)) => (Field::Text, 5),
+                    Some(("name", _)) => (Field::Name, 5),
+                    _ => (Field::Text, 0),
+                };
+                let after_field = &rest[field_len..];
+                if let Some(phrase) = after_field.strip_prefix('"') {
+                    let Some(end) = phrase.find('"') else {
+                        return Err(error("unterminated phrase"));
+                    };
+                    (field_len + end + 2, Token::Phrase(field, &phrase[..end]))
+                } else {
+                    let end = after_field
+                        .find(|c: char| c.is_whitespace() || is_special(c))
+                        .unwrap_or(after_field.len());
+                    let word = &after_field[..end];
+                    let token = match word {
+                        "" => return Err(error("expected a word or phrase after the field")),
+                        "AND" if field_len == 0 => Token::And,
+                        "OR" if field_len == 0 => Token::Or,
+                        "NOT" if field_len == 0 => Token::Not,
+                        _ => Token::Word(field, word),
+                    };
+                    (field_len + end, token)
+                }
+            }
+        };
+        tokens.push((position, token));
+        rest = &rest[len..];
+    }
+}
+
+/// The digits and dots at the start of `s`
+fn number_prefix(s: &str) -> &str {
+    let end = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    &s[..end]
+}
+
+/// A recursive descent parser. Each rule returns `None` for parts of the
+/// query without any terms, such as a stop word, which are left out.
+struct Parser<'t, 'q> {
+    tokens: &'t [(usize, Token<'q>)],
+    next: usize,
+    /// The length of the query, to point errors at the end
+    end: usize,
+    /// How many `unary`s we are in, up to [MAX_DEPTH]
+    depth: usize,
+    analyzer: &'t Analyzer,
+}
+
+impl<'q> Parser<'_, 'q> {
+    fn peek(&self) -> Option<Token<'q>> {
+        self.tokens.get(self.next).map(|&(_, token)| token)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens
+            .get(self.next)
+            .map_or(self.end, |&(position, _)| position)
+    }
+
+    fn error(&self, message: &'static str) -> ParseError {
+        ParseError {
+            position: self.position(),
+            message,
+        }
+    }
+
+    /// `and (OR? and)*`
+    fn or(&mut self) -> Result<Option<Query>, ParseError> {
+        let mut clauses = vec![self.and()?];
+        loop {
+            match self.peek() {
+                None | Some(Token::Close) => break,
+                Some(Token::Or) => self.next += 1,
+                _ => {}
+            }
+            clauses.push(self.and()?);
+        }
+        Ok(combine(clauses, Query::Or))
+    }
+
+    /// `not (AND not)*`
+    fn and(&mut self) -> Result<Option<Query>, ParseError> {
+        let mut clauses = vec![self.not()?];
+        while self.peek() == Some(Token::And) {
+            self.next += 1;
+            clauses.push(self.not()?);
+        }
+        Ok(combine(clauses, Query::And))
+    }
+
+    /// `unary (NOT unary)*`, where `a NOT b` is `a AND (NOT b)`
+    fn not(&mut self) -> Result<Option<Query>, ParseError> {
+        let mut clauses = vec![self.unary()?];
+        while self.peek() == Some(Token::Not) {
+            self.next += 1;
+            let excluded = self.unary()?;
+            clauses.push(excluded.map(|query| Query::Not(Box::new(query))));
+        }
+        Ok(combine(clauses, Query::And))
+    }
+
+    /// `NOT unary | primary`. Every level of nesting, of parentheses or
+    /// `NOT`s, goes through here, so this is where the depth is limited.
+    fn unary(&mut self) -> Result<Option<Query>, ParseError> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error("query nested too deeply"));
+        }
+        self.depth += 1;
+        let query = if self.peek() == Some(Token::Not) {
+            self.next += 1;
+            self.unary()
+                .map(|query| query.map(|query| Query::Not(Box::new(query))))
+        } else {
+            self.primary()
+        };
+        self.depth -= 1;
+        query
+    }
+
+    /// `( or ) | word | phrase`, followed by any boosts, and for words
+    /// by a fuzzy distance
+    fn primary(&mut self) -> Result<Option<Query>, ParseError> {
+        let Some(token) = self.peek() else {
+            return Err(self.error("expected a word, phrase or '('"));
+        };
+        let mut query = match token {
+            Token::Open => {
+                self.next += 1;
+                let query = self.or()?;
+                if self.peek() != Some(Token::Close) {
+                    return Err(self.error("expected ')'"));
+                }
+                self.next += 1;
+                query
+            }
+            Token::Word(field, word) => {
+                self.next += 1;
+                let terms = self.terms(word);
+                if let Some(Token::Fuzzy(max_distance)) = self.peek() {
+                    self.next += 1;
+                    // A word that analyzes to several terms needs them all
+                    let fuzzy = terms.into_iter().map(|term| {
+                        Some(Query::Fuzzy {
+                            field,
+                            term,
+                            max_distance,
+                        })
+                    });
+                    combine(fuzzy.collect(), Query::And)
+                } else {
+                    phrase(field, terms)
+                }
+            }
+            Token::Phrase(field, text) => {
+                self.next += 1;
+                phrase(field, self.terms(text))
+            }
+            _ => return Err(self.error("expected a word, phrase or '('")),
+        };
+
+        loop {
+            match self.peek() {
+                Some(Token::Boost(boost)) => {
+                    self.next += 1;
+                    // `romeo^2^3` is `romeo^6`, rather than boosts nested as
+                    // deep as there are `^`s
+                    query = query.map(|query| match query {
+                        Query::Boost(query, outer) => Query::Boost(query, outer * boost),
+                        query => Query::Boost(Box::new(query), boost),
+                    });
+                }
+                Some(Token::Fuzzy(_)) => return Err(self.error("only words can be fuzzy")),
+                _ => return Ok(query),
+            }
+        }
+    }
+
+    fn terms(&self, text: &str) -> Vec<String> {
+        self.analyzer
+            .terms(text)
+            .map(|term| term.into_owned())
+            .collect()
+    }
+}
+
+/// A single term, or a phrase for more
+fn phrase(field: Field, mut terms: Vec<String>) -> Option<Query> {
+    match terms.len() {
+        0 => None,
+        1 => Some(Query::Term {
+            field,
+            term: terms.pop().unwrap(),
+        }),
+        _ => Some(Query::Phrase { field, terms }),
+    }
+}
+
+/// Combine the clauses that are left, if there is more than one
+fn combine(clauses: Vec<Option<Query>>, combine: fn(Vec<Query>) -> Query) -> Option<Query> {
+    let mut clauses: Vec<_> = clauses.into_iter().flatten().collect();
+    match clauses.len() {
+        0 => None,
+        1 => clauses.pop(),
+        _ => Some(combine(clauses)),
+    }
+}
+
+/// The documents matching a query, with their scores
+type Matches = BTreeMap<DocId, f64>;
+
+/// Find the `n_results` documents that best match `query`, scoring terms
+/// and phrases with `scorer`
+pub fn search_query<'a>(
+    index: &'a impl SearchIndex,
+    scorer: &(impl Scorer + ?Sized),
+    query: &Query,
+    n_results: usize,
+) -> SearchResultQueue<'a> {
+    let searcher = Searcher {
+        index,
+        scorer,
+        collection: index.collection(),
+    };
+    searcher
+        .evaluate(query)
+        .into_par_iter()
+        .filter_map(|(doc, score)| Some((score, doc, index.document(doc)?.name)))
+        .fold(
+            || SearchResultQueue::new(n_results),
+            |mut state, (score, doc, name)| {
+                state.push(score, doc, name);
+                state
+            },
+        )
+        .reduce(
+            || SearchResultQueue::new(n_results),
+            SearchResultQueue::append,
+        )
+}
+
+struct Searcher<'a, I, S: ?Sized> {
+    index: &'a I,
+    scorer: &'a S,
+    collection: Collection,
+}
+
+impl<I: SearchIndex, S: Scorer + ?Sized> Searcher<'_, I, S> {
+    fn evaluate(&self, query: &Query) -> Matches {
+        match query {
+            Query::Term { field, term } => match field {
+                Field::Text => self.term(self.index, &self.collection, term),
+                Field::Name => {
+                    let names = self.index.names();
+                    self.term(names, &names.collection(), term)
+                }
+            },
+            Query::Fuzzy {
+                field,
+                term,
+                max_distance,
+            } => match field {
+                Field::Text => self.fuzzy(self.index, &self.collection, term, *max_distance),
+                Field::Name => {
+                    let names = self.index.names();
+                    self.fuzzy(names, &names.collection(), term, *max_distance)
+                }
+            },
+            Query::Phrase { field, terms } => match field {
+                Field::Text => self.phrase(self.index, &self.collection, terms),
+                Field::Name => {
+                    let names = self.index.names();
+                    self.phrase(names, &names.collection(), terms)
+                }
+            },
+            Query::And(queries) => self.and(queries),
+            Query::Or(queries) => {
+                let mut matches = Matches::new();
+                for query in queries {
+                    for (doc, score) in self.evaluate(query) {
+                        *matches.entry(doc).or_insert(0.0) += score;
+                    }
+                }
+                matches
+            }
+            Query::Not(query) => {
+                let excluded = self.evaluate(query);
+                self.all_documents()
+                    .filter(|(doc, _)| !excluded.contains_key(doc))
+                    .collect()
+            }
+            Query::Boost(query, boost) => {
+                let mut matches = self.evaluate(query);
+                matches.values_mut().for_each(|score| *score *= boost);
+                matches
+            }
+        }
+    }
+
+    /// Intersect the clauses, leaving out the ones under a `NOT` rather
+    /// than going over every document that does not match them
+    fn and(&self, queries: &[Query]) -> Matches {
+        let (excluded, included): (Vec<_>, Vec<_>) = queries
+            .iter()
+            .partition(|query| matches!(query, Query::Not(_)));
+
+        let mut included = included.into_iter();
+        let mut matches = match included.next() {
+            Some(query) => self.evaluate(query),
+            None => self.all_documents().collect(),
+        };
+        for query in included {
+            let other = self.evaluate(query);
+            matches.retain(|doc, score| match other.get(doc) {
+                Some(other) => {
+                    *score += other;
+                    true
+                }
+                None => false,
+            });
+        }
+        for query in excluded {
+            let Query::Not(query) = query else {
+                unreachable!()
+            };
+            let other = self.evaluate(query);
+            matches.retain(|doc, _| !other.contains_key(doc));
+        }
+        matches
+    }
+
+    fn all_documents(&self) -> impl Iterator<Item = (DocId, f64)> + '_ {
+        self.index.documents().map(|(doc, _)| (doc, 0.0))
+    }
+
+    fn score(&self, collection: &Collection, term: TermMatch) -> f64 {
+        self.scorer.score(collection, &term)
+    }
+
+    fn term(&self, index: &impl SearchIndex, collection: &Collection, term: &str) -> Matches {
+        let postings = index.postings(term);
+        let document_frequency = postings.len();
+        postings
+            .map(|posting| {
+                let term = TermMatch {
+                    frequency: posting.frequency,
+                    document_length: document_length(index, posting.doc),
+                    document_frequency,
+                };
+                (posting.doc, self.score(collection, term))
+            })
+            .collect()
+    }
+
+    /// Any term close enough to `term`, where one typo halves the score,
+    /// two make it a third, and so on
+    fn fuzzy(
+        &self,
+        index: &impl SearchIndex,
+        collection: &Collection,
+        term: &str,
+        max_distance: u32,
+    ) -> Matches {
+        let automaton = LevenshteinAutomaton::new(term, max_distance);
+        let mut matches = Matches::new();
+        // Sorted, so the scores are always added up in the same order
+        for (other, distance) in index.fuzzy_terms(&automaton) {
+            let weight = 1.0 / (1 + distance) as f64;
+            for (doc, score) in self.term(index, collection, other) {
+                *matches.entry(doc).or_insert(0.0) += weight * score;
+            }
+        }
+        matches
+    }
+
+    /// A phrase scores like a term that occurs wherever the phrase does
+    fn phrase(
+        &self,
+        index: &impl SearchIndex,
+        collection: &Collection,
+        terms: &[String],
+    ) -> Matches {
+        // Where the phrase starts in each document, as far as we know yet
+        let mut starts: BTreeMap<DocId, Vec<u32>> = index
+            .positions(&terms[0])
+            .map(|(posting, positions)| (posting.doc, positions))
+            .collect();
+        for (offset, term) in (1..).zip(&terms[1..]) {
+            if starts.is_empty() {
+                break;
+            }
+            let positions: BTreeMap<DocId, Vec<u32>> = index
+                .positions(term)
+                .filter(|(posting, _)| starts.contains_key(&posting.doc))
+                .map(|(posting, positions)| (posting.doc, positions))
+                .collect();
+            starts.retain(|doc, starts| {
+                let Some(positions) = positions.get(doc) else {
+                    return false;
+                };
+                starts.retain(|start| positions.binary_search(&(start + offset)).is_ok());
+                !starts.is_empty()
+            });
+        }
+
+        let document_frequency = starts.len();
+        starts
+            .into_iter()
+            .map(|(doc, starts)| {
+                let term = TermMatch {
+                    frequency: starts.len() as u32,
+                    document_length: document_length(index, doc),
+                    document_frequency,
+                };
+                (doc, self.score(collection, term))
+            })
+            .collect()
+    }
+}
+
+fn document_length(index: &impl SearchIndex, doc: DocId) -> u32 {
+    // Postings only refer to documents that are in the index
+    index.document(doc).map_or(0, |document| document.length)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::index::NameIndex;
+    use crate::{search_with, Bm25, Index, TfIdf};
+
+    fn parse(query: &str) -> Result<Query, ParseError> {
+        Query::parse(query, &Analyzer::default())
+    }
+
+    fn term(term: &str) -> Query {
+        Query::Term {
+            field: Field::Text,
+            term: term.to_owned(),
+        }
+    }
+
+    fn not(query: Query) -> Query {
+        Query::Not(Box::new(query))
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("Romeo"), Ok(term("romeo")));
+        assert_eq!(
+            parse("romeo juliet OR nurse"),
+            Ok(Query::Or(vec![term("romeo"), term("juliet"), term("nurs")]))
+        );
+        assert_eq!(
+            parse("romeo AND juliet nurse"),
+            Ok(Query::Or(vec![
+                Query::And(vec![term("romeo"), term("juliet")]),
+                term("nurs")
+            ]))
+        );
+        assert_eq!(
+            parse("romeo AND (juliet OR nurse)"),
+            Ok(Query::And(vec![
+                term("romeo"),
+                Query::Or(vec![term("juliet"), term("nurs")])
+            ]))
+        );
+        assert_eq!(
+            parse("romeo NOT juliet AND NOT nurse"),
+            Ok(Query::And(vec![
+                Query::And(vec![term("romeo"), not(term("juliet"))]),
+                not(term("nurs"))
+            ]))
+        );
+        assert_eq!(parse("NOT NOT romeo"), Ok(not(not(term("romeo")))));
+        assert_eq!(
+            parse(r#""Romeo and Juliet"^2"#),
+            Ok(Query::Boost(
+                Box::new(Query::Phrase {
+                    field: Field::Text,
+                    terms: vec!["romeo".into(), "juliet".into()]
+                }),
+                2.0
+            ))
+        );
+        assert_eq!(
+            parse("name:Juliet^1.5 text:nurse"),
+            Ok(Query::Or(vec![
+                Query::Boost(
+                    Box::new(Query::Term {
+                        field: Field::Name,
+                        term: "juliet".into()
+                    }),
+                    1.5
+                ),
+                term("nurs")
+            ]))
+        );
+        assert_eq!(
+            parse("romoe~ julet~1"),
+            Ok(Query::Or(vec![
+                Query::Fuzzy {
+                    field: Field::Text,
+                    term: "romo".into(),
+                    max_distance: 2
+                },
+                Query::Fuzzy {
+                    field: Field::Text,
+                    term: "julet".into(),
+                    max_distance: 1
+                },
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_parse_left_out() {
+        // Stop words and punctuation leave nothing to search for
+        assert_eq!(parse("the AND romeo"), Ok(term("romeo")));
+        assert_eq!(parse("romeo NOT (the OR a)"), Ok(term("romeo")));
+        assert_eq!(parse("the NOT romeo"), Ok(not(term("romeo"))));
+        assert_eq!(parse(r#"romeo "" ..."#), Ok(term("romeo")));
+        assert_eq!(parse(""), Ok(Query::Or(Vec::new())));
+        assert_eq!(parse("the^2"), Ok(Query::Or(Vec::new())));
+        // Words that turn into several terms are phrases
+        assert_eq!(
+            parse("rock-and-roll"),
+            Ok(Query::Phrase {
+                field: Field::Text,
+                terms: vec!["rock".into(), "roll".into()]
+            })
+        );
+        // Operators are only operators in capitals, and a colon is only a
+        // field after a field name
+        assert_eq!(
+            parse("romeo and juliet or nurse"),
+            Ok(Query::Or(vec![term("romeo"), term("juliet"), term("nurs")]))
+        );
+        assert_eq!(
+            parse("act:1"),
+            Ok(Query::Phrase {
+                field: Field::Text,
+                terms: vec!["act".into(), "1".into()]
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |query| parse(query).unwrap_err();
+        assert_eq!(
+            error("romeo AND"),
+            ParseError {
+                position: 9,
+                message: "expected a word, phrase or '('"
+            }
+        );
+        assert_eq!(error("(romeo juliet").message, "expected ')'");
+        assert_eq!(error("romeo)").position, 5);
+        assert_eq!(error(r#"romeo "and juliet"#).message, "unterminated phrase");
+        assert_eq!(error("romeo^").message, "expected a number after '^'");
+        assert_eq!(error("romeo^x").message, "expected a number after '^'");
+        assert_eq!(
+            error("romeo~3").message,
+            "expected a number of typos from 0 to 2 after '~'"
+        );
+        assert_eq!(
+            error(r#""romeo juliet"~"#).message,
+            "only words can be fuzzy"
+        );
+        assert_eq!(
+            error("name: romeo").message,
+            "expected a word or phrase after the field"
+        );
+        assert_eq!(
+            error("romeo AND").to_string(),
+            "expected a word, phrase or '(' at 9"
+        );
+    }
+
+    #[test]
+    fn test_parse_depth() {
+        let nested = |depth| format!("{}romeo{}", "(".repeat(depth), ")".repeat(depth));
+        assert_eq!(parse(&nested(MAX_DEPTH - 1)), Ok(term("romeo")));
+        assert_eq!(
+            parse(&nested(MAX_DEPTH)),
+            Err(ParseError {
+                position: MAX_DEPTH,
+                message: "query nested too deeply"
+            })
+        );
+        assert!(parse(&nested(100_000)).is_err());
+        assert!(parse(&format!("{}romeo", "NOT ".repeat(100_000))).is_err());
+
+        // boosts multiply rather than nest
+        let boosted = format!("romeo{}", "^1".repeat(100_000));
+        assert_eq!(
+            parse(&boosted),
+            Ok(Query::Boost(Box::new(term("romeo")), 1.0))
+        );
+        assert_eq!(
+            parse("(romeo^2)^3"),
+            Ok(Query::Boost(Box::new(term("romeo")), 6.0))
+        );
+    }
+
+    const DOCUMENTS: &[(&str, &str)] = &[
+        (
+            "Romeo and Juliet",
+            "Romeo loves Juliet. Juliet loves Romeo.",
+        ),
+        (
+            "The Nurse",
+            "The nurse talks to Juliet about Romeo, and about Paris.",
+        ),
+        (
+            "Paris",
+            "Paris wants to marry Juliet, and Romeo kills Paris.",
+        ),
+        (
+            "Verona",
+            "Two households, both alike in dignity, in fair Verona.",
+        ),
+    ];
+
+    fn names(index: &Index, query: &str) -> Vec<&'static str> {
+        let query = Query::parse(query, index.analyzer()).unwrap();
+        let mut names: Vec<_> = search_query(index, &TfIdf, &query, 10)
+            .into_results()
+            .into_iter()
+            .map(|(_, name)| {
+                let (name, _) = DOCUMENTS.iter().find(|(n, _)| *n == name).unwrap();
+                *name
+            })
+            .collect();
+        names.sort_unstable();
+        names
+    }
+
+    #[test]
+    fn test_boolean() {
+        let index = Index::build(DOCUMENTS);
+        assert_eq!(names(&index, "paris"), ["Paris", "The Nurse"]);
+        assert_eq!(
+            names(&index, "paris OR verona"),
+            ["Paris", "The Nurse", "Verona"]
+        );
+        assert_eq!(names(&index, "paris AND kills"), ["Paris"]);
+        assert_eq!(names(&index, "romeo NOT paris"), ["Romeo and Juliet"]);
+        assert_eq!(names(&index, "NOT juliet"), ["Verona"]);
+        assert_eq!(names(&index, "NOT (paris OR verona)"), ["Romeo and Juliet"]);
+        assert_eq!(names(&index, "nothing"), Vec::<&str>::new());
+        assert_eq!(names(&index, ""), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn test_phrase() {
+        let index = Index::build(DOCUMENTS);
+        assert_eq!(names(&index, r#""loves Juliet""#), ["Romeo and Juliet"]);
+        assert_eq!(
+            names(&index, r#""juliet loves romeo""#),
+            ["Romeo and Juliet"]
+        );
+        // Stop words are left out of the text as well as the phrase
+        assert_eq!(names(&index, r#""marry Juliet and Romeo""#), ["Paris"]);
+        assert_eq!(names(&index, r#""romeo juliet""#), Vec::<&str>::new());
+        assert_eq!(names(&index, r#""romeo nobody""#), Vec::<&str>::new());
+
+        // "loves juliet" occurs once in the first document, "loves romeo"
+        // once too, so they score the same
+        let score = |query| {
+            let query = Query::parse(query, index.analyzer()).unwrap();
+            search_query(&index, &TfIdf, &query, 1).into_results()[0].0
+        };
+        assert_eq!(score(r#""loves juliet""#), score(r#""loves romeo""#));
+        assert!(score(r#""loves juliet""#) > 0.0);
+    }
+
+    #[test]
+    fn test_fields_and_boosts() {
+        let index = Index::build(DOCUMENTS);
+        assert_eq!(names(&index, "name:paris"), ["Paris"]);
+        assert_eq!(
+            names(&index, r#"name:"romeo and juliet""#),
+            ["Romeo and Juliet"]
+        );
+        assert_eq!(names(&index, "name:households"), Vec::<&str>::new());
+
+        // The names are indexed once, and kept up to date after that
+        let mut changed = index.clone();
+        assert!(std::ptr::eq(changed.names(), changed.names()));
+        let (name, text) = DOCUMENTS[2];
+        assert_eq!(changed.remove(2).as_deref(), Some(name));
+        assert_eq!(names(&changed, "name:paris"), Vec::<&str>::new());
+        changed.add(name, text);
+        assert_eq!(names(&changed, "name:paris"), ["Paris"]);
+        let rebuilt = NameIndex::default();
+        assert_eq!(changed.names(), rebuilt.get_or_build(&changed));
+
+        let ranked = |query| {
+            let query = Query::parse(query, index.analyzer()).unwrap();
+            search_query(&index, &Bm25::default(), &query, 10)
+                .into_results()
+                .into_iter()
+                .map(|(_, name)| name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ranked("paris"), ["Paris", "The Nurse"]);
+        assert_eq!(ranked("paris^2 name:nurse^10"), ["The Nurse", "Paris"]);
+
+        let query = Query::parse("paris^2", index.analyzer()).unwrap();
+        let boosted = search_query(&index, &TfIdf, &query, 1).into_results()[0].0;
+        let query = Query::parse("paris", index.analyzer()).unwrap();
+        assert_eq!(
+            search_query(&index, &TfIdf, &query, 1).into_results()[0].0 * 2.0,
+            boosted
+        );
+    }
+
+    #[test]
+    fn test_fuzzy() {
+        let index = Index::build(DOCUMENTS);
+        assert_eq!(names(&index, "veronna~1"), ["Verona"]);
+        assert_eq!(names(&index, "veronna"), Vec::<&str>::new());
+        assert_eq!(names(&index, "name:pariss~"), ["Paris"]);
+        assert_eq!(names(&index, "juleit~ AND kils~"), ["Paris"]);
+
+        // A typo scores less than the real thing
+        let score = |query| {
+            let query = Query::parse(query, index.analyzer()).unwrap();
+            search_query(&index, &TfIdf, &query, 1).into_results()[0].0
+        };
+        assert_eq!(score("verona~"), score("verona"));
+        assert_eq!(score("verone~"), score("verona") / 2.0);
+    }
+
+    #[test]
+    fn test_matches_plain_search() {
+        // Without operators, a query scores like a plain search, except for
+        // the documents that do not match at all
+        let index = Index::build(DOCUMENTS);
+        for text in ["romeo", "romeo paris", "juliet nurse verona"] {
+            let query = Query::parse(text, index.analyzer()).unwrap();
+            let results = search_query(&index, &Bm25::default(), &query, 4).into_results();
+            let plain = search_with(&index, &Bm25::default(), text, 4).into_results();
+            assert_eq!(results[..], plain[..results.len()], "{text}");
+        }
+    }
+
+    #[test]
+    fn test_mapped() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index");
+        let mut index = Index::build(DOCUMENTS);
+        index.remove(1);
+        index.save(&path).unwrap();
+        let mapped = crate::MappedIndex::open(&path).unwrap();
+
+        for text in [
+            r#""loves juliet" OR name:paris^2"#,
+            "juleit~ NOT verona",
+            "NOT romeo",
+        ] {
+            let query = Query::parse(text, index.analyzer()).unwrap();
+            assert_eq!(
+                search_query(&mapped, &TfIdf, &query, 4).into_results(),
+                search_query(&index, &TfIdf, &query, 4).into_results(),
+                "{text}"
+            );
+        }
+    }
+}
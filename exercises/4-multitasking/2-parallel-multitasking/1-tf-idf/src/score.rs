@@ -0,0 +1,161 @@
+//! Ways to score how well a document matches a query term. The score of a
+//! document for a query is the sum of its scores for the query terms.
+
+/// What a scorer knows about the index as a whole
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Collection {
+    /// The number of documents, not counting removed ones
+    pub document_count: usize,
+    /// The average length of a document, in terms
+    pub average_length: f64,
+}
+
+impl Collection {
+    /// The collection of `document_count` documents that are `total_length`
+    /// terms long together
+    pub fn new(document_count: usize, total_length: u64) -> Self {
+        Collection {
+            document_count,
+            average_length: total_length as f64 / document_count.max(1) as f64,
+        }
+    }
+}
+
+/// How often a query term occurs, in a document and in the index
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TermMatch {
+    /// How often the term occurs in the document
+    pub frequency: u32,
+    /// The length of the document, in terms
+    pub document_length: u32,
+    /// The number of documents the term occurs in
+    pub document_frequency: usize,
+}
+
+pub trait Scorer: Sync {
+    /// The score of a document for a single query term. This is only asked
+    /// for documents the term occurs in; other documents score 0.
+    fn score(&self, collection: &Collection, term: &TermMatch) -> f64;
+}
+
+/// `log10(N / df)`: terms that occur in every document score 0, and rarer
+/// terms score higher
+fn inverse_document_frequency(collection: &Collection, term: &TermMatch) -> f64 {
+    (collection.document_count as f64 / term.document_frequency as f64).log10()
+}
+
+/// The classic tf·idf: how often the term occurs in the document, times
+/// how rare it is in the index
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TfIdf;
+
+impl Scorer for TfIdf {
+    fn score(&self, collection: &Collection, term: &TermMatch) -> f64 {
+        term.frequency as f64 * inverse_document_frequency(collection, term)
+    }
+}
+
+/// tf·idf with the frequency divided by the length of the document, so that
+/// long documents do not win just by being long
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct NormalizedTfIdf;
+
+impl Scorer for NormalizedTfIdf {
+    fn score(&self, collection: &Collection, term: &TermMatch) -> f64 {
+        let tf = term.frequency as f64 / term.document_length.max(1) as f64;
+        tf * inverse_document_frequency(collection, term)
+    }
+}
+
+/// Okapi BM25. More occurrences of a term add less and less to the score,
+/// and documents longer than average need more of them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bm25 {
+    /// How quickly the score saturates as the frequency grows: 0 ignores
+    /// the frequency altogether, larger values come closer to tf·idf
+    pub k1: f64,
+    /// How much the document length matters, from 0 (not at all) to 1
+    pub b: f64,
+}
+
+impl Default for Bm25 {
+    fn default() -> Self {
+        Bm25 { k1: 1.2, b: 0.75 }
+    }
+}
+
+impl Scorer for Bm25 {
+    fn score(&self, collection: &Collection, term: &TermMatch) -> f64 {
+        let n = collection.document_count as f64;
+        let df = term.document_frequency as f64;
+        // Unlike `log(N / df)`, this never gets negative
+        let idf = (1.0 + (n - df + 0.5) / (df + 0.5)).ln();
+
+        let tf = term.frequency as f64;
+        let relative_length = if collection.average_length > 0.0 {
+            term.document_length as f64 / collection.average_length
+        } else {
+            1.0
+        };
+        let norm = self.k1 * (1.0 - self.b + self.b * relative_length);
+        idf * tf * (self.k1 + 1.0) / (tf + norm)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const COLLECTION: Collection = Collection {
+        document_count: 100,
+        average_length: 50.0,
+    };
+
+    fn term(frequency: u32, document_length: u32, document_frequency: usize) -> TermMatch {
+        TermMatch {
+            frequency,
+            document_length,
+            document_frequency,
+        }
+    }
+
+    #[test]
+    fn test_tf_idf() {
+        assert_eq!(TfIdf.score(&COLLECTION, &term(3, 50, 10)), 3.0);
+        assert_eq!(TfIdf.score(&COLLECTION, &term(3, 50, 100)), 0.0);
+        assert_eq!(NormalizedTfIdf.score(&COLLECTION, &term(5, 50, 1)), 0.2);
+        assert_eq!(
+            NormalizedTfIdf.score(&COLLECTION, &term(10, 100, 1)),
+            NormalizedTfIdf.score(&COLLECTION, &term(5, 50, 1))
+        );
+    }
+
+    #[test]
+    fn test_bm25() {
+        let bm25 = Bm25::default();
+        let score = |frequency, length, df| bm25.score(&COLLECTION, &term(frequency, length, df));
+
+        // Rare terms count more, and no term counts negatively
+        assert!(score(1, 50, 1) > score(1, 50, 10));
+        assert!(score(1, 50, 100) > 0.0);
+        // More occurrences count more, but less and less so
+        let gains: Vec<_> = (1..5)
+            .map(|tf| score(tf + 1, 50, 10) - score(tf, 50, 10))
+            .collect();
+        assert!(gains.windows(2).all(|w| w[0] > w[1] && w[1] > 0.0));
+        assert!(score(1000, 50, 10) < (bm25.k1 + 1.0) * score(1, 50, 10));
+        // Long documents need more occurrences
+        assert!(score(2, 100, 10) < score(2, 50, 10));
+        let ignore_length = Bm25 { b: 0.0, ..bm25 };
+        assert_eq!(
+            ignore_length.score(&COLLECTION, &term(2, 100, 10)),
+            ignore_length.score(&COLLECTION, &term(2, 50, 10))
+        );
+
+        let empty = Collection {
+            document_count: 1,
+            average_length: 0.0,
+        };
+        assert!(bm25.score(&empty, &term(1, 0, 1)).is_finite());
+    }
+}
@@ -0,0 +1,348 @@
+//! Snippets: the parts of a document that match a query best, with the
+//! matching words highlighted.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::{Analyzer, Field, LevenshteinAutomaton, Query};
+
+/// How to mark the matching words in a snippet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Markup {
+    /// Bold red, for terminals. Control characters in the text are
+    /// written as `\u{..}` escapes, so that a document can't send the
+    /// terminal escape sequences of its own.
+    Ansi,
+    /// `<mark>` tags, with the rest of the text escaped
+    Html,
+    /// Not at all
+    Plain,
+}
+
+impl Markup {
+    /// `text` made safe to write out in this markup, without marking
+    /// anything
+    pub fn escape(self, text: &str) -> String {
+        let mut escaped = String::with_capacity(text.len());
+        push_escaped(&mut escaped, self, text);
+        escaped
+    }
+}
+
+/// A part of a document
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snippet<'t> {
+    document: &'t str,
+    /// Where the snippet is in the document, in bytes
+    pub range: Range<usize>,
+    /// Where the matching words are in the document, in bytes
+    pub matches: Vec<Range<usize>>,
+}
+
+impl<'t> Snippet<'t> {
+    pub fn text(&self) -> &'t str {
+        &self.document[self.range.clone()]
+    }
+
+    /// The text of the snippet on a single line, with the matches marked
+    pub fn highlight(&self, markup: Markup) -> String {
+        let (open, close) = match markup {
+            Markup::Ansi => ("\x1b[1;31m", "\x1b[0m"),
+            Markup::Html => ("<mark>", "</mark>"),
+            Markup::Plain => ("", ""),
+        };
+
+        let mut highlighted = String::new();
+        let mut end = self.range.start;
+        for range in &self.matches {
+            push_text(&mut highlighted, markup, &self.document[end..range.start]);
+            highlighted.push_str(open);
+            push_text(&mut highlighted, markup, &self.document[range.clone()]);
+            highlighted.push_str(close);
+            end = range.end;
+        }
+        push_text(
+            &mut highlighted,
+            markup,
+            &self.document[end..self.range.end],
+        );
+        highlighted
+    }
+}
+
+/// Appends `text` with its whitespace collapsed, since line breaks are just
+/// where the document happened to wrap
+fn push_text(out: &mut String, markup: Markup, text: &str) {
+    for (i, part) in text.split(char::is_whitespace).enumerate() {
+        if i > 0 && !out.ends_with(' ') {
+            out.push(' ');
+        }
+        push_escaped(out, markup, part);
+    }
+}
+
+fn push_escaped(out: &mut String, markup: Markup, text: &str) {
+    match markup {
+        Markup::Html => {
+            for c in text.chars() {
+                match c {
+                    '&' => out.push_str("&amp;"),
+                    '<' => out.push_str("&lt;"),
+                    '>' => out.push_str("&gt;"),
+                    '"' => out.push_str("&quot;"),
+                    '\'' => out.push_str("&#39;"),
+                    _ => out.push(c),
+                }
+            }
+        }
+        Markup::Ansi => {
+            for c in text.chars() {
+                if c.is_control() {
+                    out.extend(c.escape_unicode());
+                } else {
+                    out.push(c);
+                }
+            }
+        }
+        Markup::Plain => out.push_str(text),
+    }
+}
+
+/// Finds the snippets of documents that match a query best
+#[derive(Debug, Clone)]
+pub struct Highlighter<'a> {
+    analyzer: &'a Analyzer,
+    /// The terms in the query, and the fuzzy terms after them
+    terms: Vec<String>,
+    fuzzy: Vec<LevenshteinAutomaton>,
+    /// How many terms a snippet is long
+    pub window: usize,
+    /// The most snippets to find in a document
+    pub max_snippets: usize,
+}
+
+impl<'a> Highlighter<'a> {
+    /// Highlight the terms of `query` in the text of documents, which are
+    /// analyzed by `analyzer`. Terms under a `NOT` are not highlighted.
+    pub fn new(analyzer: &'a Analyzer, query: &Query) -> Self {
+        let mut highlighter = Highlighter {
+            analyzer,
+            terms: Vec::new(),
+            fuzzy: Vec::new(),
+            window: 30,
+            max_snippets: 2,
+        };
+        highlighter.add_terms(query);
+        highlighter
+    }
+
+    fn add_terms(&mut self, query: &Query) {
+        match query {
+            Query::Term {
+                field: Field::Text,
+                term,
+            } => self.terms.push(term.clone()),
+            Query::Phrase {
+                field: Field::Text,
+                terms,
+            } => self.terms.extend(terms.iter().cloned()),
+            Query::Fuzzy {
+                field: Field::Text,
+                term,
+                max_distance,
+            } => self
+                .fuzzy
+                .push(LevenshteinAutomaton::new(term, *max_distance)),
+            Query::And(queries) | Query::Or(queries) => {
+                queries.iter().for_each(|query| self.add_terms(query));
+            }
+            Query::Boost(query, _) => self.add_terms(query),
+            Query::Term { .. } | Query::Phrase { .. } | Query::Fuzzy { .. } | Query::Not(_) => {}
+        }
+    }
+
+    /// Which term of the query `term` is, if any
+    fn query_term(&self, term: &str) -> Option<usize> {
+        if let Some(i) = self.terms.iter().position(|t| t == term) {
+            return Some(i);
+        }
+        let fuzzy = self.fuzzy.iter().position(|f| f.matches(term).is_some())?;
+        Some(self.terms.len() + fuzzy)
+    }
+
+    /// The best snippets of `document`, in the order they occur in it.
+    ///
+    /// Snippets are scored by how many of the query terms they contain,
+    /// and then by how many matches they contain in total, so that a
+    /// snippet with both "romeo" and "juliet" beats one with "romeo" twice.
+    /// If nothing matches, this is the start of the document.
+    pub fn snippets<'t>(&self, document: &'t str) -> Vec<Snippet<'t>> {
+        let body = self.analyzer.document_body(document);
+        // The body is a slice of the document
+        let offset = body.as_ptr() as usize - document.as_ptr() as usize;
+        let mut words = Vec::new();
+        // The positions of the matches in `words`, and which term they match
+        let mut matches = Vec::new();
+        let mut cache = HashMap::new();
+        for (range, term) in self.analyzer.terms_with_offsets(body) {
+            let query_term = *cache
+                .entry(term)
+                .or_insert_with_key(|term| self.query_term(term));
+            if let Some(query_term) = query_term {
+                matches.push((words.len(), query_term));
+            }
+            words.push(range.start + offset..range.end + offset);
+        }
+        if words.is_empty() || self.max_snippets == 0 {
+            return Vec::new();
+        }
+        let window = self.window.max(1);
+
+        // Try windows around every match, starting a little before it
+        let mut candidates: Vec<_> = matches
+            .iter()
+            .enumerate()
+            .map(|(i, &(position, _))| {
+                let start = position.saturating_sub(window / 4);
+                let end = (start + window).min(words.len());
+                let mut distinct = Vec::new();
+                let mut count = 0;
+                let first = matches[..i].partition_point(|&(p, _)| p < start);
+                for &(_, query_term) in matches[first..].iter().take_while(|&&(p, _)| p < end) {
+                    count += 1;
+                    if !distinct.contains(&query_term) {
+                        distinct.push(query_term);
+                    }
+                }
+                ((distinct.len(), count), start..end)
+            })
+            .collect();
+        if candidates.is_empty() {
+            candidates.push(((0, 0), 0..window.min(words.len())));
+        }
+        // Best first, and earliest first among equals
+        candidates.sort_by_key(|(score, window)| (std::cmp::Reverse(*score), window.start));
+
+        let mut chosen: Vec<Range<usize>> = Vec::new();
+        for (_, window) in candidates {
+            if chosen.len() == self.max_snippets {
+                break;
+            }
+            if chosen
+                .iter()
+                .all(|other| window.end <= other.start || other.end <= window.start)
+            {
+                chosen.push(window);
+            }
+        }
+        chosen.sort_by_key(|window| window.start);
+
+        chosen
+            .into_iter()
+            .map(|window| Snippet {
+                document,
+                range: words[window.start].start..words[window.end - 1].end,
+                matches: matches
+                    .iter()
+                    .filter(|&&(position, _)| window.contains(&position))
+                    .map(|&(position, _)| words[position].clone())
+                    .collect(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn snippets(query: &str, document: &str, window: usize) -> Vec<String> {
+        let analyzer = Analyzer::default();
+        let query = Query::parse(query, &analyzer).unwrap();
+        let mut highlighter = Highlighter::new(&analyzer, &query);
+        highlighter.window = window;
+        highlighter
+            .snippets(document)
+            .iter()
+            .map(|snippet| snippet.highlight(Markup::Html))
+            .collect()
+    }
+
+    #[test]
+    fn test_snippets() {
+        let document = "Two households, both alike in dignity, in fair Verona, \
+                        where we lay our scene. A pair of star-crossed lovers \
+                        take their life; Romeo and Juliet. Romeo is a Montague.";
+        assert_eq!(
+            snippets("romeo juliet", document, 4),
+            ["life; <mark>Romeo</mark> and <mark>Juliet</mark>. <mark>Romeo</mark>"]
+        );
+        // Two windows, in document order
+        assert_eq!(
+            snippets("households montague", document, 2),
+            ["<mark>households</mark>, both", "<mark>Montague</mark>"]
+        );
+        assert_eq!(
+            snippets("verona~ NOT romeo", document, 3),
+            ["<mark>Verona</mark>, where we"]
+        );
+        // Without matches, the start of the document
+        assert_eq!(
+            snippets("NOT nothing", document, 3),
+            ["Two households, both"]
+        );
+        assert_eq!(snippets("romeo", "", 3), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_offsets() {
+        let analyzer = Analyzer::default();
+        let document = "*** START OF THE PROJECT GUTENBERG EBOOK X ***\nRomeo!\n";
+        let query = Query::parse("romeo", &analyzer).unwrap();
+        let snippets = Highlighter::new(&analyzer, &query).snippets(document);
+        assert_eq!(snippets.len(), 1);
+        assert_eq!(snippets[0].range, 47..52);
+        assert_eq!(snippets[0].matches.len(), 1);
+        assert_eq!(snippets[0].matches[0], 47..52);
+        assert_eq!(snippets[0].text(), "Romeo");
+    }
+
+    #[test]
+    fn test_highlight() {
+        let document = "a <b>Romeo</b> &\n  Juliet's";
+        let snippet = Snippet {
+            document,
+            range: 0..document.len(),
+            matches: vec![5..10, 19..27],
+        };
+        assert_eq!(
+            snippet.highlight(Markup::Html),
+            "a &lt;b&gt;<mark>Romeo</mark>&lt;/b&gt; &amp; <mark>Juliet&#39;s</mark>"
+        );
+        assert_eq!(
+            snippet.highlight(Markup::Ansi),
+            "a <b>\x1b[1;31mRomeo\x1b[0m</b> & \x1b[1;31mJuliet's\x1b[0m"
+        );
+        assert_eq!(
+            snippet.highlight(Markup::Plain),
+            "a <b>Romeo</b> & Juliet's"
+        );
+    }
+
+    #[test]
+    fn test_escape() {
+        let document = "\x1b]0;pwned\x07Romeo\u{9b}2J";
+        let snippet = Snippet {
+            document,
+            range: 0..document.len(),
+            matches: vec![4..9, 10..15],
+        };
+        assert_eq!(
+            snippet.highlight(Markup::Ansi),
+            "\\u{1b}]0;\x1b[1;31mpwned\x1b[0m\\u{7}\x1b[1;31mRomeo\x1b[0m\\u{9b}2J"
+        );
+        assert_eq!(Markup::Ansi.escape("a\x1bb"), "a\\u{1b}b");
+        assert_eq!(Markup::Html.escape("<a>"), "&lt;a&gt;");
+        assert_eq!(Markup::Plain.escape("<a>\x1b"), "<a>\x1b");
+    }
+}
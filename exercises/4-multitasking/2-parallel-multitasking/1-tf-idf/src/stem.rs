@@ -0,0 +1,314 @@
+//! The Porter stemmer, which strips English suffixes so that for example
+//! "connected", "connecting" and "connection" all become "connect".
+//!
+//! This follows Martin Porter's reference implementation, including its
+//! departures from the original paper ("bli" and "logi" in step 2).
+
+use std::borrow::Cow;
+
+/// Stem `word`, which is expected to be lowercase. Words that contain
+/// anything but ASCII letters are returned unchanged.
+pub fn stem(word: &str) -> Cow<'_, str> {
+    if word.len() <= 2 || !word.bytes().all(|b| b.is_ascii_lowercase()) {
+        return Cow::Borrowed(word);
+    }
+
+    let mut stemmer = Stemmer {
+        word: word.as_bytes().to_vec(),
+    };
+    stemmer.step_1ab();
+    stemmer.step_1c();
+    stemmer.step_2();
+    stemmer.step_3();
+    stemmer.step_4();
+    stemmer.step_5();
+
+    let stemmed = stemmer.word;
+    if word.as_bytes().starts_with(&stemmed) {
+        Cow::Borrowed(&word[..stemmed.len()])
+    } else {
+        // Only ASCII letters are ever put in
+        Cow::Owned(String::from_utf8(stemmed).unwrap())
+    }
+}
+
+struct Stemmer {
+    word: Vec<u8>,
+}
+
+/// Whether `stem[i]` is a consonant. A 'y' is a consonant at the start,
+/// and after a vowel.
+fn is_consonant(stem: &[u8], i: usize) -> bool {
+    match stem[i] {
+        b'a' | b'e' | b'i' | b'o' | b'u' => false,
+        b'y' => i == 0 || !is_consonant(stem, i - 1),
+        _ => true,
+    }
+}
+
+/// The number of vowel-consonant sequences in `stem`: writing it as
+/// `[C](VC)^m[V]`, this is `m`
+fn measure(stem: &[u8]) -> usize {
+    let mut m = 0;
+    let mut previous_vowel = false;
+    for i in 0..stem.len() {
+        let consonant = is_consonant(stem, i);
+        if consonant && previous_vowel {
+            m += 1;
+        }
+        previous_vowel = !consonant;
+    }
+    m
+}
+
+fn has_vowel(stem: &[u8]) -> bool {
+    (0..stem.len()).any(|i| !is_consonant(stem, i))
+}
+
+/// Whether `stem` ends in the same consonant twice
+fn ends_double_consonant(stem: &[u8]) -> bool {
+    let n = stem.len();
+    n >= 2 && stem[n - 1] == stem[n - 2] && is_consonant(stem, n - 1)
+}
+
+/// Whether `stem` ends consonant-vowel-consonant, where the last consonant
+/// is not 'w', 'x' or 'y', as in "hop" but not in "snow"
+fn ends_cvc(stem: &[u8]) -> bool {
+    let n = stem.len();
+    n >= 3
+        && is_consonant(stem, n - 1)
+        && !is_consonant(stem, n - 2)
+        && is_consonant(stem, n - 3)
+        && !matches!(stem[n - 1], b'w' | b'x' | b'y')
+}
+
+impl Stemmer {
+    /// The word without `suffix`, if it ends in it
+    fn stem_before(&self, suffix: &str) -> Option<&[u8]> {
+        self.word.strip_suffix(suffix.as_bytes())
+    }
+
+    fn replace_suffix(&mut self, suffix_len: usize, replacement: &str) {
+        self.word.truncate(self.word.len() - suffix_len);
+        self.word.extend_from_slice(replacement.as_bytes());
+    }
+
+    /// Replace the first of `rules` whose suffix the word ends in, if the
+    /// rest of the word has a measure of more than `min_measure`
+    fn replace_first(&mut self, rules: &[(&str, &str)], min_measure: usize) {
+        for &(suffix, replacement) in rules {
+            if let Some(stem) = self.stem_before(suffix) {
+                if measure(stem) > min_measure {
+                    self.replace_suffix(suffix.len(), replacement);
+                }
+                return;
+            }
+        }
+    }
+
+    /// Plurals, and "-ed" and "-ing"
+    fn step_1ab(&mut self) {
+        if self.word.ends_with(b"sses") || self.word.ends_with(b"ies") {
+            self.word.truncate(self.word.len() - 2);
+        } else if self.word.ends_with(b"s") && !self.word.ends_with(b"ss") {
+            self.word.pop();
+        }
+
+        if let Some(stem) = self.stem_before("eed") {
+            if measure(stem) > 0 {
+                self.word.pop();
+            }
+            return;
+        }
+        let removed = ["ed", "ing"]
+            .into_iter()
+            .find(|suffix| self.stem_before(suffix).is_some_and(has_vowel));
+        let Some(removed) = removed else {
+            return;
+        };
+        self.word.truncate(self.word.len() - removed.len());
+
+        if self.word.ends_with(b"at") || self.word.ends_with(b"bl") || self.word.ends_with(b"iz") {
+            self.word.push(b'e');
+        } else if ends_double_consonant(&self.word)
+            && !matches!(self.word.last(), Some(b'l' | b's' | b'z'))
+        {
+            self.word.pop();
+        } else if measure(&self.word) == 1 && ends_cvc(&self.word) {
+            self.word.push(b'e');
+        }
+    }
+
+    /// A final 'y' after a vowel becomes 'i'
+    fn step_1c(&mut self) {
+        if self.stem_before("y").is_some_and(has_vowel) {
+            *self.word.last_mut().unwrap() = b'i';
+        }
+    }
+
+    /// Double suffixes become single ones
+    fn step_2(&mut self) {
+        self.replace_first(
+            &[
+                ("ational", "ate"),
+                ("tional", "tion"),
+                ("enci", "ence"),
+                ("anci", "ance"),
+                ("izer", "ize"),
+                ("bli", "ble"),
+                ("alli", "al"),
+                ("entli", "ent"),
+                ("eli", "e"),
+                ("ousli", "ous"),
+                ("ization", "ize"),
+                ("ation", "ate"),
+                ("ator", "ate"),
+                ("alism", "al"),
+                ("iveness", "ive"),
+                ("fulness", "ful"),
+                ("ousness", "ous"),
+                ("aliti", "al"),
+                ("iviti", "ive"),
+                ("biliti", "ble"),
+                ("logi", "log"),
+            ],
+            0,
+        );
+    }
+
+    fn step_3(&mut self) {
+        self.replace_first(
+            &[
+                ("icate", "ic"),
+                ("ative", ""),
+                ("alize", "al"),
+                ("iciti", "ic"),
+                ("ical", "ic"),
+                ("ful", ""),
+                ("ness", ""),
+            ],
+            0,
+        );
+    }
+
+    /// Remove the remaining suffixes from long enough words
+    fn step_4(&mut self) {
+        const SUFFIXES: &[&str] = &[
+            "al", "ance", "ence", "er", "ic", "able", "ible", "ant", "ement", "ment", "ent", "ion",
+            "ou", "ism", "ate", "iti", "ous", "ive", "ize",
+        ];
+        for suffix in SUFFIXES {
+            if let Some(stem) = self.stem_before(suffix) {
+                let allowed = *suffix != "ion" || matches!(stem.last(), Some(b's' | b't'));
+                if allowed && measure(stem) > 1 {
+                    self.word.truncate(stem.len());
+                }
+                // Like the other steps, only the first match counts
+                return;
+            }
+        }
+    }
+
+    /// Remove a final 'e', and a double 'l' from long enough words
+    fn step_5(&mut self) {
+        if let Some(stem) = self.stem_before("e") {
+            let m = measure(stem);
+            if m > 1 || (m == 1 && !ends_cvc(stem)) {
+                self.word.pop();
+            }
+        }
+        if self.word.ends_with(b"ll") && measure(&self.word) > 1 {
+            self.word.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_stem() {
+        // Examples from the paper, run through all the steps
+        let examples = [
+            ("caresses", "caress"),
+            ("ponies", "poni"),
+            ("ties", "ti"),
+            ("cats", "cat"),
+            ("feed", "feed"),
+            ("agreed", "agre"),
+            ("plastered", "plaster"),
+            ("bled", "bled"),
+            ("motoring", "motor"),
+            ("sing", "sing"),
+            ("conflated", "conflat"),
+            ("troubled", "troubl"),
+            ("sized", "size"),
+            ("hopping", "hop"),
+            ("tanned", "tan"),
+            ("falling", "fall"),
+            ("hissing", "hiss"),
+            ("fizzed", "fizz"),
+            ("failing", "fail"),
+            ("filing", "file"),
+            ("happy", "happi"),
+            ("sky", "sky"),
+            ("relational", "relat"),
+            ("conditional", "condit"),
+            ("rational", "ration"),
+            ("digitizer", "digit"),
+            ("conformabli", "conform"),
+            ("differentli", "differ"),
+            ("vietnamization", "vietnam"),
+            ("predication", "predic"),
+            ("operator", "oper"),
+            ("feudalism", "feudal"),
+            ("decisiveness", "decis"),
+            ("hopefulness", "hope"),
+            ("callousness", "callous"),
+            ("sensitiviti", "sensit"),
+            ("sensibiliti", "sensibl"),
+            ("triplicate", "triplic"),
+            ("formative", "form"),
+            ("electrical", "electr"),
+            ("goodness", "good"),
+            ("revival", "reviv"),
+            ("allowance", "allow"),
+            ("inference", "infer"),
+            ("airliner", "airlin"),
+            ("gyroscopic", "gyroscop"),
+            ("adjustable", "adjust"),
+            ("defensible", "defens"),
+            ("irritant", "irrit"),
+            ("replacement", "replac"),
+            ("dependent", "depend"),
+            ("adoption", "adopt"),
+            ("communism", "commun"),
+            ("activate", "activ"),
+            ("homologous", "homolog"),
+            ("effective", "effect"),
+            ("bowdlerize", "bowdler"),
+            ("probate", "probat"),
+            ("rate", "rate"),
+            ("cease", "ceas"),
+            ("controll", "control"),
+            ("roll", "roll"),
+            ("generalizations", "gener"),
+            ("romeos", "romeo"),
+        ];
+        for (word, stemmed) in examples {
+            assert_eq!(stem(word), stemmed, "{word}");
+        }
+    }
+
+    #[test]
+    fn test_unchanged() {
+        for word in ["", "is", "Running", "café", "r2d2"] {
+            assert!(matches!(stem(word), Cow::Borrowed(s) if s == word));
+        }
+        // Only allocates when the stem is not a prefix of the word
+        assert!(matches!(stem("running"), Cow::Borrowed("run")));
+        assert!(matches!(stem("ponies"), Cow::Borrowed("poni")));
+        assert!(matches!(stem("happy"), Cow::Owned(_)));
+    }
+}
@@ -0,0 +1,788 @@
+//! Storing an index in a file, and searching it straight from a memory map.
+//!
+//! All numbers are little endian. The file consists of:
+//!
+//! - a header: the magic bytes `TFIX`, the format version, the number of
+//!   document slots and of terms, the lengths of the string and postings
+//!   sections, the [analyzer](crate::Analyzer) flags, the offset and length
+//!   of its stop words in the string section, and the length of the
+//!   positions section, each as a `u32`. The flags are 1 for stripping the
+//!   Gutenberg header, 2 for stripping punctuation, 4 for lowercasing and 8
+//!   for stemming; the stop words are sorted and separated by newlines.
+//! - the document table, 12 bytes per document slot: the offset and length
+//!   of its name in the string section, and its length in terms. Removed
+//!   documents have a length of `u32::MAX`.
+//! - the term table, 20 bytes per term, sorted by term: the offset and
+//!   length of the term in the string section, the offset of its postings
+//!   in the postings section, the number of postings, and the offset of its
+//!   positions in the positions section
+//! - the string section, with the UTF-8 names and terms
+//! - the postings section: for every posting, the difference between its
+//!   document id and the previous one (or the id itself for the first),
+//!   and the frequency, each as an unsigned LEB128 varint
+//! - the positions section: for every posting, as many varints as its
+//!   frequency, with the difference between each position of the term in
+//!   the document and the previous one (or the position itself for the
+//!   first)
+//!
+//! Opening a file checks all of this, so queries can trust it.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::ops::{Bound, Range};
+use std::path::Path;
+
+use memmap2::Mmap;
+
+use crate::index::{NameIndex, PositionsInner, PostingsInner, StoredDocument, TermPostings};
+use crate::{
+    Analyzer, Collection, DocId, Document, Index, LevenshteinAutomaton, Positions, Posting,
+    Postings, SearchIndex,
+};
+
+const MAGIC: [u8; 4] = *b"TFIX";
+const VERSION: u32 = 3;
+const HEADER_SIZE: usize = 40;
+const DOCUMENT_SIZE: usize = 12;
+const TERM_SIZE: usize = 20;
+const REMOVED: u32 = u32::MAX;
+
+const STRIP_GUTENBERG: u32 = 1;
+const STRIP_PUNCTUATION: u32 = 2;
+const LOWERCASE: u32 = 4;
+const STEM: u32 = 8;
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+fn too_large() -> io::Error {
+    invalid_data("index is too large for the file format")
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Read a varint from the start of `bytes`, returning it and its length
+fn read_varint(bytes: &[u8]) -> Option<(u32, usize)> {
+    let mut value = 0u32;
+    for (i, &byte) in bytes.iter().enumerate().take(5) {
+        let bits = u32::from(byte & 0x7f);
+        if i == 4 && bits > 0x0f {
+            return None;
+        }
+        value |= bits << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+/// Append `s` to `strings`, returning its offset and length
+fn push_str(strings: &mut Vec<u8>, s: &str) -> io::Result<[u32; 2]> {
+    let offset = u32::try_from(strings.len()).map_err(|_| too_large())?;
+    let len = u32::try_from(s.len()).map_err(|_| too_large())?;
+    strings.extend_from_slice(s.as_bytes());
+    Ok([offset, len])
+}
+
+impl Index {
+    /// Write the index in the format described in the [module](self)
+    pub fn write_to(&self, out: impl Write) -> io::Result<()> {
+        let mut out = BufWriter::new(out);
+        let mut strings = Vec::new();
+        let mut postings = Vec::new();
+        let mut positions = Vec::new();
+
+        let analyzer = &self.analyzer;
+        let flags = [
+            (analyzer.strip_gutenberg, STRIP_GUTENBERG),
+            (analyzer.strip_punctuation, STRIP_PUNCTUATION),
+            (analyzer.lowercase, LOWERCASE),
+            (analyzer.stem, STEM),
+        ]
+        .into_iter()
+        .filter(|&(enabled, _)| enabled)
+        .fold(0, |flags, (_, flag)| flags | flag);
+        let stop_words = analyzer.stop_words.iter().map(String::as_str);
+        let stop_words = push_str(&mut strings, &stop_words.collect::<Vec<_>>().join("\n"))?;
+
+        let mut documents = Vec::with_capacity(self.documents.len() * DOCUMENT_SIZE);
+        for document in &self.documents {
+            let fields = match document {
+                Some(document) => {
+                    let [offset, len] = push_str(&mut strings, &document.name)?;
+                    [offset, len, document.length]
+                }
+                None => [0, 0, REMOVED],
+            };
+            documents.extend(fields.iter().flat_map(|field| field.to_le_bytes()));
+        }
+
+        let mut term_table = Vec::with_capacity(self.postings.len() * TERM_SIZE);
+        for (term, term_postings) in &self.postings {
+            let [offset, len] = push_str(&mut strings, term)?;
+            let postings_offset = u32::try_from(postings.len()).map_err(|_| too_large())?;
+            let positions_offset = u32::try_from(positions.len()).map_err(|_| too_large())?;
+            let mut previous = 0;
+            for (posting, term_positions) in
+                term_postings.postings.iter().zip(&term_postings.positions)
+            {
+                write_varint(&mut postings, posting.doc - previous);
+                write_varint(&mut postings, posting.frequency);
+                previous = posting.doc;
+
+                let mut previous = 0;
+                for &position in term_positions.iter() {
+                    write_varint(&mut positions, position - previous);
+                    previous = position;
+                }
+            }
+            let count = term_postings.postings.len() as u32;
+            let fields = [offset, len, postings_offset, count, positions_offset];
+            term_table.extend(fields.iter().flat_map(|field| field.to_le_bytes()));
+        }
+
+        let header = [
+            u32::from_le_bytes(MAGIC),
+            VERSION,
+            u32::try_from(self.documents.len()).map_err(|_| too_large())?,
+            u32::try_from(self.postings.len()).map_err(|_| too_large())?,
+            u32::try_from(strings.len()).map_err(|_| too_large())?,
+            u32::try_from(postings.len()).map_err(|_| too_large())?,
+            flags,
+            stop_words[0],
+            stop_words[1],
+            u32::try_from(positions.len()).map_err(|_| too_large())?,
+        ];
+        for field in header {
+            out.write_all(&field.to_le_bytes())?;
+        }
+        for section in [documents, term_table, strings, postings, positions] {
+            out.write_all(&section)?;
+        }
+        out.flush()
+    }
+
+    /// Write the index to `path`. The file is replaced at once, so it is
+    /// safe to save over an index that is mapped.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        self.write_to(File::create(&temp)?)?;
+        fs::rename(&temp, path)
+    }
+
+    /// Read an index saved with [save](Self::save), to change it
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(MappedIndex::open(path)?.to_index())
+    }
+}
+
+/// An index saved with [Index::save], searched without reading it into memory
+#[derive(Debug)]
+pub struct MappedIndex {
+    mmap: Mmap,
+    analyzer: Analyzer,
+    document_slots: u32,
+    document_count: usize,
+    /// The sum of the lengths of the documents
+    total_length: u64,
+    term_count: u32,
+    strings: Range<usize>,
+    postings: Range<usize>,
+    positions: Range<usize>,
+    names: NameIndex,
+}
+
+impl MappedIndex {
+    /// Map the index in `path`, checking that it is valid
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = File::open(path)?;
+        // SAFETY: the file must not be changed while it is mapped. `save`
+        // replaces files rather than writing into them.
+        let mmap = unsafe { Mmap::map(&file)? };
+        let mut index = MappedIndex {
+            mmap,
+            analyzer: Analyzer::whitespace(),
+            document_slots: 0,
+            document_count: 0,
+            total_length: 0,
+            term_count: 0,
+            strings: 0..0,
+            postings: 0..0,
+            positions: 0..0,
+            names: NameIndex::default(),
+        };
+        index.validate()?;
+        Ok(index)
+    }
+
+    fn validate(&mut self) -> io::Result<()> {
+        let bytes = &self.mmap[..];
+        if bytes.len() < HEADER_SIZE || bytes[..4] != MAGIC {
+            return Err(invalid_data("not an index file"));
+        }
+        let version = read_u32(bytes, 4);
+        if version != VERSION {
+            return Err(invalid_data(format!("unsupported index version {version}")));
+        }
+        let [document_slots, term_count, strings_len, postings_len] =
+            [8, 12, 16, 20].map(|offset| read_u32(bytes, offset));
+        let positions_len = read_u32(bytes, 36);
+        let strings_start =
+            HEADER_SIZE + document_slots as usize * DOCUMENT_SIZE + term_count as usize * TERM_SIZE;
+        let postings_start = strings_start + strings_len as usize;
+        let positions_start = postings_start + postings_len as usize;
+        if bytes.len() != positions_start + positions_len as usize {
+            return Err(invalid_data("index file has the wrong size"));
+        }
+        self.document_slots = document_slots;
+        self.term_count = term_count;
+        self.strings = strings_start..postings_start;
+        self.postings = postings_start..positions_start;
+        self.positions = positions_start..bytes.len();
+
+        let strings = &self.mmap[self.strings.clone()];
+        let string = |offset: u32, len: u32| {
+            let range = offset as usize..offset as usize + len as usize;
+            strings
+                .get(range)
+                .and_then(|s| std::str::from_utf8(s).ok())
+                .ok_or_else(|| invalid_data("invalid string in index"))
+        };
+
+        let flags = read_u32(bytes, 24);
+        if flags & !(STRIP_GUTENBERG | STRIP_PUNCTUATION | LOWERCASE | STEM) != 0 {
+            return Err(invalid_data("unknown analyzer flags in index"));
+        }
+        let stop_words = string(read_u32(bytes, 28), read_u32(bytes, 32))?;
+        self.analyzer = Analyzer {
+            strip_gutenberg: flags & STRIP_GUTENBERG != 0,
+            strip_punctuation: flags & STRIP_PUNCTUATION != 0,
+            lowercase: flags & LOWERCASE != 0,
+            stem: flags & STEM != 0,
+            stop_words: stop_words
+                .split_terminator('\n')
+                .map(str::to_owned)
+                .collect(),
+        };
+
+        let mut document_count = 0;
+        let mut total_length = 0;
+        for doc in 0..document_slots {
+            let [offset, len, length] = self.document_fields(doc);
+            if length != REMOVED {
+                string(offset, len)?;
+                document_count += 1;
+                total_length += u64::from(length);
+            }
+        }
+
+        let postings = &self.mmap[self.postings.clone()];
+        let positions = &self.mmap[self.positions.clone()];
+        let mut previous_term = None;
+        let mut expected_offset = 0;
+        let mut expected_positions_offset = 0;
+        for i in 0..term_count {
+            let [offset, len, postings_offset, count, positions_offset] = self.term_fields(i);
+            let term = string(offset, len)?;
+            if previous_term.is_some_and(|previous| previous >= term) {
+                return Err(invalid_data("terms in index are not sorted"));
+            }
+            previous_term = Some(term);
+            if postings_offset != expected_offset || count == 0 {
+                return Err(invalid_data("invalid postings in index"));
+            }
+            if positions_offset != expected_positions_offset {
+                return Err(invalid_data("invalid positions in index"));
+            }
+
+            let mut decoder = PositionsDecoder {
+                postings: PostingsDecoder {
+                    bytes: &postings[postings_offset as usize..],
+                    previous: None,
+                    remaining: count,
+                },
+                bytes: &positions[positions_offset as usize..],
+            };
+            for _ in 0..count {
+                let (posting, term_positions) = decoder
+                    .try_next()
+                    .filter(|(posting, _)| posting.doc < document_slots && posting.frequency > 0)
+                    .ok_or_else(|| invalid_data("invalid postings in index"))?;
+                let length = self.document_fields(posting.doc)[2];
+                if length == REMOVED {
+                    return Err(invalid_data("postings of removed document in index"));
+                }
+                if term_positions.last().is_some_and(|&last| last >= length) {
+                    return Err(invalid_data("invalid positions in index"));
+                }
+            }
+            expected_offset = (postings.len() - decoder.postings.bytes.len()) as u32;
+            expected_positions_offset = (positions.len() - decoder.bytes.len()) as u32;
+        }
+        if expected_offset as usize != postings.len() {
+            return Err(invalid_data("invalid postings in index"));
+        }
+        if expected_positions_offset as usize != positions.len() {
+            return Err(invalid_data("invalid positions in index"));
+        }
+        self.document_count = document_count;
+        self.total_length = total_length;
+        Ok(())
+    }
+
+    fn document_fields(&self, doc: DocId) -> [u32; 3] {
+        let start = HEADER_SIZE + doc as usize * DOCUMENT_SIZE;
+        [0, 4, 8].map(|offset| read_u32(&self.mmap, start + offset))
+    }
+
+    fn term_fields(&self, i: u32) -> [u32; 5] {
+        let start =
+            HEADER_SIZE + self.document_slots as usize * DOCUMENT_SIZE + i as usize * TERM_SIZE;
+        [0, 4, 8, 12, 16].map(|offset| read_u32(&self.mmap, start + offset))
+    }
+
+    fn string(&self, offset: u32, len: u32) -> &str {
+        let start = self.strings.start + offset as usize;
+        let bytes = &self.mmap[start..start + len as usize];
+        // Checked when the file was opened
+        std::str::from_utf8(bytes).unwrap()
+    }
+
+    fn term(&self, i: u32) -> &str {
+        let [offset, len, ..] = self.term_fields(i);
+        self.string(offset, len)
+    }
+
+    fn term_postings(&self, i: u32) -> PostingsDecoder<'_> {
+        let [_, _, offset, count, _] = self.term_fields(i);
+        PostingsDecoder {
+            bytes: &self.mmap[self.postings.start + offset as usize..],
+            previous: None,
+            remaining: count,
+        }
+    }
+
+    fn term_positions(&self, i: u32) -> PositionsDecoder<'_> {
+        let [.., offset] = self.term_fields(i);
+        PositionsDecoder {
+            postings: self.term_postings(i),
+            bytes: &self.mmap[self.positions.start + offset as usize..],
+        }
+    }
+
+    /// The index of `term` in the term table
+    fn find_term(&self, term: &str) -> Option<u32> {
+        let (mut low, mut high) = (0, self.term_count);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            match self.term(mid).cmp(term) {
+                std::cmp::Ordering::Less => low = mid + 1,
+                std::cmp::Ordering::Greater => high = mid,
+                std::cmp::Ordering::Equal => return Some(mid),
+            }
+        }
+        None
+    }
+
+    /// The index in the term table of the first term after `bound`, or
+    /// `term_count` if there is none
+    fn seek_term(&self, bound: Bound<&str>) -> u32 {
+        let (mut low, mut high) = (0, self.term_count);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let before = match bound {
+                Bound::Included(after) => self.term(mid) < after,
+                Bound::Excluded(after) => self.term(mid) <= after,
+                Bound::Unbounded => false,
+            };
+            if before {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        low
+    }
+
+    /// Copy the index into memory, to change it
+    pub fn to_index(&self) -> Index {
+        let mut index = Index {
+            analyzer: self.analyzer.clone(),
+            documents: (0..self.document_slots)
+                .map(|doc| {
+                    self.document(doc).map(|document| StoredDocument {
+                        name: document.name.to_owned(),
+                        length: document.length,
+                        terms: Vec::new(),
+                    })
+                })
+                .collect(),
+            postings: (0..self.term_count)
+                .map(|i| {
+                    let (postings, positions) = self
+                        .term_positions(i)
+                        .map(|(posting, positions)| (posting, positions.into_boxed_slice()))
+                        .unzip();
+                    let term = TermPostings {
+                        postings,
+                        positions,
+                    };
+                    (self.term(i).to_owned(), term)
+                })
+                .collect::<BTreeMap<_, _>>(),
+            live: self.document_count,
+            total_length: self.total_length,
+            names: NameIndex::default(),
+        };
+        index.record_terms();
+        index
+    }
+}
+
+impl SearchIndex for MappedIndex {
+    fn analyzer(&self) -> &Analyzer {
+        &self.analyzer
+    }
+
+    fn document_count(&self) -> usize {
+        self.document_count
+    }
+
+    fn collection(&self) -> Collection {
+        Collection::new(self.document_count, self.total_length)
+    }
+
+    fn term_count(&self) -> usize {
+        self.term_count as usize
+    }
+
+    fn document_slots(&self) -> DocId {
+        self.document_slots
+    }
+
+    fn document(&self, doc: DocId) -> Option<Document<'_>> {
+        if doc >= self.document_slots {
+            return None;
+        }
+        let [offset, len, length] = self.document_fields(doc);
+        (length != REMOVED).then(|| Document {
+            name: self.string(offset, len),
+            length,
+        })
+    }
+
+    fn postings(&self, term: &str) -> Postings<'_> {
+        let postings = match self.find_term(term) {
+            Some(i) => self.term_postings(i),
+            None => PostingsDecoder::empty(),
+        };
+        Postings(PostingsInner::Mapped(postings))
+    }
+
+    fn positions(&self, term: &str) -> Positions<'_> {
+        let positions = match self.find_term(term) {
+            Some(i) => self.term_positions(i),
+            None => PositionsDecoder {
+                postings: PostingsDecoder::empty(),
+                bytes: &[],
+            },
+        };
+        Positions(PositionsInner::Mapped(positions))
+    }
+
+    fn terms(&self) -> impl Iterator<Item = &str> {
+        (0..self.term_count).map(|i| self.term(i))
+    }
+
+    fn names(&self) -> &Index {
+        self.names.get_or_build(self)
+    }
+
+    fn fuzzy_terms(&self, automaton: &LevenshteinAutomaton) -> Vec<(&str, u32)> {
+        automaton.walk(|bound| {
+            let i = self.seek_term(bound);
+            (i < self.term_count).then(|| self.term(i))
+        })
+    }
+}
+
+/// Decodes the postings of a term from the postings section
+pub(crate) struct PostingsDecoder<'a> {
+    bytes: &'a [u8],
+    previous: Option<DocId>,
+    remaining: u32,
+}
+
+impl PostingsDecoder<'_> {
+    fn empty() -> Self {
+        PostingsDecoder {
+            bytes: &[],
+            previous: None,
+            remaining: 0,
+        }
+    }
+
+    /// The next posting, or `None` if the data is invalid
+    fn try_next(&mut self) -> Option<Posting> {
+        let (delta, len) = read_varint(self.bytes)?;
+        let (frequency, frequency_len) = read_varint(&self.bytes[len..])?;
+        let doc = match self.previous {
+            None => delta,
+            // Ids are strictly increasing
+            Some(previous) if delta > 0 => previous.checked_add(delta)?,
+            Some(_) => return None,
+        };
+        self.bytes = &self.bytes[len + frequency_len..];
+        self.previous = Some(doc);
+        self.remaining -= 1;
+        Some(Posting { doc, frequency })
+    }
+}
+
+impl Iterator for PostingsDecoder<'_> {
+    type Item = Posting;
+
+    fn next(&mut self) -> Option<Posting> {
+        if self.remaining == 0 {
+            return None;
+        }
+        // Checked when the file was opened
+        Some(self.try_next().expect("invalid postings"))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining as usize, Some(self.remaining as usize))
+    }
+}
+
+/// Decodes the postings of a term along with their positions
+pub(crate) struct PositionsDecoder<'a> {
+    postings: PostingsDecoder<'a>,
+    bytes: &'a [u8],
+}
+
+impl PositionsDecoder<'_> {
+    /// The next posting and its positions, or `None` if the data is invalid
+    fn try_next(&mut self) -> Option<(Posting, Vec<u32>)> {
+        let posting = self.postings.try_next()?;
+        // The frequency is not checked yet, so do not allocate by it
+        let mut positions = Vec::with_capacity((posting.frequency as usize).min(self.bytes.len()));
+        let mut previous = None;
+        for _ in 0..posting.frequency {
+            let (delta, len) = read_varint(self.bytes)?;
+            let position = match previous {
+                None => delta,
+                // Positions are strictly increasing
+                Some(previous) if delta > 0 => u32::checked_add(previous, delta)?,
+                Some(_) => return None,
+            };
+            self.bytes = &self.bytes[len..];
+            positions.push(position);
+            previous = Some(position);
+        }
+        Some((posting, positions))
+    }
+}
+
+impl Iterator for PositionsDecoder<'_> {
+    type Item = (Posting, Vec<u32>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.postings.remaining == 0 {
+            return None;
+        }
+        // Checked when the file was opened
+        Some(self.try_next().expect("invalid positions"))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.postings.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::search_index;
+
+    const DOCUMENTS: &[(&str, &str)] = &[
+        ("fruit", "apple banana apple cherry"),
+        ("trees", "apple tree pear tree oak"),
+        ("colours", "red green blue cherry red"),
+        (
+            "numbers",
+            "one two three four five six seven eight nine ten",
+        ),
+    ];
+
+    #[test]
+    fn test_varint() {
+        let values = [0, 1, 127, 128, 300, 1 << 21, u32::MAX];
+        let mut bytes = Vec::new();
+        for value in values {
+            write_varint(&mut bytes, value);
+        }
+        let mut rest = &bytes[..];
+        for value in values {
+            let (read, len) = read_varint(rest).unwrap();
+            assert_eq!(read, value);
+            rest = &rest[len..];
+        }
+        assert!(rest.is_empty());
+        assert_eq!(read_varint(&[0x80]), None);
+        assert_eq!(read_varint(&[0xff, 0xff, 0xff, 0xff, 0x1f]), None);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index");
+        let mut index = Index::build(DOCUMENTS);
+        index.remove(2);
+        index.save(&path).unwrap();
+
+        let mapped = MappedIndex::open(&path).unwrap();
+        assert_eq!(mapped.document_count(), 3);
+        assert_eq!(mapped.term_count(), index.term_count());
+        assert_eq!(mapped.document(2), None);
+        assert_eq!(mapped.document(1), index.document(1));
+        assert_eq!(mapped.document(9), None);
+        assert_eq!(mapped.collection(), index.collection());
+        let lengths = mapped.documents().map(|(_, d)| u64::from(d.length));
+        assert_eq!(mapped.collection(), Collection::new(3, lengths.sum()));
+        for term in ["appl", "cherri", "ten", "red", "zzz", ""] {
+            assert!(mapped.postings(term).eq(index.postings(term)), "{term}");
+            assert!(mapped.positions(term).eq(index.positions(term)), "{term}");
+        }
+        for query in ["apple", "tree cherry", "nothing"] {
+            assert_eq!(
+                search_index(&mapped, query, 2).into_results(),
+                search_index(&index, query, 2).into_results()
+            );
+        }
+        assert_eq!(mapped.to_index(), index);
+    }
+
+    #[test]
+    fn test_fuzzy_terms() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index");
+        let index = Index::build_with(Analyzer::whitespace(), DOCUMENTS);
+        index.save(&path).unwrap();
+        let mapped = MappedIndex::open(&path).unwrap();
+
+        for term in ["apple", "tree", "tne", "red", "x", ""] {
+            for max_distance in 0..3 {
+                let automaton = LevenshteinAutomaton::new(term, max_distance);
+                let mut scan: Vec<_> = index
+                    .terms()
+                    .filter_map(|other| Some((other, automaton.matches(other)?)))
+                    .collect();
+                scan.sort_unstable();
+                assert_eq!(index.fuzzy_terms(&automaton), scan, "{term}");
+                assert_eq!(mapped.fuzzy_terms(&automaton), scan, "{term}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_analyzer() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index");
+        for analyzer in [
+            Analyzer::default(),
+            Analyzer::whitespace(),
+            Analyzer::default()
+                .stem(false)
+                .stop_words(["cherry", "red"]),
+        ] {
+            let index = Index::build_with(analyzer.clone(), DOCUMENTS);
+            index.save(&path).unwrap();
+            let mapped = MappedIndex::open(&path).unwrap();
+            assert_eq!(mapped.analyzer(), &analyzer);
+            assert_eq!(
+                search_index(&mapped, "Cherry red", 2).into_results(),
+                search_index(&index, "Cherry red", 2).into_results()
+            );
+        }
+    }
+
+    #[test]
+    fn test_incremental() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index");
+        Index::build(&DOCUMENTS[..2]).save(&path).unwrap();
+
+        let mapped = MappedIndex::open(&path).unwrap();
+        let mut index = Index::load(&path).unwrap();
+        for (name, text) in &DOCUMENTS[2..] {
+            index.add(name, text);
+        }
+        index.remove(0);
+        index.save(&path).unwrap();
+        // The old mapping still sees the old file
+        assert_eq!(mapped.document_count(), 2);
+
+        let mut expected = Index::build(DOCUMENTS);
+        expected.remove(0);
+        assert_eq!(Index::load(&path).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index");
+        let mut bytes = Vec::new();
+        Index::build(DOCUMENTS).write_to(&mut bytes).unwrap();
+
+        let open = |bytes: &[u8]| {
+            fs::write(&path, bytes).unwrap();
+            MappedIndex::open(&path).unwrap_err().to_string()
+        };
+        assert_eq!(open(b"TFIY"), "not an index file");
+        assert_eq!(
+            open(&bytes[..bytes.len() - 1]),
+            "index file has the wrong size"
+        );
+        let mut corrupt = bytes.clone();
+        corrupt[4] = 9;
+        assert_eq!(open(&corrupt), "unsupported index version 9");
+        let mut corrupt = bytes.clone();
+        corrupt[24] = 16;
+        assert_eq!(open(&corrupt), "unknown analyzer flags in index");
+
+        // Make the first posting point past the last document
+        let postings_start =
+            bytes.len() - read_u32(&bytes, 36) as usize - read_u32(&bytes, 20) as usize;
+        let mut corrupt = bytes.clone();
+        corrupt[postings_start] = 100;
+        assert_eq!(open(&corrupt), "invalid postings in index");
+
+        // Make the last position point past the end of its document
+        let mut corrupt = bytes.clone();
+        *corrupt.last_mut().unwrap() = 100;
+        assert_eq!(open(&corrupt), "invalid positions in index");
+
+        // Make the first term sort after the second
+        let term_table = HEADER_SIZE + DOCUMENTS.len() * DOCUMENT_SIZE;
+        let strings_start = term_table + read_u32(&bytes, 12) as usize * TERM_SIZE;
+        let mut corrupt = bytes.clone();
+        corrupt[strings_start + read_u32(&bytes, term_table) as usize] = b'z';
+        assert_eq!(open(&corrupt), "terms in index are not sorted");
+    }
+}
@@ -0,0 +1,92 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::thread;
+
+/// The mutexes to compare, behind one interface
+trait Lock: Sync {
+    fn new(value: u64) -> Self;
+    fn increment(&self);
+}
+
+impl Lock for mutex::Mutex<u64> {
+    fn new(value: u64) -> Self {
+        mutex::Mutex::new(value)
+    }
+
+    fn increment(&self) {
+        *self.lock().unwrap() += 1;
+    }
+}
+
+impl Lock for mutex::TicketLock<u64> {
+    fn new(value: u64) -> Self {
+        mutex::TicketLock::new(value)
+    }
+
+    fn increment(&self) {
+        *self.lock().unwrap() += 1;
+    }
+}
+
+impl Lock for std::sync::Mutex<u64> {
+    fn new(value: u64) -> Self {
+        std::sync::Mutex::new(value)
+    }
+
+    fn increment(&self) {
+        *self.lock().unwrap() += 1;
+    }
+}
+
+impl Lock for parking_lot::Mutex<u64> {
+    fn new(value: u64) -> Self {
+        parking_lot::Mutex::new(value)
+    }
+
+    fn increment(&self) {
+        *self.lock() += 1;
+    }
+}
+
+/// How often every thread locks the mutex
+const INCREMENTS: u64 = 10_000;
+
+/// `threads` threads all incrementing the same counter
+fn contend<L: Lock>(threads: u64) {
+    let lock = L::new(0);
+    thread::scope(|s| {
+        for _ in 0..threads {
+            s.spawn(|| {
+                for _ in 0..INCREMENTS {
+                    lock.increment();
+                }
+            });
+        }
+    });
+}
+
+/// Compare our mutexes with the ones in std and parking_lot, from no
+/// contention at all to many more threads than cores
+fn bench_contention(c: &mut Criterion) {
+    let mut group = c.benchmark_group("contention");
+    for threads in [1, 2, 4, 8, 16] {
+        group.throughput(Throughput::Elements(threads * INCREMENTS));
+        group.bench_with_input(BenchmarkId::new("futex", threads), &threads, |b, &n| {
+            b.iter(|| contend::<mutex::Mutex<u64>>(n))
+        });
+        group.bench_with_input(BenchmarkId::new("ticket", threads), &threads, |b, &n| {
+            b.iter(|| contend::<mutex::TicketLock<u64>>(n))
+        });
+        group.bench_with_input(BenchmarkId::new("std", threads), &threads, |b, &n| {
+            b.iter(|| contend::<std::sync::Mutex<u64>>(n))
+        });
+        group.bench_with_input(
+            BenchmarkId::new("parking_lot", threads),
+            &threads,
+            |b, &n| b.iter(|| contend::<parking_lot::Mutex<u64>>(n)),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_contention);
+criterion_main!(benches);
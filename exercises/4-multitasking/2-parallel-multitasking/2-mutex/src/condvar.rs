@@ -0,0 +1,265 @@
+use std::{sync::LockResult, time::Duration, time::Instant};
+
+use crate::futex::Futex;
+use crate::poison;
+use crate::sync::{AtomicUsize, Ordering};
+use crate::MutexGuard;
+
+/// Lets threads holding a [Mutex](crate::Mutex) wait until another thread
+/// changes the value in it and notifies them.
+///
+/// Like any condition variable, this can wake up threads that weren't
+/// notified, so always check the condition again after waiting, or use
+/// [wait_while](Self::wait_while). This follows
+/// https://marabos.nl/atomics/building-locks.html#condition-variable
+#[derive(Debug)]
+pub struct Condvar {
+    /// Incremented by every notification, so that a notification between
+    /// unlocking the mutex and starting to wait makes the wait return
+    counter: Futex,
+    /// How many threads are waiting, so that notifying nobody is cheap.
+    /// Threads only start waiting while holding the mutex, and notifiers
+    /// change the condition while holding it too, so they always see the
+    /// threads that are waiting for that change.
+    waiters: AtomicUsize,
+}
+
+/// Whether [Condvar::wait_timeout] returned because it ran out of time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WaitTimeoutResult(bool);
+
+impl WaitTimeoutResult {
+    pub fn timed_out(&self) -> bool {
+        self.0
+    }
+}
+
+impl Condvar {
+    pub fn new() -> Self {
+        Condvar {
+            counter: Futex::new(0),
+            waiters: AtomicUsize::new(0),
+        }
+    }
+
+    /// Wake up one of the waiting threads, if any
+    pub fn notify_one(&self) {
+        if self.waiters.load(Ordering::Relaxed) > 0 {
+            self.counter.fetch_add(1, Ordering::Relaxed);
+            self.counter.wake_one();
+        }
+    }
+
+    /// Wake up all waiting threads
+    pub fn notify_all(&self) {
+        if self.waiters.load(Ordering::Relaxed) > 0 {
+            self.counter.fetch_add(1, Ordering::Relaxed);
+            self.counter.wake_all();
+        }
+    }
+
+    /// Unlock the mutex, wait for a notification, and lock it again. Like
+    /// [Mutex::lock](crate::Mutex::lock), this returns an error if the mutex
+    /// got poisoned.
+    pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T>) -> LockResult<MutexGuard<'a, T>> {
+        self.waiters.fetch_add(1, Ordering::Relaxed);
+        // read before unlocking, so that we can't miss a notification
+        let counter = self.counter.load(Ordering::Relaxed);
+
+        let mutex = guard.mutex;
+        drop(guard);
+        self.counter.wait(counter);
+
+        self.waiters.fetch_sub(1, Ordering::Relaxed);
+        mutex.lock()
+    }
+
+    /// Wait for notifications until `condition` returns false
+    pub fn wait_while<'a, T>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        mut condition: impl FnMut(&mut T) -> bool,
+    ) -> LockResult<MutexGuard<'a, T>> {
+        while condition(&mut guard) {
+            guard = self.wait(guard)?;
+        }
+        Ok(guard)
+    }
+
+    /// Like [wait](Self::wait), but gives up after `timeout`
+    pub fn wait_timeout<'a, T>(
+        &self,
+        guard: MutexGuard<'a, T>,
+        timeout: Duration,
+    ) -> LockResult<(MutexGuard<'a, T>, WaitTimeoutResult)> {
+        self.waiters.fetch_add(1, Ordering::Relaxed);
+        let counter = self.counter.load(Ordering::Relaxed);
+
+        let mutex = guard.mutex;
+        drop(guard);
+        let start = Instant::now();
+        self.counter.wait_timeout(counter, timeout);
+        let timed_out = start.elapsed() >= timeout;
+
+        self.waiters.fetch_sub(1, Ordering::Relaxed);
+        poison::map_result(mutex.lock(), |guard| (guard, WaitTimeoutResult(timed_out)))
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Condvar::new()
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use super::*;
+    use crate::Mutex;
+    use std::thread;
+
+    #[test]
+    fn test_wait_while() {
+        let queue = Mutex::new(Vec::new());
+        let not_empty = Condvar::new();
+        thread::scope(|s| {
+            s.spawn(|| {
+                for i in 0..100 {
+                    queue.lock().unwrap().push(i);
+                    not_empty.notify_one();
+                }
+            });
+
+            let mut received = Vec::new();
+            while received.len() < 100 {
+                let mut queue = not_empty
+                    .wait_while(queue.lock().unwrap(), |queue| queue.is_empty())
+                    .unwrap();
+                received.append(&mut queue);
+            }
+            assert_eq!(received, (0..100).collect::<Vec<_>>());
+        });
+    }
+
+    #[test]
+    fn test_notify_all() {
+        let started = Mutex::new(false);
+        let start = Condvar::new();
+        thread::scope(|s| {
+            let waiters: Vec<_> = (0..4)
+                .map(|_| {
+                    s.spawn(|| {
+                        let started = start
+                            .wait_while(started.lock().unwrap(), |started| !*started)
+                            .unwrap();
+                        assert!(*started);
+                    })
+                })
+                .collect();
+            *started.lock().unwrap() = true;
+            start.notify_all();
+            for waiter in waiters {
+                waiter.join().unwrap();
+            }
+        });
+    }
+
+    #[test]
+    fn test_wait_timeout() {
+        let mutex = Mutex::new(());
+        let condvar = Condvar::new();
+        let timeout = Duration::from_millis(20);
+        let start = Instant::now();
+        let (_guard, result) = condvar
+            .wait_timeout(mutex.lock().unwrap(), timeout)
+            .unwrap();
+        assert!(result.timed_out());
+        assert!(start.elapsed() >= timeout);
+
+        let done = Mutex::new(false);
+        thread::scope(|s| {
+            s.spawn(|| {
+                *done.lock().unwrap() = true;
+                condvar.notify_one();
+            });
+            let mut guard = done.lock().unwrap();
+            while !*guard {
+                let (next, result) = condvar
+                    .wait_timeout(guard, Duration::from_secs(10))
+                    .unwrap();
+                assert!(!result.timed_out());
+                guard = next;
+            }
+        });
+    }
+
+    #[test]
+    fn test_poison() {
+        let mutex = Mutex::new(0);
+        let condvar = Condvar::new();
+        thread::scope(|s| {
+            // locked before the other thread can poison it
+            let guard = mutex.lock().unwrap();
+            let panicked = s.spawn(|| {
+                let _guard = mutex.lock().unwrap();
+                condvar.notify_one();
+                panic!("while holding the lock");
+            });
+            let error = condvar
+                .wait_while(guard, |_| !mutex.is_poisoned())
+                .unwrap_err();
+            assert_eq!(**error.get_ref(), 0);
+            drop(error);
+            assert!(panicked.join().is_err());
+        });
+    }
+}
+
+/// Run with `RUSTFLAGS="--cfg loom" cargo test --release --lib`
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::*;
+    use crate::Mutex;
+    use loom::{sync::Arc, thread};
+
+    #[test]
+    fn test_notify_one() {
+        loom::model(|| {
+            let pair = Arc::new((Mutex::new(false), Condvar::new()));
+            let notifier = thread::spawn({
+                let pair = pair.clone();
+                move || {
+                    *pair.0.lock().unwrap() = true;
+                    pair.1.notify_one();
+                }
+            });
+            // a notification must not get lost between checking the flag and
+            // starting to wait, or this waits forever
+            let (flag, condvar) = &*pair;
+            let guard = condvar
+                .wait_while(flag.lock().unwrap(), |set| !*set)
+                .unwrap();
+            assert!(*guard);
+            drop(guard);
+            notifier.join().unwrap();
+        });
+    }
+
+    #[test]
+    fn test_notify_all() {
+        loom::model(|| {
+            let pair = Arc::new((Mutex::new(false), Condvar::new()));
+            let waiter = thread::spawn({
+                let pair = pair.clone();
+                move || {
+                    let (flag, condvar) = &*pair;
+                    drop(condvar.wait_while(flag.lock().unwrap(), |set| !*set));
+                }
+            });
+            let (flag, condvar) = &*pair;
+            *flag.lock().unwrap() = true;
+            condvar.notify_all();
+            waiter.join().unwrap();
+        });
+    }
+}
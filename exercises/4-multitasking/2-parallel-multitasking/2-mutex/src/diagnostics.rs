@@ -0,0 +1,214 @@
+//! How long locks are waited for and held, and how often threads have to
+//! wait for them at all. The numbers are only kept with the `diagnostics`
+//! feature: without it, everything here is empty and compiles to nothing, so
+//! the locks can record unconditionally.
+
+#[cfg(feature = "diagnostics")]
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+/// What happened to a lock so far, from [Mutex::stats](crate::Mutex::stats),
+/// [TicketLock::stats](crate::TicketLock::stats) or
+/// [RwLock::stats](crate::RwLock::stats)
+#[cfg(feature = "diagnostics")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LockStats {
+    /// How often the lock was taken
+    pub acquisitions: u64,
+    /// How often it was taken after waiting for another thread to release it
+    pub contentions: u64,
+    /// The time spent waiting for the lock, by all threads together
+    pub total_wait: Duration,
+    pub max_wait: Duration,
+    /// The time the lock was held, by all threads together
+    pub total_hold: Duration,
+    pub max_hold: Duration,
+}
+
+#[cfg(feature = "diagnostics")]
+impl LockStats {
+    /// The average time the lock was held for
+    pub fn mean_hold(&self) -> Duration {
+        mean(self.total_hold, self.acquisitions)
+    }
+
+    /// The average time a thread that had to wait for the lock waited
+    pub fn mean_wait(&self) -> Duration {
+        mean(self.total_wait, self.contentions)
+    }
+}
+
+#[cfg(feature = "diagnostics")]
+fn mean(total: Duration, count: u64) -> Duration {
+    match count {
+        0 => Duration::ZERO,
+        count => Duration::from_nanos((total.as_nanos() / u128::from(count)) as u64),
+    }
+}
+
+/// The statistics of one lock. These are plain std atomics even under loom:
+/// they are not part of how the lock works, so there is nothing to check.
+#[cfg(feature = "diagnostics")]
+#[derive(Debug, Default)]
+pub(crate) struct Recorder {
+    acquisitions: AtomicU64,
+    contentions: AtomicU64,
+    /// In nanoseconds
+    total_wait: AtomicU64,
+    max_wait: AtomicU64,
+    total_hold: AtomicU64,
+    max_hold: AtomicU64,
+}
+
+#[cfg(not(feature = "diagnostics"))]
+#[derive(Debug, Default)]
+pub(crate) struct Recorder;
+
+/// When a thread started waiting for a lock
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Waiting(#[cfg(feature = "diagnostics")] Instant);
+
+/// When a thread got a lock, kept in its guard
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Held(#[cfg(feature = "diagnostics")] Instant);
+
+#[cfg(feature = "diagnostics")]
+impl Recorder {
+    pub(crate) fn new() -> Self {
+        Recorder::default()
+    }
+
+    /// Call this before trying to take the lock
+    pub(crate) fn waiting(&self) -> Waiting {
+        Waiting(Instant::now())
+    }
+
+    /// Call this once the lock is taken. `contended` is whether it couldn't
+    /// be taken straight away.
+    pub(crate) fn acquired(&self, waiting: Waiting, contended: bool) -> Held {
+        let now = Instant::now();
+        self.acquisitions.fetch_add(1, Ordering::Relaxed);
+        if contended {
+            let wait = nanos(now - waiting.0);
+            self.contentions.fetch_add(1, Ordering::Relaxed);
+            self.total_wait.fetch_add(wait, Ordering::Relaxed);
+            self.max_wait.fetch_max(wait, Ordering::Relaxed);
+        }
+        Held(now)
+    }
+
+    /// Call this just before releasing the lock
+    pub(crate) fn released(&self, held: Held) {
+        let hold = nanos(held.0.elapsed());
+        self.total_hold.fetch_add(hold, Ordering::Relaxed);
+        self.max_hold.fetch_max(hold, Ordering::Relaxed);
+    }
+
+    pub(crate) fn stats(&self) -> LockStats {
+        let duration = |nanos: &AtomicU64| Duration::from_nanos(nanos.load(Ordering::Relaxed));
+        LockStats {
+            acquisitions: self.acquisitions.load(Ordering::Relaxed),
+            contentions: self.contentions.load(Ordering::Relaxed),
+            total_wait: duration(&self.total_wait),
+            max_wait: duration(&self.max_wait),
+            total_hold: duration(&self.total_hold),
+            max_hold: duration(&self.max_hold),
+        }
+    }
+}
+
+/// Some 584 years, which is plenty
+#[cfg(feature = "diagnostics")]
+fn nanos(duration: Duration) -> u64 {
+    duration.as_nanos().try_into().unwrap_or(u64::MAX)
+}
+
+#[cfg(not(feature = "diagnostics"))]
+impl Recorder {
+    pub(crate) fn new() -> Self {
+        Recorder
+    }
+
+    #[inline(always)]
+    pub(crate) fn waiting(&self) -> Waiting {
+        Waiting()
+    }
+
+    #[inline(always)]
+    pub(crate) fn acquired(&self, _waiting: Waiting, _contended: bool) -> Held {
+        Held()
+    }
+
+    #[inline(always)]
+    pub(crate) fn released(&self, _held: Held) {}
+}
+
+#[cfg(all(test, feature = "diagnostics", not(loom)))]
+mod test {
+    use super::*;
+    use crate::{Mutex, RwLock, TicketLock};
+    use std::thread;
+
+    #[test]
+    fn test_uncontended() {
+        let mutex = Mutex::new(0);
+        for _ in 0..3 {
+            *mutex.lock().unwrap() += 1;
+        }
+        *mutex.try_lock().unwrap() += 1;
+        assert!(mutex.lock_timeout(Duration::ZERO).is_ok());
+        let stats = mutex.stats();
+        assert_eq!(stats.acquisitions, 5);
+        assert_eq!(stats.contentions, 0);
+        assert_eq!(stats.total_wait, Duration::ZERO);
+        assert_eq!(stats.mean_wait(), Duration::ZERO);
+
+        let rwlock = RwLock::new(0);
+        let (first, second) = (rwlock.read().unwrap(), rwlock.try_read().unwrap());
+        drop((first, second));
+        *rwlock.write().unwrap() += 1;
+        *rwlock.try_write().unwrap() += 1;
+        let stats = rwlock.stats();
+        assert_eq!(stats.acquisitions, 4);
+        assert_eq!(stats.contentions, 0);
+    }
+
+    #[test]
+    fn test_contended() {
+        let hold = Duration::from_millis(20);
+        let mutex = Mutex::new(());
+        let ticket = TicketLock::new(());
+        let rwlock = RwLock::new(());
+        thread::scope(|s| {
+            let mutex_guard = mutex.lock().unwrap();
+            let ticket_guard = ticket.lock().unwrap();
+            let read_guard = rwlock.read().unwrap();
+            let waiters = [
+                s.spawn(|| drop(mutex.lock().unwrap())),
+                s.spawn(|| drop(ticket.lock().unwrap())),
+                s.spawn(|| drop(rwlock.write().unwrap())),
+            ];
+            // Once a waiter shows, it has found the lock taken, and it
+            // waits from before the sleep until after it
+            while !(mutex.has_waiters() && ticket.has_waiters() && rwlock.has_waiters()) {
+                thread::yield_now();
+            }
+            thread::sleep(hold);
+            drop((mutex_guard, ticket_guard, read_guard));
+            for waiter in waiters {
+                waiter.join().unwrap();
+            }
+        });
+        for stats in [mutex.stats(), ticket.stats(), rwlock.stats()] {
+            assert_eq!(stats.acquisitions, 2);
+            assert_eq!(stats.contentions, 1);
+            assert!(stats.max_hold >= hold, "{stats:?}");
+            assert!(stats.total_hold >= stats.max_hold, "{stats:?}");
+            assert!(stats.mean_hold() >= hold / 2, "{stats:?}");
+            assert!(stats.max_wait >= hold, "{stats:?}");
+            assert_eq!(stats.total_wait, stats.max_wait);
+        }
+    }
+}
@@ -0,0 +1,171 @@
+//! Waiting for an atomic to change, without spinning. [atomic_wait] covers
+//! waiting and waking on every platform, but not waiting with a timeout.
+//! Linux has a futex syscall for that. Elsewhere, a thread waiting with a
+//! timeout parks instead, and waking the futex unparks it.
+
+use std::ops::Deref;
+use std::time::Duration;
+
+use crate::sync::AtomicU32;
+
+/// An atomic that threads can wait on until another thread wakes them up
+#[cfg(not(loom))]
+#[derive(Debug)]
+pub(crate) struct Futex(AtomicU32);
+
+/// Under loom, a futex is modelled by a mutex and a condition variable:
+/// holding the mutex while checking the value and starting to wait makes
+/// that a single step, as the kernel does for a real futex.
+#[cfg(loom)]
+#[derive(Debug)]
+pub(crate) struct Futex {
+    value: AtomicU32,
+    lock: loom::sync::Mutex<()>,
+    waiters: loom::sync::Condvar,
+}
+
+#[cfg(not(loom))]
+impl Futex {
+    pub(crate) const fn new(value: u32) -> Self {
+        Futex(AtomicU32::new(value))
+    }
+
+    /// If the value is `value`, wait until woken up. This might also return
+    /// spuriously.
+    pub(crate) fn wait(&self, value: u32) {
+        atomic_wait::wait(&self.0, value);
+    }
+
+    /// If the value is `value`, wait until woken up or until `timeout` has
+    /// passed. Like [wait](Self::wait), this might also return spuriously.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub(crate) fn wait_timeout(&self, value: u32, timeout: Duration) {
+        // `time_t` is 32 bits on some targets, so longer timeouts are cut
+        // short, and a waiter wakes up spuriously after some 68 years.
+        // Nanoseconds are below a billion, which fits in any `c_long`.
+        let timeout = libc::timespec {
+            tv_sec: timeout.as_secs().min(libc::time_t::MAX as u64) as libc::time_t,
+            tv_nsec: timeout.subsec_nanos() as libc::c_long,
+        };
+        // SAFETY: the futex syscall only reads the atomic and the timespec,
+        // which both outlive the call
+        unsafe {
+            libc::syscall(
+                libc::SYS_futex,
+                &self.0 as *const AtomicU32,
+                libc::FUTEX_WAIT | libc::FUTEX_PRIVATE_FLAG,
+                value,
+                &timeout as *const libc::timespec,
+            );
+        }
+    }
+
+    /// If the value is `value`, wait until woken up or until `timeout` has
+    /// passed. Like [wait](Self::wait), this might also return spuriously.
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    pub(crate) fn wait_timeout(&self, value: u32, timeout: Duration) {
+        let thread = std::thread::current();
+        timed_waiters().push((self.address(), thread.clone()));
+        // Checked after registering, so a change and wake in between is not
+        // missed
+        if self.0.load(crate::sync::Ordering::Relaxed) == value {
+            std::thread::park_timeout(timeout);
+        }
+        // Unless a wake already took us off the list
+        let mut waiters = timed_waiters();
+        if let Some(i) = waiters
+            .iter()
+            .position(|(_, waiter)| waiter.id() == thread.id())
+        {
+            waiters.swap_remove(i);
+        }
+    }
+
+    /// Wake up one of the threads waiting, if any
+    pub(crate) fn wake_one(&self) {
+        atomic_wait::wake_one(&self.0);
+        // This might wake a thread in `wait` and one in `wait_timeout`, but
+        // then one of them wakes up spuriously, which they allow for
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        {
+            let mut waiters = timed_waiters();
+            let address = self.address();
+            if let Some(i) = waiters.iter().position(|&(a, _)| a == address) {
+                waiters.swap_remove(i).1.unpark();
+            }
+        }
+    }
+
+    /// Wake up all threads waiting
+    pub(crate) fn wake_all(&self) {
+        atomic_wait::wake_all(&self.0);
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        timed_waiters().retain(|(address, waiter)| {
+            let waiting = *address == self.address();
+            if waiting {
+                waiter.unpark();
+            }
+            !waiting
+        });
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    fn address(&self) -> usize {
+        &self.0 as *const AtomicU32 as usize
+    }
+}
+
+/// The threads in [Futex::wait_timeout], with the address of the futex they
+/// wait on. Wakes take threads off the list, so that two `wake_one`s wake
+/// two different threads.
+#[cfg(not(any(loom, target_os = "linux", target_os = "android")))]
+fn timed_waiters() -> std::sync::MutexGuard<'static, Vec<(usize, std::thread::Thread)>> {
+    static WAITERS: std::sync::Mutex<Vec<(usize, std::thread::Thread)>> =
+        std::sync::Mutex::new(Vec::new());
+    // Nothing panics while holding it
+    WAITERS.lock().unwrap()
+}
+
+#[cfg(loom)]
+impl Futex {
+    pub(crate) fn new(value: u32) -> Self {
+        Futex {
+            value: AtomicU32::new(value),
+            lock: loom::sync::Mutex::new(()),
+            waiters: loom::sync::Condvar::new(),
+        }
+    }
+
+    pub(crate) fn wait(&self, value: u32) {
+        let lock = self.lock.lock().unwrap();
+        if self.value.load(crate::sync::Ordering::Relaxed) == value {
+            drop(self.waiters.wait(lock).unwrap());
+        }
+    }
+
+    /// loom has no clock, so this times out straight away
+    pub(crate) fn wait_timeout(&self, _value: u32, _timeout: Duration) {
+        loom::thread::yield_now();
+    }
+
+    pub(crate) fn wake_one(&self) {
+        let _lock = self.lock.lock().unwrap();
+        self.waiters.notify_one();
+    }
+
+    pub(crate) fn wake_all(&self) {
+        let _lock = self.lock.lock().unwrap();
+        self.waiters.notify_all();
+    }
+}
+
+impl Deref for Futex {
+    type Target = AtomicU32;
+
+    fn deref(&self) -> &AtomicU32 {
+        #[cfg(not(loom))]
+        return &self.0;
+        #[cfg(loom)]
+        return &self.value;
+    }
+}
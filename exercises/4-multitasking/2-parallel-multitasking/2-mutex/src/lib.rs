@@ -0,0 +1,49 @@
+//! Locks that put waiting threads to sleep rather than spinning, following
+//! <https://marabos.nl/atomics/building-locks.html> from "Rust Atomics and
+//! Locks":
+//!
+//! - [Mutex] waits on a futex, so a thread only asks the operating system for
+//!   help when the lock is actually contended;
+//! - [RwLock] lets many readers or one writer in at a time;
+//! - [Condvar] waits for another thread to change what a [Mutex] protects;
+//! - [TicketLock] serves threads in the order they arrived, where [Mutex]
+//!   lets a thread that keeps relocking keep others waiting.
+//!
+//! With the `diagnostics` feature, every lock records how often threads had
+//! to wait for it and for how long, see `Mutex::stats`. In debug builds,
+//! locks also check that they are always taken in the same order, and panic
+//! where a different order could deadlock.
+//!
+//! The loom tests check every interleaving of a few threads. Run them with
+//! `RUSTFLAGS="--cfg loom" cargo test --release --lib`.
+
+/// Fails to compile if `$t` is `Send`: then both impls apply, and which
+/// one `check` comes from is ambiguous
+#[cfg(all(test, not(loom)))]
+macro_rules! assert_not_send {
+    ($t:ty) => {{
+        trait AmbiguousIfSend<A> {
+            fn check() {}
+        }
+        impl<T: ?Sized> AmbiguousIfSend<()> for T {}
+        impl<T: ?Sized + Send> AmbiguousIfSend<u8> for T {}
+        <$t as AmbiguousIfSend<_>>::check();
+    }};
+}
+
+mod condvar;
+mod diagnostics;
+mod futex;
+mod lock_order;
+mod mutex;
+mod poison;
+mod rwlock;
+mod sync;
+mod ticket;
+
+pub use condvar::{Condvar, WaitTimeoutResult};
+#[cfg(feature = "diagnostics")]
+pub use diagnostics::LockStats;
+pub use mutex::{MappedMutexGuard, Mutex, MutexGuard};
+pub use rwlock::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+pub use ticket::{TicketLock, TicketLockGuard};
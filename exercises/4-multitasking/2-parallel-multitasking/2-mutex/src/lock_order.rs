@@ -0,0 +1,308 @@
+//! Catching potential deadlocks in debug builds.
+//!
+//! Two threads that take the same two locks in opposite orders can each end
+//! up holding one and waiting for the other forever. That only happens when
+//! they are unlucky with the timing, but taking the locks in both orders is
+//! a mistake every time. So in debug builds, every lock remembers which
+//! locks were held when it was taken, and a thread about to wait for a lock
+//! panics if some thread ever took the locks it holds while holding that
+//! one, directly or through other locks: a cycle in the order locks are
+//! taken in.
+//!
+//! The locks a thread holds are tracked per thread. That works because no
+//! guard is `Send`: a lock is always released by the thread that took it.
+//! In release builds and under loom, whose threads all run on a single real
+//! one, none of this happens.
+
+#[cfg(all(debug_assertions, not(loom)))]
+mod tracking {
+    use std::cell::RefCell;
+    use std::collections::{btree_map::Entry, BTreeMap, BTreeSet};
+    use std::fmt::Write;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Mutex, PoisonError};
+
+    static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+    /// A std mutex, since taking one of ours here would come back here
+    static ORDER: Mutex<Order> = Mutex::new(Order {
+        after: BTreeMap::new(),
+        before: BTreeMap::new(),
+    });
+
+    /// The order locks were taken in, kept both ways round so that
+    /// forgetting a lock only touches the locks it was ordered with
+    struct Order {
+        /// For every lock, the locks that were taken while holding it
+        after: BTreeMap<u64, BTreeSet<u64>>,
+        /// For every lock, the locks that were held while taking it
+        before: BTreeMap<u64, BTreeSet<u64>>,
+    }
+
+    thread_local! {
+        /// The locks this thread holds, in the order it took them
+        static HELD: RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+    }
+
+    /// A lock, as far as lock ordering is concerned. Ids are never reused,
+    /// and dropping one forgets the order of its lock.
+    #[derive(Debug)]
+    pub(crate) struct LockId(u64);
+
+    impl LockId {
+        pub(crate) fn new() -> Self {
+            LockId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+        }
+
+        /// Call this before waiting for the lock. Panics if this thread
+        /// holds it already, or if waiting for it could deadlock with a
+        /// thread that takes the locks in another order.
+        #[track_caller]
+        pub(crate) fn before_lock(&self) {
+            let id = self.0;
+            HELD.with_borrow(|held| {
+                if held.contains(&id) {
+                    panic!("lock #{id} is already held by this thread");
+                }
+                if held.is_empty() {
+                    return;
+                }
+                let mut order = ORDER.lock().unwrap_or_else(PoisonError::into_inner);
+                for &other in held {
+                    if let Some(path) = path(&order.after, id, other) {
+                        drop(order);
+                        let mut cycle = String::new();
+                        for lock in path {
+                            let _ = write!(cycle, "#{lock} -> ");
+                        }
+                        panic!(
+                            "potential deadlock: taking lock #{id} while holding lock #{other}, \
+                             but they have been taken in the order {cycle}#{id} before"
+                        );
+                    }
+                }
+                for &other in held {
+                    order.after.entry(other).or_default().insert(id);
+                    order.before.entry(id).or_default().insert(other);
+                }
+            });
+        }
+
+        /// Call this once the lock is taken, also without waiting
+        pub(crate) fn locked(&self) {
+            HELD.with_borrow_mut(|held| held.push(self.0));
+        }
+
+        /// Call this when releasing the lock
+        pub(crate) fn unlocked(&self) {
+            // this may run while the thread's locals are being destroyed
+            let _ = HELD.try_with(|held| {
+                let mut held = held.borrow_mut();
+                if let Some(i) = held.iter().rposition(|&id| id == self.0) {
+                    held.remove(i);
+                }
+            });
+        }
+    }
+
+    impl Drop for LockId {
+        fn drop(&mut self) {
+            let id = self.0;
+            let mut order = ORDER.lock().unwrap_or_else(PoisonError::into_inner);
+            for after in order.after.remove(&id).into_iter().flatten() {
+                forget(&mut order.before, after, id);
+            }
+            for before in order.before.remove(&id).into_iter().flatten() {
+                forget(&mut order.after, before, id);
+            }
+        }
+    }
+
+    /// Take `lock` out of the set of `key`, and drop the set once it is empty
+    fn forget(locks: &mut BTreeMap<u64, BTreeSet<u64>>, key: u64, lock: u64) {
+        if let Entry::Occupied(mut entry) = locks.entry(key) {
+            entry.get_mut().remove(&lock);
+            if entry.get().is_empty() {
+                entry.remove();
+            }
+        }
+    }
+
+    #[cfg(test)]
+    impl LockId {
+        pub(crate) fn id(&self) -> u64 {
+            self.0
+        }
+    }
+
+    /// Whether the lock with id `id` is still ordered with any other lock
+    #[cfg(test)]
+    pub(crate) fn is_ordered(id: u64) -> bool {
+        let order = ORDER.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut sets = order.after.values().chain(order.before.values());
+        order.after.contains_key(&id)
+            || order.before.contains_key(&id)
+            || sets.any(|locks| locks.contains(&id))
+    }
+
+    /// The locks from `from` to `to` in `order`, if `to` was ever taken
+    /// after `from`
+    fn path(order: &BTreeMap<u64, BTreeSet<u64>>, from: u64, to: u64) -> Option<Vec<u64>> {
+        // depth first, remembering where we came from to find the way back
+        let mut came_from = BTreeMap::from([(from, from)]);
+        let mut stack = vec![from];
+        while let Some(lock) = stack.pop() {
+            if lock == to {
+                let mut path = vec![to];
+                while *path.last().unwrap() != from {
+                    path.push(came_from[path.last().unwrap()]);
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for &next in order.get(&lock).into_iter().flatten() {
+                if let Entry::Vacant(entry) = came_from.entry(next) {
+                    entry.insert(lock);
+                    stack.push(next);
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(not(all(debug_assertions, not(loom))))]
+mod tracking {
+    #[derive(Debug)]
+    pub(crate) struct LockId;
+
+    impl LockId {
+        pub(crate) fn new() -> Self {
+            LockId
+        }
+
+        #[inline(always)]
+        pub(crate) fn before_lock(&self) {}
+
+        #[inline(always)]
+        pub(crate) fn locked(&self) {}
+
+        #[inline(always)]
+        pub(crate) fn unlocked(&self) {}
+    }
+}
+
+pub(crate) use tracking::LockId;
+
+#[cfg(all(test, debug_assertions, not(loom)))]
+mod test {
+    use super::tracking::is_ordered;
+    use super::LockId;
+    use crate::{Mutex, RwLock, TicketLock};
+    use std::panic::{self, AssertUnwindSafe};
+
+    /// The message `f` panics with
+    fn panic_message(f: impl FnOnce()) -> String {
+        let payload = panic::catch_unwind(AssertUnwindSafe(f)).unwrap_err();
+        payload.downcast::<String>().map(|s| *s).unwrap_or_default()
+    }
+
+    #[test]
+    fn test_consistent_order() {
+        let a = Mutex::new(0);
+        let b = Mutex::new(0);
+        for _ in 0..3 {
+            let _a = a.lock().unwrap();
+            let _b = b.lock().unwrap();
+        }
+        // one at a time, in any order
+        drop(b.lock().unwrap());
+        drop(a.lock().unwrap());
+    }
+
+    #[test]
+    fn test_cycle() {
+        let a = Mutex::new(0);
+        let b = TicketLock::new(0);
+        let c = RwLock::new(0);
+        {
+            let _a = a.lock().unwrap();
+            let _b = b.lock().unwrap();
+        }
+        {
+            let _b = b.lock().unwrap();
+            let _c = c.read().unwrap();
+        }
+        // c after a, through b. Nothing else holds the locks, so this would
+        // only deadlock with worse timing.
+        let message = panic_message(|| {
+            let _c = c.write().unwrap();
+            let _a = a.lock().unwrap();
+        });
+        assert!(message.starts_with("potential deadlock"), "{message}");
+        assert!(message.contains(" -> "), "{message}");
+        // the panic came before `a` was taken, while holding `c`
+        assert!(!a.is_poisoned());
+        assert!(c.is_poisoned());
+    }
+
+    #[test]
+    fn test_try_lock_out_of_order() {
+        let a = Mutex::new(0);
+        let b = Mutex::new(0);
+        {
+            let _a = a.lock().unwrap();
+            let _b = b.lock().unwrap();
+        }
+        // try_lock never waits, so it can't deadlock
+        let _b = b.lock().unwrap();
+        assert!(a.try_lock().is_ok());
+    }
+
+    #[test]
+    fn test_relock() {
+        let a = Mutex::new(0);
+        let message = panic_message(|| {
+            let _first = a.lock().unwrap();
+            let _second = a.lock();
+        });
+        assert!(message.contains("already held"), "{message}");
+    }
+
+    #[test]
+    fn test_dropped_lock_is_forgotten() {
+        let a = Mutex::new(0);
+        {
+            let b = Mutex::new(0);
+            let _b = b.lock().unwrap();
+            let _a = a.lock().unwrap();
+        }
+        let b = Mutex::new(0);
+        let _a = a.lock().unwrap();
+        let _b = b.lock().unwrap();
+    }
+
+    #[test]
+    fn test_drop_forgets_both_ways() {
+        let locks = [LockId::new(), LockId::new(), LockId::new()];
+        for lock in &locks {
+            lock.before_lock();
+            lock.locked();
+        }
+        for lock in locks.iter().rev() {
+            lock.unlocked();
+        }
+        let [a, b, c] = locks;
+        let ids = [a.id(), b.id(), c.id()];
+        assert!(ids.iter().all(|&id| is_ordered(id)));
+
+        drop(b);
+        assert!(!is_ordered(ids[1]));
+        assert!(is_ordered(ids[0]) && is_ordered(ids[2]));
+        drop(a);
+        // Nothing is left for c once the locks it was ordered with are gone
+        assert!(!is_ordered(ids[2]));
+        drop(c);
+        assert!(!ids.iter().any(|&id| is_ordered(id)));
+    }
+}
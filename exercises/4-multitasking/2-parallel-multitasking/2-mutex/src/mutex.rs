@@ -0,0 +1,645 @@
+use std::{
+    fmt,
+    marker::PhantomData,
+    mem::ManuallyDrop,
+    ops::{Deref, DerefMut},
+    ptr::NonNull,
+    sync::{LockResult, TryLockError, TryLockResult},
+    time::{Duration, Instant},
+};
+
+use crate::diagnostics::{self, Held};
+use crate::futex::Futex;
+use crate::lock_order::LockId;
+use crate::poison;
+use crate::sync::{Ordering, UnsafeCell};
+
+/// Nobody holds the lock
+const UNLOCKED: u32 = 0;
+/// Somebody holds the lock, and nobody is waiting for it
+const LOCKED: u32 = 1;
+/// Somebody holds the lock, and others may be waiting for it
+const CONTENDED: u32 = 2;
+
+/// How often to check whether the lock has been released before going to
+/// sleep. Locks are often held only briefly, and spinning is much cheaper
+/// than a round trip through the kernel.
+#[cfg(not(loom))]
+const SPIN_LIMIT: usize = 100;
+/// loom would try every interleaving of every spin
+#[cfg(loom)]
+const SPIN_LIMIT: usize = 0;
+
+/// The lock of a [Mutex], without the value it protects
+struct RawMutex {
+    /// [UNLOCKED], [LOCKED] or [CONTENDED]. Only [unlock](Self::unlock) in
+    /// the [CONTENDED] state has to wake up a waiting thread, so that an
+    /// uncontended lock never makes a system call.
+    state: Futex,
+    stats: diagnostics::Recorder,
+    order: LockId,
+}
+
+impl RawMutex {
+    fn new() -> Self {
+        RawMutex {
+            state: Futex::new(UNLOCKED),
+            stats: diagnostics::Recorder::new(),
+            order: LockId::new(),
+        }
+    }
+
+    /// Wait for the lock, checking the lock order first and recording how
+    /// long that took
+    #[track_caller]
+    fn lock(&self) -> Held {
+        self.order.before_lock();
+        let waiting = self.stats.waiting();
+        let contended = !self.try_lock();
+        if contended {
+            self.block_until_you_lock();
+        }
+        self.order.locked();
+        self.stats.acquired(waiting, contended)
+    }
+
+    /// Like [lock](Self::lock), but gives up after `timeout`
+    #[track_caller]
+    fn lock_timeout_tracked(&self, timeout: Duration) -> Option<Held> {
+        self.order.before_lock();
+        let waiting = self.stats.waiting();
+        let contended = !self.try_lock();
+        if contended && !self.lock_timeout(timeout) {
+            return None;
+        }
+        self.order.locked();
+        Some(self.stats.acquired(waiting, contended))
+    }
+
+    /// Like [lock](Self::lock), but only if nobody holds the lock. Not
+    /// waiting can't deadlock, so this doesn't check the lock order.
+    fn try_lock_tracked(&self) -> Option<Held> {
+        let waiting = self.stats.waiting();
+        if !self.try_lock() {
+            return None;
+        }
+        self.order.locked();
+        Some(self.stats.acquired(waiting, false))
+    }
+
+    /// Undo [lock](Self::lock)
+    fn release(&self, held: Held) {
+        self.stats.released(held);
+        self.order.unlocked();
+        self.unlock();
+    }
+
+    fn try_lock(&self) -> bool {
+        self.state
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    fn block_until_you_lock(&self) {
+        if self.try_lock() {
+            return;
+        }
+
+        // spin for a while, but only while nobody is sleeping: they would be
+        // woken up first anyway
+        let mut spins = 0;
+        while self.state.load(Ordering::Relaxed) == LOCKED && spins < SPIN_LIMIT {
+            std::hint::spin_loop();
+            spins += 1;
+        }
+        if self.try_lock() {
+            return;
+        }
+
+        // We can't tell whether others are waiting, so we have to assume they
+        // are, and leave the state at `CONTENDED` when we get the lock
+        while self.state.swap(CONTENDED, Ordering::Acquire) != UNLOCKED {
+            self.state.wait(CONTENDED);
+        }
+    }
+
+    /// Like [block_until_you_lock](Self::block_until_you_lock), but gives
+    /// up after `timeout`
+    fn lock_timeout(&self, timeout: Duration) -> bool {
+        if self.try_lock() {
+            return true;
+        }
+
+        let deadline = Instant::now().checked_add(timeout);
+        while self.state.swap(CONTENDED, Ordering::Acquire) != UNLOCKED {
+            // a timeout too long to represent is as good as no timeout
+            let remaining = match deadline {
+                Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                    Some(remaining) => remaining,
+                    None => return false,
+                },
+                None => timeout,
+            };
+            // Giving up leaves the state at `CONTENDED`, which is fine: the
+            // next unlock makes a system call it didn't need to
+            self.state.wait_timeout(CONTENDED, remaining);
+        }
+        true
+    }
+
+    fn unlock(&self) {
+        if self.state.swap(UNLOCKED, Ordering::Release) == CONTENDED {
+            self.state.wake_one();
+        }
+    }
+}
+
+pub struct Mutex<T> {
+    raw: RawMutex,
+    poison: poison::Flag,
+    cell: UnsafeCell<T>,
+}
+
+// even with a reference to `Mutex<T>`, we can actually move a value of type T between threads. But
+// moving values of type T is only allowed if `T: Send`
+unsafe impl<T: Send> Sync for Mutex<T> {
+    /* no methods to implement */
+}
+
+pub struct MutexGuard<'a, T> {
+    pub(crate) mutex: &'a Mutex<T>,
+    poison: poison::Guard,
+    held: Held,
+    // sharing a guard between threads shares the `T`, so the guard is only
+    // `Sync` if `T` is. A `&Mutex<T>` alone would make it `Sync` if `T: Send`.
+    _marker: PhantomData<&'a mut T>,
+    // Like std's, the guard stays on the thread that locked the mutex:
+    // poisoning asks whether that thread panicked, and the lock order is
+    // tracked per thread
+    _not_send: PhantomData<*const ()>,
+}
+
+// SAFETY: sharing a guard only shares the `T`
+unsafe impl<T: Sync> Sync for MutexGuard<'_, T> {}
+
+/// A [MutexGuard] for a part of the value in the [Mutex], made by
+/// [MutexGuard::map]
+pub struct MappedMutexGuard<'a, T> {
+    raw: &'a RawMutex,
+    poison_flag: &'a poison::Flag,
+    poison: poison::Guard,
+    held: Held,
+    // a pointer rather than a `&mut T`, which would claim that nobody else
+    // accesses the value until the end of 'a, even after we unlock
+    value: NonNull<T>,
+    _marker: PhantomData<&'a mut T>,
+}
+
+// SAFETY: sharing a guard only shares the `T`. Like a `MutexGuard`, it is
+// not `Send`, which the pointer already sees to.
+unsafe impl<T: Sync> Sync for MappedMutexGuard<'_, T> {}
+
+impl<T> Mutex<T> {
+    pub fn new(value: T) -> Self {
+        Mutex {
+            raw: RawMutex::new(),
+            poison: poison::Flag::new(),
+            cell: UnsafeCell::new(value),
+        }
+    }
+
+    /// Only call this while holding the lock
+    fn guard(&self, held: Held) -> LockResult<MutexGuard<'_, T>> {
+        poison::map_result(self.poison.guard(), |poison| MutexGuard {
+            mutex: self,
+            poison,
+            held,
+            _marker: PhantomData,
+            _not_send: PhantomData,
+        })
+    }
+
+    /// Whether some thread may be waiting for the lock
+    #[cfg(all(test, feature = "diagnostics", not(loom)))]
+    pub(crate) fn has_waiters(&self) -> bool {
+        self.raw.state.load(Ordering::Relaxed) == CONTENDED
+    }
+
+    /// Wait for the lock. This returns a [PoisonError](std::sync::PoisonError)
+    /// if another thread panicked while holding it, which still holds the
+    /// lock.
+    ///
+    /// In debug builds, this panics if the thread holds the lock already, or
+    /// if it holds other locks that have been taken while holding this one,
+    /// which could deadlock.
+    #[track_caller]
+    pub fn lock(&self) -> LockResult<MutexGuard<'_, T>> {
+        let held = self.raw.lock();
+        self.guard(held)
+    }
+
+    /// Lock the mutex if nobody else holds it, without waiting
+    pub fn try_lock(&self) -> TryLockResult<MutexGuard<'_, T>> {
+        match self.raw.try_lock_tracked() {
+            Some(held) => Ok(self.guard(held)?),
+            None => Err(TryLockError::WouldBlock),
+        }
+    }
+
+    /// Lock the mutex, waiting at most `timeout` for others to release it.
+    /// Running out of time is a [TryLockError::WouldBlock].
+    #[track_caller]
+    pub fn lock_timeout(&self, timeout: Duration) -> TryLockResult<MutexGuard<'_, T>> {
+        match self.raw.lock_timeout_tracked(timeout) {
+            Some(held) => Ok(self.guard(held)?),
+            None => Err(TryLockError::WouldBlock),
+        }
+    }
+
+    /// How often the mutex has been locked, and how long threads waited for
+    /// it and held it
+    #[cfg(feature = "diagnostics")]
+    pub fn stats(&self) -> diagnostics::LockStats {
+        self.raw.stats.stats()
+    }
+
+    /// Whether a thread panicked while holding the lock
+    pub fn is_poisoned(&self) -> bool {
+        self.poison.get()
+    }
+
+    /// Mark the mutex as no longer poisoned, for example after repairing
+    /// the value through a [PoisonError](std::sync::PoisonError)
+    pub fn clear_poison(&self) {
+        self.poison.clear();
+    }
+
+    /// A mutable reference to the value. Nobody else can hold the lock while
+    /// we have the mutex mutably borrowed, so there is no need to lock it.
+    pub fn get_mut(&mut self) -> LockResult<&mut T> {
+        let poisoned = self.poison.get();
+        // SAFETY: we have the mutex mutably borrowed
+        let value = self.cell.with_mut(|value| unsafe { &mut *value });
+        if poisoned {
+            Err(std::sync::PoisonError::new(value))
+        } else {
+            Ok(value)
+        }
+    }
+
+    pub fn into_inner(self) -> LockResult<T> {
+        // we own the mutex, so no one else can hold the lock
+        let poisoned = self.poison.get();
+        let value = self.cell.into_inner();
+        if poisoned {
+            Err(std::sync::PoisonError::new(value))
+        } else {
+            Ok(value)
+        }
+    }
+}
+
+impl<T: Default> Default for Mutex<T> {
+    fn default() -> Self {
+        Mutex::new(T::default())
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Mutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("Mutex");
+        match self.try_lock() {
+            Ok(guard) => d.field("data", &&*guard),
+            Err(TryLockError::Poisoned(error)) => d.field("data", &&**error.get_ref()),
+            Err(TryLockError::WouldBlock) => d.field("data", &format_args!("<locked>")),
+        };
+        d.field("poisoned", &self.is_poisoned()).finish()
+    }
+}
+
+impl<'a, T> MutexGuard<'a, T> {
+    /// A guard for the part of the value that `f` picks, such as a field.
+    /// This is `MutexGuard::map(guard, f)` rather than a method, so that it
+    /// doesn't hide a `map` method of `T`.
+    pub fn map<U>(guard: Self, f: impl FnOnce(&mut T) -> &mut U) -> MappedMutexGuard<'a, U> {
+        let mutex = guard.mutex;
+        // SAFETY: we hold the lock. If `f` panics, `guard` still unlocks.
+        let value = NonNull::from(f(mutex.cell.with_mut(|value| unsafe { &mut *value })));
+        // the mapped guard unlocks instead
+        let guard = ManuallyDrop::new(guard);
+        MappedMutexGuard {
+            raw: &mutex.raw,
+            poison_flag: &mutex.poison,
+            poison: guard.poison,
+            held: guard.held,
+            value,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, T> MappedMutexGuard<'a, T> {
+    /// Like [MutexGuard::map], for a part of a part
+    pub fn map<U>(guard: Self, f: impl FnOnce(&mut T) -> &mut U) -> MappedMutexGuard<'a, U> {
+        // SAFETY: we hold the lock. If `f` panics, `guard` still unlocks.
+        let value = NonNull::from(f(unsafe { &mut *guard.value.as_ptr() }));
+        let guard = ManuallyDrop::new(guard);
+        MappedMutexGuard {
+            raw: guard.raw,
+            poison_flag: guard.poison_flag,
+            poison: guard.poison,
+            held: guard.held,
+            value,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        // unsafe code will be covered in module F. The standard API for `UnsafeCell` is not
+        // sufficient to implement this function, even though it does not break any of rust's rules.
+        // We explicitly take on the task of verifying correctness here, and promise to the compiler
+        // the operation below is valid.
+        //
+        // SAFETY: we have a shared reference to the mutex guard,
+        // and therefore have (shared) access to the value protected by the mutex
+        self.mutex.cell.with(|value| unsafe { &*value })
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // unsafe code will be covered in module F. The standard API for `UnsafeCell` is not
+        // sufficient to implement this function, even though it does not break any of rust's rules.
+        // We explicitly take on the task of verifying correctness here, and promise to the compiler
+        // the operation below is valid.
+        //
+        // SAFETY: we have an exclusive reference to the mutex guard,
+        // and therefore have exclusive access to the value protected by the mutex
+        self.mutex.cell.with_mut(|value| unsafe { &mut *value })
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for MutexGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.poison.done(&self.poison);
+        self.mutex.raw.release(self.held);
+    }
+}
+
+impl<T> Deref for MappedMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        // SAFETY: as for `MutexGuard`, the value is part of the one in the
+        // mutex, and we hold the lock
+        unsafe { self.value.as_ref() }
+    }
+}
+
+impl<T> DerefMut for MappedMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // SAFETY: as for `MutexGuard`, the value is part of the one in the
+        // mutex, and we hold the lock
+        unsafe { self.value.as_mut() }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for MappedMutexGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T> Drop for MappedMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.poison_flag.done(&self.poison);
+        self.raw.release(self.held);
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_counter() {
+        let counter = Mutex::new(0);
+        thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for _ in 0..10_000 {
+                        *counter.lock().unwrap() += 1;
+                    }
+                });
+            }
+        });
+        assert_eq!(counter.into_inner().unwrap(), 80_000);
+    }
+
+    #[test]
+    fn test_try_lock() {
+        let mut mutex = Mutex::new(1);
+        let guard = mutex.try_lock().unwrap();
+        assert!(matches!(mutex.try_lock(), Err(TryLockError::WouldBlock)));
+        // from another thread too
+        thread::scope(|s| {
+            s.spawn(|| assert!(mutex.try_lock().is_err()))
+                .join()
+                .unwrap()
+        });
+        drop(guard);
+        *mutex.try_lock().unwrap() += 1;
+        assert_eq!(*mutex.get_mut().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_lock_timeout() {
+        let mutex = Mutex::new(());
+        assert!(mutex.lock_timeout(Duration::ZERO).is_ok());
+
+        let guard = mutex.lock().unwrap();
+        let timeout = Duration::from_millis(50);
+        thread::scope(|s| {
+            let start = Instant::now();
+            s.spawn(|| {
+                let result = mutex.lock_timeout(timeout);
+                assert!(matches!(result, Err(TryLockError::WouldBlock)));
+            })
+            .join()
+            .unwrap();
+            assert!(start.elapsed() >= timeout);
+
+            // released while waiting
+            let waiter = s.spawn(|| mutex.lock_timeout(Duration::MAX).is_ok());
+            thread::sleep(Duration::from_millis(20));
+            drop(guard);
+            assert!(waiter.join().unwrap());
+        });
+    }
+
+    /// Panic in another thread while holding `mutex`, after applying `f`
+    fn panic_holding<T: Send>(mutex: &Mutex<T>, f: impl FnOnce(&mut T) + Send) {
+        thread::scope(|s| {
+            let panicked = s.spawn(|| {
+                let mut guard = mutex.lock().unwrap();
+                f(&mut guard);
+                panic!("while holding the lock");
+            });
+            assert!(panicked.join().is_err());
+        });
+    }
+
+    #[test]
+    fn test_poison() {
+        let mutex = Mutex::new(vec![1]);
+        panic_holding(&mutex, |v| v.push(2));
+        assert!(mutex.is_poisoned());
+
+        // the lock was released, and the value is still there
+        let error = mutex.lock().unwrap_err();
+        assert_eq!(**error.get_ref(), [1, 2]);
+        let mut guard = error.into_inner();
+        guard.pop();
+        drop(guard);
+        assert!(matches!(mutex.try_lock(), Err(TryLockError::Poisoned(_))));
+        assert!(matches!(
+            mutex.lock_timeout(Duration::ZERO),
+            Err(TryLockError::Poisoned(_))
+        ));
+
+        assert_eq!(format!("{mutex:?}"), "Mutex { data: [1], poisoned: true }");
+        mutex.clear_poison();
+        assert!(!mutex.is_poisoned());
+        assert_eq!(*mutex.lock().unwrap(), [1]);
+
+        panic_holding(&mutex, |_| {});
+        assert_eq!(mutex.into_inner().unwrap_err().into_inner(), [1]);
+    }
+
+    #[test]
+    fn test_no_poison() {
+        let mutex = Mutex::new(0);
+        // panicking without holding the lock
+        thread::scope(|s| {
+            let panicked = s.spawn(|| {
+                *mutex.lock().unwrap() += 1;
+                panic!("after releasing the lock");
+            });
+            assert!(panicked.join().is_err());
+        });
+        assert!(!mutex.is_poisoned());
+
+        // locking while unwinding from a panic that happened elsewhere
+        struct LockOnDrop<'a>(&'a Mutex<i32>);
+        impl Drop for LockOnDrop<'_> {
+            fn drop(&mut self) {
+                *self.0.lock().unwrap() += 1;
+            }
+        }
+        thread::scope(|s| {
+            let panicked = s.spawn(|| {
+                let _lock_on_drop = LockOnDrop(&mutex);
+                panic!("before locking");
+            });
+            assert!(panicked.join().is_err());
+        });
+        assert!(!mutex.is_poisoned());
+        assert_eq!(mutex.into_inner().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_map() {
+        let mutex = Mutex::new((1, vec![String::from("a")]));
+        let mut names = MutexGuard::map(mutex.lock().unwrap(), |(_, names)| names);
+        names.push(String::from("b"));
+        // still locked
+        assert!(mutex.try_lock().is_err());
+        let mut first = MappedMutexGuard::map(names, |names| &mut names[0]);
+        first.push('c');
+        drop(first);
+        assert_eq!(mutex.lock().unwrap().1, ["ac", "b"]);
+
+        // panicking while holding a mapped guard poisons the mutex too
+        thread::scope(|s| {
+            let panicked = s.spawn(|| {
+                let mut count = MutexGuard::map(mutex.lock().unwrap(), |(count, _)| count);
+                *count += 1;
+                panic!("while holding the lock");
+            });
+            assert!(panicked.join().is_err());
+        });
+        assert!(mutex.is_poisoned());
+        mutex.clear_poison();
+
+        // and so does panicking in the function, which releases the lock
+        thread::scope(|s| {
+            let panicked = s.spawn(|| {
+                drop(MutexGuard::map(mutex.lock().unwrap(), |_| -> &mut i32 {
+                    panic!("in map")
+                }))
+            });
+            assert!(panicked.join().is_err());
+        });
+        assert!(mutex.is_poisoned());
+        assert_eq!(mutex.into_inner().unwrap_err().into_inner().0, 2);
+    }
+
+    #[test]
+    fn test_guard_not_send() {
+        fn assert_sync<T: Sync>() {}
+        assert_not_send!(MutexGuard<'static, i32>);
+        assert_not_send!(MappedMutexGuard<'static, i32>);
+        assert_sync::<MutexGuard<'static, i32>>();
+        assert_sync::<MappedMutexGuard<'static, i32>>();
+    }
+}
+
+/// Run with `RUSTFLAGS="--cfg loom" cargo test --release --lib`
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::*;
+    use loom::{sync::Arc, thread};
+
+    #[test]
+    fn test_counter() {
+        loom::model(|| {
+            let counter = Arc::new(Mutex::new(0));
+            let other = thread::spawn({
+                let counter = counter.clone();
+                move || *counter.lock().unwrap() += 1
+            });
+            *counter.lock().unwrap() += 1;
+            other.join().unwrap();
+            assert_eq!(*counter.lock().unwrap(), 2);
+        });
+    }
+
+    #[test]
+    fn test_try_lock() {
+        loom::model(|| {
+            let mutex = Arc::new(Mutex::new(0));
+            let other = thread::spawn({
+                let mutex = mutex.clone();
+                move || {
+                    if let Ok(mut guard) = mutex.try_lock() {
+                        *guard += 1;
+                    }
+                }
+            });
+            *mutex.lock().unwrap() += 1;
+            other.join().unwrap();
+            assert!(matches!(*mutex.lock().unwrap(), 1 | 2));
+        });
+    }
+}
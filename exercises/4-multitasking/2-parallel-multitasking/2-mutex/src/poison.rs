@@ -0,0 +1,65 @@
+//! Poisoning, the way std does it: a lock that was held by a thread that
+//! panicked is marked as poisoned, because the panic may have left the value
+//! it protects half updated. Later attempts to lock it return a
+//! [PoisonError](std::sync::PoisonError), which still gives access to the
+//! value for those who know how to repair it.
+
+use std::sync::{LockResult, PoisonError};
+use std::thread;
+
+use crate::sync::{AtomicBool, Ordering};
+
+/// Whether a lock is poisoned
+#[derive(Debug)]
+pub(crate) struct Flag(AtomicBool);
+
+/// Whether the thread holding a lock was already panicking when it locked
+/// it. Locking in a destructor while unwinding does not poison the lock,
+/// because the panic did not happen while holding it.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Guard {
+    panicking: bool,
+}
+
+impl Flag {
+    pub(crate) fn new() -> Self {
+        Flag(AtomicBool::new(false))
+    }
+
+    /// Start holding the lock. `Err` if it was poisoned.
+    pub(crate) fn guard(&self) -> LockResult<Guard> {
+        let guard = Guard {
+            panicking: thread::panicking(),
+        };
+        if self.get() {
+            Err(PoisonError::new(guard))
+        } else {
+            Ok(guard)
+        }
+    }
+
+    /// Stop holding the lock, poisoning it if the thread started panicking
+    /// in the meantime
+    pub(crate) fn done(&self, guard: &Guard) {
+        if !guard.panicking && thread::panicking() {
+            self.0.store(true, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn get(&self) -> bool {
+        // Relaxed is enough: the lock itself orders the accesses to the value
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn clear(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Apply `f` to the value of a [LockResult], poisoned or not
+pub(crate) fn map_result<T, U>(result: LockResult<T>, f: impl FnOnce(T) -> U) -> LockResult<U> {
+    match result {
+        Ok(value) => Ok(f(value)),
+        Err(error) => Err(PoisonError::new(f(error.into_inner()))),
+    }
+}
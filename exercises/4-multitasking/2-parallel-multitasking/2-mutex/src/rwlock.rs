@@ -0,0 +1,511 @@
+use std::{
+    fmt,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    sync::{LockResult, TryLockError, TryLockResult},
+};
+
+use crate::diagnostics::{self, Held};
+use crate::futex::Futex;
+use crate::lock_order::LockId;
+use crate::poison;
+use crate::sync::{Ordering, UnsafeCell};
+
+/// The state of an [RwLock] when a writer holds it
+const WRITE_LOCKED: u32 = u32::MAX;
+
+/// A lock that many readers can hold at once, or a single writer.
+///
+/// Writers get priority: once a writer is waiting, new readers wait until it
+/// is done, so that a steady stream of readers can't keep writers out
+/// forever. This follows https://marabos.nl/atomics/building-locks.html#reader-writer-lock
+pub struct RwLock<T> {
+    /// Twice the number of readers, plus one if a writer is waiting, or
+    /// [WRITE_LOCKED]. Readers wait on this to become even.
+    state: Futex,
+    /// Writers wait on this rather than on `state`, which changes with every
+    /// reader that comes or goes. It is incremented whenever a waiting
+    /// writer may be able to lock.
+    writer_wake_counter: Futex,
+    poison: poison::Flag,
+    /// Reads and writes together
+    stats: diagnostics::Recorder,
+    order: LockId,
+    cell: UnsafeCell<T>,
+}
+
+// readers on different threads share the `T`, so it must be `Sync`, and a
+// writer can move a `T` from one thread to another, so it must be `Send`
+unsafe impl<T: Send + Sync> Sync for RwLock<T> {}
+
+// Neither guard is `Send`, as for `MutexGuard`, and sharing one only shares
+// the `T`
+
+pub struct RwLockReadGuard<'a, T> {
+    rwlock: &'a RwLock<T>,
+    held: Held,
+    _not_send: PhantomData<*const ()>,
+}
+
+unsafe impl<T: Sync> Sync for RwLockReadGuard<'_, T> {}
+
+pub struct RwLockWriteGuard<'a, T> {
+    rwlock: &'a RwLock<T>,
+    poison: poison::Guard,
+    held: Held,
+    _marker: PhantomData<&'a mut T>,
+    _not_send: PhantomData<*const ()>,
+}
+
+unsafe impl<T: Sync> Sync for RwLockWriteGuard<'_, T> {}
+
+impl<T> RwLock<T> {
+    pub fn new(value: T) -> Self {
+        RwLock {
+            state: Futex::new(0),
+            writer_wake_counter: Futex::new(0),
+            poison: poison::Flag::new(),
+            stats: diagnostics::Recorder::new(),
+            order: LockId::new(),
+            cell: UnsafeCell::new(value),
+        }
+    }
+
+    fn read_guard(&self, held: Held) -> LockResult<RwLockReadGuard<'_, T>> {
+        self.order.locked();
+        let guard = RwLockReadGuard {
+            rwlock: self,
+            held,
+            _not_send: PhantomData,
+        };
+        // only writers poison the lock, but readers still find out
+        if self.poison.get() {
+            Err(std::sync::PoisonError::new(guard))
+        } else {
+            Ok(guard)
+        }
+    }
+
+    fn write_guard(&self, held: Held) -> LockResult<RwLockWriteGuard<'_, T>> {
+        self.order.locked();
+        poison::map_result(self.poison.guard(), |poison| RwLockWriteGuard {
+            rwlock: self,
+            poison,
+            held,
+            _marker: PhantomData,
+            _not_send: PhantomData,
+        })
+    }
+
+    /// Wait until no writer holds or waits for the lock, and share it with
+    /// the other readers.
+    ///
+    /// In debug builds, this checks the lock order like
+    /// [Mutex::lock](crate::Mutex::lock). Reading takes part too: with a
+    /// writer waiting, a reader waits as well.
+    #[track_caller]
+    pub fn read(&self) -> LockResult<RwLockReadGuard<'_, T>> {
+        self.order.before_lock();
+        let waiting = self.stats.waiting();
+        let mut contended = false;
+        let mut state = self.state.load(Ordering::Relaxed);
+        loop {
+            if state.is_multiple_of(2) {
+                assert!(state < WRITE_LOCKED - 2, "too many readers");
+                match self.state.compare_exchange_weak(
+                    state,
+                    state + 2,
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => return self.read_guard(self.stats.acquired(waiting, contended)),
+                    Err(actual) => state = actual,
+                }
+            }
+            // a writer holds the lock or is waiting for it
+            if !state.is_multiple_of(2) {
+                contended = true;
+                self.state.wait(state);
+                state = self.state.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Share the lock with the other readers if no writer holds or waits for
+    /// it, without waiting
+    pub fn try_read(&self) -> TryLockResult<RwLockReadGuard<'_, T>> {
+        let waiting = self.stats.waiting();
+        let mut state = self.state.load(Ordering::Relaxed);
+        while state.is_multiple_of(2) {
+            assert!(state < WRITE_LOCKED - 2, "too many readers");
+            match self.state.compare_exchange_weak(
+                state,
+                state + 2,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Ok(self.read_guard(self.stats.acquired(waiting, false))?),
+                Err(actual) => state = actual,
+            }
+        }
+        Err(TryLockError::WouldBlock)
+    }
+
+    /// Wait until nobody else holds the lock, and take it
+    #[track_caller]
+    pub fn write(&self) -> LockResult<RwLockWriteGuard<'_, T>> {
+        self.order.before_lock();
+        let waiting = self.stats.waiting();
+        let mut contended = false;
+        let mut state = self.state.load(Ordering::Relaxed);
+        loop {
+            // nobody holds the lock, though another writer may be waiting
+            if state <= 1 {
+                match self.state.compare_exchange(
+                    state,
+                    WRITE_LOCKED,
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => return self.write_guard(self.stats.acquired(waiting, contended)),
+                    Err(actual) => {
+                        state = actual;
+                        continue;
+                    }
+                }
+            }
+            contended = true;
+            // keep new readers out
+            if state.is_multiple_of(2) {
+                if let Err(actual) = self.state.compare_exchange(
+                    state,
+                    state + 1,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    state = actual;
+                    continue;
+                }
+            }
+            // Read the counter before checking the state again: a wake up
+            // after the check increments it, so `wait` won't sleep through it
+            let wake_counter = self.writer_wake_counter.load(Ordering::Acquire);
+            state = self.state.load(Ordering::Relaxed);
+            if state >= 2 {
+                self.writer_wake_counter.wait(wake_counter);
+                state = self.state.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Take the lock if nobody else holds it, without waiting
+    pub fn try_write(&self) -> TryLockResult<RwLockWriteGuard<'_, T>> {
+        let waiting = self.stats.waiting();
+        let mut state = self.state.load(Ordering::Relaxed);
+        while state <= 1 {
+            match self.state.compare_exchange(
+                state,
+                WRITE_LOCKED,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Ok(self.write_guard(self.stats.acquired(waiting, false))?),
+                Err(actual) => state = actual,
+            }
+        }
+        Err(TryLockError::WouldBlock)
+    }
+
+    fn read_unlock(&self, held: Held) {
+        self.stats.released(held);
+        self.order.unlocked();
+        // the last reader wakes up the writer waiting, if any
+        if self.state.fetch_sub(2, Ordering::Release) == 3 {
+            self.writer_wake_counter.fetch_add(1, Ordering::Release);
+            self.writer_wake_counter.wake_one();
+        }
+    }
+
+    fn write_unlock(&self, held: Held) {
+        self.stats.released(held);
+        self.order.unlocked();
+        // This forgets whether other writers are waiting, but they set it
+        // again when they wake up. We don't know whether readers or writers
+        // are waiting, so wake up one writer and all readers.
+        self.state.store(0, Ordering::Release);
+        self.writer_wake_counter.fetch_add(1, Ordering::Release);
+        self.writer_wake_counter.wake_one();
+        self.state.wake_all();
+    }
+
+    /// How often the lock has been taken, and how long threads waited for it
+    /// and held it, counting reads and writes together. Readers hold the
+    /// lock at the same time, so the total time it was held can be longer
+    /// than it existed.
+    #[cfg(feature = "diagnostics")]
+    pub fn stats(&self) -> diagnostics::LockStats {
+        self.stats.stats()
+    }
+
+    /// Whether some writer is waiting for the readers to be done
+    #[cfg(all(test, feature = "diagnostics", not(loom)))]
+    pub(crate) fn has_waiters(&self) -> bool {
+        let state = self.state.load(Ordering::Relaxed);
+        state != WRITE_LOCKED && !state.is_multiple_of(2)
+    }
+
+    /// Whether a thread panicked while holding the lock for writing
+    pub fn is_poisoned(&self) -> bool {
+        self.poison.get()
+    }
+
+    /// Mark the lock as no longer poisoned
+    pub fn clear_poison(&self) {
+        self.poison.clear();
+    }
+
+    /// A mutable reference to the value. Nobody else can hold the lock while
+    /// we have it mutably borrowed, so there is no need to lock it.
+    pub fn get_mut(&mut self) -> LockResult<&mut T> {
+        let poisoned = self.poison.get();
+        // SAFETY: we have the lock mutably borrowed
+        let value = self.cell.with_mut(|value| unsafe { &mut *value });
+        if poisoned {
+            Err(std::sync::PoisonError::new(value))
+        } else {
+            Ok(value)
+        }
+    }
+
+    pub fn into_inner(self) -> LockResult<T> {
+        let poisoned = self.poison.get();
+        let value = self.cell.into_inner();
+        if poisoned {
+            Err(std::sync::PoisonError::new(value))
+        } else {
+            Ok(value)
+        }
+    }
+}
+
+impl<T: Default> Default for RwLock<T> {
+    fn default() -> Self {
+        RwLock::new(T::default())
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for RwLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("RwLock");
+        match self.try_read() {
+            Ok(guard) => d.field("data", &&*guard),
+            Err(TryLockError::Poisoned(error)) => d.field("data", &&**error.get_ref()),
+            Err(TryLockError::WouldBlock) => d.field("data", &format_args!("<locked>")),
+        };
+        d.field("poisoned", &self.is_poisoned()).finish()
+    }
+}
+
+impl<T> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: we share the lock with other readers only
+        self.rwlock.cell.with(|value| unsafe { &*value })
+    }
+}
+
+impl<T> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: nobody else holds the lock
+        self.rwlock.cell.with(|value| unsafe { &*value })
+    }
+}
+
+impl<T> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: nobody else holds the lock
+        self.rwlock.cell.with_mut(|value| unsafe { &mut *value })
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for RwLockReadGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for RwLockWriteGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T> Drop for RwLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.rwlock.read_unlock(self.held);
+    }
+}
+
+impl<T> Drop for RwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.rwlock.poison.done(&self.poison);
+        self.rwlock.write_unlock(self.held);
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use super::*;
+    use std::{thread, time::Duration};
+
+    #[test]
+    fn test_readers_and_writers() {
+        let lock = RwLock::new(0);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        *lock.write().unwrap() += 1;
+                    }
+                });
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        let value = *lock.read().unwrap();
+                        assert!(value <= 4000);
+                    }
+                });
+            }
+        });
+        assert_eq!(lock.into_inner().unwrap(), 4000);
+    }
+
+    #[test]
+    fn test_try() {
+        let lock = RwLock::new(1);
+        let first = lock.read().unwrap();
+        let second = lock.try_read().unwrap();
+        assert_eq!(*first + *second, 2);
+        assert!(matches!(lock.try_write(), Err(TryLockError::WouldBlock)));
+        drop((first, second));
+
+        let mut writer = lock.try_write().unwrap();
+        *writer += 1;
+        assert!(matches!(lock.try_read(), Err(TryLockError::WouldBlock)));
+        assert!(matches!(lock.try_write(), Err(TryLockError::WouldBlock)));
+        drop(writer);
+        assert_eq!(*lock.read().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_writer_preferred() {
+        let lock = RwLock::new(0);
+        let reader = lock.read().unwrap();
+        thread::scope(|s| {
+            let writer = s.spawn(|| *lock.write().unwrap() += 1);
+            // wait for the writer to start waiting
+            while lock.try_read().is_ok() {
+                thread::sleep(Duration::from_millis(1));
+            }
+            // so new readers have to wait for it too
+            let late_reader = s.spawn(|| *lock.read().unwrap());
+            drop(reader);
+            writer.join().unwrap();
+            assert_eq!(late_reader.join().unwrap(), 1);
+        });
+    }
+
+    #[test]
+    fn test_poison() {
+        let lock = RwLock::new(vec![1]);
+        // panicking readers don't poison the lock
+        thread::scope(|s| {
+            let reader = s.spawn(|| {
+                let _guard = lock.read().unwrap();
+                panic!("while reading");
+            });
+            assert!(reader.join().is_err());
+        });
+        assert!(!lock.is_poisoned());
+
+        thread::scope(|s| {
+            let writer = s.spawn(|| {
+                lock.write().unwrap().push(2);
+                let _guard = lock.write().unwrap();
+                panic!("while writing");
+            });
+            assert!(writer.join().is_err());
+        });
+        assert!(lock.is_poisoned());
+        assert_eq!(**lock.read().unwrap_err().get_ref(), [1, 2]);
+        assert!(matches!(lock.try_write(), Err(TryLockError::Poisoned(_))));
+        assert_eq!(
+            format!("{lock:?}"),
+            "RwLock { data: [1, 2], poisoned: true }"
+        );
+        lock.clear_poison();
+        assert_eq!(lock.into_inner().unwrap(), [1, 2]);
+    }
+
+    #[test]
+    fn test_guards_not_send() {
+        assert_not_send!(RwLockReadGuard<'static, i32>);
+        assert_not_send!(RwLockWriteGuard<'static, i32>);
+    }
+}
+
+/// Run with `RUSTFLAGS="--cfg loom" cargo test --release --lib`
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::*;
+    use loom::{sync::Arc, thread};
+
+    #[test]
+    fn test_reader_and_writer() {
+        loom::model(|| {
+            let lock = Arc::new(RwLock::new(0));
+            let writer = thread::spawn({
+                let lock = lock.clone();
+                move || *lock.write().unwrap() += 1
+            });
+            // either before or after the write, never during
+            let value = *lock.read().unwrap();
+            assert!(value == 0 || value == 1);
+            writer.join().unwrap();
+            assert_eq!(*lock.read().unwrap(), 1);
+        });
+    }
+
+    #[test]
+    fn test_writers() {
+        loom::model(|| {
+            let lock = Arc::new(RwLock::new(0));
+            let writer = thread::spawn({
+                let lock = lock.clone();
+                move || *lock.write().unwrap() += 1
+            });
+            *lock.write().unwrap() += 1;
+            writer.join().unwrap();
+            assert_eq!(*lock.read().unwrap(), 2);
+        });
+    }
+
+    #[test]
+    fn test_last_reader_wakes_writer() {
+        loom::model(|| {
+            let lock = Arc::new(RwLock::new(0));
+            let reader = lock.read().unwrap();
+            let writer = thread::spawn({
+                let lock = lock.clone();
+                move || *lock.write().unwrap() = 1
+            });
+            // the writer may be waiting by now, and this has to wake it up
+            assert_eq!(*reader, 0);
+            drop(reader);
+            writer.join().unwrap();
+            assert_eq!(*lock.read().unwrap(), 1);
+        });
+    }
+}
@@ -0,0 +1,378 @@
+use std::{
+    fmt,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    sync::{LockResult, TryLockError, TryLockResult},
+};
+
+use crate::diagnostics::{self, Held};
+use crate::futex::Futex;
+use crate::lock_order::LockId;
+use crate::poison;
+use crate::sync::{AtomicU32, Ordering, UnsafeCell};
+
+/// See the one in [Mutex](crate::Mutex)
+#[cfg(not(loom))]
+const SPIN_LIMIT: usize = 100;
+#[cfg(loom)]
+const SPIN_LIMIT: usize = 0;
+
+/// A mutex that hands out the lock in the order threads asked for it.
+///
+/// [Mutex](crate::Mutex) lets whichever thread happens to try at the right
+/// moment have the lock, usually the one that just released it and is
+/// still running. Under heavy contention, that can keep another thread
+/// waiting indefinitely. Here every thread draws a ticket, like at a
+/// bakery counter, and waits until its number is served, so nobody waits
+/// for more than the threads that came before it.
+///
+/// Fairness has a price: the lock can only go to the next thread in line,
+/// even if it is asleep, and all waiting threads wake up to check whether
+/// it is their turn. Prefer [Mutex](crate::Mutex) unless threads starve.
+pub struct TicketLock<T> {
+    /// The ticket the next thread to ask for the lock gets
+    next_ticket: AtomicU32,
+    /// The ticket of the thread that holds the lock, or may take it.
+    /// Waiting threads sleep on this until it is their ticket.
+    now_serving: Futex,
+    poison: poison::Flag,
+    stats: diagnostics::Recorder,
+    order: LockId,
+    cell: UnsafeCell<T>,
+}
+
+// as for `Mutex`
+unsafe impl<T: Send> Sync for TicketLock<T> {}
+
+pub struct TicketLockGuard<'a, T> {
+    lock: &'a TicketLock<T>,
+    poison: poison::Guard,
+    held: Held,
+    _marker: PhantomData<&'a mut T>,
+    // as for `MutexGuard`
+    _not_send: PhantomData<*const ()>,
+}
+
+// SAFETY: sharing a guard only shares the `T`
+unsafe impl<T: Sync> Sync for TicketLockGuard<'_, T> {}
+
+impl<T> TicketLock<T> {
+    pub fn new(value: T) -> Self {
+        TicketLock {
+            next_ticket: AtomicU32::new(0),
+            now_serving: Futex::new(0),
+            poison: poison::Flag::new(),
+            stats: diagnostics::Recorder::new(),
+            order: LockId::new(),
+            cell: UnsafeCell::new(value),
+        }
+    }
+
+    /// Only call this while holding the lock
+    fn guard(&self, held: Held) -> LockResult<TicketLockGuard<'_, T>> {
+        self.order.locked();
+        poison::map_result(self.poison.guard(), |poison| TicketLockGuard {
+            lock: self,
+            poison,
+            held,
+            _marker: PhantomData,
+            _not_send: PhantomData,
+        })
+    }
+
+    /// Wait until every thread that asked for the lock before us had it,
+    /// and take it. Like [Mutex::lock](crate::Mutex::lock), this returns an
+    /// error if the lock is poisoned, and checks the lock order in debug
+    /// builds.
+    #[track_caller]
+    pub fn lock(&self) -> LockResult<TicketLockGuard<'_, T>> {
+        self.order.before_lock();
+        let waiting = self.stats.waiting();
+        // Whether the lock was held when we drew our ticket. Reading the
+        // ticket being served before drawing ours settles that as we draw:
+        // if it changes in between, somebody held the lock while we asked.
+        let serving = self.now_serving.load(Ordering::Acquire);
+        // Tickets wrap around, which is fine as long as fewer than 2^32
+        // threads wait at once. See `unlock` for why this is `AcqRel`.
+        let ticket = self.next_ticket.fetch_add(1, Ordering::AcqRel);
+        let contended = serving != ticket;
+        let mut spins = 0;
+        loop {
+            let serving = self.now_serving.load(Ordering::Acquire);
+            if serving == ticket {
+                break;
+            }
+            if spins < SPIN_LIMIT {
+                std::hint::spin_loop();
+                spins += 1;
+            } else {
+                self.now_serving.wait(serving);
+            }
+        }
+        self.guard(self.stats.acquired(waiting, contended))
+    }
+
+    /// Take the lock if nobody holds it or waits for it, without waiting
+    pub fn try_lock(&self) -> TryLockResult<TicketLockGuard<'_, T>> {
+        let waiting = self.stats.waiting();
+        // Only the thread holding the lock changes the ticket being served,
+        // so if it is the next ticket, nobody holds the lock
+        let serving = self.now_serving.load(Ordering::Acquire);
+        match self.next_ticket.compare_exchange(
+            serving,
+            serving.wrapping_add(1),
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+        ) {
+            Ok(_) => Ok(self.guard(self.stats.acquired(waiting, false))?),
+            Err(_) => Err(TryLockError::WouldBlock),
+        }
+    }
+
+    fn unlock(&self, held: Held) {
+        self.stats.released(held);
+        self.order.unlocked();
+        let next = self
+            .now_serving
+            .fetch_add(1, Ordering::Release)
+            .wrapping_add(1);
+        // There is no way to wake up only the thread with the next ticket,
+        // so wake them all, if anybody is waiting.
+        //
+        // Adding zero rather than loading reads the latest ticket handed
+        // out. A thread that draws its ticket after that reads what we
+        // wrote here, so it sees the new ticket being served rather than
+        // going to sleep without us waking it up.
+        if self.next_ticket.fetch_add(0, Ordering::AcqRel) != next {
+            self.now_serving.wake_all();
+        }
+    }
+
+    /// How often the lock has been taken, and how long threads waited for it
+    /// and held it
+    #[cfg(feature = "diagnostics")]
+    pub fn stats(&self) -> diagnostics::LockStats {
+        self.stats.stats()
+    }
+
+    /// Whether some thread has drawn a ticket after the one being served
+    #[cfg(all(test, feature = "diagnostics", not(loom)))]
+    pub(crate) fn has_waiters(&self) -> bool {
+        let serving = self.now_serving.load(Ordering::Relaxed);
+        self.next_ticket
+            .load(Ordering::Relaxed)
+            .wrapping_sub(serving)
+            > 1
+    }
+
+    /// Whether a thread panicked while holding the lock
+    pub fn is_poisoned(&self) -> bool {
+        self.poison.get()
+    }
+
+    /// Mark the lock as no longer poisoned
+    pub fn clear_poison(&self) {
+        self.poison.clear();
+    }
+
+    /// A mutable reference to the value. Nobody else can hold the lock while
+    /// we have it mutably borrowed, so there is no need to lock it.
+    pub fn get_mut(&mut self) -> LockResult<&mut T> {
+        let poisoned = self.poison.get();
+        // SAFETY: we have the lock mutably borrowed
+        let value = self.cell.with_mut(|value| unsafe { &mut *value });
+        if poisoned {
+            Err(std::sync::PoisonError::new(value))
+        } else {
+            Ok(value)
+        }
+    }
+
+    pub fn into_inner(self) -> LockResult<T> {
+        let poisoned = self.poison.get();
+        let value = self.cell.into_inner();
+        if poisoned {
+            Err(std::sync::PoisonError::new(value))
+        } else {
+            Ok(value)
+        }
+    }
+}
+
+impl<T: Default> Default for TicketLock<T> {
+    fn default() -> Self {
+        TicketLock::new(T::default())
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for TicketLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("TicketLock");
+        match self.try_lock() {
+            Ok(guard) => d.field("data", &&*guard),
+            Err(TryLockError::Poisoned(error)) => d.field("data", &&**error.get_ref()),
+            Err(TryLockError::WouldBlock) => d.field("data", &format_args!("<locked>")),
+        };
+        d.field("poisoned", &self.is_poisoned()).finish()
+    }
+}
+
+impl<T> Deref for TicketLockGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: nobody else holds the lock
+        self.lock.cell.with(|value| unsafe { &*value })
+    }
+}
+
+impl<T> DerefMut for TicketLockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: nobody else holds the lock
+        self.lock.cell.with_mut(|value| unsafe { &mut *value })
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for TicketLockGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T> Drop for TicketLockGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.poison.done(&self.poison);
+        self.lock.unlock(self.held);
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use super::*;
+    use std::{thread, time::Duration};
+
+    #[test]
+    fn test_counter() {
+        let counter = TicketLock::new(0);
+        thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for _ in 0..10_000 {
+                        *counter.lock().unwrap() += 1;
+                    }
+                });
+            }
+        });
+        assert_eq!(counter.into_inner().unwrap(), 80_000);
+    }
+
+    #[test]
+    fn test_fifo() {
+        let lock = TicketLock::new(Vec::new());
+        let guard = lock.lock().unwrap();
+        thread::scope(|s| {
+            for i in 0..8 {
+                s.spawn({
+                    let lock = &lock;
+                    move || lock.lock().unwrap().push(i)
+                });
+                // wait until it has drawn its ticket, so that they line up
+                // in order
+                while lock.next_ticket.load(Ordering::Relaxed) != i + 2 {
+                    thread::sleep(Duration::from_millis(1));
+                }
+            }
+            drop(guard);
+        });
+        assert_eq!(lock.into_inner().unwrap(), (0..8).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_try_lock() {
+        let mut lock = TicketLock::new(1);
+        let guard = lock.try_lock().unwrap();
+        assert!(matches!(lock.try_lock(), Err(TryLockError::WouldBlock)));
+        drop(guard);
+        *lock.try_lock().unwrap() += 1;
+        assert_eq!(*lock.get_mut().unwrap(), 2);
+        assert_eq!(
+            format!("{lock:?}"),
+            "TicketLock { data: 2, poisoned: false }"
+        );
+    }
+
+    #[test]
+    fn test_wrap_around() {
+        let lock = TicketLock::new(0);
+        lock.next_ticket.store(u32::MAX, Ordering::Relaxed);
+        lock.now_serving.store(u32::MAX, Ordering::Relaxed);
+        for _ in 0..3 {
+            *lock.lock().unwrap() += 1;
+            *lock.try_lock().unwrap() += 1;
+        }
+        assert_eq!(lock.now_serving.load(Ordering::Relaxed), 5);
+        assert_eq!(lock.into_inner().unwrap(), 6);
+    }
+
+    #[test]
+    fn test_poison() {
+        let lock = TicketLock::new(0);
+        thread::scope(|s| {
+            let panicked = s.spawn(|| {
+                let mut guard = lock.lock().unwrap();
+                *guard += 1;
+                panic!("while holding the lock");
+            });
+            assert!(panicked.join().is_err());
+        });
+        assert!(lock.is_poisoned());
+        assert_eq!(*lock.lock().unwrap_err().into_inner(), 1);
+        lock.clear_poison();
+        assert!(lock.try_lock().is_ok());
+    }
+
+    #[test]
+    fn test_guard_not_send() {
+        assert_not_send!(TicketLockGuard<'static, i32>);
+    }
+}
+
+/// Run with `RUSTFLAGS="--cfg loom" cargo test --release --lib`
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::*;
+    use loom::{sync::Arc, thread};
+
+    #[test]
+    fn test_counter() {
+        loom::model(|| {
+            let counter = Arc::new(TicketLock::new(0));
+            let other = thread::spawn({
+                let counter = counter.clone();
+                move || *counter.lock().unwrap() += 1
+            });
+            *counter.lock().unwrap() += 1;
+            other.join().unwrap();
+            assert_eq!(*counter.lock().unwrap(), 2);
+        });
+    }
+
+    #[test]
+    fn test_try_lock() {
+        loom::model(|| {
+            let lock = Arc::new(TicketLock::new(0));
+            let other = thread::spawn({
+                let lock = lock.clone();
+                move || {
+                    if let Ok(mut guard) = lock.try_lock() {
+                        *guard += 1;
+                    }
+                }
+            });
+            *lock.lock().unwrap() += 1;
+            other.join().unwrap();
+            let value = *lock.lock().unwrap();
+            assert!(value == 1 || value == 2);
+        });
+    }
+}
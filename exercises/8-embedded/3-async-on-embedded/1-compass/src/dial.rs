@@ -7,6 +7,7 @@ use embassy_nrf::{
 };
 use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, once_lock::OnceLock};
 use lsm303agr::MagneticField;
+use vector_math::{Float, Vec2};
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Direction {
@@ -53,9 +54,9 @@ impl Direction {
     }
 
     fn from_xy(x: f32, y: f32) -> Self {
-        let alpha = libm::atan2f(-y, x);
+        let alpha = Vec2::new(x, -y).angle();
         let part =
-            libm::roundf((alpha + core::f32::consts::PI) * 8.0 / core::f32::consts::PI) as u32;
+            Float::round((alpha + core::f32::consts::PI) * 8.0 / core::f32::consts::PI) as u32;
 
         match (part + 4) % 16 {
             0 => Self::North,